@@ -0,0 +1,472 @@
+//! Backend for xdg-desktop-portal, so that sandboxed apps can take screenshots
+//! and control the desktop remotely through the standard portals.
+//!
+//! Implements `org.freedesktop.impl.portal.Screenshot` and
+//! `org.freedesktop.impl.portal.RemoteDesktop` on the session bus by sending
+//! commands to the compositor over its IPC socket: `screenshot PATH` and
+//! `input SCREEN EVENT...`. For want of streams, absolute pointer motion
+//! takes the stream number to be the number of a screen.
+//!
+//! Nothing is granted without the user's consent. Screenshots are allowed to
+//! the apps that the permission store of xdg-desktop-portal says may take them;
+//! otherwise, and whenever a remote desktop session starts, the user is asked by
+//! running `$CHARLIE_PORTAL_ASK QUESTION` (by default `zenity --question --text
+//! QUESTION`), whose success means yes. Answers about screenshots are remembered
+//! in the permission store.
+//!
+//! ScreenCast is left for later: frames can't be streamed without PipeWire,
+//! which this doesn't link to yet.
+//!
+//! Install `portal/charlie.portal` where xdg-desktop-portal looks for backends,
+//! and start this alongside the compositor.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::Command,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use slog::{Drain, Logger, o, info, warn};
+
+use zbus::{
+    ObjectServer, dbus_interface, dbus_proxy, blocking::{Connection, ConnectionBuilder},
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+const BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.charlie";
+const BUS_PATH: &str = "/org/freedesktop/portal/desktop";
+
+/// How long to wait for the compositor to save a screenshot
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Responses to portal requests
+const SUCCESS:   u32 = 0;
+const CANCELLED: u32 = 1;
+const FAILED:    u32 = 2;
+
+/// Device types of the RemoteDesktop portal
+const KEYBOARD: u32 = 1;
+const POINTER:  u32 = 2;
+
+/// Table and id under which xdg-desktop-portal keeps who may take screenshots
+const SCREENSHOT_PERMISSION: &str = "screenshot";
+
+/// How the user is asked, unless `CHARLIE_PORTAL_ASK` says otherwise
+const ASK_DEFAULT: &str = "zenity --question --text";
+
+type Results = HashMap<String, OwnedValue>;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.impl.portal.PermissionStore",
+    default_service = "org.freedesktop.impl.portal.PermissionStore",
+    default_path = "/org/freedesktop/impl/portal/PermissionStore"
+)]
+trait PermissionStore {
+    fn lookup (&self, table: &str, id: &str) -> zbus::Result<(HashMap<String, Vec<String>>, OwnedValue)>;
+    fn set_permission (&self, table: &str, create: bool, id: &str, app: &str, permissions: &[&str])
+        -> zbus::Result<()>;
+}
+
+fn main () -> Result<(), Box<dyn Error>> {
+    let logger = Logger::root(Mutex::new(slog_term::term_full().fuse()).fuse(), o!());
+    let consent = Arc::new(Consent::new(&logger));
+    let sessions = Arc::new(Mutex::new(HashMap::new()));
+    let _connection = ConnectionBuilder::session()?
+        .name(BUS_NAME)?
+        .serve_at(BUS_PATH, Screenshot { logger: logger.clone(), consent: consent.clone() })?
+        .serve_at(BUS_PATH, RemoteDesktop { logger: logger.clone(), consent, sessions })?
+        .build()?;
+    info!(logger, "Serving portals as {BUS_NAME}");
+    loop {
+        std::thread::park();
+    }
+}
+
+/// Asks the user before an app is let do something, unless the permission store has the answer.
+struct Consent {
+    logger: Logger,
+    /// Where xdg-desktop-portal keeps what apps may do. Reached over a connection of its own,
+    /// since calling out over the connection of the portals while it's handling a call would hang
+    store:  Option<PermissionStoreProxyBlocking<'static>>,
+    /// The command that asks, which is given the question as its last argument
+    ask:    Vec<String>,
+}
+
+impl Consent {
+
+    fn new (logger: &Logger) -> Self {
+        let store = Connection::session().and_then(|connection| PermissionStoreProxyBlocking::new(&connection));
+        let store = match store {
+            Ok(store) => Some(store),
+            Err(e) => {
+                warn!(logger, "Could not reach the permission store, will ask every time: {e}");
+                None
+            }
+        };
+        let ask = std::env::var("CHARLIE_PORTAL_ASK").unwrap_or_else(|_| ASK_DEFAULT.into());
+        Self { logger: logger.clone(), store, ask: ask.split_whitespace().map(String::from).collect() }
+    }
+
+    /// What the user said before about letting an app do something, if it's known.
+    /// Apps outside of sandboxes have no app id to remember it by.
+    fn remembered (&self, table: &str, id: &str, app_id: &str) -> Option<bool> {
+        if app_id.is_empty() {
+            return None
+        }
+        let (permissions, _) = self.store.as_ref()?.lookup(table, id).ok()?;
+        permissions.get(app_id)?.first().map(|answer| answer == "yes")
+    }
+
+    /// Remember what the user said about letting an app do something.
+    fn remember (&self, table: &str, id: &str, app_id: &str, answer: bool) {
+        if let (Some(store), false) = (self.store.as_ref(), app_id.is_empty()) {
+            let answer = if answer { "yes" } else { "no" };
+            if let Err(e) = store.set_permission(table, true, id, app_id, &[answer]) {
+                warn!(self.logger, "Could not remember that {app_id} may {table}: {answer}: {e}");
+            }
+        }
+    }
+
+    /// Ask the user a question, taking anything but a yes for a no.
+    fn ask (&self, question: &str) -> bool {
+        let (program, args) = match self.ask.split_first() {
+            Some(command) => command,
+            None => return false
+        };
+        match Command::new(program).args(args).arg(question).status() {
+            Ok(status) => status.success(),
+            Err(e) => {
+                warn!(self.logger, "Could not ask \"{question}\" with {program}: {e}");
+                false
+            }
+        }
+    }
+
+    /// Whether an app may take a screenshot. If xdg-desktop-portal already found out,
+    /// it says so; otherwise the permission store or the user are asked.
+    fn screenshot (&self, app_id: &str, checked: bool) -> bool {
+        if checked {
+            return true
+        }
+        let (table, id) = (SCREENSHOT_PERMISSION, SCREENSHOT_PERMISSION);
+        if let Some(answer) = self.remembered(table, id, app_id) {
+            return answer
+        }
+        let answer = self.ask(&format!("Allow {} to take a screenshot?", app_name(app_id)));
+        self.remember(table, id, app_id, answer);
+        answer
+    }
+
+    /// Whether an app may control these devices, asked anew for each session.
+    fn remote_desktop (&self, app_id: &str, devices: u32) -> bool {
+        let devices = match (devices & KEYBOARD != 0, devices & POINTER != 0) {
+            (true, true)   => "keyboard and pointer",
+            (true, false)  => "keyboard",
+            (false, true)  => "pointer",
+            (false, false) => return true,
+        };
+        self.ask(&format!("Allow {} to control your {devices}?", app_name(app_id)))
+    }
+
+}
+
+/// How to call an app when asking about it.
+fn app_name (app_id: &str) -> &str {
+    if app_id.is_empty() { "an app outside of any sandbox" } else { app_id }
+}
+
+/// Find the control socket of the compositor we're running under.
+fn socket_path () -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(path) = std::env::var_os("CHARLIE_SOCKET") {
+        return Ok(path.into())
+    }
+    let display = std::env::var("WAYLAND_DISPLAY")
+        .map_err(|_|"neither CHARLIE_SOCKET nor WAYLAND_DISPLAY is set")?;
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+        .ok_or("XDG_RUNTIME_DIR is not set")?;
+    Ok(dir.join(format!("charlie-{display}.sock")))
+}
+
+/// Send a command over the IPC socket, failing if the compositor says it failed.
+fn command (command: &str) -> Result<String, Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket_path()?)?;
+    writeln!(stream, "{command}")?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    match reply.strip_prefix("error:") {
+        Some(error) => Err(error.trim().into()),
+        None => Ok(reply)
+    }
+}
+
+/// Save a screenshot of the active output, and wait until the compositor says it's saved.
+fn screenshot (path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    // Listen before asking, so the event isn't missed
+    let mut events = UnixStream::connect(socket_path()?)?;
+    events.set_read_timeout(Some(SCREENSHOT_TIMEOUT))?;
+    writeln!(events, "subscribe")?;
+    command(&format!("screenshot {}", path.display()))?;
+    let (saved, failed) = (
+        format!("screenshot saved {}", path.display()),
+        format!("screenshot failed {}", path.display()),
+    );
+    for line in BufReader::new(events).lines() {
+        let line = line?;
+        if line == saved {
+            return Ok(())
+        }
+        if let Some(error) = line.strip_prefix(&failed) {
+            return Err(error.trim_start_matches(':').trim().into())
+        }
+    }
+    Err("the compositor went away".into())
+}
+
+/// Where screenshots are saved, as XDG_PICTURES_DIR would have it.
+fn pictures_dir () -> PathBuf {
+    std::env::var_os("XDG_PICTURES_DIR").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Pictures")))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Implements org.freedesktop.impl.portal.Screenshot.
+struct Screenshot {
+    logger:  Logger,
+    consent: Arc<Consent>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Screenshot")]
+impl Screenshot {
+
+    fn screenshot (
+        &self,
+        _handle:        OwnedObjectPath,
+        app_id:         String,
+        _parent_window: String,
+        options:        HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        let checked = options.get("permission_store_checked")
+            .and_then(|checked| bool::try_from(checked.clone()).ok())
+            .unwrap_or(false);
+        if !self.consent.screenshot(&app_id, checked) {
+            info!(self.logger, "Not letting {} take a screenshot", app_name(&app_id));
+            return (CANCELLED, Results::new())
+        }
+        let dir = pictures_dir();
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+        let path = dir.join(format!("Screenshot-{seconds}.png"));
+        let result = std::fs::create_dir_all(&dir).map_err(Into::into).and_then(|_| screenshot(&path));
+        match result {
+            Ok(()) => {
+                let uri = format!("file://{}", path.display());
+                let mut results = Results::new();
+                results.insert("uri".into(), Value::from(uri).into());
+                (SUCCESS, results)
+            },
+            Err(e) => {
+                warn!(self.logger, "Screenshot for {} failed: {e}", app_name(&app_id));
+                (FAILED, Results::new())
+            }
+        }
+    }
+
+    #[dbus_interface(property, name = "version")]
+    fn version (&self) -> u32 {
+        1
+    }
+
+}
+
+/// A remote desktop session that an app asked for.
+#[derive(Default)]
+struct Session {
+    /// Which kinds of devices the app may use
+    devices: u32,
+    /// Whether the session has started, and input is passed on
+    started: bool,
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Session>>>;
+
+/// Implements org.freedesktop.impl.portal.Session for a remote desktop session.
+struct PortalSession {
+    path:     OwnedObjectPath,
+    sessions: Sessions,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Session")]
+impl PortalSession {
+
+    async fn close (&self, #[zbus(object_server)] server: &ObjectServer) {
+        self.sessions.lock().unwrap().remove(self.path.as_str());
+        let _ = server.remove::<Self, _>(&self.path).await;
+    }
+
+    #[dbus_interface(property, name = "version")]
+    fn version (&self) -> u32 {
+        1
+    }
+
+}
+
+/// Implements org.freedesktop.impl.portal.RemoteDesktop by injecting input into the compositor.
+struct RemoteDesktop {
+    logger:   Logger,
+    consent:  Arc<Consent>,
+    sessions: Sessions,
+}
+
+impl RemoteDesktop {
+
+    /// Pass an input event on to the compositor, if the session may send it.
+    /// Events go to the screen of the given stream, or else to the active screen.
+    fn inject (&self, session: &OwnedObjectPath, device: u32, screen: Option<u32>, event: String) {
+        let allowed = self.sessions.lock().unwrap().get(session.as_str())
+            .map(|session| session.started && session.devices & device != 0)
+            .unwrap_or(false);
+        if allowed {
+            let screen = screen.map(|screen| screen.to_string()).unwrap_or_else(|| "active".into());
+            if let Err(e) = command(&format!("input {screen} {event}")) {
+                warn!(self.logger, "Could not inject {event}: {e}");
+            }
+        }
+    }
+
+}
+
+fn state (pressed: u32) -> &'static str {
+    if pressed != 0 { "pressed" } else { "released" }
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.RemoteDesktop")]
+impl RemoteDesktop {
+
+    async fn create_session (
+        &self,
+        #[zbus(object_server)] server: &ObjectServer,
+        _handle:        OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id:        String,
+        _options:       HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        self.sessions.lock().unwrap().insert(session_handle.to_string(), Session::default());
+        let session = PortalSession { path: session_handle.clone(), sessions: self.sessions.clone() };
+        match server.at(&session_handle, session).await {
+            Ok(_) => (SUCCESS, Results::new()),
+            Err(e) => {
+                warn!(self.logger, "Could not export session {}: {e}", session_handle.as_str());
+                self.sessions.lock().unwrap().remove(session_handle.as_str());
+                (FAILED, Results::new())
+            }
+        }
+    }
+
+    fn select_devices (
+        &self,
+        _handle:        OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id:        String,
+        options:        HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        let devices = options.get("types")
+            .and_then(|types| u32::try_from(types.clone()).ok())
+            .unwrap_or(KEYBOARD | POINTER);
+        match self.sessions.lock().unwrap().get_mut(session_handle.as_str()) {
+            Some(session) => {
+                session.devices = devices & (KEYBOARD | POINTER);
+                (SUCCESS, Results::new())
+            },
+            None => (CANCELLED, Results::new())
+        }
+    }
+
+    fn start (
+        &self,
+        _handle:        OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        app_id:         String,
+        _parent_window: String,
+        _options:       HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        // Don't hold the sessions while the user thinks about it
+        let devices = match self.sessions.lock().unwrap().get(session_handle.as_str()) {
+            Some(session) => session.devices,
+            None => return (CANCELLED, Results::new())
+        };
+        if !self.consent.remote_desktop(&app_id, devices) {
+            info!(self.logger, "Not letting {} control the desktop", app_name(&app_id));
+            return (CANCELLED, Results::new())
+        }
+        match self.sessions.lock().unwrap().get_mut(session_handle.as_str()) {
+            Some(session) => {
+                session.started = true;
+                // Only what the user was asked about
+                session.devices = devices;
+                let mut results = Results::new();
+                results.insert("devices".into(), Value::from(session.devices).into());
+                (SUCCESS, results)
+            },
+            None => (CANCELLED, Results::new())
+        }
+    }
+
+    fn notify_pointer_motion (
+        &self, session_handle: OwnedObjectPath, _options: HashMap<String, OwnedValue>, dx: f64, dy: f64
+    ) {
+        self.inject(&session_handle, POINTER, None, format!("motion {dx} {dy} {dx} {dy}"))
+    }
+
+    fn notify_pointer_motion_absolute (
+        &self, session_handle: OwnedObjectPath, _options: HashMap<String, OwnedValue>,
+        stream: u32, x: f64, y: f64
+    ) {
+        self.inject(&session_handle, POINTER, Some(stream), format!("absolute {x} {y}"))
+    }
+
+    fn notify_pointer_button (
+        &self, session_handle: OwnedObjectPath, _options: HashMap<String, OwnedValue>,
+        button: i32, pressed: u32
+    ) {
+        self.inject(&session_handle, POINTER, None, format!("button {button} {}", state(pressed)))
+    }
+
+    fn notify_pointer_axis (
+        &self, session_handle: OwnedObjectPath, _options: HashMap<String, OwnedValue>, dx: f64, dy: f64
+    ) {
+        self.inject(&session_handle, POINTER, None, format!("axis continuous {dx} {dy} - -"))
+    }
+
+    fn notify_pointer_axis_discrete (
+        &self, session_handle: OwnedObjectPath, _options: HashMap<String, OwnedValue>,
+        axis: u32, steps: i32
+    ) {
+        let event = match axis {
+            0 => format!("axis wheel - - - {steps}"),
+            _ => format!("axis wheel - - {steps} -"),
+        };
+        self.inject(&session_handle, POINTER, None, event)
+    }
+
+    fn notify_keyboard_keycode (
+        &self, session_handle: OwnedObjectPath, _options: HashMap<String, OwnedValue>,
+        keycode: i32, pressed: u32
+    ) {
+        self.inject(&session_handle, KEYBOARD, None, format!("key {keycode} {}", state(pressed)))
+    }
+
+    #[dbus_interface(property)]
+    fn available_device_types (&self) -> u32 {
+        KEYBOARD | POINTER
+    }
+
+    #[dbus_interface(property, name = "version")]
+    fn version (&self) -> u32 {
+        1
+    }
+
+}
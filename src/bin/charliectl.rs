@@ -0,0 +1,199 @@
+//! Send a command to a running Charlie instance and print the response.
+//!
+//! Usage: charliectl workspace jump NAME
+//!
+//! With `--wayland`, talks to the compositor over the `charlie_control_v1`
+//! protocol instead of the IPC socket. This also happens when the IPC socket
+//! can't be found. In that mode, `charliectl --wayland state` lists the
+//! outputs, workspaces and windows.
+//!
+//! `charliectl subscribe` keeps running and prints events as they happen,
+//! one per line, e.g. `output added HDMI-A-1`, for scripting around them.
+
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use wayland_client::{
+    Connection, Dispatch, QueueHandle,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::wl_registry::WlRegistry,
+};
+
+use self::protocol::{
+    charlie_control_v1::CharlieControlV1,
+    charlie_control_state_v1::{self, CharlieControlStateV1},
+    charlie_control_result_v1::{self, CharlieControlResultV1},
+};
+
+/// Code generated from `protocols/charlie-control-v1.xml`.
+mod protocol {
+    #![allow(non_upper_case_globals, non_camel_case_types, unused)]
+    use wayland_client;
+    pub mod __interfaces {
+        wayland_scanner::generate_interfaces!("protocols/charlie-control-v1.xml");
+    }
+    use self::__interfaces::*;
+    wayland_scanner::generate_client_code!("protocols/charlie-control-v1.xml");
+}
+
+fn main () -> Result<(), Box<dyn Error>> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let wayland = args.first().map(|arg| arg == "--wayland").unwrap_or(false);
+    if wayland {
+        args.remove(0);
+    }
+    if args.is_empty() {
+        eprintln!("usage: charliectl [--wayland] COMMAND [ARGS...]");
+        std::process::exit(2);
+    }
+    let reply = match socket_path() {
+        Ok(path) if !wayland && path.exists() && args == ["subscribe"] => return subscribe(path),
+        Ok(path) if !wayland && path.exists() => command_socket(path, &args)?,
+        _ => command_wayland(&args)?
+    };
+    print!("{reply}");
+    if reply.starts_with("error:") {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Find the control socket of the compositor we're running under.
+fn socket_path () -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(path) = std::env::var_os("CHARLIE_SOCKET") {
+        return Ok(path.into())
+    }
+    let display = std::env::var("WAYLAND_DISPLAY")
+        .map_err(|_|"neither CHARLIE_SOCKET nor WAYLAND_DISPLAY is set")?;
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+        .ok_or("XDG_RUNTIME_DIR is not set")?;
+    Ok(dir.join(format!("charlie-{display}.sock")))
+}
+
+/// Write the arguments out as a command, quoting the ones that are empty,
+/// have whitespace, or start with a quote, so that they arrive as they are.
+fn line (args: &[String]) -> String {
+    args.iter().map(|arg| {
+        if !arg.is_empty() && !arg.starts_with('"') && !arg.contains(char::is_whitespace) {
+            return arg.clone()
+        }
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    }).collect::<Vec<_>>().join(" ")
+}
+
+/// Send a command over the IPC socket.
+fn command_socket (path: PathBuf, args: &[String]) -> Result<String, Box<dyn Error>> {
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "{}", line(args))?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// Print the events sent over the IPC socket until the compositor exits.
+fn subscribe (path: PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "subscribe")?;
+    let stdout = std::io::stdout();
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.starts_with("error:") {
+            eprintln!("{line}");
+            std::process::exit(1);
+        }
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "{line}")?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Send a command over the control protocol.
+fn command_wayland (args: &[String]) -> Result<String, Box<dyn Error>> {
+    let connection = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<Control>(&connection)?;
+    let handle = queue.handle();
+    let control: CharlieControlV1 = globals.bind(&handle, 1..=1, ())
+        .map_err(|_| "the compositor doesn't offer charlie_control_v1")?;
+    let mut state = Control::default();
+    if args == ["state"] {
+        control.get_state(&handle, ());
+    } else {
+        control.command(line(args), &handle, ());
+    }
+    while state.reply.is_none() {
+        queue.blocking_dispatch(&mut state)?;
+    }
+    control.destroy();
+    connection.flush()?;
+    Ok(state.reply.unwrap_or_default())
+}
+
+/// Collects the response of the compositor.
+#[derive(Default)]
+struct Control {
+    /// Lines received so far
+    lines: Vec<String>,
+    /// Set once the response is complete
+    reply: Option<String>,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Control {
+    fn event (
+        _: &mut Self, _: &WlRegistry, _: <WlRegistry as wayland_client::Proxy>::Event,
+        _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>
+    ) {}
+}
+
+impl Dispatch<CharlieControlV1, ()> for Control {
+    fn event (
+        _: &mut Self, _: &CharlieControlV1, _: <CharlieControlV1 as wayland_client::Proxy>::Event,
+        _: &(), _: &Connection, _: &QueueHandle<Self>
+    ) {}
+}
+
+impl Dispatch<CharlieControlStateV1, ()> for Control {
+    fn event (
+        state: &mut Self, _: &CharlieControlStateV1, event: charlie_control_state_v1::Event,
+        _: &(), _: &Connection, _: &QueueHandle<Self>
+    ) {
+        match event {
+            charlie_control_state_v1::Event::Output { screen, name, width, height, scale } => {
+                state.lines.push(format!("output {screen} {name} {width}x{height} {scale}"))
+            },
+            charlie_control_state_v1::Event::Workspace { index, name, current } => {
+                let current = if current != 0 { " current" } else { "" };
+                state.lines.push(format!("workspace {index} {name}{current}"))
+            },
+            charlie_control_state_v1::Event::Window { id, workspace, app_id, title } => {
+                state.lines.push(format!(
+                    "window {id} {workspace} {} {}",
+                    app_id.unwrap_or_default(), title.unwrap_or_default()
+                ))
+            },
+            charlie_control_state_v1::Event::Done => {
+                let mut reply = state.lines.join("\n");
+                reply.push('\n');
+                state.reply = Some(reply)
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<CharlieControlResultV1, ()> for Control {
+    fn event (
+        state: &mut Self, _: &CharlieControlResultV1, event: charlie_control_result_v1::Event,
+        _: &(), _: &Connection, _: &QueueHandle<Self>
+    ) {
+        state.reply = Some(match event {
+            charlie_control_result_v1::Event::Success { reply } => format!("{reply}\n"),
+            charlie_control_result_v1::Event::Failure { message } => format!("error: {message}\n"),
+            _ => return
+        });
+    }
+}
@@ -1,2 +1,3 @@
+pub mod software;
 pub mod udev;
 pub mod winit;
@@ -0,0 +1,153 @@
+use crate::prelude::*;
+
+use smithay::{
+    output::{PhysicalProperties, Subpixel, Mode},
+    backend::renderer::{Bind, Unbind, pixman::PixmanRenderer},
+    wayland::{
+        dmabuf::DmabufState,
+        output::OutputManagerState,
+        shm::ShmState
+    },
+};
+
+use crate::state::shm::EXTRA_FORMATS;
+
+/// How often outputs are rendered, since there's no vblank to wait for
+const FRAME_INTERVAL: Duration = Duration::from_micros(16_667);
+
+/// Renders on the CPU with pixman, into outputs that only exist in memory.
+///
+/// This is the fallback for when EGL can't be initialized: clients can connect and draw
+/// into shm buffers, and the session can be inspected over IPC, but nothing is shown
+/// and no input arrives. Effects, the night light tint and thumbnails need GLES2,
+/// so they're skipped.
+pub struct SoftwareEngine {
+    logger:       Logger,
+    running:      Arc<AtomicBool>,
+    renderer:     Rc<RefCell<PixmanRenderer>>,
+    shm:          ShmState,
+    /// Never gets a global, since only shm buffers are supported
+    dmabuf_state: DmabufState,
+    out_manager:  OutputManagerState,
+    outputs:      Vec<SoftwareOutput>,
+    /// When the outputs were last rendered
+    last_frame:   Cell<Option<Instant>>,
+}
+
+/// An output that's rendered into an image in memory.
+pub struct SoftwareOutput {
+    pub screen: ScreenId,
+    pub output: Output,
+    /// Holds the last frame rendered for the output
+    pub image:  pixman::Image<'static, 'static>,
+}
+
+/// Pixman can't run shaders, so it keeps the defaults.
+impl EngineRenderer for PixmanRenderer {}
+
+impl Engine for SoftwareEngine {
+
+    type Renderer = PixmanRenderer;
+
+    /// Initialize software engine
+    fn new <T: App<Self>> (logger: &Logger, display: &DisplayHandle) -> CharlieResult<Self> {
+        debug!(logger, "Starting software engine");
+        let renderer = PixmanRenderer::new().map_err(CharlieError::other)?;
+        Ok(Self {
+            logger:       logger.clone(),
+            running:      Arc::new(AtomicBool::new(true)),
+            renderer:     Rc::new(RefCell::new(renderer)),
+            shm:          ShmState::new::<T, _>(&display, EXTRA_FORMATS.to_vec(), logger.clone()),
+            dmabuf_state: DmabufState::new(),
+            out_manager:  OutputManagerState::new_with_xdg_output::<T>(&display),
+            outputs:      vec![],
+            last_frame:   Cell::new(None),
+        })
+    }
+
+    fn logger (&self) -> Logger {
+        self.logger.clone()
+    }
+
+    fn renderer (&self) -> RefMut<PixmanRenderer> {
+        self.renderer.borrow_mut()
+    }
+
+    /// Render each output into its image, at most once per frame interval
+    fn render <R: App<Self> + 'static> (app: &mut R) -> CharlieResult<()> {
+        let engine = app.engine();
+        if let Some(last) = engine.last_frame.get().filter(|last| last.elapsed() < FRAME_INTERVAL) {
+            app.redraw_at(last + FRAME_INTERVAL);
+            return Ok(())
+        }
+        engine.last_frame.set(Some(Instant::now()));
+        for index in 0..engine.outputs.len() {
+            let (screen, output, image) = {
+                let output = &app.engine().outputs[index];
+                (output.screen, output.output.clone(), output.image.clone())
+            };
+            let size = (image.width() as i32, image.height() as i32).into();
+            app.engine().renderer().bind(image).map_err(CharlieError::other)?;
+            app.render(&output, &size, screen)?;
+            app.engine().renderer().unbind().map_err(CharlieError::other)?;
+        }
+        Ok(())
+    }
+
+    /// There are no input devices to read
+    fn update <U: App<Self> + 'static> (_app: &mut U) -> CharlieResult<()> {
+        Ok(())
+    }
+
+    fn dmabuf_state (&mut self) -> &mut DmabufState {
+        &mut self.dmabuf_state
+    }
+
+    fn shm_state (&self) -> &ShmState {
+        &self.shm
+    }
+
+    fn running (&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    fn stop <T: App<Self>> (&mut self, _display: &DisplayHandle) -> CharlieResult<()> {
+        debug!(self.logger, "Stopping software engine");
+        self.running.store(false, Ordering::SeqCst);
+        self.outputs.clear();
+        Ok(())
+    }
+
+}
+
+impl Inputs for SoftwareEngine {
+    fn input_capabilities (&self) -> InputCapabilities {
+        InputCapabilities { keyboard: false, pointer: false, touch: false }
+    }
+}
+
+impl Outputs for SoftwareEngine {
+    fn output_added (
+        &mut self, name: &str, screen: ScreenId, width: i32, height: i32
+    ) -> CharlieResult<()> {
+        debug!(self.logger, "Adding software output {name}: {width}x{height}");
+        let output = Output::new(name.to_string(), PhysicalProperties {
+            size: (width, height).into(), subpixel: Subpixel::Unknown,
+            make: "Charlie".into(), model: "Software".into()
+        }, self.logger.clone());
+        output.change_current_state(
+            Some(Mode { size: (width, height).into(), refresh: 60_000 }), None, None, None
+        );
+        let image = pixman::Image::new(
+            pixman::FormatCode::A8R8G8B8, width as usize, height as usize, true
+        ).map_err(|_| CharlieError::other(format!("could not allocate {width}x{height} image")))?;
+        self.outputs.push(SoftwareOutput { screen, output, image });
+        Ok(())
+    }
+    fn output_get (&self, screen: ScreenId) -> Option<Output> {
+        self.outputs.iter().find(|o| o.screen == screen).map(|o| o.output.clone())
+    }
+    fn output_count (&self) -> usize {
+        self.outputs.len()
+    }
+}
@@ -5,7 +5,7 @@ use smithay::{
     backend::{
         allocator::dmabuf::Dmabuf,
         egl::{
-            Error as EGLError, EGLContext, EGLSurface,
+            Error as EGLError, EGLContext, EGLSurface, EGLDevice,
             native::XlibWindow,
             context::GlAttributes,
             display::EGLDisplay
@@ -25,13 +25,13 @@ use smithay::{
     },
     wayland::{
         buffer::BufferHandler,
-        dmabuf::{DmabufGlobal, DmabufState, DmabufHandler, ImportError},
+        dmabuf::{DmabufGlobal, DmabufState, DmabufHandler, DmabufFeedback, DmabufFeedbackBuilder, ImportError},
         output::{OutputManagerState},
         shm::{ShmHandler, ShmState}
     },
     reexports::{
         winit::{
-            dpi::LogicalSize,
+            dpi::{LogicalSize, PhysicalSize},
             event::{Event, WindowEvent, ElementState, KeyboardInput, Touch, TouchPhase},
             event_loop::{ControlFlow, EventLoop as WinitEventLoop},
             platform::run_return::EventLoopExtRunReturn,
@@ -44,6 +44,12 @@ use smithay::{
 
 use wayland_egl as wegl;
 
+use crate::state::shm::EXTRA_FORMATS;
+use crate::state::mode::windowed_modes;
+
+/// How often the host windows are checked for events while nothing else wakes the main loop
+const HOST_POLL_INTERVAL: Duration = Duration::from_millis(8);
+
 /// Contains the winit and wayland event loops, spawns one or more windows,
 /// and dispatches events to them.
 pub struct WinitEngine {
@@ -56,18 +62,35 @@ pub struct WinitEngine {
     renderer:      Rc<RefCell<Gles2Renderer>>,
     shm:           ShmState,
     dmabuf_state:  DmabufState,
-    dmabuf_global: DmabufGlobal,
+    dmabuf_global: Option<DmabufGlobal>,
+    /// Preferred device and formats, if the render node is known.
+    /// There's no direct scanout when nested, so this is the same for all surfaces.
+    dmabuf_feedback: Option<DmabufFeedback>,
     outputs:       Rc<RefCell<HashMap<WindowId, WinitHostWindow>>>,
     out_manager:   OutputManagerState,
+    /// The session that the host windows are shown in
+    host:          HostSession,
+    /// Kinds of input the host has delivered so far
+    capabilities:  Cell<InputCapabilities>,
+    /// Whether the host clipboard may have changed since it was last read
+    selection_stale: Cell<bool>,
+    /// Text last read from the host clipboard
+    selection:     Option<String>,
 }
 
 impl Engine for WinitEngine {
 
+    type Renderer = Gles2Renderer;
+
     /// Initialize winit engine
-    fn new <T: App<Self>> (logger: &Logger, display: &DisplayHandle) -> Result<Self, Box<dyn Error>> {
+    fn new <T: App<Self>> (logger: &Logger, display: &DisplayHandle) -> CharlieResult<Self> {
 
         debug!(logger, "Starting Winit engine");
 
+        // Remember the host session before WAYLAND_DISPLAY is pointed at our own socket
+        let host = HostSession::from_env();
+        debug!(logger, "Host session: {host:?}");
+
         // Create the Winit event loop
         let winit_events = WinitEventLoop::new();
 
@@ -80,24 +103,33 @@ impl Engine for WinitEngine {
             .map_err(WinitError::InitFailed)?);
 
         // Create the renderer and EGL context
-        let egl_display = EGLDisplay::new(window, logger.clone()).unwrap();
+        let egl_display = EGLDisplay::new(window, logger.clone())?;
         let egl_context = EGLContext::new_with_config(&egl_display, GlAttributes {
             version: (3, 0), profile: None, vsync: true, debug: cfg!(debug_assertions),
         }, Default::default(), logger.clone())?;
         let mut renderer = make_renderer(logger, &egl_context)?;
 
-        // Init dmabuf support
+        // Init dmabuf support, telling clients which device we render with if we can find out
         renderer.bind_wl_display(&display)?;
+        let formats = renderer.dmabuf_formats().cloned().collect::<Vec<_>>();
         let mut dmabuf_state = DmabufState::new();
-        let dmabuf_global = dmabuf_state.create_global::<T, _>(
-            display,
-            renderer.dmabuf_formats().cloned().collect::<Vec<_>>(),
-            logger.clone(),
-        );
+        let dmabuf_feedback = EGLDevice::device_for_display(&egl_display).ok()
+            .and_then(|device| device.try_get_render_node().ok().flatten())
+            .and_then(|node| DmabufFeedbackBuilder::new(node.dev_id(), formats.clone()).build().ok());
+        let dmabuf_global = match &dmabuf_feedback {
+            Some(feedback) => {
+                debug!(logger, "Advertising dmabuf feedback");
+                dmabuf_state.create_global_with_default_feedback::<T, _>(display, feedback, logger.clone())
+            },
+            None => {
+                warn!(logger, "Could not find render node, advertising dmabuf formats without feedback");
+                dmabuf_state.create_global::<T, _>(display, formats, logger.clone())
+            }
+        };
 
         Ok(Self {
             logger:        logger.clone(),
-            shm:           ShmState::new::<T, _>(&display, vec![], logger.clone()),
+            shm:           ShmState::new::<T, _>(&display, EXTRA_FORMATS.to_vec(), logger.clone()),
             out_manager:   OutputManagerState::new_with_xdg_output::<T>(&display),
             running:       Arc::new(AtomicBool::new(true)),
             started:       Cell::new(None),
@@ -105,9 +137,15 @@ impl Engine for WinitEngine {
             egl_display,
             egl_context,
             dmabuf_state,
-            dmabuf_global,
+            dmabuf_global: Some(dmabuf_global),
+            dmabuf_feedback,
             renderer:      Rc::new(RefCell::new(renderer)),
             outputs:       Rc::new(RefCell::new(HashMap::new())),
+            host,
+            // Host windows always get keyboard and pointer events; touch only if the host has it
+            capabilities:  Cell::new(InputCapabilities { keyboard: true, pointer: true, touch: false }),
+            selection_stale: Cell::new(true),
+            selection:     None,
         })
     }
 
@@ -120,7 +158,7 @@ impl Engine for WinitEngine {
     }
 
     /// Render to each host window
-    fn render <R: App<Self> + 'static> (app: &mut R) -> StdResult<()> {
+    fn render <R: App<Self> + 'static> (app: &mut R) -> CharlieResult<()> {
         let outputs = app.engine().outputs.clone();
         for (_, output) in outputs.borrow().iter() {
             if let Some(size) = output.resized.take() {
@@ -135,49 +173,91 @@ impl Engine for WinitEngine {
     }
 
     /// Dispatch input events from the host window to the hosted root widget.
-    fn update <U: App<Self> + 'static> (app: &mut U) -> StdResult<()> {
+    fn update <U: App<Self> + 'static> (app: &mut U) -> CharlieResult<()> {
         let engine = app.engine();
-        let mut closed = false;
         if engine.started.get().is_none() {
-            //let event = InputEvent::DeviceAdded { device: WinitVirtualDevice };
-            //callback(0, WinitEvent::Input(event));
             engine.started.set(Some(Instant::now()));
         }
         let started = &engine.started.get().unwrap();
         let logger = engine.logger.clone();
         let winit_events = engine.winit_events.clone();
+        let mut inputs = vec![];
+        let mut closed = vec![];
+        let mut exposed = false;
         winit_events.borrow_mut().run_return(|event, _target, control_flow| {
-            //debug!(self.logger, "{target:?}");
             match event {
                 Event::RedrawEventsCleared => {
                     *control_flow = ControlFlow::Exit;
                 }
-                Event::RedrawRequested(_id) => {
-                    //callback(0, WinitEvent::Refresh);
-                }
                 Event::WindowEvent { window_id, event } => {
-                    closed = engine.window_update(&window_id, event)
+                    exposed = true;
+                    closed.extend(engine.window_update(&window_id, event, &mut inputs))
+                }
+                Event::RedrawRequested(_) => {
+                    exposed = true;
                 }
                 _ => {}
             }
         });
 
-        if closed {
-            Err(WinitHostError::WindowClosed.into())
-        } else {
-            Ok(())
+        if !closed.is_empty() && engine.outputs.borrow().is_empty() {
+            warn!(logger, "All host windows closed, keeping clients alive");
         }
 
+        // A closed host window is an unplugged output
+        for screen in closed {
+            app.output_lost(screen)?;
+        }
+
+        // The host windows may have been resized, exposed or focused
+        if exposed {
+            app.redraw();
+        }
+
+        // Input is delivered on the screen of the host window it arrived at
+        for (event, screen) in inputs {
+            app.input(event, screen)?;
+        }
+
+        Ok(())
+
     }
 
     fn dmabuf_state (&mut self) -> &mut smithay::wayland::dmabuf::DmabufState {
         &mut self.dmabuf_state
     }
 
+    fn dmabuf_feedback (&self, _screen: ScreenId, _scanout: bool) -> Option<&DmabufFeedback> {
+        self.dmabuf_feedback.as_ref()
+    }
+
     fn shm_state (&self) -> &smithay::wayland::shm::ShmState {
         &self.shm
     }
 
+    fn running (&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Host window events are only read when the engine is updated
+    fn poll_interval (&self) -> Option<Duration> {
+        Some(HOST_POLL_INTERVAL)
+    }
+
+    /// Destroy the dmabuf global and close all host windows
+    fn stop <T: App<Self>> (&mut self, display: &DisplayHandle) -> CharlieResult<()> {
+        debug!(self.logger, "Stopping Winit engine");
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(global) = self.dmabuf_global.take() {
+            self.dmabuf_state.destroy_global::<T>(display, global);
+        }
+        // Drop the EGL surfaces before the windows they belong to go away
+        for (_, window) in self.outputs.borrow_mut().drain() {
+            drop(window.surface);
+        }
+        Ok(())
+    }
+
 }
 
 impl WinitEngine {
@@ -187,12 +267,21 @@ impl WinitEngine {
         self.outputs.borrow_mut().insert(window_id, window);
     }
 
-    pub fn window_update <'a> (&self, window_id: &WindowId, event: WindowEvent<'a>) -> bool {
-        match self.outputs.borrow().get(window_id) {
+    /// Handle an event of a host window, collecting the input it carries
+    /// along with the screen that the window displays.
+    /// Returns the screen of the window if the event closed it.
+    pub fn window_update <'a> (
+        &self,
+        window_id: &WindowId,
+        event:     WindowEvent<'a>,
+        inputs:    &mut Vec<(InputEvent<WinitInput>, ScreenId)>
+    ) -> Option<ScreenId> {
+        let closed = match self.outputs.borrow().get(window_id) {
             Some(window) => {
                 let duration = Instant::now().duration_since(self.started.get().unwrap());
                 let nanos    = duration.subsec_nanos() as u64;
                 let time     = ((1000 * duration.as_secs()) + (nanos / 1_000_000)) as u32;
+                self.probe(&event);
                 let result   = match event {
                     WindowEvent::CloseRequested |
                     WindowEvent::Destroyed      |
@@ -210,16 +299,38 @@ impl WinitEngine {
                         => Self::update_touch(time, window, event),
                     _ => vec![],
                 };
-                if window.closing.get() {
-                    self.window_del(&window_id);
-                    return true;
+                for event in result {
+                    if let WinitEvent::Input(event) = event {
+                        inputs.push((event, window.screen));
+                    }
                 }
+                window.closing.get().then_some(window.screen)
             },
             None => {
-                warn!(self.logger, "Received event for unknown window id {window_id:?}")
+                warn!(self.logger, "Received event for unknown window id {window_id:?}");
+                None
             }
+        };
+        if closed.is_some() {
+            self.window_del(&window_id);
+        }
+        closed
+    }
+
+    /// Learn about the host session from the events it sends.
+    fn probe <'a> (&self, event: &WindowEvent<'a>) {
+        match event {
+            // The host has a touchscreen after all
+            WindowEvent::Touch(_) if !self.capabilities.get().touch => {
+                info!(self.logger, "Host delivered touch input, enabling touch");
+                self.capabilities.set(InputCapabilities { touch: true, ..self.capabilities.get() });
+            },
+            // The user may have copied something in another host window
+            WindowEvent::Focused(true) => {
+                self.selection_stale.set(true);
+            },
+            _ => {}
         }
-        false
     }
 
     pub fn window_del (&self, window_id: &WindowId) -> () {
@@ -338,15 +449,92 @@ impl WinitEngine {
 }
 
 impl Inputs for WinitEngine {
-    fn input_added (&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+    fn input_added (&mut self, name: &str) -> CharlieResult<()> {
         Ok(())
     }
+    fn input_capabilities (&self) -> InputCapabilities {
+        self.capabilities.get()
+    }
+    fn host_selection (&mut self) -> Option<String> {
+        if !self.selection_stale.replace(false) {
+            return None
+        }
+        match self.host.read_selection() {
+            Ok(text) if self.selection.as_ref() != Some(&text) => {
+                self.selection = Some(text.clone());
+                Some(text)
+            },
+            Ok(_) => None,
+            Err(e) => {
+                debug!(self.logger, "Could not read host clipboard: {e}");
+                None
+            }
+        }
+    }
+    fn host_cursor_theme (&self) -> Option<(String, u32)> {
+        self.host.cursor_theme()
+    }
+}
+
+/// How to reach the session that hosts the compositor's windows.
+#[derive(Debug, Clone, Default)]
+pub struct HostSession {
+    /// The host's Wayland socket, if it's a Wayland session
+    pub wayland_display: Option<String>,
+    /// The host's X11 display
+    pub display:         Option<String>,
+}
+
+impl HostSession {
+
+    pub fn from_env () -> Self {
+        Self {
+            wayland_display: std::env::var("WAYLAND_DISPLAY").ok(),
+            display:         std::env::var("DISPLAY").ok(),
+        }
+    }
+
+    /// Read the text on the host clipboard with `wl-paste` or `xclip`.
+    pub fn read_selection (&self) -> StdResult<String> {
+        let output = if let Some(wayland_display) = &self.wayland_display {
+            std::process::Command::new("wl-paste")
+                .args(["--no-newline", "--type", "text"])
+                .env("WAYLAND_DISPLAY", wayland_display)
+                .output()?
+        } else if let Some(display) = &self.display {
+            std::process::Command::new("xclip")
+                .args(["-out", "-selection", "clipboard"])
+                .env("DISPLAY", display)
+                .output()?
+        } else {
+            return Err("no host session".into())
+        };
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into())
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    /// The host's cursor theme, from the environment or from GNOME's settings.
+    pub fn cursor_theme (&self) -> Option<(String, u32)> {
+        let gsettings = |key: &str| std::process::Command::new("gsettings")
+            .args(["get", "org.gnome.desktop.interface", key])
+            .output().ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().trim_matches('\'').to_string());
+        let theme = std::env::var("XCURSOR_THEME").ok().or_else(|| gsettings("cursor-theme"))?;
+        let size  = std::env::var("XCURSOR_SIZE").ok().or_else(|| gsettings("cursor-size"))
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(24);
+        Some((theme, size))
+    }
+
 }
 
 impl Outputs for WinitEngine {
     fn output_added (
         &mut self, name: &str, screen: ScreenId, width: i32, height: i32
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> CharlieResult<()> {
         let window = WinitHostWindow::new(
             &self.logger,
             &self.winit_events.borrow(),
@@ -360,6 +548,46 @@ impl Outputs for WinitEngine {
         self.outputs.borrow_mut().insert(window_id, window);
         Ok(())
     }
+    /// Hide or show the host window of a screen
+    fn output_enable (&mut self, screen: ScreenId, enabled: bool) -> CharlieResult<()> {
+        match self.outputs.borrow().values().find(|w| w.screen == screen) {
+            Some(window) => {
+                window.window.set_visible(enabled);
+                Ok(())
+            },
+            None => Err(CharlieError::Config(format!("no output for screen {screen}")))
+        }
+    }
+    /// Resize the host window of a screen to the size of the mode
+    fn output_mode (&mut self, screen: ScreenId, mode: Mode) -> CharlieResult<()> {
+        match self.outputs.borrow().values().find(|w| w.screen == screen) {
+            Some(window) => {
+                window.window.set_inner_size(PhysicalSize::new(mode.size.w as u32, mode.size.h as u32));
+                window.output.change_current_state(Some(mode), None, None, None);
+                Ok(())
+            },
+            None => Err(CharlieError::Config(format!("no output for screen {screen}")))
+        }
+    }
+    /// Switch to the offered mode with the size closest to the given one
+    fn output_set_mode (&mut self, screen: ScreenId, size: Size<i32, Physical>)
+        -> CharlieResult<Size<i32, Physical>>
+    {
+        let output = self.output_get(screen)
+            .ok_or_else(|| CharlieError::Config(format!("no output for screen {screen}")))?;
+        let previous = output.current_mode().map(|mode| mode.size).unwrap_or(size);
+        let distance = |mode: &Mode| (mode.size.w - size.w).abs() + (mode.size.h - size.h).abs();
+        let mode = output.modes().into_iter().min_by_key(distance)
+            .ok_or(CharlieError::Unsupported("mode switching without modes"))?;
+        self.output_mode(screen, mode)?;
+        Ok(previous)
+    }
+    fn output_get (&self, screen: ScreenId) -> Option<Output> {
+        self.outputs.borrow().values().find(|w| w.screen == screen).map(|w| w.output.clone())
+    }
+    fn output_count (&self) -> usize {
+        self.outputs.borrow().len()
+    }
 }
 
 #[derive(Debug)]
@@ -375,12 +603,12 @@ impl std::fmt::Display for WinitHostError {
 
 impl std::error::Error for WinitHostError {}
 
-fn make_renderer (logger: &Logger, egl: &EGLContext) -> Result<Gles2Renderer, Box<dyn Error>> {
+fn make_renderer (logger: &Logger, egl: &EGLContext) -> CharlieResult<Gles2Renderer> {
     let egl = make_context(logger, egl)?;
     Ok(unsafe { Gles2Renderer::new(egl, logger.clone()) }?)
 }
 
-fn make_context (logger: &Logger, egl: &EGLContext) -> Result<EGLContext, Box<dyn Error>> {
+fn make_context (logger: &Logger, egl: &EGLContext) -> CharlieResult<EGLContext> {
     Ok(EGLContext::new_shared_with_config(egl.display(), egl, GlAttributes {
         version: (3, 0), profile: None, vsync: true, debug: cfg!(debug_assertions),
     }, Default::default(), logger.clone())?)
@@ -413,7 +641,7 @@ pub struct WinitHostWindow {
 }
 
 /// Build a host window
-impl<'a> WinitHostWindow {
+impl WinitHostWindow {
 
     /// Create a new host window
     pub fn new (
@@ -424,7 +652,7 @@ impl<'a> WinitHostWindow {
         width:  i32,
         height: i32,
         screen: ScreenId
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> CharlieResult<Self> {
 
         // Determine the window dimensions
         let (w, h, hz, subpixel) = (width, height, 60_000, Subpixel::Unknown);
@@ -434,14 +662,19 @@ impl<'a> WinitHostWindow {
             size: (w, h).into(), subpixel, make: "Smithay".into(), model: "Winit".into()
         }, logger.clone());
 
-        // Set the output's mode
-        output.change_current_state(
-            Some(Mode { size: (w, h).into(), refresh: hz }), None, None, None
-        );
-
         // Build the host window
         let window = Self::build(logger, events, title, width, height)?;
 
+        // Offer the window's size and the common sizes that fit on the monitor, starting with the former
+        let monitor = window.current_monitor()
+            .map(|monitor| (monitor.size().width as i32, monitor.size().height as i32).into());
+        let preferred = Mode { size: (w, h).into(), refresh: hz };
+        for mode in windowed_modes(preferred.size, monitor) {
+            output.add_mode(mode);
+        }
+        output.set_preferred(preferred);
+        output.change_current_state(Some(preferred), None, None, None);
+
         // Store the window's inner size
         let (w, h): (u32, u32) = window.inner_size().into();
         let size = WindowSize {
@@ -479,7 +712,7 @@ impl<'a> WinitHostWindow {
         title:  &str,
         width:  i32,
         height: i32
-    ) -> Result<WinitWindow, Box<dyn Error>> {
+    ) -> CharlieResult<WinitWindow> {
 
         debug!(logger, "Building Winit window: {title} ({width}x{height})");
 
@@ -499,7 +732,7 @@ impl<'a> WinitHostWindow {
         logger: &Logger,
         egl:    &EGLContext,
         window: &WinitWindow
-    ) -> Result<Rc<EGLSurface>, Box<dyn Error>> {
+    ) -> CharlieResult<Rc<EGLSurface>> {
         debug!(logger, "Setting up Winit window: {window:?}");
         debug!(logger, "Created EGL context for Winit window");
         let surface = if let Some(surface) = window.wayland_surface() {
@@ -509,7 +742,7 @@ impl<'a> WinitHostWindow {
         } else {
             unreachable!("No backends for winit other then Wayland and X11 are supported")
         };
-        let _ = egl.unbind()?;
+        let _ = egl.unbind().map_err(CharlieError::other)?;
         Ok(Rc::new(surface))
     }
 
@@ -519,7 +752,7 @@ impl<'a> WinitHostWindow {
         egl:             &EGLContext,
         (width, height): (i32, i32),
         surface:         *mut std::os::raw::c_void
-    ) -> Result<EGLSurface, Box<dyn Error>> {
+    ) -> CharlieResult<EGLSurface> {
         debug!(logger, "Using Wayland backend for Winit window");
         Ok(EGLSurface::new(
             egl.display(),
@@ -537,7 +770,7 @@ impl<'a> WinitHostWindow {
         logger: &Logger,
         egl:    &EGLContext,
         window: XlibWindow
-    ) -> Result<EGLSurface, Box<dyn Error>> {
+    ) -> CharlieResult<EGLSurface> {
         debug!(logger, "Using X11 backend for Winit window {window:?}");
         Ok(EGLSurface::new(
             egl.display(),
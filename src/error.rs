@@ -0,0 +1,91 @@
+use std::{error::Error, backtrace::Backtrace};
+
+use thiserror::Error;
+
+use smithay::backend::{
+    SwapBuffersError,
+    egl::Error as EGLError,
+    drm::DrmError,
+    renderer::gles2::Gles2Error,
+    winit::Error as WinitError,
+};
+
+pub type CharlieResult<T> = Result<T, CharlieError>;
+
+/// Everything that can go wrong in the compositor.
+#[derive(Debug, Error)]
+pub enum CharlieError {
+    #[error("EGL error: {0}")]
+    EGL(#[from] EGLError),
+    #[error("Winit error: {0}")]
+    Winit(#[from] WinitError),
+    #[error("DRM error: {0}")]
+    Drm(#[from] DrmError),
+    #[error("Renderer error: {0}")]
+    Renderer(#[from] Gles2Error),
+    #[error("Could not swap buffers: {0}")]
+    SwapBuffers(#[from] SwapBuffersError),
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("Not supported by this engine: {0}")]
+    Unsupported(&'static str),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// An error with a description of what was being done when it happened
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        source:  Box<CharlieError>,
+    },
+    /// Any other error, together with where it was converted
+    #[error("{source}")]
+    Other {
+        source:    Box<dyn Error>,
+        backtrace: Backtrace,
+    },
+}
+
+impl CharlieError {
+
+    /// Wrap any other error.
+    pub fn other (error: impl Into<Box<dyn Error>>) -> Self {
+        Self::Other { source: error.into(), backtrace: Backtrace::capture() }
+    }
+
+    /// Wrap an error reported by the Wayland or event loop machinery.
+    pub fn protocol (error: impl std::fmt::Display) -> Self {
+        Self::Protocol(error.to_string())
+    }
+
+    /// The backtrace of where this error was created, if captured.
+    pub fn backtrace (&self) -> Option<&Backtrace> {
+        match self {
+            Self::Other { backtrace, .. } => Some(backtrace),
+            Self::Context { source, .. } => source.backtrace(),
+            _ => None
+        }
+    }
+
+}
+
+impl From<Box<dyn Error>> for CharlieError {
+    fn from (error: Box<dyn Error>) -> Self {
+        Self::other(error)
+    }
+}
+
+/// Attach a description of what was being done to an error.
+pub trait Context<T> {
+    fn context (self, context: impl Into<String>) -> CharlieResult<T>;
+}
+
+impl<T, E: Into<CharlieError>> Context<T> for Result<T, E> {
+    fn context (self, context: impl Into<String>) -> CharlieResult<T> {
+        self.map_err(|e| CharlieError::Context {
+            context: context.into(),
+            source:  Box::new(e.into())
+        })
+    }
+}
@@ -3,20 +3,48 @@
 #[macro_use] extern crate wayland_delegate;
 
 mod prelude;
+mod error;
 mod traits;
 mod engines;
 mod state;
 
 use crate::prelude::*;
 use crate::engines::winit::WinitEngine;
+use crate::engines::software::SoftwareEngine;
 
-fn main () -> StdResult<()> {
+fn main () -> CharlieResult<()> {
     let (logger, _guard) = init_log();
-    Charlie::<WinitEngine>::new(logger)?
+    let reason = match Charlie::<WinitEngine>::new(logger.clone()) {
+        Ok(charlie) => session(charlie)?,
+        // Without working GL, still bring up a session for debugging
+        Err(e @ (CharlieError::EGL(_) | CharlieError::Renderer(_))) => {
+            warn!(logger, "Could not initialize GL ({e}), falling back to software rendering");
+            session(Charlie::<SoftwareEngine>::new(logger)?)?
+        },
+        Err(e) => return Err(e)
+    };
+    match reason {
+        ExitReason::Error(e) => Err(e),
+        _ => Ok(())
+    }
+}
+
+/// Set up the session and run it until it ends.
+fn session <E: Engine> (charlie: Charlie<E>) -> CharlieResult<ExitReason> {
+    charlie
         .startup("glxgears", &[])?
         .startup("wezterm", &[])?
         .output("Alice",  720, 540, 0.0, 0.0)?
         .output("Bob",    480, 720, 0.0, 0.0)?
         .input("Charlie", "data/cursor.png")?
+        .protocol_log(protocol_log_arg().as_deref())?
         .run()
 }
+
+/// The filter given with `--protocol-log[=FILTER]`, which records all requests if it's left out.
+fn protocol_log_arg () -> Option<String> {
+    std::env::args().find_map(|arg| match arg.as_str() {
+        "--protocol-log" => Some("*".into()),
+        _ => arg.strip_prefix("--protocol-log=").map(Into::into)
+    })
+}
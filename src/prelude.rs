@@ -1,6 +1,7 @@
 pub(crate) use crate::{
+    error::{CharlieError, CharlieResult, Context},
     traits::*,
-    state::Charlie,
+    state::{Charlie, ExitReason},
     state::desktop::ScreenState
 };
 
@@ -8,7 +9,7 @@ pub(crate) use std::{
     error::Error,
     rc::Rc,
     cell::{Cell, RefCell, RefMut},
-    sync::{Arc, Mutex, atomic::AtomicBool},
+    sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}},
     time::{Instant, Duration},
     path::Path,
     collections::{HashMap, hash_map::Entry},
@@ -67,34 +68,12 @@ pub(crate) fn init_log () -> (Logger, slog_scope::GlobalLoggerGuard) {
 
 }
 
-pub fn import_bitmap (renderer: &mut Gles2Renderer, path: impl AsRef<Path>)
-    -> Result<Gles2Texture, Box<dyn Error>>
+pub fn import_bitmap <R: EngineRenderer> (renderer: &mut R, path: impl AsRef<Path>)
+    -> Result<R::TextureId, Box<dyn Error>>
 {
     let image = image::io::Reader::open(path)?.with_guessed_format()?.decode()?.to_rgba8();
     let size = (image.width() as i32, image.height() as i32);
-    let mut tex = 0;
-    renderer.with_context(|gl| unsafe {
-        use smithay::backend::renderer::gles2::ffi;
-        gl.GenTextures(1, &mut tex);
-        gl.BindTexture(ffi::TEXTURE_2D, tex);
-        gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_WRAP_S, ffi::CLAMP_TO_EDGE as i32);
-        gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_WRAP_T, ffi::CLAMP_TO_EDGE as i32);
-        gl.TexImage2D(
-            ffi::TEXTURE_2D,
-            0,
-            ffi::RGBA as i32,
-            image.width() as i32,
-            image.height() as i32,
-            0,
-            ffi::RGBA,
-            ffi::UNSIGNED_BYTE as u32,
-            image.as_ptr() as *const _,
-        );
-        gl.BindTexture(ffi::TEXTURE_2D, 0);
-    })?;
-    Ok(unsafe {
-        Gles2Texture::from_raw(renderer, tex, size.into())
-    })
+    Ok(renderer.import_memory(image.as_raw(), size.into(), false)?)
 }
 
 pub type ScreenId = usize;
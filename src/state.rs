@@ -1,15 +1,142 @@
 mod prelude;
+pub mod acceleration;
+pub mod action;
+pub mod background;
+pub mod capture;
+pub mod clipboard;
+pub mod color;
+pub mod command;
+pub mod companion;
+pub mod content;
+pub mod control;
+pub mod crash;
+pub mod cursor;
+pub mod dbus;
+pub mod decoration;
 pub mod desktop;
+pub mod dialog;
+pub mod drag;
+pub mod dmabuf;
+pub mod effects;
+pub mod foreign;
+pub mod fullscreen;
+pub mod game;
+pub mod gamma;
+pub mod geometry;
+pub mod hints;
+pub mod host;
+pub mod hotcorner;
+pub mod icon;
+pub mod identity;
+pub mod inhibitor;
+pub mod injection;
 mod input;
+pub mod ipc;
+pub mod keymap;
+pub mod kiosk;
+pub mod latency;
+pub mod layout;
+pub mod manage;
+pub mod memory;
+pub mod mode;
+pub mod monitor;
+pub mod nightlight;
+pub mod notify;
+pub mod occlusion;
+pub mod osd;
+pub mod pip;
+pub mod placeholder;
+pub mod placement;
+pub mod process;
+pub mod profile;
+pub mod protocol;
+pub mod record;
+pub mod redraw;
+pub mod resize;
+pub mod rules;
+pub mod scaling;
+pub mod script;
+pub mod security;
+pub mod session;
+pub mod shader;
+pub mod shape;
+pub mod shm;
+pub mod snapshot;
+pub mod stacking;
+pub mod stall;
+pub mod surface;
+pub mod swallow;
+pub mod switch;
+pub mod sync;
+pub mod tearing;
+pub mod text;
+pub mod thumbnail;
+pub mod transaction;
+pub mod transform;
+pub mod warp;
+pub mod watchdog;
+pub mod workspace;
+pub mod xselection;
 pub mod xwayland;
 
 use self::prelude::*;
 use self::desktop::Desktop;
 use self::input::Input;
+use self::process::{Processes, Startup, Restart};
+use self::ipc::{Ipc, IPC_SOCKET_ENV};
+use self::rules::WindowRule;
+use self::placeholder::{Placeholder, PLACEHOLDER_TICK};
+use self::notify::Notifications;
+use self::hints::Hints;
+use self::osd::Osd;
+use self::background::Background;
+use self::script::Scripts;
+use self::thumbnail::Thumbnails;
+use self::capture::Captures;
+use self::hotcorner::HotCorners;
+use self::geometry::GeometryStore;
+use self::command::Commands;
+use self::stall::Stalls;
+use self::crash::Crashes;
+use self::latency::Latency;
+use self::session::SessionStore;
+use self::gamma::GammaControlState;
+use self::inhibitor::InputInhibitorState;
+use self::kiosk::Kiosk;
+use self::injection::VirtualInput;
+use self::foreign::ForeignState;
+use self::game::GameMode;
+use self::tearing::Tearing;
+use self::shape::CursorShapes;
+use self::redraw::Redraw;
+use self::content::{ContentType, ContentTypeState};
+use self::dialog::Dialogs;
+use self::nightlight::NightLight;
+use self::effects::Effects;
+use self::sync::ExplicitSync;
+use self::control::ControlState;
+use self::dbus::DbusService;
+use self::resize::Resizing;
+use self::drag::Moving;
+use self::layout::OutputLayout;
+use self::switch::Switches;
+use self::watchdog::Watchdog;
+use self::transaction::Transactions;
+use self::icon::Icons;
+use self::color::ColorDepths;
+use self::memory::Memory;
+use self::mode::Modes;
+use self::monitor::Monitors;
+use self::profile::Profiler;
+use self::protocol::ProtocolLog;
+use self::clipboard::Clipboard;
+use self::security::{Security, Protocol};
+use self::identity::{ClientState, Identities};
+use self::xwayland::Xwm;
 
 use smithay::{
     wayland::socket::ListeningSocketSource,
-    reexports::wayland_server::backend::{ClientId, ClientData, DisconnectReason},
+    output::Scale,
     reexports::calloop::{PostAction, Interest, Mode, generic::Generic}
 };
 
@@ -18,59 +145,288 @@ pub struct Charlie<E: Engine> {
     pub logger:  Logger,
     pub display: Rc<RefCell<Display<Self>>>,
     pub events:  Rc<RefCell<EventLoop<'static, Self>>>,
-    /// Commands to run after successful initialization
-    pub startup: Vec<(String, Vec<String>)>,
+    /// Handle for adding event sources, usable while the event loop is dispatching
+    pub handle:  LoopHandle<'static, Self>,
+    /// Supervisor for startup commands and other child processes
+    pub processes: Processes,
+    /// Control socket for `charliectl`
+    pub ipc:     Option<Ipc>,
     /// The collection of windows and their layouts
-    pub desktop: Desktop,
+    pub desktop: Desktop<E::Renderer>,
     /// The collection of input devices
     pub input:   Input<E>,
     /// Engine-specific state
     pub engine:  E,
+    /// Set to stop the main loop
+    pub exit:    Option<ExitReason>,
+    /// Stands in for the outputs when all of them are gone
+    pub placeholder: Placeholder,
+    /// Notifications received over D-Bus
+    pub notifications: Notifications<E::Renderer>,
+    /// Labels over windows, and the letters that pick them
+    pub hints: Hints<E::Renderer>,
+    /// Volume and brightness shown after media keys are pressed
+    pub osd:   Osd<E::Renderer>,
+    /// Screenshots waiting for the next frame
+    pub captures: Captures,
+    /// Color and wallpapers behind the windows
+    pub background: Background<E::Renderer>,
+    /// User scripts that respond to events
+    pub scripts: Scripts,
+    /// Offscreen renders of windows
+    pub thumbnails: Thumbnails,
+    /// Actions triggered by screen edges and corners
+    pub hot_corners: HotCorners,
+    /// Where windows were last seen, by app id and title
+    pub geometry: GeometryStore,
+    /// The layout of the windows, put back on the next start
+    pub session: SessionStore,
+    /// Gamma ramps set by clients
+    pub gamma:   GammaControlState,
+    /// The client that has taken all input, such as a screen locker
+    pub input_inhibitor: InputInhibitorState,
+    /// Whether the session runs one application only
+    pub kiosk:   Kiosk,
+    /// Pointers and keyboards that trusted clients send input through
+    pub virtual_input: VirtualInput,
+    /// Windows exported by clients for others to parent their dialogs to
+    pub foreign: ForeignState,
+    /// Lower latency for the focused fullscreen window
+    pub game:    GameMode,
+    /// Which surfaces may be shown with tearing
+    pub tearing: Tearing,
+    /// Cursors that clients asked for by name
+    pub cursor_shapes: CursorShapes,
+    /// What clients say their surfaces show
+    pub content_type: ContentTypeState,
+    /// Which dialogs their clients say are modal
+    pub dialogs: Dialogs,
+    /// Whether the outputs need rendering, and when to wake up
+    pub redraw:  Redraw,
+    /// Warmer colors at night
+    pub night_light: NightLight,
+    /// Effects applied to whole outputs
+    pub effects: Effects,
+    /// Fences that client buffers are synchronized with
+    pub sync:    ExplicitSync,
+    /// Wayland counterpart of the IPC socket, for trusted clients
+    pub control: ControlState,
+    /// Compositor state served over D-Bus to desktop components
+    pub dbus:    DbusService,
+    /// Interactive resizes of windows
+    pub resizing: Resizing,
+    /// Interactive moves of windows
+    pub moving:  Moving,
+    /// Where the outputs are relative to each other
+    pub layout:  OutputLayout,
+    /// Responses to the lid, tablet mode and power switches
+    pub switches: Switches,
+    /// Pings clients to notice when they hang
+    pub watchdog: Watchdog,
+    /// What protocol handlers asked to be done once they've returned
+    pub commands: Commands,
+    /// Notices when the main loop runs over budget or stops turning
+    pub stalls:  Stalls,
+    /// Reports on clients that went away unexpectedly
+    pub crashes: Crashes,
+    /// Measures how long inputs take to reach clients and the screen
+    pub latency: Latency,
+    /// Changes to several windows that are waiting to be shown together
+    pub transactions: Transactions,
+    /// Icons of apps, for telling windows apart in compositor UI
+    pub icons:   Icons<E::Renderer>,
+    /// How many bits per channel outputs are rendered with
+    pub depths:  ColorDepths,
+    /// Which modes outputs are put in
+    pub modes:   Modes,
+    /// Sets of output configurations, applied when their outputs are connected
+    pub monitors: Monitors,
+    /// How long the phases of the main loop take, when asked
+    pub profiler: Profiler,
+    /// Requests of clients, when asked to record them
+    pub protocols: ProtocolLog,
+    /// How much memory clients' buffers take, and how much they may
+    pub memory:  Memory,
+    /// Limits on what clients copy and paste between each other
+    pub clipboard: Clipboard,
+    /// Which clients are offered the privileged protocols
+    pub security: Security,
+    /// Who the connected clients are
+    pub clients: Identities,
+    /// The window manager of XWayland, once it's running
+    pub xwm:     Option<Xwm>,
+}
+
+/// Why the main loop stopped.
+#[derive(Debug)]
+pub enum ExitReason {
+    /// The user asked to quit
+    Quit,
+    /// The engine can't go on, e.g. because its host window was closed
+    Stopped,
+    /// An unrecoverable error occurred
+    Error(CharlieError),
 }
 
 impl<E: Engine> Charlie<E> {
 
-    pub fn new (logger: Logger) -> StdResult<Self> {
+    pub fn new (logger: Logger) -> CharlieResult<Self> {
 
         // Create the event loop
         let events = EventLoop::try_new()?;
 
         // Create the display
-        let display = Display::new()?;
+        let display = Display::new().map_err(CharlieError::protocol)?;
 
         // Create the engine
         let engine = E::new::<Self>(&logger, &display.handle())?;
 
+        // When nested, draw the same cursors as the host session, and have clients do so too
+        if let Some((theme, size)) = engine.host_cursor_theme() {
+            if std::env::var_os("XCURSOR_THEME").is_none() {
+                std::env::set_var("XCURSOR_THEME", theme);
+            }
+            if std::env::var_os("XCURSOR_SIZE").is_none() {
+                std::env::set_var("XCURSOR_SIZE", size.to_string());
+            }
+        }
+
         // Init xwayland
         crate::state::xwayland::init_xwayland(
             &logger,
             &events.handle(),
             &display.handle(),
-            Box::new(|x|Ok(()))//x.1.ready())
+            Box::new(|app: &mut Self| app.ready())
         )?;
 
         let desktop = Desktop::new::<E>(&logger, &display.handle())?;
 
         let input = Input::new(&logger, &display.handle())?;
 
+        let security = Security::new(&logger);
+
+        let gamma = GammaControlState::new::<E>(
+            &logger, &display.handle(), security.gate(Protocol::GammaControl)
+        );
+
+        let input_inhibitor = InputInhibitorState::new::<E>(
+            &logger, &display.handle(), security.gate(Protocol::InputInhibitor)
+        );
+
+        let virtual_input = VirtualInput::new::<E>(
+            &logger, &display.handle(),
+            security.gate(Protocol::VirtualPointer), security.gate(Protocol::VirtualKeyboard)
+        );
+
+        let foreign = ForeignState::new::<E>(&logger, &display.handle());
+
+        let tearing = Tearing::new::<E>(&logger, &display.handle());
+
+        let cursor_shapes = CursorShapes::new::<E>(&logger, &display.handle());
+
+        let content_type = ContentTypeState::new::<E>(&logger, &display.handle());
+
+        let dialogs = Dialogs::new::<E>(&logger, &display.handle());
+
+        let sync = ExplicitSync::new::<E>(&logger, &display.handle());
+
+        let control = ControlState::new::<E>(
+            &logger, &display.handle(), security.gate(Protocol::Control)
+        );
+
+        // Load user scripts from the config directory
+        let mut scripts = Scripts::new(&logger);
+        if let Some(dir) = Scripts::config_dir().filter(|dir| dir.is_dir()) {
+            if let Err(e) = scripts.load_dir(&dir) {
+                warn!(logger, "Could not load scripts from {}: {e}", dir.display());
+            }
+        }
+
+        // Remember window geometry across restarts
+        let mut geometry = GeometryStore::new(&logger);
+        if let Some(path) = GeometryStore::default_path() {
+            if let Err(e) = geometry.load(&path) {
+                warn!(logger, "Could not load window geometry from {}: {e}", path.display());
+            }
+        }
+
+        // Put the windows back where they were in the last session
+        let mut session = SessionStore::new(&logger);
+        if let Some(path) = SessionStore::default_path() {
+            if let Err(e) = session.load(&path) {
+                warn!(logger, "Could not load session from {}: {e}", path.display());
+            }
+        }
+
         Ok(Self {
             logger:  logger.clone(),
+            handle:  events.handle(),
             events:  Rc::new(RefCell::new(events)),
             display: Rc::new(RefCell::new(display)),
             engine,
-            startup: vec![],
+            processes: Processes::new(&logger),
+            ipc:     None,
+            exit:    None,
+            placeholder: Placeholder::new(&logger),
+            notifications: Notifications::new(&logger),
+            hints:   Hints::new(&logger),
+            osd:     Osd::new(&logger),
+            captures: Captures::new(&logger),
+            background: Background::new(&logger),
+            scripts,
+            thumbnails: Thumbnails::new(&logger),
+            hot_corners: HotCorners::new(&logger),
+            geometry,
+            session,
+            gamma,
+            input_inhibitor,
+            kiosk:   Kiosk::new(),
+            virtual_input,
+            foreign,
+            game:    GameMode::new(&logger),
+            tearing,
+            cursor_shapes,
+            content_type,
+            dialogs,
+            redraw:  Redraw::new(&logger),
+            night_light: NightLight::new(&logger),
+            effects: Effects::new(&logger),
+            sync,
+            control,
+            dbus:    DbusService::new(&logger),
+            resizing: Resizing::new(&logger),
+            moving:  Moving::new(&logger),
+            layout:  OutputLayout::new(&logger),
+            switches: Switches::new(&logger),
+            watchdog: Watchdog::new(&logger),
+            stalls:  Stalls::new(&logger),
+            crashes: Crashes::new(&logger),
+            latency: Latency::new(&logger),
+            commands: Commands::new(&logger),
+            transactions: Transactions::new(&logger),
+            icons:   Icons::new(&logger),
+            depths:  ColorDepths::new(&logger),
+            modes:   Modes::new(&logger),
+            monitors: Monitors::new(&logger),
+            profiler: Profiler::new(&logger),
+            protocols: ProtocolLog::new(&logger),
+            memory:  Memory::new(&logger),
+            clipboard: Clipboard::new(&logger),
+            security,
+            clients: Identities::default(),
+            xwm:     None,
             desktop,
             input,
         })
     }
 
     /// Perform a procedure with this app instance as part of a method call chain.
-    pub fn with (self, cb: impl Fn(Self)->StdResult<Self>) -> StdResult<Self> {
+    pub fn with (self, cb: impl Fn(Self)->CharlieResult<Self>) -> CharlieResult<Self> {
         cb(self)
     }
 
     /// Run an instance of an application.
-    pub fn run (mut self) -> StdResult<()> {
+    pub fn run (mut self) -> CharlieResult<ExitReason> {
 
         // Listen for events
         let display = self.display.clone();
@@ -78,10 +434,14 @@ impl<E: Engine> Charlie<E> {
         self.events.borrow().handle().insert_source(
             Generic::new(fd, Interest::READ, Mode::Level),
             move |_, _, state| {
+                let span = state.profiler.begin();
+                state.stalls.enter("clients");
                 display.borrow_mut().dispatch_clients(state)?;
+                state.stalls.idle();
+                state.profiler.end(span, "clients", None);
                 Ok(PostAction::Continue)
             }
-        )?;
+        ).map_err(CharlieError::other)?;
 
         // Create a socket
         let socket = ListeningSocketSource::new_auto(self.logger.clone()).unwrap();
@@ -90,65 +450,401 @@ impl<E: Engine> Charlie<E> {
         // Listen for new clients
         let socket_logger  = self.logger.clone();
         let mut socket_display = self.display.borrow().handle();
+        let identities = self.clients.clone();
+        let disconnects = self.crashes.disconnects.clone();
         self.events.borrow().handle().insert_source(socket, move |client, _, _| {
             debug!(socket_logger, "New client {client:?}");
+            let state = ClientState::new(&client, false, &identities, &disconnects);
             socket_display.insert_client(
                 client.try_clone().expect("Could not clone socket for engine dispatcher"),
-                Arc::new(ClientState)
+                Arc::new(state)
             ).expect("Could not insert client in engine display");
-        })?;
+        }).map_err(CharlieError::other)?;
+
+        // Listen for clients that get the privileged protocols by connecting
+        if let Some(name) = self.security.socket.clone() {
+            let socket = ListeningSocketSource::with_name(&name, self.logger.clone())
+                .map_err(CharlieError::other)?;
+            let socket_logger  = self.logger.clone();
+            let mut socket_display = self.display.borrow().handle();
+            let identities = self.clients.clone();
+            let disconnects = self.crashes.disconnects.clone();
+            self.events.borrow().handle().insert_source(socket, move |client, _, _| {
+                debug!(socket_logger, "New privileged client {client:?}");
+                let state = ClientState::new(&client, true, &identities, &disconnects);
+                socket_display.insert_client(client, Arc::new(state))
+                    .expect("Could not insert client in engine display");
+            }).map_err(CharlieError::other)?;
+            info!(self.logger, "Listening for privileged clients on {name}");
+        }
         std::env::set_var("WAYLAND_DISPLAY", &socket_name);
+        self.processes.set_env("WAYLAND_DISPLAY", socket_name.to_string_lossy());
+
+        // Listen for control commands
+        let ipc = Ipc::init(&self.logger, &self.events.borrow().handle(), &socket_name)?;
+        self.processes.set_env(IPC_SOCKET_ENV, ipc.path.to_string_lossy());
+        self.ipc = Some(ipc);
+
+        // Reap exited children
+        Processes::init(&self.events.borrow().handle())?;
+
+        // Show notifications, unless another notification daemon is running
+        let handle = self.handle.clone();
+        if let Err(e) = self.notifications.init(&handle) {
+            warn!(self.logger, "Not serving notifications: {e}");
+        }
+
+        // Show the levels that media keys change
+        if let Err(e) = self.osd.init(&handle) {
+            warn!(self.logger, "Media keys will not run commands: {e}");
+        }
+
+        // Tell desktop components about outputs, workspaces and focus
+        if let Err(e) = self.dbus.init() {
+            warn!(self.logger, "Not serving compositor state over D-Bus: {e}");
+        }
 
         // Run main loop
         let display = self.display.clone();
         let events  = self.events.clone();
 
-        loop {
+        let reason = loop {
+
+            if let Some(reason) = self.exit.take() {
+                break reason
+            }
+
+            if !self.engine.running() {
+                break ExitReason::Stopped
+            }
+
+            // Sleep while another session owns the devices
+            if !self.engine.session_active() {
+                // Show the session again once it's back
+                self.redraw.request();
+                if let Err(e) = events.borrow_mut().dispatch(Some(PLACEHOLDER_TICK), &mut self) {
+                    break ExitReason::Error(CharlieError::other(e))
+                }
+                continue
+            }
 
             // Respond to user input
+            self.stalls.turn_start();
+            let span = self.profiler.begin();
             if let Err(e) = E::update(&mut self) {
                 crit!(self.logger, "Update error: {e}");
-                break
+                break ExitReason::Error(e)
             }
+            self.profiler.end(span, "input", None);
+            self.stalls.enter("housekeeping");
 
-            // Render display
-            if let Err(e) = E::render(&mut self) {
-                crit!(self.logger, "Render error: {e}");
-                break
-            }
+            // Do what protocol handlers asked for while clients were dispatched
+            self.commands_flush();
+
+            // Report clients that went away unexpectedly
+            self.crashes_flush();
+
+            // Follow the host's input capabilities and clipboard
+            self.host_sync();
+
+            // Perform what scripts asked for while handling events
+            self.scripts_flush();
+
+            // Send the pointer motion of this turn, entering and leaving surfaces
+            self.pointer_frame();
+
+            // Ask the window being resized for the latest size, if it's due
+            self.resize_flush();
+
+            // Ping the focused client, and notice the ones that stopped answering
+            self.watchdog_tick();
+
+            // Leave game mode if its window lost focus or fullscreen
+            self.game_mode_check();
+
+            // Let outputs tear whose fullscreen windows asked for it
+            self.tearing_update();
+
+            // Move the windows of the transactions that have caught up
+            self.transactions_flush();
+
+            // Pass on another chunk of the selections being pasted
+            self.selection_flush();
+            self.x11_selection_flush();
+
+            // Announce changes to outputs, workspaces and focus over D-Bus
+            self.dbus_update();
+
+            // Keep clients alive at a slow pace if there's nowhere to render
+            let handle = display.borrow().handle();
+            let timeout = if self.engine.output_count() == 0 {
+                self.placeholder.enable::<Self>(&handle);
+                if let Some(output) = self.placeholder.output() {
+                    self.desktop.send_frames(output);
+                }
+                Some(PLACEHOLDER_TICK)
+            } else if self.redraw.take() {
+                self.placeholder.disable::<Self>(&handle);
+                // Follow the color temperature of the night light
+                self.night_light_update();
+                // Render window thumbnails, unless a game wants all the time there is
+                if !self.game.active() {
+                    if let Err(e) = self.thumbnails_update() {
+                        warn!(self.logger, "Could not render thumbnails: {e}");
+                    }
+                }
+                // Throttle the frame callbacks of windows that can't be seen
+                self.occlusion_update();
+                // Render display
+                self.stalls.enter("render");
+                let span = self.profiler.begin();
+                if let Err(e) = E::render(&mut self) {
+                    crit!(self.logger, "Render error: {e}");
+                    break ExitReason::Error(e)
+                }
+                self.profiler.end(span, "render", None);
+                self.latency.presented();
+                // Let clients reuse the buffers that are no longer shown
+                self.explicit_sync_release();
+                self.redraw_timeout()
+            } else {
+                // Sleep until there's something to render
+                self.redraw_timeout()
+            };
 
             // Flush display/client messages
-            display.borrow_mut().flush_clients()?;
+            self.stalls.enter("flush");
+            if let Err(e) = display.borrow_mut().flush_clients() {
+                break ExitReason::Error(e.into())
+            }
+            self.latency.delivered();
+            self.stalls.turn_end();
 
             // Dispatch state to next event loop tick
-            events.borrow_mut().dispatch(Some(Duration::from_millis(1)), &mut self)?;
+            if let Err(e) = events.borrow_mut().dispatch(timeout, &mut self) {
+                break ExitReason::Error(CharlieError::other(e))
+            }
+        };
+
+        info!(self.logger, "Exiting: {reason:?}");
+        self.shutdown()?;
+        Ok(reason)
+    }
+
+    /// Stop the main loop at the start of the next iteration.
+    pub fn quit (&mut self) {
+        self.exit = Some(ExitReason::Quit);
+    }
+
+    /// Close windows, stop child processes, and release engine resources.
+    fn shutdown (&mut self) -> CharlieResult<()> {
+
+        // Remember where the windows were
+        self.geometry_save();
+        if let Err(e) = self.session_save() {
+            warn!(self.logger, "Could not save session: {e}");
         }
 
+        // Ask clients to close their windows
+        self.desktop.close_all();
+        self.display.borrow_mut().flush_clients()?;
+
+        // Stop child processes
+        self.processes.shutdown();
+
+        // Stop listening for commands
+        self.ipc = None;
+
+        // Write out the profile, if one is being taken
+        if let Err(e) = self.profiler.stop() {
+            warn!(self.logger, "Could not write profile: {e}");
+        }
+
+        // Destroy globals and drop host resources
+        let handle = self.display.borrow().handle();
+        self.gamma.destroy::<E>(&handle);
+        self.input_inhibitor.destroy::<E>(&handle);
+        self.virtual_input.destroy::<E>(&handle);
+        self.foreign.destroy::<E>(&handle);
+        self.tearing.destroy::<E>(&handle);
+        self.cursor_shapes.destroy::<E>(&handle);
+        self.content_type.destroy::<E>(&handle);
+        self.dialogs.destroy::<E>(&handle);
+        self.control.destroy::<E>(&handle);
+        self.engine.stop::<Self>(&handle)?;
+
+        // Let clients know about the removed globals before we go
+        self.display.borrow_mut().flush_clients()?;
+
         Ok(())
     }
 
     /// When the app is ready to run, this spawns the startup processes.
-    pub fn ready (&self) -> Result<(), Box<dyn Error>> {
+    pub fn ready (&mut self) -> Result<(), Box<dyn Error>> {
         debug!(self.logger, "DISPLAY={:?}", ::std::env::var("DISPLAY"));
         debug!(self.logger, "WAYLAND_DISPLAY={:?}", ::std::env::var("WAYLAND_DISPLAY"));
-        debug!(self.logger, "{:?}", self.startup);
-        for (cmd, args) in self.startup.iter() {
-            debug!(self.logger, "Spawning {cmd} {args:?}");
-            std::process::Command::new(cmd).args(args).spawn()?;
+        debug!(self.logger, "{:?}", self.processes.startup);
+        if let Ok(display) = std::env::var("DISPLAY") {
+            self.processes.set_env("DISPLAY", display);
+        }
+        self.kiosk_startup();
+        self.processes.spawn_startup()?;
+        self.processes.started = true;
+        let outputs: Vec<String> = self.desktop.screens.iter().filter_map(|screen| screen.output.clone()).collect();
+        for output in outputs {
+            self.companions_start(&output);
+        }
+        // A kiosk runs its application, and not the ones of the last session
+        if !self.kiosk.enabled {
+            self.session_launch();
         }
         Ok(())
     }
 
-    pub fn startup (self, cmd: impl AsRef<str>, args: &[&str]) -> StdResult<Self> {
+    /// Run a command once the compositor is ready.
+    pub fn startup (mut self, cmd: impl AsRef<str>, args: &[&str]) -> CharlieResult<Self> {
+        self.processes.startup.push(Startup::new(cmd, args, Restart::Never));
         Ok(self)
     }
 
-    pub fn output (mut self, name: &str, w: i32, h: i32, x: f64, y: f64) -> StdResult<Self> {
-        self.engine.output_added(name, 0, w, h)?;
+    /// Run a command once the compositor is ready, and restart it if it crashes.
+    pub fn respawn (mut self, cmd: impl AsRef<str>, args: &[&str]) -> CharlieResult<Self> {
+        self.processes.startup.push(Startup::new(cmd, args, Restart::OnCrash));
         Ok(self)
     }
 
-    pub fn input (self, name: impl AsRef<str>, cursor: impl AsRef<str>) -> StdResult<Self> {
+    pub fn output (mut self, name: &str, w: i32, h: i32, x: f64, y: f64) -> CharlieResult<Self> {
+        self.output_add(name, w, h, x, y)?;
+        Ok(self)
+    }
+
+    /// Add a viewport into the workspace and an engine output to display it.
+    pub fn output_add (&mut self, name: &str, w: i32, h: i32, x: f64, y: f64) -> CharlieResult<()> {
+        let mut screen = ScreenState::new((x, y), (w as f64, h as f64));
+        screen.output = Some(name.into());
+        let screen = self.desktop.screen_add(screen);
+        self.engine.output_added(name, screen, w, h)?;
+        self.output_depth_apply(screen);
+        if let Err(e) = self.output_mode_apply(screen) {
+            warn!(self.logger, "Could not set the mode of {name}: {e}");
+        }
+        self.output_profiles_apply();
+        self.layout_arrange();
+        self.companions_start(name);
+        self.scripts.hook("on_output_added", vec![name.into()]);
+        self.ipc_event(&format!("output added {name}"));
+        Ok(())
+    }
+
+    /// Forget the output of a screen that went away, moving its windows and pointers
+    /// to the first screen that's left, where they keep their place relative to the screen.
+    /// The screen stays, without an output, so that the ids of the others don't change.
+    pub fn output_remove (&mut self, screen: ScreenId) {
+        let name = match self.desktop.screens.get_mut(screen).and_then(|s| s.output.take()) {
+            Some(name) => name,
+            None => return
+        };
+        info!(self.logger, "Output {name} of screen {screen} is gone");
+        self.engine.output_removed().unwrap_or_else(|e| warn!(self.logger, "{e}"));
+        let target = self.desktop.screens.iter().position(|s| s.output.is_some());
+        if let Some(target) = target {
+            // Fullscreen windows go back to where they were, without restoring the lost mode
+            let fullscreen: Vec<ToplevelSurface> = self.desktop.windows_all()
+                .filter(|w| w.fullscreen_on(screen))
+                .filter_map(|w| match w.window.toplevel() {
+                    Kind::Xdg(toplevel) => Some(toplevel.clone()),
+                    Kind::X11(_) => None
+                })
+                .collect();
+            for surface in fullscreen {
+                if let Some(fullscreen) = self.desktop.window_state_mut(surface.wl_surface())
+                    .and_then(|w| w.fullscreen.as_mut())
+                {
+                    fullscreen.restore_mode = None;
+                }
+                if let Err(e) = self.unfullscreen(&surface) {
+                    warn!(self.logger, "Could not restore {surface:?} from fullscreen: {e}");
+                }
+            }
+            self.screen_evacuate(screen, target);
+            for pointer in self.input.pointers.iter_mut().filter(|p| p.screen == screen) {
+                pointer.screen = target;
+            }
+            if self.desktop.active_screen == screen {
+                self.desktop.active_screen = target;
+            }
+        }
+        self.switches.lid_disabled.retain(|disabled| *disabled != screen);
+        self.monitors.disabled.retain(|disabled| *disabled != screen);
+        self.output_profiles_apply();
+        self.layout_arrange();
+        self.companions_stop(&name);
+        self.scripts.hook("on_output_removed", vec![name.clone().into()]);
+        self.ipc_event(&format!("output removed {name}"));
+    }
+
+    /// Add a seat, with a fallback cursor image for when no cursor theme is found.
+    pub fn input (mut self, name: impl AsRef<str>, cursor: impl AsRef<str>) -> CharlieResult<Self> {
+        let texture = import_bitmap(&mut *self.engine.renderer(), cursor.as_ref())
+            .context(format!("Could not load cursor image {}", cursor.as_ref()))?;
+        self.input.seat_add(name.as_ref(), texture)?;
+        if self.input.numlock {
+            let index = self.input.keyboards.len() - 1;
+            self.keyboard_locks_set(index, KeyboardLeds { num_lock: true, caps_lock: false });
+        }
+        Ok(self)
+    }
+
+    /// Apply properties to matching windows.
+    pub fn rule (mut self, rule: WindowRule) -> CharlieResult<Self> {
+        self.desktop.rule_add(rule);
+        Ok(self)
+    }
+
+    /// The logical size of the area shown on the output that displays a screen.
+    pub fn screen_area (&self, screen: ScreenId) -> Option<Size<f64, Logical>> {
+        let output = self.engine.output_get(screen)?;
+        let mode = output.current_mode()?;
+        let size = self::transform::transform_size(output.current_transform(), mode.size);
+        Some(size.to_f64().to_logical(output.current_scale().fractional_scale()))
+    }
+
+    /// Rotate or flip the output that displays a screen.
+    pub fn output_transform (&mut self, screen: ScreenId, transform: Transform) -> CharlieResult<()> {
+        let output = self.engine.output_get(screen)
+            .ok_or_else(|| CharlieError::Config(format!("no output for screen {screen}")))?;
+        output.change_current_state(None, Some(transform), None, None);
+        self.layout_arrange();
+        Ok(())
+    }
+
+    /// Set the scale of the output that displays a screen.
+    /// Windows and pointers on it are drawn this many times bigger.
+    pub fn output_scale (&mut self, screen: ScreenId, scale: f64) -> CharlieResult<()> {
+        if !(scale > 0.0) {
+            return Err(CharlieError::Config(format!("invalid scale {scale}")))
+        }
+        let output = self.engine.output_get(screen)
+            .ok_or_else(|| CharlieError::Config(format!("no output for screen {screen}")))?;
+        let scale = if scale.fract() == 0.0 {
+            Scale::Integer(scale as i32)
+        } else {
+            Scale::Fractional(scale)
+        };
+        output.change_current_state(None, None, Some(scale), None);
+        self.layout_arrange();
+        Ok(())
+    }
+
+    /// Whether fullscreen windows may change the output mode to match their size.
+    pub fn fullscreen_mode_switch (mut self, enabled: bool) -> CharlieResult<Self> {
+        self.desktop.fullscreen_mode_switch = enabled;
+        Ok(self)
+    }
+
+    /// Whether switching to the current workspace goes back to the previous one.
+    pub fn auto_back_and_forth (mut self, enabled: bool) -> CharlieResult<Self> {
+        self.desktop.workspaces.auto_back_and_forth = enabled;
         Ok(self)
     }
 
@@ -164,13 +860,34 @@ impl<E: Engine> App<E> for Charlie<E> {
         &mut self.engine
     }
 
+    fn input <B: InputBackend> (&mut self, event: InputEvent<B>, screen: ScreenId) -> CharlieResult<()> {
+        self.latency.input();
+        self.redraw.request();
+        self.update((event, screen))
+    }
+
+    fn redraw (&mut self) {
+        self.redraw.request()
+    }
+
+    fn redraw_at (&mut self, at: Instant) {
+        self.redraw.request_at(at)
+    }
+
+    fn output_lost (&mut self, screen: ScreenId) -> CharlieResult<()> {
+        self.output_remove(screen);
+        Ok(())
+    }
+
     /// Render the desktop and pointer for this output
     fn render (
         &mut self,
         output: &Output,
         size:   &Size<i32, Physical>,
         screen: ScreenId
-    ) -> StdResult<()> {
+    ) -> CharlieResult<()> {
+
+        let span = self.profiler.begin();
 
         let mut renderer = self.engine.renderer();
 
@@ -181,42 +898,111 @@ impl<E: Engine> App<E> for Charlie<E> {
             output.current_scale()
         );
 
+        let scale = scale.fractional_scale();
+
+        // Everything is drawn in the untransformed space of the output
+        let area = self::transform::transform_size(transform, size);
+
+        // Upload the wallpaper
+        let name = output.name();
+        self.background.prepare(&mut *renderer, &name)?;
+
         // Import window surfaces
-        self.desktop.import(&mut *renderer)?;
+        self.desktop.import(&mut *renderer, &self.memory.refused)?;
+        if self.desktop.textures.deferred() {
+            self.redraw.request();
+        }
+
+        // Draw the shadow image for this output's scale
+        self.desktop.decorations.prepare(&mut *renderer, scale)?;
+
+        // Rasterize cursors for this output's scale
+        for pointer in self.input.pointers.iter_mut() {
+            pointer.cursor.prepare(&mut *renderer, scale)?;
+        }
+
+        // Compile the post-processing shaders, if the renderer can run them
+        if let Some(gles2) = renderer.gles2() {
+            self.effects.prepare(gles2)?;
+            self.night_light.prepare(gles2)?;
+        }
+
+        // Rasterize the labels of windows
+        self.hints.prepare(&mut *renderer, &self.desktop, scale)?;
+
+        // Rasterize the volume and brightness overlay
+        self.osd.prepare(&mut *renderer, scale)?;
+
+        // Notifications are shown on the screen that last received input
+        let active = screen == self.desktop.active_screen;
+        if active {
+            self.notifications.prepare(&mut *renderer, scale)?;
+        }
+
+        // Sample with the filter that the fullscreen window on this screen asks for, if any
+        let filter = self.desktop.scaling_on(screen).filter();
+        renderer.upscale_filter(filter).map_err(CharlieError::other)?;
+        renderer.downscale_filter(filter).map_err(CharlieError::other)?;
 
         // Begin frame
-        let mut frame = renderer.render(size, Transform::Flipped180)?;
+        let transform = self::transform::compose(transform, Transform::Flipped180);
+        let mut frame = renderer.render(size, transform).map_err(CharlieError::other)?;
 
-        // Clear frame
-        frame.clear([0.2, 0.3, 0.4, 1.0], &[Rectangle::from_loc_and_size((0, 0), size)])?;
+        // Draw background
+        self.background.render(&mut frame, &name, area)?;
 
         // Render window surfaces
-        self.desktop.render(&mut frame, screen, size)?;
+        self.desktop.render(&mut frame, screen, area, scale)?;
 
-        // Render pointers
-        for pointer in self.input.pointers.iter_mut() {
-            pointer.render(&mut frame, &size, &self.desktop.screens[screen])?;
+        // Outline the size a window is being resized to
+        self.resize_render(&mut frame, screen, scale)?;
+
+        // Label windows
+        self.hints.render(&mut frame, &self.desktop, screen, scale)?;
+
+        // Render notifications
+        if active {
+            self.notifications.render(&mut frame, area, scale)?;
+        }
+
+        // Show the volume or brightness over everything, fullscreen windows included
+        self.osd.render(&mut frame, &name, screen, area, scale)?;
+
+        // Render the pointers that are on this screen
+        for pointer in self.input.pointers.iter_mut().filter(|pointer| pointer.screen == screen) {
+            pointer.render(&mut frame, &area, scale, &self.desktop.screens[screen])?;
+        }
+
+        // Apply effects to the whole output, and tint everything for the night light
+        if let Some(gles2) = <E::Renderer as EngineRenderer>::gles2_frame(&mut frame) {
+            // Games in game mode or covering the screen are shown as they are
+            let game = self.desktop.windows_visible()
+                .any(|w| w.fullscreen_on(screen) && w.content_type == ContentType::Game);
+            if !self.game.active() && !game {
+                self.effects.render(gles2, screen, size, active)?;
+            }
+            self.night_light.render(gles2, screen)?;
+            // Save the frame for the screenshots that wait for it
+            self.captures.render(gles2, screen, size)?;
         }
 
         // End frame
-        frame.finish()?;
+        frame.finish().map_err(CharlieError::other)?;
+
+        // Tell clients which outputs their windows are on
+        self.desktop.update_output(output, screen);
 
         // Advance time
         self.desktop.send_frames(output);
 
+        self.profiler.end(span, "render output", Some(screen));
+
         Ok(())
 
     }
 
 }
 
-struct ClientState;
-
-impl ClientData for ClientState {
-    fn initialized (&self, _client_id: ClientId) {}
-    fn disconnected (&self, _client_id: ClientId, _reason: DisconnectReason) {}
-}
-
 #[delegate_output]
 impl<E: Engine> smithay::wayland::buffer::BufferHandler for Charlie<E> {
     fn buffer_destroyed(&mut self, _buffer: &wayland_server::protocol::wl_buffer::WlBuffer) {}
@@ -0,0 +1,158 @@
+use super::prelude::*;
+
+/// Device name under which the settings for all other devices are stored
+pub const ALL_DEVICES: &str = "*";
+
+/// Pointer speed, in logical pixels per millisecond, above which the adaptive profile speeds up
+const ADAPTIVE_THRESHOLD: f64 = 0.4;
+
+/// The most that the adaptive profile multiplies motion by
+const ADAPTIVE_MAX_GAIN: f64 = 3.0;
+
+/// Gaps between events longer than this don't count as one motion
+const MAX_INTERVAL: u32 = 100;
+
+/// How relative motion is mapped to pointer movement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccelProfile {
+    /// Move by the same factor regardless of speed
+    Flat,
+    /// Move further the faster the device is moved
+    Adaptive,
+}
+
+impl std::str::FromStr for AccelProfile {
+    type Err = Box<dyn Error>;
+    fn from_str (profile: &str) -> StdResult<Self> {
+        Ok(match profile {
+            "flat"     => Self::Flat,
+            "adaptive" => Self::Adaptive,
+            _ => return Err(format!("unknown acceleration profile: {profile}").into())
+        })
+    }
+}
+
+/// How a touchpad turns finger motion into scrolling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollMethod {
+    /// Scroll by moving two fingers
+    TwoFinger,
+    /// Scroll by moving a finger along the edge
+    Edge,
+    /// Scroll by moving the pointer while a button is held
+    OnButton,
+    /// Don't scroll
+    None,
+}
+
+impl std::str::FromStr for ScrollMethod {
+    type Err = Box<dyn Error>;
+    fn from_str (method: &str) -> StdResult<Self> {
+        Ok(match method {
+            "two-finger" => Self::TwoFinger,
+            "edge"       => Self::Edge,
+            "on-button"  => Self::OnButton,
+            "none"       => Self::None,
+            _ => return Err(format!("unknown scroll method: {method}").into())
+        })
+    }
+}
+
+/// Settings for a relative pointer device.
+///
+/// Acceleration, natural scrolling and left-handed mode are applied by the compositor,
+/// so they work with any engine. Tapping and the scroll method can only be set on the device
+/// itself, by engines that read devices through libinput; None leaves the device's default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointerConfig {
+    pub profile:        AccelProfile,
+    /// From -1 (slowest) to 1 (fastest), like libinput's acceleration speed
+    pub speed:          f64,
+    /// Multiplier applied after acceleration
+    pub sensitivity:    f64,
+    /// Whether content moves with the fingers, rather than the scrollbar
+    pub natural_scroll: bool,
+    /// Whether the left and right buttons are swapped
+    pub left_handed:    bool,
+    /// Whether tapping a touchpad clicks
+    pub tap:            Option<bool>,
+    pub scroll_method:  Option<ScrollMethod>,
+}
+
+impl Default for PointerConfig {
+    fn default () -> Self {
+        Self {
+            profile:        AccelProfile::Adaptive,
+            speed:          0.0,
+            sensitivity:    1.0,
+            natural_scroll: false,
+            left_handed:    false,
+            tap:            None,
+            scroll_method:  None,
+        }
+    }
+}
+
+impl PointerConfig {
+
+    pub fn new (profile: AccelProfile) -> Self {
+        Self { profile, ..Default::default() }
+    }
+
+    pub fn speed (mut self, speed: f64) -> Self {
+        self.speed = speed.clamp(-1.0, 1.0);
+        self
+    }
+
+    pub fn sensitivity (mut self, sensitivity: f64) -> Self {
+        self.sensitivity = sensitivity.max(0.0);
+        self
+    }
+
+    /// Which way scrolling goes.
+    pub fn scroll_direction (&self) -> f64 {
+        if self.natural_scroll { -1.0 } else { 1.0 }
+    }
+
+    /// Scale an unaccelerated motion that took `interval` milliseconds.
+    pub fn accelerate (&self, delta: Point<f64, Logical>, interval: Option<u32>) -> Point<f64, Logical> {
+        let base = 1.0 + self.speed;
+        let gain = match (self.profile, interval.filter(|dt| *dt > 0 && *dt <= MAX_INTERVAL)) {
+            (AccelProfile::Adaptive, Some(dt)) => {
+                let velocity = (delta.x * delta.x + delta.y * delta.y).sqrt() / dt as f64;
+                (velocity / ADAPTIVE_THRESHOLD).clamp(1.0, ADAPTIVE_MAX_GAIN)
+            },
+            _ => 1.0
+        };
+        let factor = base * gain * self.sensitivity;
+        (delta.x * factor, delta.y * factor).into()
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Set how relative motion of a device is accelerated.
+    /// Use [ALL_DEVICES] as the name to set the default for devices without their own settings.
+    pub fn pointer_config (mut self, device: &str, config: PointerConfig) -> CharlieResult<Self> {
+        self.pointer_config_set(device, config);
+        Ok(self)
+    }
+
+    /// Change the settings of a device, applying them to it if it's present.
+    pub fn pointer_config_set (&mut self, device: &str, config: PointerConfig) {
+        self.input.pointer_configs.insert(device.into(), config);
+        if let Err(e) = self.engine.input_configure(device, &config) {
+            warn!(self.logger, "Could not configure {device}: {e}");
+        }
+    }
+
+    /// Apply the settings of a device that was just plugged in.
+    pub fn pointer_config_added (&mut self, device: &str) {
+        let config = self.input.pointer_config(device);
+        if let Err(e) = self.engine.input_configure(device, &config) {
+            warn!(self.logger, "Could not configure {device}: {e}");
+        }
+    }
+
+}
@@ -0,0 +1,469 @@
+use super::prelude::*;
+
+use serde::{Serialize, Deserialize};
+
+use super::manage::{Edge, Gaps};
+use super::osd::MediaKey;
+use super::process::{Startup, Restart};
+use super::stacking::StackLayer;
+use super::ipc::ipc_quote as quote;
+
+/// Something that the compositor can be asked to do, the same way
+/// from a key binding, an IPC command, the control protocol or a script.
+///
+/// Actions that act on a window take its id, or act on the focused window if it's None.
+/// Each action has a text form, which is the IPC command that performs it, and a JSON form,
+/// which `charliectl action` takes, e.g. `{"move_by":[10,0]}` or `"quit"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Quit the compositor
+    Quit,
+    /// Switch to another virtual terminal
+    VtSwitch(i32),
+    /// Run a command
+    Exec(String, Vec<String>),
+    /// Do what the power button is set to do
+    Power,
+    /// Change the volume or brightness as a media key does, showing the new level
+    MediaKey(MediaKey),
+    /// Show the named workspace, creating it if missing
+    Workspace(String),
+    /// Show the named workspace, or the previous one if it's showing and that's enabled
+    WorkspaceSwitch(String),
+    /// Show the previously visible workspace
+    WorkspaceBackAndForth,
+    /// Make everything on the active screen bigger, or smaller if negative, by this much
+    ScaleStep(f64),
+    /// Set the scale of a screen's output
+    OutputScale(ScreenId, f64),
+    /// Make the gaps around windows this many logical pixels wider, or narrower if negative
+    GapsBy(i32),
+    /// Show and focus a window, restoring it if minimized
+    Focus(u64),
+    /// Show and focus a window, switching to its workspace
+    JumpTo(u64),
+    /// Focus the next window, restoring it if minimized
+    Cycle,
+    /// Label the visible windows with letters to pick one from
+    Hints,
+    /// Ask a window to close
+    Close(u64),
+    /// Close the focused window, or kill it if it's not responding
+    Kill,
+    /// Hide a window
+    Minimize(Option<u64>),
+    /// Show the window that was hidden last on the current workspace
+    Restore,
+    /// Put a window on top of the others in its layer
+    Raise(Option<u64>),
+    /// Put a window below the others in its layer
+    Lower(Option<u64>),
+    /// Keep the focused window in a layer, or put it back in the normal one
+    ToggleLayer(StackLayer),
+    /// Put a window at these coordinates
+    Move(u64, f64, f64),
+    /// Make a window this size
+    Resize(u64, f64, f64),
+    /// Move the focused window by this many logical pixels
+    MoveBy(i32, i32),
+    /// Make the focused window this many logical pixels bigger
+    ResizeBy(i32, i32),
+    /// Move the focused window against an edge of its screen
+    MoveToEdge(Edge),
+    /// Move the focused window to a screen, or to the next one if None
+    MoveToScreen(Option<ScreenId>),
+    /// Move the focused window to the middle of its screen
+    Center,
+    /// Make the focused window fill its screen, or put it back
+    ToggleFloating,
+    /// Shrink a window into a corner above all workspaces, or put it back
+    TogglePip(Option<u64>),
+    /// Scale the focused window by whole numbers when it's fullscreen, or stop doing so
+    ToggleIntegerScaling,
+    /// Move the pointer to the middle of the focused window
+    WarpToFocused,
+    /// Move the pointer to a point on a screen
+    WarpPointer(ScreenId, f64, f64),
+    /// Keep the pointer on a screen
+    ConfinePointer(ScreenId),
+    /// Let the pointer leave the screen it's kept on
+    ReleasePointer,
+    /// Write the state of the compositor as JSON to a file, or a new file in the runtime directory
+    /// if None, or reply with it if the path is `-`
+    Snapshot(Option<String>),
+}
+
+/// Whether a window can be made this wide or tall.
+pub fn valid_length (length: f64) -> bool {
+    length.is_finite() && length > 0.0
+}
+
+impl Action {
+
+    /// Whether the action is performed again while its key is held.
+    /// Actions that move focus don't repeat, since moving focus stops the repeat.
+    pub fn repeats (&self) -> bool {
+        matches!(self, Self::Raise(_) | Self::Lower(_) | Self::ScaleStep(_)
+            | Self::MoveBy(..) | Self::ResizeBy(..) | Self::GapsBy(..)
+            | Self::MediaKey(MediaKey::VolumeUp | MediaKey::VolumeDown
+                | MediaKey::BrightnessUp | MediaKey::BrightnessDown))
+    }
+
+    /// The action that an IPC command stands for, if it stands for one.
+    pub fn parse (args: &[&str]) -> Option<StdResult<Self>> {
+        let id = |id: &str| -> StdResult<Option<u64>> { Ok(Some(id.parse()?)) };
+        let length = |length: &str| -> StdResult<f64> {
+            let length = length.parse()?;
+            if valid_length(length) { Ok(length) } else { Err(format!("invalid window size: {length}").into()) }
+        };
+        let action = || -> StdResult<Option<Self>> { Ok(Some(match args {
+            ["quit"]                            => Self::Quit,
+            ["vt", vt]                          => Self::VtSwitch(vt.parse()?),
+            ["spawn", cmd, args @ ..]           => Self::Exec(cmd.to_string(), args.iter().map(|a| a.to_string()).collect()),
+            ["power"]                           => Self::Power,
+            ["media", key]                      => Self::MediaKey(key.parse()?),
+            ["workspace", "jump", name]         => Self::Workspace(name.to_string()),
+            ["workspace", "switch", name]       => Self::WorkspaceSwitch(name.to_string()),
+            ["workspace", "back_and_forth"]     => Self::WorkspaceBackAndForth,
+            ["output", "scale_step", step]      => Self::ScaleStep(step.parse()?),
+            ["output", "scale", screen, scale]  => Self::OutputScale(screen.parse()?, scale.parse()?),
+            ["gaps", "grow", px]                => Self::GapsBy(px.parse()?),
+            ["window", "focus", id]             => Self::Focus(id.parse()?),
+            ["window", "jump", id]              => Self::JumpTo(id.parse()?),
+            ["window", "cycle"]                 => Self::Cycle,
+            ["window", "hints"]                 => Self::Hints,
+            ["window", "close", id]             => Self::Close(id.parse()?),
+            ["window", "kill"]                  => Self::Kill,
+            ["window", "minimize"]              => Self::Minimize(None),
+            ["window", "minimize", i]           => Self::Minimize(id(i)?),
+            ["window", "restore"]               => Self::Restore,
+            ["window", "raise"]                 => Self::Raise(None),
+            ["window", "raise", i]              => Self::Raise(id(i)?),
+            ["window", "lower"]                 => Self::Lower(None),
+            ["window", "lower", i]              => Self::Lower(id(i)?),
+            ["window", "toggle_layer", layer]   => Self::ToggleLayer(layer.parse()?),
+            ["window", "move", id, x, y]        => Self::Move(id.parse()?, x.parse()?, y.parse()?),
+            ["window", "resize", id, w, h]      => Self::Resize(id.parse()?, length(w)?, length(h)?),
+            ["window", "nudge", dx, dy]         => Self::MoveBy(dx.parse()?, dy.parse()?),
+            ["window", "grow", dw, dh]          => Self::ResizeBy(dw.parse()?, dh.parse()?),
+            ["window", "edge", edge]            => Self::MoveToEdge(edge.parse()?),
+            ["window", "screen", "next"]        => Self::MoveToScreen(None),
+            ["window", "screen", screen]        => Self::MoveToScreen(Some(screen.parse()?)),
+            ["window", "center"]                => Self::Center,
+            ["window", "float"]                 => Self::ToggleFloating,
+            ["window", "pip"]                   => Self::TogglePip(None),
+            ["window", "pip", i]                => Self::TogglePip(id(i)?),
+            ["window", "integer_scaling"]       => Self::ToggleIntegerScaling,
+            ["pointer", "warp", "focused"]      => Self::WarpToFocused,
+            ["pointer", "warp", screen, x, y]   => Self::WarpPointer(screen.parse()?, x.parse()?, y.parse()?),
+            ["pointer", "confine", screen]      => Self::ConfinePointer(screen.parse()?),
+            ["pointer", "release"]              => Self::ReleasePointer,
+            ["snapshot"]                        => Self::Snapshot(None),
+            ["snapshot", path]                  => Self::Snapshot(Some(path.to_string())),
+            _ => return Ok(None)
+        })) };
+        action().transpose()
+    }
+
+}
+
+/// The IPC command that performs the action.
+impl std::fmt::Display for Action {
+    fn fmt (&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let window = |id: &Option<u64>| id.map(|id| format!(" {id}")).unwrap_or_default();
+        match self {
+            Self::Quit                     => write!(f, "quit"),
+            Self::VtSwitch(vt)             => write!(f, "vt {vt}"),
+            Self::Exec(cmd, args)          => write!(f, "spawn {}{}", quote(cmd), args.iter().map(|arg| format!(" {}", quote(arg))).collect::<String>()),
+            Self::Power                    => write!(f, "power"),
+            Self::MediaKey(key)            => write!(f, "media {}", key.name()),
+            Self::Workspace(name)          => write!(f, "workspace jump {}", quote(name)),
+            Self::WorkspaceSwitch(name)    => write!(f, "workspace switch {}", quote(name)),
+            Self::WorkspaceBackAndForth    => write!(f, "workspace back_and_forth"),
+            Self::ScaleStep(step)          => write!(f, "output scale_step {step}"),
+            Self::OutputScale(screen, s)   => write!(f, "output scale {screen} {s}"),
+            Self::GapsBy(px)               => write!(f, "gaps grow {px}"),
+            Self::Focus(id)                => write!(f, "window focus {id}"),
+            Self::JumpTo(id)               => write!(f, "window jump {id}"),
+            Self::Cycle                    => write!(f, "window cycle"),
+            Self::Hints                    => write!(f, "window hints"),
+            Self::Close(id)                => write!(f, "window close {id}"),
+            Self::Kill                     => write!(f, "window kill"),
+            Self::Minimize(id)             => write!(f, "window minimize{}", window(id)),
+            Self::Restore                  => write!(f, "window restore"),
+            Self::Raise(id)                => write!(f, "window raise{}", window(id)),
+            Self::Lower(id)                => write!(f, "window lower{}", window(id)),
+            Self::ToggleLayer(layer)       => write!(f, "window toggle_layer {}", layer.name()),
+            Self::Move(id, x, y)           => write!(f, "window move {id} {x} {y}"),
+            Self::Resize(id, w, h)         => write!(f, "window resize {id} {w} {h}"),
+            Self::MoveBy(dx, dy)           => write!(f, "window nudge {dx} {dy}"),
+            Self::ResizeBy(dw, dh)         => write!(f, "window grow {dw} {dh}"),
+            Self::MoveToEdge(edge)         => write!(f, "window edge {}", edge.name()),
+            Self::MoveToScreen(None)       => write!(f, "window screen next"),
+            Self::MoveToScreen(Some(s))    => write!(f, "window screen {s}"),
+            Self::Center                   => write!(f, "window center"),
+            Self::ToggleFloating           => write!(f, "window float"),
+            Self::TogglePip(id)            => write!(f, "window pip{}", window(id)),
+            Self::ToggleIntegerScaling     => write!(f, "window integer_scaling"),
+            Self::WarpToFocused            => write!(f, "pointer warp focused"),
+            Self::WarpPointer(screen, x, y) => write!(f, "pointer warp {screen} {x} {y}"),
+            Self::ConfinePointer(screen)   => write!(f, "pointer confine {screen}"),
+            Self::ReleasePointer           => write!(f, "pointer release"),
+            Self::Snapshot(None)           => write!(f, "snapshot"),
+            Self::Snapshot(Some(path))     => write!(f, "snapshot {}", quote(path)),
+        }
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Perform an action, returning what to reply to IPC.
+    pub fn perform (&mut self, action: Action) -> StdResult<String> {
+        debug!(self.logger, "Performing {action}");
+        match action {
+            Action::Quit => {
+                self.quit()
+            },
+            Action::VtSwitch(vt) => {
+                info!(self.logger, "Switching to VT {vt}");
+                self.engine.change_vt(vt)?
+            },
+            Action::Exec(cmd, args) => {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                let pid = self.processes.spawn(Startup::new(cmd, &args, Restart::Never))?;
+                return Ok(pid.to_string())
+            },
+            Action::Power => {
+                self.power_pressed()
+            },
+            Action::MediaKey(key) => {
+                self.media_key(key)
+            },
+            Action::Workspace(name) => {
+                self.desktop.workspaces.jump(&name)
+            },
+            Action::WorkspaceSwitch(name) => {
+                let index = self.desktop.workspaces.find_or_add(&name);
+                self.desktop.workspaces.switch(index)
+            },
+            Action::WorkspaceBackAndForth => {
+                self.desktop.workspaces.back_and_forth()
+            },
+            Action::ScaleStep(step) => {
+                self.scale_step(step)
+            },
+            Action::OutputScale(screen, scale) => {
+                self.output_scale(screen, scale)?
+            },
+            Action::GapsBy(step) => {
+                let gaps = self.desktop.gaps;
+                self.gaps_set(Gaps { inner: gaps.inner + step, outer: gaps.outer + step })
+            },
+            Action::Focus(id) => {
+                let surface = self.action_window(Some(id))?;
+                self.desktop.unminimize(&surface);
+                self.focus(Some(surface));
+            },
+            Action::JumpTo(id) => {
+                self.window_jump(id)?
+            },
+            Action::Cycle => {
+                self.cycle()
+            },
+            Action::Hints => {
+                self.hints_start()
+            },
+            Action::Close(id) => {
+                let surface = self.action_window(Some(id))?;
+                self.window_close(&surface);
+            },
+            Action::Kill => {
+                self.window_kill()
+            },
+            Action::Minimize(id) => {
+                let surface = self.action_window(id)?;
+                self.minimize(&surface);
+            },
+            Action::Restore => {
+                let workspace = &self.desktop.workspaces.list[self.desktop.workspaces.current];
+                if let Some(surface) = workspace.minimized.last().cloned() {
+                    self.desktop.unminimize(&surface);
+                    self.focus(Some(surface));
+                }
+            },
+            Action::Raise(id) => {
+                let surface = self.action_window(id)?;
+                self.desktop.raise(&surface);
+            },
+            Action::Lower(id) => {
+                let surface = self.action_window(id)?;
+                self.desktop.lower(&surface);
+            },
+            Action::ToggleLayer(layer) => {
+                self.toggle_focused_layer(layer)
+            },
+            Action::Move(id, x, y) => {
+                let window = self.desktop.window_by_id(id).ok_or("no such window")?;
+                window.center = (x, y).into();
+                let surface = window.window.toplevel().wl_surface().clone();
+                self.geometry_remember(&surface);
+            },
+            Action::Resize(id, w, h) => {
+                if !valid_length(w) || !valid_length(h) {
+                    return Err(format!("invalid window size: {w}x{h}").into())
+                }
+                let window = self.desktop.window_by_id(id).ok_or("no such window")?;
+                window.size = (w, h).into();
+                if let Kind::Xdg(toplevel) = window.window.toplevel() {
+                    let size = window.size.to_i32_round();
+                    toplevel.with_pending_state(|state| state.size = Some(size));
+                    toplevel.send_configure();
+                }
+                let surface = window.window.toplevel().wl_surface().clone();
+                self.geometry_remember(&surface);
+            },
+            Action::MoveBy(dx, dy) => {
+                let surface = self.action_window(None)?;
+                self.window_move_by(&surface, dx as f64, dy as f64);
+            },
+            Action::ResizeBy(dw, dh) => {
+                let surface = self.action_window(None)?;
+                self.window_resize_by(&surface, dw, dh);
+            },
+            Action::MoveToEdge(edge) => {
+                let surface = self.action_window(None)?;
+                self.window_move_to_edge(&surface, edge);
+            },
+            Action::MoveToScreen(screen) => {
+                let surface = self.action_window(None)?;
+                let screen = match screen {
+                    Some(screen) if screen >= self.desktop.screens.len() => return Err("no such screen".into()),
+                    Some(screen) => screen,
+                    None => (self.window_screen(&surface) + 1) % self.desktop.screens.len().max(1)
+                };
+                self.window_move_to_screen(&surface, screen);
+            },
+            Action::Center => {
+                let surface = self.action_window(None)?;
+                self.window_center(&surface);
+            },
+            Action::ToggleFloating => {
+                let surface = self.action_window(None)?;
+                self.window_toggle_floating(&surface);
+            },
+            Action::TogglePip(id) => {
+                let surface = self.action_window(id)?;
+                self.window_toggle_pip(&surface);
+            },
+            Action::ToggleIntegerScaling => {
+                let surface = self.action_window(None)?;
+                self.window_toggle_integer_scaling(&surface);
+            },
+            Action::WarpToFocused => {
+                self.pointer_warp_to_focused()
+            },
+            Action::WarpPointer(screen, x, y) => {
+                self.pointer_warp(screen, (x, y).into())?
+            },
+            Action::ConfinePointer(screen) => {
+                self.pointer_confine(screen, None)?
+            },
+            Action::ReleasePointer => {
+                self.pointer_release()
+            },
+            Action::Snapshot(Some(path)) if path == "-" => {
+                return Ok(serde_json::to_string_pretty(&self.snapshot())?)
+            },
+            Action::Snapshot(path) => {
+                return Ok(self.snapshot_save(path.as_deref())?.display().to_string())
+            },
+        }
+        self.redraw.request();
+        Ok("ok".into())
+    }
+
+    /// The window with this id, or the focused window if None.
+    fn action_window (&mut self, id: Option<u64>) -> StdResult<WlSurface> {
+        match id {
+            Some(id) => Ok(self.desktop.window_by_id(id).ok_or("no such window")?
+                .window.toplevel().wl_surface().clone()),
+            None => Ok(self.desktop.focused.clone().ok_or("no window is focused")?)
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ipc::ipc_split;
+
+    /// The action that the text form of an action stands for.
+    fn reparse (action: &Action) -> Action {
+        let words = ipc_split(&action.to_string()).unwrap();
+        let args: Vec<&str> = words.iter().map(String::as_str).collect();
+        Action::parse(&args).unwrap().unwrap()
+    }
+
+    #[test]
+    fn text_round_trip () {
+        let actions = [
+            Action::Quit,
+            Action::VtSwitch(2),
+            Action::Exec("foot".into(), vec![]),
+            Action::Exec("my editor".into(), vec!["-e".into(), "".into(), "two  words".into()]),
+            Action::Exec("sh".into(), vec!["-c".into(), r#"echo "a\b""#.into(), r#""quoted""#.into()]),
+            Action::Power,
+            Action::MediaKey(MediaKey::VolumeUp),
+            Action::Workspace("web".into()),
+            Action::WorkspaceSwitch("two words".into()),
+            Action::WorkspaceBackAndForth,
+            Action::ScaleStep(-0.25),
+            Action::OutputScale(1, 1.5),
+            Action::GapsBy(-4),
+            Action::Focus(3),
+            Action::JumpTo(4),
+            Action::Cycle,
+            Action::Hints,
+            Action::Close(5),
+            Action::Kill,
+            Action::Minimize(None),
+            Action::Minimize(Some(6)),
+            Action::Restore,
+            Action::Raise(None),
+            Action::Raise(Some(7)),
+            Action::Lower(None),
+            Action::Lower(Some(8)),
+            Action::ToggleLayer(StackLayer::Above),
+            Action::Move(9, -10.5, 20.0),
+            Action::Resize(10, 640.0, 480.5),
+            Action::MoveBy(-10, 0),
+            Action::ResizeBy(0, 10),
+            Action::MoveToEdge(Edge::Left),
+            Action::MoveToScreen(None),
+            Action::MoveToScreen(Some(1)),
+            Action::Center,
+            Action::ToggleFloating,
+            Action::TogglePip(None),
+            Action::TogglePip(Some(11)),
+            Action::ToggleIntegerScaling,
+            Action::WarpToFocused,
+            Action::WarpPointer(0, 1.5, 2.0),
+            Action::ConfinePointer(1),
+            Action::ReleasePointer,
+            Action::Snapshot(None),
+            Action::Snapshot(Some("/tmp/state of things.json".into())),
+        ];
+        for action in actions.iter() {
+            assert_eq!(&reparse(action), action, "{action}");
+        }
+    }
+
+    #[test]
+    fn invalid_size () {
+        for (w, h) in [("0", "10"), ("10", "-1"), ("NaN", "10"), ("10", "inf")] {
+            assert!(Action::parse(&["window", "resize", "1", w, h]).unwrap().is_err());
+        }
+    }
+}
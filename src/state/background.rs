@@ -0,0 +1,219 @@
+use super::prelude::*;
+
+use smithay::backend::renderer::ImportMem;
+
+use std::path::PathBuf;
+
+/// Color behind everything, if no wallpaper covers it
+const DEFAULT_COLOR: [f32; 4] = [0.2, 0.3, 0.4, 1.0];
+
+/// How a wallpaper is fitted to an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WallpaperMode {
+    /// Scale to cover the whole output, cropping what doesn't fit
+    Fill,
+    /// Scale to fit inside the output, leaving the background color around it
+    Fit,
+    /// Scale to the size of the output, ignoring the aspect ratio
+    Stretch,
+    /// Show at the original size in the middle of the output
+    Center,
+    /// Repeat at the original size from the top left corner
+    Tile,
+}
+
+impl std::str::FromStr for WallpaperMode {
+    type Err = Box<dyn Error>;
+    fn from_str (mode: &str) -> StdResult<Self> {
+        Ok(match mode {
+            "fill"    => Self::Fill,
+            "fit"     => Self::Fit,
+            "stretch" => Self::Stretch,
+            "center"  => Self::Center,
+            "tile"    => Self::Tile,
+            _ => return Err(format!("unknown wallpaper mode: {mode}").into())
+        })
+    }
+}
+
+/// An image loaded from disk, uploaded to the renderer once on first use.
+pub struct Wallpaper<R: EngineRenderer> {
+    pub path:  PathBuf,
+    pub mode:  WallpaperMode,
+    image:     image::RgbaImage,
+    texture:   Option<R::TextureId>,
+}
+
+impl<R: EngineRenderer> Wallpaper<R> {
+
+    pub fn load (path: impl Into<PathBuf>, mode: WallpaperMode) -> StdResult<Self> {
+        let path  = path.into();
+        let image = image::io::Reader::open(&path)?.with_guessed_format()?.decode()?.to_rgba8();
+        Ok(Self { path, mode, image, texture: None })
+    }
+
+    fn size (&self) -> Size<i32, Buffer> {
+        (self.image.width() as i32, self.image.height() as i32).into()
+    }
+
+    fn prepare (&mut self, renderer: &mut R) -> StdResult<()> {
+        if self.texture.is_none() {
+            self.texture = Some(renderer.import_memory(self.image.as_raw(), self.size(), false)?);
+        }
+        Ok(())
+    }
+
+    fn render (&self, frame: &mut R::Frame<'_>, area: Size<i32, Physical>) -> StdResult<()> {
+        let texture = match self.texture.as_ref() {
+            Some(texture) => texture,
+            None => return Ok(())
+        };
+        let image = self.size().to_f64();
+        let (w, h) = (area.w as f64, area.h as f64);
+        let full = Rectangle::<f64, Buffer>::from_loc_and_size((0.0, 0.0), image);
+        let centered = |size: Size<f64, Physical>| Rectangle::<i32, Physical>::from_loc_and_size(
+            (((w - size.w) / 2.0).round() as i32, ((h - size.h) / 2.0).round() as i32),
+            size.to_i32_round()
+        );
+        let mut draw = |src: Rectangle<f64, Buffer>, dest: Rectangle<i32, Physical>| {
+            frame.render_texture_from_to(
+                texture, src, dest, &[Rectangle::from_loc_and_size((0, 0), dest.size)],
+                Transform::Normal, 1.0
+            )
+        };
+        match self.mode {
+            WallpaperMode::Stretch => {
+                draw(full, Rectangle::from_loc_and_size((0, 0), area))?;
+            },
+            WallpaperMode::Fill => {
+                // Crop the image to the aspect ratio of the output
+                let scale = (w / image.w).max(h / image.h);
+                let crop = Size::<f64, Buffer>::from((w / scale, h / scale));
+                let src = Rectangle::from_loc_and_size(
+                    ((image.w - crop.w) / 2.0, (image.h - crop.h) / 2.0), crop
+                );
+                draw(src, Rectangle::from_loc_and_size((0, 0), area))?;
+            },
+            WallpaperMode::Fit => {
+                let scale = (w / image.w).min(h / image.h);
+                draw(full, centered((image.w * scale, image.h * scale).into()))?;
+            },
+            WallpaperMode::Center => {
+                draw(full, centered((image.w, image.h).into()))?;
+            },
+            WallpaperMode::Tile => {
+                let (tile_w, tile_h) = (image.w as i32, image.h as i32);
+                for y in (0..area.h).step_by(tile_h.max(1) as usize) {
+                    for x in (0..area.w).step_by(tile_w.max(1) as usize) {
+                        draw(full, Rectangle::from_loc_and_size((x, y), (tile_w, tile_h)))?;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+}
+
+/// What is drawn behind the windows of each output.
+pub struct Background<R: EngineRenderer> {
+    logger:         Logger,
+    /// Solid color behind the wallpaper
+    pub color:      [f32; 4],
+    /// Wallpaper for outputs that don't have their own
+    pub wallpaper:  Option<Wallpaper<R>>,
+    /// Wallpapers by output name
+    pub wallpapers: HashMap<String, Wallpaper<R>>,
+}
+
+impl<R: EngineRenderer> Background<R> {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:     logger.clone(),
+            color:      DEFAULT_COLOR,
+            wallpaper:  None,
+            wallpapers: HashMap::new(),
+        }
+    }
+
+    /// Set the wallpaper of one output, or of all outputs that don't have their own.
+    pub fn set_wallpaper (
+        &mut self,
+        output: Option<&str>,
+        path:   impl Into<PathBuf>,
+        mode:   WallpaperMode
+    ) -> StdResult<()> {
+        let wallpaper = Wallpaper::load(path, mode)?;
+        debug!(self.logger, "Wallpaper for {}: {:?} ({mode:?})",
+            output.unwrap_or("all outputs"), wallpaper.path);
+        match output {
+            Some(output) => { self.wallpapers.insert(output.into(), wallpaper); },
+            None => self.wallpaper = Some(wallpaper)
+        }
+        Ok(())
+    }
+
+    /// Remove the wallpaper of one output, or the one shared by all outputs.
+    pub fn clear_wallpaper (&mut self, output: Option<&str>) {
+        match output {
+            Some(output) => { self.wallpapers.remove(output); },
+            None => self.wallpaper = None
+        }
+    }
+
+    fn get (&self, output: &str) -> Option<&Wallpaper<R>> {
+        self.wallpapers.get(output).or(self.wallpaper.as_ref())
+    }
+
+    /// Upload the wallpaper of this output, unless already done.
+    pub fn prepare (&mut self, renderer: &mut R, output: &str) -> StdResult<()> {
+        match self.wallpapers.get_mut(output) {
+            Some(wallpaper) => wallpaper.prepare(renderer),
+            None => match self.wallpaper.as_mut() {
+                Some(wallpaper) => wallpaper.prepare(renderer),
+                None => Ok(())
+            }
+        }
+    }
+
+    /// Fill the frame with the background color and wallpaper of this output.
+    pub fn render (&self, frame: &mut R::Frame<'_>, output: &str, area: Size<i32, Physical>)
+        -> StdResult<()>
+    {
+        frame.clear(self.color, &[Rectangle::from_loc_and_size((0, 0), area)])?;
+        if let Some(wallpaper) = self.get(output) {
+            wallpaper.render(frame, area)?;
+        }
+        Ok(())
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Set the color shown where there's no wallpaper.
+    pub fn background (mut self, color: [f32; 4]) -> CharlieResult<Self> {
+        self.background.color = color;
+        Ok(self)
+    }
+
+    /// Set the wallpaper of all outputs that don't have their own.
+    pub fn wallpaper (mut self, path: impl Into<PathBuf>, mode: WallpaperMode) -> CharlieResult<Self> {
+        self.background.set_wallpaper(None, path, mode).context("Could not load wallpaper")?;
+        Ok(self)
+    }
+
+    /// Set the wallpaper of one output.
+    pub fn output_wallpaper (
+        mut self,
+        output: &str,
+        path:   impl Into<PathBuf>,
+        mode:   WallpaperMode
+    ) -> CharlieResult<Self> {
+        self.background.set_wallpaper(Some(output), path, mode)
+            .context(format!("Could not load wallpaper for {output}"))?;
+        Ok(self)
+    }
+
+}
@@ -0,0 +1,185 @@
+use super::prelude::*;
+use super::transform::transform_size;
+
+use std::path::PathBuf;
+
+use smithay::backend::renderer::{Bind, Offscreen, Unbind, gles2::ffi};
+use smithay::reexports::calloop::channel::{channel, Sender, Event as ChannelEvent};
+
+/// Screenshots of outputs, taken from the next frame rendered on them.
+///
+/// The pixels are read back right before the frame is finished, so they show what the output
+/// shows, effects included, and are saved as PNG on a thread of their own. Whether that worked
+/// is announced to IPC subscribers as `screenshot saved PATH` or `screenshot failed PATH: ERROR`.
+/// Reading frames back needs GLES2, so engines whose renderer isn't built on it can't take any.
+pub struct Captures {
+    logger:  Logger,
+    /// Screenshots waiting for the next frame of their screen
+    pending: Vec<(ScreenId, PathBuf)>,
+    /// Lets the main loop know when a screenshot has been saved, once one has been taken
+    saved:   Option<Sender<(PathBuf, Result<(), String>)>>,
+}
+
+impl Captures {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), pending: vec![], saved: None }
+    }
+
+    /// Take the screenshots waiting for this frame, before it's finished.
+    pub fn render (&mut self, frame: &mut Gles2Frame, screen: ScreenId, size: Size<i32, Physical>)
+        -> CharlieResult<()>
+    {
+        if !self.pending.iter().any(|(s, _)| *s == screen) {
+            return Ok(())
+        }
+        let (paths, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter().partition(|(s, _)| *s == screen);
+        self.pending = pending;
+        let pixels = read_pixels(frame, size)?;
+        let saved = self.saved.clone();
+        let logger = self.logger.clone();
+        std::thread::spawn(move || {
+            for (_, path) in paths {
+                let result = image::RgbaImage::from_raw(size.w as u32, size.h as u32, pixels.clone())
+                    .ok_or_else(|| "the frame has the wrong size".to_string())
+                    .and_then(|image| image.save(&path).map_err(|e| e.to_string()));
+                match &result {
+                    Ok(()) => debug!(logger, "Saved screenshot to {}", path.display()),
+                    Err(e) => warn!(logger, "Could not save screenshot to {}: {e}", path.display()),
+                }
+                if let Some(saved) = saved.as_ref() {
+                    let _ = saved.send((path, result));
+                }
+            }
+        });
+        Ok(())
+    }
+
+}
+
+/// Encode pixels read from a frame as PNG.
+fn encode_png (pixels: &[u8], size: Size<i32, Physical>) -> StdResult<Vec<u8>> {
+    let mut png = vec![];
+    image::codecs::png::PngEncoder::new(&mut png)
+        .encode(pixels, size.w as u32, size.h as u32, image::ColorType::Rgba8)?;
+    Ok(png)
+}
+
+/// Read the pixels of a frame, top row first.
+fn read_pixels (frame: &mut Gles2Frame, size: Size<i32, Physical>) -> CharlieResult<Vec<u8>> {
+    let (w, h) = (size.w as usize, size.h as usize);
+    let mut pixels = vec![0u8; w * h * 4];
+    frame.with_context(|gl| unsafe {
+        gl.PixelStorei(ffi::PACK_ALIGNMENT, 1);
+        gl.ReadPixels(
+            0, 0, size.w, size.h, ffi::RGBA, ffi::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut _
+        );
+    }).map_err(CharlieError::other)?;
+    // GL has the bottom row first, and the output is opaque whatever the frame says
+    let mut image = Vec::with_capacity(pixels.len());
+    for row in pixels.chunks_exact(w * 4).rev() {
+        image.extend_from_slice(row);
+    }
+    for pixel in image.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    Ok(image)
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Save the next frame of a screen to a PNG file.
+    pub fn screenshot (&mut self, screen: ScreenId, path: impl Into<PathBuf>) -> StdResult<()> {
+        if self.engine.output_get(screen).is_none() {
+            return Err(format!("no output shows screen {screen}").into())
+        }
+        if self.engine.renderer().gles2().is_none() {
+            return Err("screenshots need a GLES2 renderer".into())
+        }
+        if self.captures.saved.is_none() {
+            let (sender, receiver) = channel();
+            self.handle.insert_source(receiver, |event, _, state| {
+                if let ChannelEvent::Msg((path, result)) = event {
+                    match result {
+                        Ok(()) => state.ipc_event(&format!("screenshot saved {}", path.display())),
+                        Err(e) => state.ipc_event(&format!("screenshot failed {}: {e}", path.display())),
+                    }
+                }
+            }).map_err(|e| format!("could not listen for screenshots: {e}"))?;
+            self.captures.saved = Some(sender);
+        }
+        self.captures.pending.push((screen, path.into()));
+        self.redraw();
+        Ok(())
+    }
+
+    /// Render what an output shows to an offscreen buffer, right away, and encode it as PNG.
+    /// Unlike [Self::screenshot], this shows only the windows, without effects or overlays.
+    pub fn capture_output (&mut self, name: &str) -> StdResult<Vec<u8>> {
+        let screen = self.desktop.screen_find(name).ok_or_else(|| format!("no output named {name}"))?;
+        let output = self.engine.output_get(screen).ok_or_else(|| format!("no output named {name}"))?;
+        let mode = output.current_mode().ok_or("the output has no mode")?;
+        let scale = output.current_scale().fractional_scale();
+        let size = transform_size(output.current_transform(), mode.size);
+        let offset = self.desktop.screens[screen].center;
+        let windows: Vec<_> = match self.desktop.windows_visible().find(|w| w.fullscreen_on(screen)) {
+            Some(window) => vec![window],
+            None => self.desktop.windows_stacked().collect()
+        };
+        let mut renderer = self.engine.renderer();
+        let renderer = renderer.gles2().ok_or("captures need a GLES2 renderer")?;
+        render_offscreen(renderer, size, |frame| {
+            frame.clear([0.0, 0.0, 0.0, 1.0], &[Rectangle::from_loc_and_size((0, 0), size)])?;
+            for window in windows.iter() {
+                window.render::<Gles2Renderer>(&self.logger, frame, offset, size, scale, 0.0, false)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Render a window on its own to an offscreen buffer, right away, and encode it as PNG.
+    /// It's rendered at the scale of the output it's on.
+    pub fn capture_window (&mut self, id: u64) -> StdResult<Vec<u8>> {
+        let window = self.desktop.windows_all().find(|w| w.id == id).ok_or("no such window")?;
+        let surface = window.window.toplevel().wl_surface().clone();
+        let scale = self.engine.output_get(self.window_screen(&surface))
+            .map(|output| output.current_scale().fractional_scale())
+            .unwrap_or(1.0);
+        let window = self.desktop.window_state(&surface).ok_or("no such window")?;
+        let size = window.window.geometry().size.to_f64().to_physical(scale).to_i32_round();
+        if size.w <= 0 || size.h <= 0 {
+            return Err("the window has no size yet".into())
+        }
+        // Put the window at the origin of the buffer
+        let offset = Point::<f64, Logical>::from((-window.center.x, -window.center.y));
+        let mut renderer = self.engine.renderer();
+        let renderer = renderer.gles2().ok_or("captures need a GLES2 renderer")?;
+        render_offscreen(renderer, size, |frame| {
+            frame.clear([0.0, 0.0, 0.0, 0.0], &[Rectangle::from_loc_and_size((0, 0), size)])?;
+            window.render::<Gles2Renderer>(&self.logger, frame, offset, size, scale, 0.0, false)
+        })
+    }
+
+}
+
+/// Render into a buffer of its own and encode what was rendered as PNG.
+/// This happens between output frames, since it binds the renderer to another target.
+fn render_offscreen (
+    renderer: &mut Gles2Renderer,
+    size:     Size<i32, Physical>,
+    draw:     impl FnOnce(&mut Gles2Frame) -> StdResult<()>
+) -> StdResult<Vec<u8>> {
+    let buffer = Offscreen::<Gles2Texture>::create_buffer(renderer, (size.w, size.h).into())?;
+    renderer.bind(buffer)?;
+    // Flipped like the outputs, so the rows are read back the same way
+    let result = renderer.render(size, Transform::Flipped180).map_err(Into::into)
+        .and_then(|mut frame| {
+            draw(&mut frame)?;
+            let pixels = read_pixels(&mut frame, size)?;
+            frame.finish()?;
+            Ok(pixels)
+        });
+    renderer.unbind()?;
+    encode_png(&result?, size)
+}
@@ -0,0 +1,340 @@
+use super::prelude::*;
+
+use std::{
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    os::{fd::{AsRawFd, FromRawFd, RawFd}, unix::net::UnixStream},
+};
+
+use super::identity::ClientState;
+
+use smithay::{
+    reexports::calloop::{PostAction, Interest, Mode, RegistrationToken, generic::Generic},
+    reexports::wayland_server::{Resource, protocol::wl_data_source::WlDataSource},
+    wayland::data_device::{set_data_device_selection, with_source_metadata},
+};
+
+/// Most bytes moved per transfer in one turn of the main loop
+const CHUNK: usize = 256 * 1024;
+
+/// How long a transfer may go without progress before it's cancelled
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which side of the sandbox a client runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Domain {
+    /// Runs directly on the host
+    Host,
+    /// Runs in a Flatpak or Snap sandbox
+    Sandboxed,
+}
+
+impl std::str::FromStr for Domain {
+    type Err = Box<dyn Error>;
+    fn from_str (domain: &str) -> StdResult<Self> {
+        Ok(match domain {
+            "host"      => Self::Host,
+            "sandboxed" => Self::Sandboxed,
+            _ => return Err(format!("unknown security domain: {domain}").into())
+        })
+    }
+}
+
+/// Whether a MIME type matches a pattern, which may end with `*` to match any suffix.
+pub fn mime_matches (pattern: &str, mime_type: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => mime_type.starts_with(prefix),
+        None => mime_type == pattern
+    }
+}
+
+/// MIME types that aren't passed on when copied by clients of a domain, or of any domain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MimeRule {
+    pub domain:  Option<Domain>,
+    pub pattern: String,
+}
+
+/// The domain of the client that a selection comes from.
+pub fn source_domain (source: &WlDataSource) -> Domain {
+    source.client()
+        .and_then(|client| client.get_data::<ClientState>().map(|state| state.identity.domain()))
+        .unwrap_or(Domain::Host)
+}
+
+/// A pipe that the main loop wakes up for while a transfer can move data through it.
+/// The transfers themselves are moved along at the end of each turn of the loop.
+pub struct Watch {
+    fd:       RawFd,
+    interest: Interest,
+    token:    Option<RegistrationToken>,
+}
+
+impl Watch {
+
+    pub fn new (fd: &impl AsRawFd, interest: Interest) -> Self {
+        Self { fd: fd.as_raw_fd(), interest, token: None }
+    }
+
+    /// Have the main loop wake up when the pipe is ready, or stop it from doing so.
+    /// A pipe must stop being watched before it's closed.
+    pub fn set <E: Engine> (&mut self, handle: &LoopHandle<'static, Charlie<E>>, wanted: bool) {
+        match (wanted, self.token.take()) {
+            (true, None) => {
+                let source = Generic::new(self.fd, self.interest, Mode::Level);
+                self.token = handle.insert_source(source, |_, _, _| Ok(PostAction::Continue)).ok();
+            },
+            (false, Some(token)) => handle.remove(token),
+            (_, token) => self.token = token
+        }
+    }
+
+}
+
+/// Data on its way from a selection to a client that pastes it.
+struct Transfer {
+    /// Where the data comes from; None once it's all been read, or if it was all known upfront
+    from:     Option<UnixStream>,
+    to:       File,
+    /// Wakes the loop when there's data to read
+    readable: Option<Watch>,
+    /// Wakes the loop when the client can take more data
+    writable: Watch,
+    /// Data read but not written yet
+    pending:  Vec<u8>,
+    /// Bytes read so far
+    total:    usize,
+    /// When data last moved
+    progress: Instant,
+}
+
+/// Safeguards on the selection that clients copy and paste between each other.
+///
+/// While any of them are set, the compositor serves client selections itself: it offers
+/// only the allowed MIME types, and copies the data from the source to each client that
+/// pastes, a chunk per turn of the main loop, so that a huge selection can't stall it.
+/// The loop wakes up for a transfer only when its pipes are ready for what it has to do.
+/// Transfers that grow over `max_size` or stop moving are cancelled.
+pub struct Clipboard {
+    logger:       Logger,
+    /// Most bytes a single paste may carry
+    pub max_size: Option<usize>,
+    /// If not empty, only MIME types matching these patterns are offered
+    pub allow:    Vec<String>,
+    /// MIME types that are never offered
+    pub deny:     Vec<MimeRule>,
+    /// The client selection being served in its place
+    source:       Option<WlDataSource>,
+    transfers:    Vec<Transfer>,
+}
+
+impl Clipboard {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:    logger.clone(),
+            max_size:  None,
+            allow:     vec![],
+            deny:      vec![],
+            source:    None,
+            transfers: vec![],
+        }
+    }
+
+    /// Whether any safeguards are set, and so client selections are served by the compositor.
+    pub fn guarded (&self) -> bool {
+        self.max_size.is_some() || !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    /// Whether a MIME type copied by a client of a domain may be offered to others.
+    pub fn allowed (&self, domain: Domain, mime_type: &str) -> bool {
+        let allowed = self.allow.is_empty()
+            || self.allow.iter().any(|pattern| mime_matches(pattern, mime_type));
+        let denied = self.deny.iter().any(|rule|
+            rule.domain.map(|d| d == domain).unwrap_or(true) && mime_matches(&rule.pattern, mime_type)
+        );
+        allowed && !denied
+    }
+
+    /// When the transfer that moved least lately will have stalled, if any are going.
+    pub fn next_wakeup (&self) -> Option<Instant> {
+        self.transfers.iter().map(|transfer| transfer.progress + STALL_TIMEOUT).min()
+    }
+
+    /// Stop all transfers, closing the pipes of the clients that are pasting.
+    pub fn cancel <E: Engine> (&mut self, handle: &LoopHandle<'static, Charlie<E>>) {
+        if !self.transfers.is_empty() {
+            info!(self.logger, "Cancelling {} selection transfers", self.transfers.len());
+            for mut transfer in self.transfers.drain(..) {
+                transfer.watch(handle, false);
+            }
+        }
+    }
+
+    /// Stop serving the client selection, once another one takes its place.
+    pub fn release <E: Engine> (&mut self, handle: &LoopHandle<'static, Charlie<E>>) {
+        self.cancel(handle);
+        self.source = None;
+    }
+
+}
+
+impl Transfer {
+
+    /// Have the loop wake up for the pipes that data can move through next,
+    /// or for none of them if the transfer is over.
+    fn watch <E: Engine> (&mut self, handle: &LoopHandle<'static, Charlie<E>>, going: bool) {
+        let room = self.pending.len() < CHUNK;
+        if let Some(readable) = self.readable.as_mut() {
+            readable.set(handle, going && room && self.from.is_some());
+        }
+        self.writable.set(handle, going && !self.pending.is_empty());
+    }
+
+    /// Move another chunk of data. Returns whether the transfer is still going.
+    fn advance (&mut self, max_size: Option<usize>) -> StdResult<bool> {
+        let mut moved = false;
+        if let Some(from) = self.from.as_mut() {
+            if self.pending.len() < CHUNK {
+                let mut chunk = vec![0; CHUNK - self.pending.len()];
+                match from.read(&mut chunk) {
+                    Ok(0) => {
+                        self.from = None;
+                        moved = true;
+                    },
+                    Ok(read) => {
+                        self.total += read;
+                        self.pending.extend_from_slice(&chunk[..read]);
+                        moved = true;
+                    },
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {},
+                    Err(e) => return Err(e.into())
+                }
+            }
+        }
+        if let Some(max_size) = max_size.filter(|max_size| self.total > *max_size) {
+            return Err(format!("selection is bigger than {max_size} bytes").into())
+        }
+        if !self.pending.is_empty() {
+            match self.to.write(&self.pending[..self.pending.len().min(CHUNK)]) {
+                Ok(written) => {
+                    self.pending.drain(..written);
+                    moved = true;
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e.into())
+            }
+        }
+        if moved {
+            self.progress = Instant::now();
+        } else if self.progress.elapsed() > STALL_TIMEOUT {
+            return Err("selection transfer stalled".into())
+        }
+        Ok(self.from.is_some() || !self.pending.is_empty())
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Cancel pastes bigger than this many bytes.
+    pub fn clipboard_max_size (mut self, bytes: usize) -> CharlieResult<Self> {
+        self.clipboard.max_size = Some(bytes);
+        Ok(self)
+    }
+
+    /// Only offer the MIME types matching this pattern, and the others allowed so far.
+    pub fn clipboard_allow (mut self, pattern: impl Into<String>) -> CharlieResult<Self> {
+        self.clipboard.allow.push(pattern.into());
+        Ok(self)
+    }
+
+    /// Don't offer the MIME types matching this pattern
+    /// when copied by clients of a domain, or of any domain if None.
+    pub fn clipboard_deny (mut self, domain: Option<Domain>, pattern: impl Into<String>) -> CharlieResult<Self> {
+        self.clipboard.deny.push(MimeRule { domain, pattern: pattern.into() });
+        Ok(self)
+    }
+
+    /// Respond to a client setting the selection, serving it in the client's place
+    /// if the selection is guarded. This runs after the data device took the selection.
+    pub fn selection_adopt (&mut self, source: WlDataSource) {
+        if !self.clipboard.guarded() || !source.is_alive() {
+            return
+        }
+        let handle = self.display.borrow().handle();
+        let domain = source_domain(&source);
+        let offered = match with_source_metadata(&source, |metadata| metadata.mime_types.clone()) {
+            Ok(offered) => offered,
+            Err(_) => return
+        };
+        let (allowed, blocked): (Vec<String>, Vec<String>) = offered.into_iter()
+            .partition(|mime_type| self.clipboard.allowed(domain, mime_type));
+        if !blocked.is_empty() {
+            info!(self.logger, "Not offering {blocked:?} copied by a {domain:?} client");
+        }
+        self.clipboard.cancel(&self.handle);
+        self.clipboard.source = Some(source);
+        self.input.selection = None;
+        for seat in self.input.seats.iter() {
+            set_data_device_selection(&handle, seat, allowed.clone());
+        }
+    }
+
+    /// Start sending the selection to a client that asked to paste it: either text that
+    /// the compositor offers, or the data of the client selection that it serves.
+    pub fn selection_transfer (&mut self, mime_type: &str, text: Option<String>, fd: RawFd) {
+        // Take ownership of the pipe so it's closed once the transfer is over
+        let to = unsafe { File::from_raw_fd(fd) };
+        if let Err(e) = nix::fcntl::fcntl(to.as_raw_fd(), nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::O_NONBLOCK)) {
+            warn!(self.logger, "Could not make selection pipe non-blocking: {e}");
+            return
+        }
+        let (from, pending) = match text {
+            Some(text) => (None, text.into_bytes()),
+            None => {
+                let source = match self.clipboard.source.as_ref().filter(|source| source.is_alive()) {
+                    Some(source) => source,
+                    None => return
+                };
+                let (ours, theirs) = match UnixStream::pair() {
+                    Ok(pair) => pair,
+                    Err(e) => {
+                        warn!(self.logger, "Could not create selection pipe: {e}");
+                        return
+                    }
+                };
+                if let Err(e) = ours.set_nonblocking(true) {
+                    warn!(self.logger, "Could not make selection pipe non-blocking: {e}");
+                    return
+                }
+                // The source gets its own copy of the other end when the event is sent
+                source.send(mime_type.into(), theirs.as_raw_fd());
+                (Some(ours), vec![])
+            }
+        };
+        let total = pending.len();
+        let readable = from.as_ref().map(|from| Watch::new(from, Interest::READ));
+        let writable = Watch::new(&to, Interest::WRITE);
+        let mut transfer = Transfer { from, to, readable, writable, pending, total, progress: Instant::now() };
+        transfer.watch(&self.handle, true);
+        self.clipboard.transfers.push(transfer);
+    }
+
+    /// Move the selection transfers along, dropping the ones that are done or went wrong.
+    pub fn selection_flush (&mut self) {
+        let (logger, max_size, handle) = (&self.clipboard.logger, self.clipboard.max_size, &self.handle);
+        self.clipboard.transfers.retain_mut(|transfer| {
+            let going = match transfer.advance(max_size) {
+                Ok(going) => going,
+                Err(e) => {
+                    warn!(logger, "Cancelled selection transfer: {e}");
+                    false
+                }
+            };
+            transfer.watch(handle, going);
+            going
+        });
+    }
+
+}
@@ -0,0 +1,118 @@
+use super::prelude::*;
+
+use smithay::backend::allocator::Fourcc;
+
+/// How many bits each color channel of an output's framebuffer has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    Eight,
+    Ten,
+}
+
+impl ColorDepth {
+
+    pub fn bits (&self) -> u32 {
+        match self {
+            Self::Eight => 8,
+            Self::Ten   => 10,
+        }
+    }
+
+    /// The pixel format of framebuffers with this depth.
+    pub fn format (&self) -> Fourcc {
+        match self {
+            Self::Eight => Fourcc::Xrgb8888,
+            Self::Ten   => Fourcc::Xrgb2101010,
+        }
+    }
+
+    /// The depth of framebuffers with this pixel format.
+    pub fn of (format: Fourcc) -> Self {
+        match format {
+            Fourcc::Xrgb2101010 | Fourcc::Argb2101010 | Fourcc::Xbgr2101010 | Fourcc::Abgr2101010 => Self::Ten,
+            _ => Self::Eight
+        }
+    }
+
+}
+
+impl std::str::FromStr for ColorDepth {
+    type Err = Box<dyn Error>;
+    fn from_str (depth: &str) -> StdResult<Self> {
+        Ok(match depth {
+            "8"  => Self::Eight,
+            "10" => Self::Ten,
+            _ => return Err(format!("unsupported color depth: {depth}").into())
+        })
+    }
+}
+
+/// The pixel format an output is rendered in, kept with the output
+/// so that everything that draws to it can find out.
+struct OutputFormat(Cell<Fourcc>);
+
+/// The pixel format an output is rendered in. Outputs whose engine didn't say are 8-bit.
+pub fn output_format (output: &Output) -> Fourcc {
+    output.user_data().get::<OutputFormat>().map(|format| format.0.get()).unwrap_or(Fourcc::Xrgb8888)
+}
+
+/// Record the pixel format an output is rendered in. Engines call this when they create
+/// an output's framebuffers, or change their format.
+pub fn output_format_set (output: &Output, format: Fourcc) {
+    output.user_data().insert_if_missing(|| OutputFormat(Cell::new(format)));
+    if let Some(current) = output.user_data().get::<OutputFormat>() {
+        current.0.set(format);
+    }
+}
+
+/// The color depth that outputs are configured to be rendered in.
+///
+/// Engines that can't render at a depth leave their outputs as they are. Deeper framebuffers
+/// are the groundwork for color management: the pixel format of each output travels with it.
+pub struct ColorDepths {
+    logger:         Logger,
+    /// Requested depths, by output name; the rest are 8-bit
+    pub requested:  HashMap<String, ColorDepth>,
+}
+
+impl ColorDepths {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), requested: HashMap::new() }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Render the named output with this many bits per channel, if its engine can.
+    pub fn output_depth (mut self, name: &str, depth: ColorDepth) -> CharlieResult<Self> {
+        self.output_depth_set(name, depth);
+        Ok(self)
+    }
+
+    /// Change the color depth of the named output, now if it exists or once it's added.
+    pub fn output_depth_set (&mut self, name: &str, depth: ColorDepth) {
+        self.depths.requested.insert(name.into(), depth);
+        if let Some(screen) = self.desktop.screen_find(name) {
+            self.output_depth_apply(screen);
+        }
+    }
+
+    /// Switch the output of a screen to its configured color depth, if it isn't at it.
+    pub fn output_depth_apply (&mut self, screen: ScreenId) {
+        let output = match self.engine.output_get(screen) {
+            Some(output) => output,
+            None => return
+        };
+        let depth = self.depths.requested.get(&output.name()).copied().unwrap_or(ColorDepth::Eight);
+        if ColorDepth::of(output_format(&output)) == depth {
+            return
+        }
+        match self.engine.output_set_format(screen, depth.format()) {
+            Ok(()) => info!(self.depths.logger, "Rendering {} at {} bits per channel", output.name(), depth.bits()),
+            Err(e) => warn!(self.depths.logger, "Could not render {} at {} bits per channel: {e}", output.name(), depth.bits())
+        }
+    }
+
+}
@@ -0,0 +1,248 @@
+use super::prelude::*;
+
+use std::collections::VecDeque;
+
+use smithay::reexports::wayland_server::Resource;
+
+/// How many commands may be performed in one turn, in case commands keep queueing others
+const MAX_COMMANDS: usize = 1024;
+
+/// Something that a protocol handler wants done to the state of the compositor.
+///
+/// Handlers run in the middle of client dispatch, often from within closures that hold
+/// on to the state of a surface or a seat. Whatever would reach back into the rest of the
+/// compositor from there is queued as a command instead, and done once per turn of the
+/// main loop, when nothing else is borrowed.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// A window was shown for the first time: place it, and let scripts know
+    MapWindow(WlSurface),
+    /// Give keyboard focus to a window, or to nothing
+    Focus(Option<WlSurface>),
+    /// Send a window its pending state
+    Configure(ToplevelSurface),
+}
+
+impl Command {
+
+    /// Whether the window the command is about is still there.
+    fn alive (&self) -> bool {
+        match self {
+            Self::MapWindow(surface)  => surface.alive(),
+            Self::Focus(surface)      => surface.as_ref().map(|surface| surface.alive()).unwrap_or(true),
+            Self::Configure(toplevel) => toplevel.alive(),
+        }
+    }
+
+}
+
+/// Commands waiting for the next turn of the main loop.
+pub struct Commands {
+    logger: Logger,
+    queue:  VecDeque<Command>,
+}
+
+impl Commands {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), queue: VecDeque::new() }
+    }
+
+    /// Queue a command for the next turn of the main loop.
+    pub fn push (&mut self, command: Command) {
+        self.queue.push_back(command);
+    }
+
+    /// The command that was queued first, skipping the ones
+    /// whose window went away while they were queued.
+    fn pop (&mut self) -> Option<Command> {
+        while let Some(command) = self.queue.pop_front() {
+            if command.alive() {
+                return Some(command)
+            }
+            debug!(self.logger, "Dropping {command:?}, its window is gone");
+        }
+        None
+    }
+
+}
+
+/// Perform the queued commands in the order they were queued, followed by the ones that
+/// they queue, up to [MAX_COMMANDS] of them.
+fn flush <T> (state: &mut T, commands: fn (&mut T) -> &mut Commands, mut perform: impl FnMut(&mut T, Command)) {
+    for _ in 0..MAX_COMMANDS {
+        match commands(state).pop() {
+            Some(command) => perform(state, command),
+            None => return
+        }
+    }
+    let commands = commands(state);
+    warn!(commands.logger, "Leaving {} commands for the next turn", commands.queue.len());
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Perform the queued commands, including the ones that they queue.
+    pub fn commands_flush (&mut self) {
+        flush(self, |state| &mut state.commands, Self::command)
+    }
+
+    fn command (&mut self, command: Command) {
+        match command {
+            Command::MapWindow(surface) => self.window_mapped(&surface),
+            Command::Focus(surface) => self.focus(surface),
+            Command::Configure(toplevel) => toplevel.send_configure(),
+        }
+    }
+
+    /// Place a newly shown window where it was before, and let scripts know about it.
+    fn window_mapped (&mut self, surface: &WlSurface) {
+        let parent = match self.desktop.window_state(surface) {
+            Some(window) => window.parent_surface(),
+            None => return
+        };
+        // A kiosk shows nothing but its application
+        if !self.kiosk_filter(surface) {
+            return
+        }
+        // Dialogs show up over their parents, rather than where they were last seen
+        if parent.is_some() {
+            self.window_place(surface);
+            self.desktop.raise(surface);
+        } else if self.kiosk_place(surface) {
+            // A kiosk shows its one application fullscreen
+        } else if let Some(screen) = self.companion_screen(surface) {
+            // Companions of an output show up on it
+            self.window_move_to_screen(surface, screen);
+        } else if self.swallow(surface) {
+            // Windows started from a terminal take its place
+        } else if !self.session_place(surface) && !self.geometry_restore(surface) {
+            self.window_place(surface);
+        }
+        let window = self.script_window(surface);
+        self.scripts.hook("on_window_mapped", vec![window]);
+        self.redraw();
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::net::UnixStream;
+
+    use smithay::reexports::wayland_server::{
+        Client, DataInit, Dispatch,
+        backend::{ClientData, protocol::ProtocolError},
+        protocol::wl_surface,
+    };
+
+    /// Stands in for the compositor, recording the commands it performs.
+    struct Test {
+        commands:  Commands,
+        performed: Vec<String>,
+    }
+
+    impl Dispatch<WlSurface, ()> for Test {
+        fn request (
+            _: &mut Self, _: &Client, _: &WlSurface, _: wl_surface::Request, _: &(), _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>
+        ) {}
+    }
+
+    struct NoData;
+
+    impl ClientData for NoData {}
+
+    fn test () -> Test {
+        let logger = Logger::root(slog::Discard, o!());
+        Test { commands: Commands::new(&logger), performed: vec![] }
+    }
+
+    /// A connected client, and the other end of its connection, which keeps it connected.
+    fn client (display: &mut Display<Test>) -> (Client, UnixStream) {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        (display.handle().insert_client(ours, Arc::new(NoData)).unwrap(), theirs)
+    }
+
+    fn surface (display: &mut Display<Test>, client: &Client) -> WlSurface {
+        client.create_resource::<WlSurface, (), Test>(&display.handle(), 5, ()).unwrap()
+    }
+
+    fn name (command: &Command) -> String {
+        match command {
+            Command::MapWindow(surface)   => format!("map {}", surface.id().protocol_id()),
+            Command::Focus(Some(surface)) => format!("focus {}", surface.id().protocol_id()),
+            Command::Focus(None)          => "unfocus".into(),
+            Command::Configure(_)         => "configure".into(),
+        }
+    }
+
+    fn perform (test: &mut Test, command: Command) {
+        test.performed.push(name(&command));
+    }
+
+    #[test]
+    fn flush_in_order () {
+        let mut display = Display::<Test>::new().unwrap();
+        let (client, _stream) = client(&mut display);
+        let (first, second) = (surface(&mut display, &client), surface(&mut display, &client));
+        let mut test = test();
+        test.commands.push(Command::MapWindow(first.clone()));
+        test.commands.push(Command::Focus(None));
+        test.commands.push(Command::MapWindow(second.clone()));
+        // Mapping focuses, after the commands that were already queued
+        flush(&mut test, |test| &mut test.commands, |test, command| {
+            if let Command::MapWindow(surface) = &command {
+                test.commands.push(Command::Focus(Some(surface.clone())));
+            }
+            perform(test, command);
+        });
+        assert_eq!(test.performed, vec![
+            name(&Command::MapWindow(first.clone())),
+            name(&Command::Focus(None)),
+            name(&Command::MapWindow(second.clone())),
+            name(&Command::Focus(Some(first))),
+            name(&Command::Focus(Some(second))),
+        ]);
+        assert!(test.commands.pop().is_none());
+    }
+
+    #[test]
+    fn flush_skips_dead_surfaces () {
+        let mut display = Display::<Test>::new().unwrap();
+        let (alive, _stream) = client(&mut display);
+        let (dead, _) = client(&mut display);
+        let (kept, gone) = (surface(&mut display, &alive), surface(&mut display, &dead));
+        let mut test = test();
+        test.commands.push(Command::MapWindow(gone.clone()));
+        test.commands.push(Command::Focus(Some(gone.clone())));
+        test.commands.push(Command::MapWindow(kept.clone()));
+        test.commands.push(Command::Focus(None));
+        dead.kill(&display.handle(), ProtocolError {
+            code:             0,
+            object_id:        0,
+            object_interface: "wl_display".into(),
+            message:          "gone".into(),
+        });
+        display.dispatch_clients(&mut test).unwrap();
+        display.flush_clients().unwrap();
+        assert!(!gone.alive());
+        flush(&mut test, |test| &mut test.commands, perform);
+        assert_eq!(test.performed, vec![name(&Command::MapWindow(kept)), name(&Command::Focus(None))]);
+    }
+
+    #[test]
+    fn flush_at_most () {
+        let mut test = test();
+        test.commands.push(Command::Focus(None));
+        // A command that keeps queueing another is cut off, and the rest left for the next turn
+        flush(&mut test, |test| &mut test.commands, |test, command| {
+            test.commands.push(command.clone());
+            perform(test, command);
+        });
+        assert_eq!(test.performed.len(), MAX_COMMANDS);
+        assert_eq!(test.commands.queue.len(), 1);
+    }
+}
@@ -0,0 +1,59 @@
+use super::prelude::*;
+
+use super::process::{Startup, Restart};
+
+/// Environment variable that tells a companion process which output it runs for
+pub const COMPANION_OUTPUT_ENV: &str = "CHARLIE_OUTPUT";
+
+impl<E: Engine> Charlie<E> {
+
+    /// Run a command for an output while it's connected, e.g. a bar on one output only,
+    /// restarting it if it crashes. The command finds the name of its output in `CHARLIE_OUTPUT`,
+    /// and its windows are put on that output.
+    pub fn output_companion (mut self, output: impl Into<String>, cmd: impl AsRef<str>, args: &[&str])
+        -> CharlieResult<Self>
+    {
+        let output = output.into();
+        let mut entry = Startup::new(cmd, args, Restart::OnCrash);
+        entry.env.push((COMPANION_OUTPUT_ENV.into(), output.clone()));
+        entry.output = Some(output);
+        self.processes.companions.push(entry);
+        Ok(self)
+    }
+
+    /// Start the companions of an output that's been connected,
+    /// unless they're running already or about to be respawned.
+    pub fn companions_start (&mut self, output: &str) {
+        if !self.processes.started {
+            return
+        }
+        for entry in self.processes.companions_due(output) {
+            info!(self.logger, "Starting {} for {output}", entry.cmd);
+            if let Err(e) = self.processes.spawn(entry) {
+                warn!(self.logger, "Could not start companion of {output}: {e}");
+            }
+        }
+    }
+
+    /// Stop the companions of an output that went away, and don't respawn the ones that crashed.
+    pub fn companions_stop (&mut self, output: &str) {
+        for token in self.processes.companions_cancel(output) {
+            self.handle.remove(token);
+        }
+        let pids: Vec<u32> = self.processes.running.iter()
+            .filter(|(_, process)| process.entry.output.as_deref() == Some(output))
+            .map(|(pid, _)| *pid)
+            .collect();
+        for pid in pids {
+            self.processes.terminate(pid);
+        }
+    }
+
+    /// The screen whose output the client of a window accompanies, if any.
+    pub fn companion_screen (&self, surface: &WlSurface) -> Option<ScreenId> {
+        let pid = self.desktop.window_state(surface)?.identity()?.pid?;
+        let output = self.processes.running.get(&(pid as u32))?.entry.output.as_deref()?;
+        self.desktop.screen_find(output)
+    }
+
+}
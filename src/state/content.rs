@@ -0,0 +1,201 @@
+use super::prelude::*;
+
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum,
+    backend::{ClientId, GlobalId, ObjectId},
+};
+
+use wayland_protocols::wp::content_type::v1::server::{
+    wp_content_type_manager_v1::{self, WpContentTypeManagerV1},
+    wp_content_type_v1::{self, WpContentTypeV1, Type},
+};
+
+/// What a client says that a surface shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContentType {
+    #[default]
+    None,
+    Photo,
+    Video,
+    Game,
+}
+
+impl ContentType {
+
+    pub fn name (&self) -> &'static str {
+        match self {
+            Self::None  => "none",
+            Self::Photo => "photo",
+            Self::Video => "video",
+            Self::Game  => "game",
+        }
+    }
+
+}
+
+impl std::str::FromStr for ContentType {
+    type Err = Box<dyn Error>;
+    fn from_str (content_type: &str) -> StdResult<Self> {
+        Ok(match content_type {
+            "none"  => Self::None,
+            "photo" => Self::Photo,
+            "video" => Self::Video,
+            "game"  => Self::Game,
+            _ => return Err(format!("unknown content type: {content_type}").into())
+        })
+    }
+}
+
+/// The content type of a surface, kept with the surface.
+#[derive(Default)]
+struct SurfaceContentType {
+    /// Whether the surface has a content type object
+    taken:   Cell<bool>,
+    /// Set by the client, and taken into use on the next commit
+    pending: Cell<ContentType>,
+}
+
+/// Lets clients say what their surfaces show (`wp_content_type_v1`).
+///
+/// Windows showing video are offered scanout formats, so they can go on overlay planes;
+/// fullscreen games skip the effects pass; and window rules can match the content type,
+/// e.g. to limit the frame rate of videos.
+pub struct ContentTypeState {
+    logger: Logger,
+    global: GlobalId,
+}
+
+impl ContentTypeState {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle) -> Self {
+        Self {
+            logger: logger.clone(),
+            global: display.create_global::<Charlie<E>, WpContentTypeManagerV1, _>(1, ()),
+        }
+    }
+
+    /// Stop advertising the protocol.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.global.clone());
+    }
+
+}
+
+impl<E: Engine> GlobalDispatch<WpContentTypeManagerV1, ()> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<WpContentTypeManagerV1>,
+        _data:     &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<E: Engine> Dispatch<WpContentTypeManagerV1, ()> for Charlie<E> {
+    fn request (
+        _state:    &mut Self,
+        _client:   &Client,
+        resource:  &WpContentTypeManagerV1,
+        request:   wp_content_type_manager_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_content_type_manager_v1::Request::GetSurfaceContentType { id, surface } => {
+                let taken = with_states(&surface, |states| {
+                    states.data_map.insert_if_missing(SurfaceContentType::default);
+                    states.data_map.get::<SurfaceContentType>().unwrap().taken.replace(true)
+                });
+                if taken {
+                    resource.post_error(
+                        wp_content_type_manager_v1::Error::AlreadyConstructed,
+                        "surface already has a content type object"
+                    );
+                    return
+                }
+                data_init.init(id, surface);
+            },
+            wp_content_type_manager_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<WpContentTypeV1, WlSurface> for Charlie<E> {
+    fn request (
+        _state:     &mut Self,
+        _client:    &Client,
+        _resource:  &WpContentTypeV1,
+        request:    wp_content_type_v1::Request,
+        surface:    &WlSurface,
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_content_type_v1::Request::SetContentType { content_type } => {
+                let content_type = match content_type {
+                    WEnum::Value(Type::Photo) => ContentType::Photo,
+                    WEnum::Value(Type::Video) => ContentType::Video,
+                    WEnum::Value(Type::Game)  => ContentType::Game,
+                    _ => ContentType::None
+                };
+                set_pending(surface, content_type);
+            },
+            wp_content_type_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+    fn destroyed (
+        _state:  &mut Self,
+        _client: ClientId,
+        _object: ObjectId,
+        surface: &WlSurface,
+    ) {
+        if surface.is_alive() {
+            with_states(surface, |states| {
+                if let Some(data) = states.data_map.get::<SurfaceContentType>() {
+                    data.taken.set(false);
+                    data.pending.set(ContentType::None);
+                }
+            });
+        }
+    }
+}
+
+/// Set the content type that a surface will have after its next commit.
+fn set_pending (surface: &WlSurface, content_type: ContentType) {
+    with_states(surface, |states| {
+        if let Some(data) = states.data_map.get::<SurfaceContentType>() {
+            data.pending.set(content_type);
+        }
+    });
+}
+
+/// The content type that a surface has been given, as of its last commit.
+fn committed (surface: &WlSurface) -> ContentType {
+    with_states(surface, |states| states.data_map.get::<SurfaceContentType>()
+        .map(|data| data.pending.get())
+        .unwrap_or_default())
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Take the content type of a window into use once its surface is committed,
+    /// updating what depends on it.
+    pub fn content_type_commit (&mut self, surface: &WlSurface) {
+        let content_type = committed(surface);
+        let window = match self.desktop.window_state_mut(surface) {
+            Some(window) if window.content_type != content_type => window,
+            _ => return
+        };
+        debug!(self.content_type.logger, "Window {} shows {}", window.id, content_type.name());
+        window.content_type  = content_type;
+        window.rules_applied = false;
+        self.dmabuf_feedback_update(surface);
+    }
+
+}
@@ -0,0 +1,173 @@
+use super::prelude::*;
+
+use super::security::{Gate, Protocol, ClientMatch};
+use super::identity::ClientState;
+use super::ipc::ipc_split;
+
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource,
+    backend::GlobalId,
+};
+
+use self::protocol::{
+    charlie_control_v1::{self, CharlieControlV1},
+    charlie_control_state_v1::CharlieControlStateV1,
+    charlie_control_result_v1::CharlieControlResultV1,
+};
+
+/// Code generated from `protocols/charlie-control-v1.xml`.
+pub mod protocol {
+    #![allow(non_upper_case_globals, non_camel_case_types, unused)]
+    use smithay::reexports::wayland_server;
+    pub mod __interfaces {
+        wayland_scanner::generate_interfaces!("protocols/charlie-control-v1.xml");
+    }
+    use self::__interfaces::*;
+    wayland_scanner::generate_server_code!("protocols/charlie-control-v1.xml");
+}
+
+/// Lets trusted clients inspect and command the compositor over Wayland
+/// (`charlie_control_v1`), as an alternative to the IPC socket.
+///
+/// Which clients are trusted is up to the [Protocol::Control] policy.
+pub struct ControlState {
+    logger: Logger,
+    global: GlobalId,
+}
+
+impl ControlState {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle, gate: Gate) -> Self {
+        Self {
+            logger: logger.clone(),
+            global: display.create_global::<Charlie<E>, CharlieControlV1, _>(1, gate),
+        }
+    }
+
+    /// Stop advertising the protocol.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.global.clone());
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Allow the executable with this file name to use the control protocol.
+    pub fn control_client (mut self, name: impl Into<String>) -> CharlieResult<Self> {
+        self.security.allow(Protocol::Control, ClientMatch::Executable(name.into()));
+        Ok(self)
+    }
+
+    /// Send a snapshot of the outputs, workspaces and windows.
+    fn control_state (&self, state: &CharlieControlStateV1) {
+        for (screen, _) in self.desktop.screens.iter().enumerate() {
+            let (output, area) = match (self.engine.output_get(screen), self.screen_area(screen)) {
+                (Some(output), Some(area)) => (output, area),
+                _ => continue
+            };
+            let scale = output.current_scale().fractional_scale();
+            state.output(screen as u32, output.name(), area.w as i32, area.h as i32, scale);
+        }
+        let current = self.desktop.workspaces.current;
+        for (index, workspace) in self.desktop.workspaces.list.iter().enumerate() {
+            state.workspace(index as u32, workspace.name.clone(), (index == current) as u32);
+        }
+        for window in self.desktop.windows_all() {
+            let (app_id, title) = window.app_id_and_title();
+            state.window(window.id as u32, window.workspace as u32, app_id, title);
+        }
+        state.done();
+    }
+
+}
+
+impl<E: Engine> GlobalDispatch<CharlieControlV1, Gate> for Charlie<E> {
+    fn bind (
+        state:     &mut Self,
+        _display:  &DisplayHandle,
+        client:    &Client,
+        resource:  New<CharlieControlV1>,
+        gate:      &Gate,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if gate.admits(client) {
+            data_init.init(resource, ());
+        } else {
+            warn!(state.control.logger, "Refused control protocol to client {:?}", client.id());
+            data_init.post_error(
+                resource,
+                charlie_control_v1::Error::Unauthorized,
+                "not a trusted control client"
+            );
+        }
+    }
+
+    fn can_view (client: Client, gate: &Gate) -> bool {
+        gate.admits(&client)
+    }
+}
+
+impl<E: Engine> Dispatch<CharlieControlV1, ()> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        client:    &Client,
+        _resource: &CharlieControlV1,
+        request:   charlie_control_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            charlie_control_v1::Request::GetState { id } => {
+                let snapshot = data_init.init(id, ());
+                state.control_state(&snapshot);
+            },
+            charlie_control_v1::Request::Command { id, command } => {
+                let result = data_init.init(id, ());
+                let identity = client.get_data::<ClientState>()
+                    .map(|client| client.identity.clone())
+                    .unwrap_or_default();
+                let reply = ipc_split(&command).and_then(|words| {
+                    let args: Vec<&str> = words.iter().map(String::as_str).collect();
+                    state.ipc_command_for(&identity, &args)
+                });
+                match reply {
+                    Ok(reply) => result.success(reply),
+                    Err(e) => {
+                        debug!(state.logger, "Control command {command} failed: {e}");
+                        result.failure(e.to_string())
+                    }
+                }
+            },
+            charlie_control_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+/// Snapshots have no requests; they're destroyed by their `done` event.
+impl<E: Engine> Dispatch<CharlieControlStateV1, ()> for Charlie<E> {
+    fn request (
+        _state:     &mut Self,
+        _client:    &Client,
+        _resource:  &CharlieControlStateV1,
+        _request:   <CharlieControlStateV1 as Resource>::Request,
+        _data:      &(),
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {}
+}
+
+/// Results have no requests; they're destroyed by their `success` or `failure` event.
+impl<E: Engine> Dispatch<CharlieControlResultV1, ()> for Charlie<E> {
+    fn request (
+        _state:     &mut Self,
+        _client:    &Client,
+        _resource:  &CharlieControlResultV1,
+        _request:   <CharlieControlResultV1 as Resource>::Request,
+        _data:      &(),
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {}
+}
@@ -0,0 +1,272 @@
+use super::prelude::*;
+
+use std::collections::VecDeque;
+
+use smithay::{
+    backend::renderer::utils::RendererSurfaceStateUserData,
+    reexports::wayland_server::backend::{ClientId, DisconnectReason},
+};
+
+use super::identity::Identity;
+use super::memory::{MemoryUsage, surface_memory};
+use super::surface::with_surface;
+
+/// Reports kept for listing over IPC, newest replacing oldest
+const MAX_REPORTS: usize = 16;
+
+/// Requests of a crashed client quoted in its report, from the protocol log
+const LAST_REQUESTS: usize = 20;
+
+/// Where the kernel is told what to do with core dumps
+const CORE_PATTERN: &str = "/proc/sys/kernel/core_pattern";
+
+/// Color of the placeholder left where a window of a crashed client was
+const GHOST_COLOR: [f32; 4] = [0.25, 0.25, 0.25, 1.0];
+
+/// A client that went away, as seen from its client data.
+#[derive(Debug, Clone)]
+pub struct Disconnect {
+    pub client:   ClientId,
+    pub identity: Identity,
+    /// The protocol error it was disconnected for, if it was
+    pub error:    Option<String>,
+}
+
+impl Disconnect {
+
+    pub fn new (client: ClientId, identity: Identity, reason: DisconnectReason) -> Self {
+        let error = match reason {
+            DisconnectReason::ConnectionClosed => None,
+            DisconnectReason::ProtocolError(error) => Some(error.to_string())
+        };
+        Self { client, identity, error }
+    }
+
+}
+
+/// Clients that went away since the last turn of the main loop.
+pub type Disconnects = Arc<Mutex<Vec<Disconnect>>>;
+
+/// A window that was torn down because its client went away without closing it.
+#[derive(Debug, Clone)]
+struct Orphan {
+    client:    ClientId,
+    id:        u64,
+    app_id:    Option<String>,
+    title:     Option<String>,
+    workspace: String,
+    center:    Point<f64, Logical>,
+    size:      Size<i32, Logical>,
+    /// What its buffers and textures took before they were released
+    memory:    MemoryUsage,
+}
+
+/// Where a window of a crashed client was, shown for a while after it's gone.
+#[derive(Debug, Clone, Copy)]
+pub struct Ghost {
+    pub center:    Point<f64, Logical>,
+    pub size:      Size<i32, Logical>,
+    pub workspace: usize,
+    pub until:     Instant,
+}
+
+/// Notices clients that go away unexpectedly, i.e. that are disconnected for a protocol error
+/// or whose connection closes while they still have windows, and reports what they left behind:
+/// their windows, their last requests if the protocol log is on, the memory their buffers took,
+/// and where to find the core dump.
+pub struct Crashes {
+    logger:           Logger,
+    /// How long to show where the windows of a crashed client were, if at all
+    pub ghost_time:   Option<Duration>,
+    /// Shared with the data of each client, which is told when it disconnects
+    pub disconnects:  Disconnects,
+    /// Windows torn down with their clients, until the disconnect is handled
+    orphans:          Vec<Orphan>,
+    pub reports:      VecDeque<String>,
+}
+
+impl Crashes {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:      logger.clone(),
+            ghost_time:  None,
+            disconnects: Arc::new(Mutex::new(vec![])),
+            orphans:     vec![],
+            reports:     VecDeque::new(),
+        }
+    }
+
+}
+
+/// Where the core dump of a process goes, according to the kernel's core pattern.
+fn core_location (pid: Option<i32>) -> String {
+    let pattern = std::fs::read_to_string(CORE_PATTERN).unwrap_or_default();
+    let pattern = pattern.trim();
+    let pid = pid.map(|pid| pid.to_string());
+    if pattern.is_empty() {
+        "core dumps are off".into()
+    } else if pattern.contains("systemd-coredump") {
+        format!("see coredumpctl info {}", pid.unwrap_or_default())
+    } else if let Some(handler) = pattern.strip_prefix('|') {
+        format!("core dumps are piped to {}", handler.split_whitespace().next().unwrap_or(handler))
+    } else {
+        format!("core dumps are written to {}", match pid {
+            Some(pid) => pattern.replace("%p", &pid),
+            None => pattern.into()
+        })
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Show where the windows of a crashed client were for this long, or not at all if None.
+    pub fn crash_ghosts (mut self, time: Option<Duration>) -> CharlieResult<Self> {
+        self.crashes.ghost_time = time;
+        Ok(self)
+    }
+
+    /// Forget a window whose toplevel was destroyed, releasing its buffer and textures.
+    /// Windows torn down because their client went away are kept for its crash report.
+    pub fn window_destroyed (&mut self, surface: &WlSurface) {
+        // Objects destroyed while their client is cleaned up no longer have one
+        let orphaned = surface.client().is_none();
+        let window = match self.desktop.window_remove(surface) {
+            Some(window) => window,
+            None => return
+        };
+        let memory = surface_memory(surface);
+        let _ = with_surface(surface, |surface_data| {
+            if let Some(data) = surface_data.data_map.get::<RendererSurfaceStateUserData>() {
+                let mut data = data.borrow_mut();
+                data.textures.clear();
+                if let Some(buffer) = data.buffer.take() {
+                    buffer.release()
+                }
+            }
+        });
+        self.desktop.textures.prune();
+        if let (true, Some(client)) = (orphaned, window.client.clone()) {
+            let (app_id, title) = window.app_id_and_title();
+            self.crashes.orphans.push(Orphan {
+                client,
+                id:        window.id,
+                app_id,
+                title,
+                workspace: self.desktop.workspaces.list.get(window.workspace)
+                    .map(|workspace| workspace.name.clone()).unwrap_or_default(),
+                center:    window.center,
+                size:      window.window.geometry().size,
+                memory,
+            });
+            if let Some(time) = self.crashes.ghost_time {
+                self.desktop.ghosts.push(Ghost {
+                    center:    window.center,
+                    size:      window.window.geometry().size,
+                    workspace: window.workspace,
+                    until:     Instant::now() + time,
+                });
+            }
+        }
+        let terminal = self.unswallow(&window);
+        if self.desktop.focused.as_ref() == Some(surface) {
+            let next = terminal.or_else(|| self.desktop.windows_visible().next()
+                .map(|w| w.window.toplevel().wl_surface().clone()));
+            self.focus(next);
+        }
+        self.redraw.request();
+    }
+
+    /// Report the clients that went away unexpectedly since the last turn,
+    /// and stop showing the ghosts of windows that have been shown for long enough.
+    pub fn crashes_flush (&mut self) {
+        let disconnects = std::mem::take(&mut *self.crashes.disconnects.lock().unwrap());
+        for disconnect in disconnects {
+            let orphans: Vec<Orphan> = self.crashes.orphans.iter()
+                .filter(|orphan| orphan.client == disconnect.client)
+                .cloned()
+                .collect();
+            self.crashes.orphans.retain(|orphan| orphan.client != disconnect.client);
+            if disconnect.error.is_none() && orphans.is_empty() {
+                continue
+            }
+            let report = self.crash_report(&disconnect, &orphans);
+            warn!(self.crashes.logger, "{report}");
+            if self.crashes.reports.len() >= MAX_REPORTS {
+                self.crashes.reports.pop_front();
+            }
+            self.crashes.reports.push_back(report);
+            let pid = disconnect.identity.pid.map(|pid| pid.to_string()).unwrap_or_else(|| "?".into());
+            self.ipc_event(&format!("client crashed {pid}"));
+        }
+        let now = Instant::now();
+        let before = self.desktop.ghosts.len();
+        self.desktop.ghosts.retain(|ghost| ghost.until > now);
+        if self.desktop.ghosts.len() != before {
+            self.redraw.request();
+        }
+        if let Some(until) = self.desktop.ghosts.iter().map(|ghost| ghost.until).min() {
+            self.redraw.request_at(until);
+        }
+    }
+
+    fn crash_report (&self, disconnect: &Disconnect, orphans: &[Orphan]) -> String {
+        let mut lines = vec![format!(
+            "Client went away {}: {}",
+            match &disconnect.error {
+                Some(error) => format!("for a protocol error ({error})"),
+                None => "with its windows open".into()
+            },
+            disconnect.identity.describe()
+        )];
+        for orphan in orphans.iter() {
+            lines.push(format!(
+                "  window {} app_id {} title {:?} on workspace {} at {},{} {}x{}, shm {} KiB, textures {} KiB",
+                orphan.id, orphan.app_id.as_deref().unwrap_or("-"), orphan.title.as_deref().unwrap_or(""),
+                orphan.workspace, orphan.center.x, orphan.center.y, orphan.size.w, orphan.size.h,
+                orphan.memory.shm / 1024, orphan.memory.textures / 1024
+            ));
+        }
+        let requests = match disconnect.identity.pid {
+            Some(pid) if self.protocols.enabled => self.protocols.report(Some(pid)),
+            _ => String::new()
+        };
+        let requests: Vec<&str> = requests.lines().collect();
+        if requests.is_empty() {
+            lines.push("  no requests recorded; start with --protocol-log to record them".into());
+        } else {
+            lines.push("  last requests:".into());
+            lines.extend(requests[requests.len().saturating_sub(LAST_REQUESTS)..].iter()
+                .map(|request| format!("    {request}")));
+        }
+        lines.push(format!("  {}", core_location(disconnect.identity.pid)));
+        lines.join("\n")
+    }
+
+}
+
+impl<R: EngineRenderer> super::desktop::Desktop<R> {
+
+    /// Show where the windows of crashed clients were on the current workspace.
+    pub fn render_ghosts (&self, frame: &mut R::Frame<'_>, screen: &ScreenState, scale: f64)
+        -> StdResult<()>
+    {
+        for ghost in self.ghosts.iter().filter(|ghost| ghost.workspace == self.workspaces.current) {
+            let geometry = Rectangle::<i32, Logical>::from_loc_and_size(
+                Point::<f64, Logical>::from((ghost.center.x + screen.center.x, ghost.center.y + screen.center.y))
+                    .to_i32_round(),
+                ghost.size
+            );
+            let area = Rectangle::<i32, Physical>::from_loc_and_size(
+                geometry.loc.to_f64().to_physical(scale).to_i32_round(),
+                geometry.size.to_f64().to_physical(scale).to_i32_round()
+            );
+            frame.clear(GHOST_COLOR, &[area])?;
+            if let Some(border) = self.decorations.border {
+                self.decorations.render_border(frame, geometry, scale, border.urgent)?;
+            }
+        }
+        Ok(())
+    }
+
+}
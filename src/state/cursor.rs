@@ -0,0 +1,244 @@
+use super::prelude::*;
+
+use smithay::backend::renderer::ImportMem;
+use smithay::reexports::wayland_server::backend::ClientId;
+
+use xcursor::{CursorTheme, parser::{parse_xcursor, Image}};
+
+/// Default cursor size at scale 1, if XCURSOR_SIZE is not set
+const DEFAULT_SIZE: u32 = 24;
+
+/// Name of the cursor shown when no other shape was asked for
+const DEFAULT_SHAPE: &str = "default";
+
+/// Older names that themes may have instead of the CSS name of a shape.
+const LEGACY_NAMES: &[(&str, &[&str])] = &[
+    ("default",       &["left_ptr"]),
+    ("context-menu",  &["left_ptr"]),
+    ("help",          &["question_arrow", "left_ptr"]),
+    ("pointer",       &["hand2", "hand1"]),
+    ("progress",      &["left_ptr_watch", "watch"]),
+    ("wait",          &["watch"]),
+    ("cell",          &["plus"]),
+    ("crosshair",     &["cross", "tcross"]),
+    ("text",          &["xterm"]),
+    ("vertical-text", &["xterm"]),
+    ("alias",         &["dnd-link"]),
+    ("copy",          &["dnd-copy"]),
+    ("move",          &["dnd-move", "fleur"]),
+    ("no-drop",       &["dnd-none"]),
+    ("not-allowed",   &["crossed_circle"]),
+    ("grab",          &["hand1"]),
+    ("grabbing",      &["fleur"]),
+    ("e-resize",      &["right_side"]),
+    ("n-resize",      &["top_side"]),
+    ("ne-resize",     &["top_right_corner"]),
+    ("nw-resize",     &["top_left_corner"]),
+    ("s-resize",      &["bottom_side"]),
+    ("se-resize",     &["bottom_right_corner"]),
+    ("sw-resize",     &["bottom_left_corner"]),
+    ("w-resize",      &["left_side"]),
+    ("ew-resize",     &["sb_h_double_arrow"]),
+    ("ns-resize",     &["sb_v_double_arrow"]),
+    ("col-resize",    &["sb_h_double_arrow"]),
+    ("row-resize",    &["sb_v_double_arrow"]),
+    ("all-scroll",    &["fleur"]),
+];
+
+/// Cursors from an xcursor theme, rasterized once for every output scale in use.
+///
+/// Each seat has its own, which starts out with the theme and size from the environment
+/// and can be changed while running. It shows the theme's default cursor, unless a client
+/// asked for another shape by name. Shapes are loaded from the theme the first time
+/// they're shown, by their CSS name or by the older X11 name that some themes still use.
+pub struct Cursor<R: EngineRenderer> {
+    logger:    Logger,
+    /// Name of the xcursor theme
+    pub theme: String,
+    /// Nominal cursor size at scale 1
+    pub size:  u32,
+    /// Name of the shape being shown
+    shape:     String,
+    /// The client that asked for the shape being shown, if any
+    pub owner: Option<ClientId>,
+    /// All frames of each shape loaded so far, at all sizes contained in the theme
+    images:    HashMap<String, Vec<Image>>,
+    /// Textures and hotspots, by shape and pixel size
+    textures:  HashMap<(String, u32), (R::TextureId, Point<i32, Physical>)>,
+}
+
+impl<R: EngineRenderer> Cursor<R> {
+
+    /// Load the cursor theme and size given by XCURSOR_THEME and XCURSOR_SIZE.
+    pub fn from_env (logger: &Logger) -> Self {
+        let theme = std::env::var("XCURSOR_THEME").unwrap_or_else(|_|"default".into());
+        let size  = std::env::var("XCURSOR_SIZE").ok().and_then(|s|s.parse().ok())
+            .unwrap_or(DEFAULT_SIZE);
+        Self::load(logger, theme, size)
+    }
+
+    /// Load the default cursor from a theme.
+    /// If that fails, the cursor will have no images and callers should use a fallback.
+    pub fn load (logger: &Logger, theme: impl Into<String>, size: u32) -> Self {
+        let mut cursor = Self {
+            logger:   logger.clone(),
+            theme:    theme.into(),
+            size,
+            shape:    DEFAULT_SHAPE.into(),
+            owner:    None,
+            images:   HashMap::new(),
+            textures: HashMap::new(),
+        };
+        cursor.load_default();
+        cursor
+    }
+
+    /// Load the default cursor of the current theme.
+    fn load_default (&mut self) {
+        match Self::load_images(&self.theme, DEFAULT_SHAPE) {
+            Ok(images) => {
+                self.images.insert(DEFAULT_SHAPE.into(), images);
+            },
+            Err(e) => warn!(self.logger, "Could not load cursor theme {}: {e}", self.theme)
+        }
+    }
+
+    fn load_images (theme: &str, shape: &str) -> StdResult<Vec<Image>> {
+        let theme = CursorTheme::load(theme);
+        let legacy = LEGACY_NAMES.iter()
+            .find(|(name, _)| *name == shape)
+            .map(|(_, legacy)| *legacy)
+            .unwrap_or(&[]);
+        let path = std::iter::once(shape).chain(legacy.iter().copied())
+            .find_map(|name| theme.load_icon(name))
+            .ok_or_else(|| format!("no {shape} cursor in theme"))?;
+        let data = std::fs::read(path)?;
+        Ok(parse_xcursor(&data).ok_or("could not parse cursor file")?)
+    }
+
+    /// Show the cursor of this shape, by its CSS name.
+    /// If the theme has no such cursor, the current shape stays.
+    pub fn set_shape (&mut self, shape: &str) -> StdResult<()> {
+        if !self.images.contains_key(shape) {
+            let images = Self::load_images(&self.theme, shape)?;
+            self.images.insert(shape.into(), images);
+        }
+        self.shape = shape.into();
+        Ok(())
+    }
+
+    /// Go back to the default cursor.
+    pub fn reset_shape (&mut self) {
+        self.shape = DEFAULT_SHAPE.into();
+        self.owner = None;
+    }
+
+    /// Switch to another theme. Textures are rasterized anew on next render.
+    pub fn set_theme (&mut self, theme: impl Into<String>) {
+        let theme = theme.into();
+        if theme == self.theme {
+            return
+        }
+        debug!(self.logger, "Cursor theme: {} -> {theme}", self.theme);
+        self.theme = theme;
+        self.images.clear();
+        self.textures.clear();
+        self.load_default();
+        let shape = std::mem::replace(&mut self.shape, DEFAULT_SHAPE.into());
+        if let Err(e) = self.set_shape(&shape) {
+            debug!(self.logger, "Showing the default cursor instead: {e}");
+        }
+    }
+
+    /// Change the nominal size. Textures are rasterized anew on next render.
+    pub fn set_size (&mut self, size: u32) {
+        if size != self.size {
+            debug!(self.logger, "Cursor size: {} -> {size}", self.size);
+            self.size = size;
+            self.textures.clear();
+        }
+    }
+
+    /// The size in pixels of the cursor on an output with the given scale.
+    pub fn pixel_size (&self, scale: f64) -> u32 {
+        (self.size as f64 * scale).round() as u32
+    }
+
+    /// Upload the frame of the current shape closest to the pixel size for this scale,
+    /// unless already cached.
+    pub fn prepare (&mut self, renderer: &mut R, scale: f64) -> StdResult<()> {
+        let size = self.pixel_size(scale);
+        let key = (self.shape.clone(), size);
+        if self.textures.contains_key(&key) {
+            return Ok(())
+        }
+        let nearest = self.images.get(&self.shape).into_iter().flatten()
+            .min_by_key(|image| (image.size as i64 - size as i64).abs());
+        if let Some(image) = nearest {
+            debug!(self.logger, "Rasterizing {}px {} cursor for scale {scale} from {}px frame",
+                size, self.shape, image.size);
+            let texture = renderer.import_memory(
+                &image.pixels_rgba,
+                (image.width as i32, image.height as i32).into(),
+                false
+            )?;
+            let hotspot = (image.xhot as i32, image.yhot as i32).into();
+            self.textures.insert(key, (texture, hotspot));
+        }
+        Ok(())
+    }
+
+    /// Get the texture and hotspot prepared for this scale.
+    pub fn get (&self, scale: f64) -> Option<&(R::TextureId, Point<i32, Physical>)> {
+        self.textures.get(&(self.shape.clone(), self.pixel_size(scale)))
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Use this cursor theme and size for the pointer of the named seat.
+    pub fn cursor (mut self, seat: &str, theme: &str, size: u32) -> CharlieResult<Self> {
+        self.cursor_set(Some(seat), Some(theme), Some(size))
+            .map_err(|e| CharlieError::Config(e.to_string()))?;
+        Ok(self)
+    }
+
+    /// Change the cursor theme or size of the named seat, or of all seats.
+    ///
+    /// Clients draw their own cursors from the theme named in their environment, so
+    /// changes to the first seat, which clients know as the default one, are passed on
+    /// to the clients started from then on. Clients that are already running keep theirs.
+    pub fn cursor_set (&mut self, seat: Option<&str>, theme: Option<&str>, size: Option<u32>)
+        -> StdResult<()>
+    {
+        let indices: Vec<usize> = match seat {
+            Some(seat) => vec![
+                self.input.seats.iter().position(|s| s.name() == seat)
+                    .ok_or_else(|| format!("no seat {seat}"))?
+            ],
+            None => (0..self.input.pointers.len()).collect()
+        };
+        for index in indices.iter() {
+            if let Some(pointer) = self.input.pointers.get_mut(*index) {
+                if let Some(theme) = theme {
+                    pointer.cursor.set_theme(theme);
+                }
+                if let Some(size) = size {
+                    pointer.cursor.set_size(size);
+                }
+            }
+        }
+        if indices.contains(&0) {
+            if let Some(theme) = theme {
+                self.processes.set_env("XCURSOR_THEME", theme);
+            }
+            if let Some(size) = size {
+                self.processes.set_env("XCURSOR_SIZE", size.to_string());
+            }
+        }
+        self.redraw();
+        Ok(())
+    }
+
+}
@@ -0,0 +1,163 @@
+use super::prelude::*;
+
+use zbus::{dbus_interface, blocking::{Connection, ConnectionBuilder}};
+
+const BUS_NAME:  &str = "org.charlie.Compositor";
+const BUS_PATH:  &str = "/org/charlie/Compositor";
+
+/// An output as described over D-Bus: name, width and height in pixels, and scale.
+type OutputInfo = (String, i32, i32, f64);
+
+/// A window as described over D-Bus: id, app id and title. The id is 0 if there's no window.
+type WindowInfo = (u64, String, String);
+
+/// What the service tells about the compositor, as of the last turn of the main loop.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Snapshot {
+    outputs:    Vec<OutputInfo>,
+    workspaces: Vec<String>,
+    workspace:  String,
+    focused:    WindowInfo,
+}
+
+/// Implements org.charlie.Compositor by reading the latest snapshot.
+struct Service {
+    snapshot: Arc<Mutex<Snapshot>>,
+}
+
+impl Service {
+    fn read <T> (&self, read: impl FnOnce(&Snapshot) -> T) -> T {
+        read(&self.snapshot.lock().unwrap())
+    }
+}
+
+#[dbus_interface(name = "org.charlie.Compositor")]
+impl Service {
+
+    #[dbus_interface(property)]
+    fn outputs (&self) -> Vec<OutputInfo> {
+        self.read(|snapshot| snapshot.outputs.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn workspaces (&self) -> Vec<String> {
+        self.read(|snapshot| snapshot.workspaces.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn current_workspace (&self) -> String {
+        self.read(|snapshot| snapshot.workspace.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn focused_window (&self) -> WindowInfo {
+        self.read(|snapshot| snapshot.focused.clone())
+    }
+
+}
+
+/// Tells desktop components such as panels, OSDs and settings daemons about the outputs,
+/// the workspaces and the focused window over the session bus, as `org.charlie.Compositor`,
+/// so that they don't need to speak the private Wayland protocol. Changes are announced with
+/// the `OutputsChanged`, `WorkspacesChanged`, `WorkspaceChanged` and `FocusChanged` signals.
+pub struct DbusService {
+    logger:      Logger,
+    /// Whether to serve the interface at all
+    pub enabled: bool,
+    /// Connection to the session bus, once started
+    connection:  Option<Connection>,
+    /// What the service last told, shared with the thread that answers calls
+    snapshot:    Arc<Mutex<Snapshot>>,
+}
+
+impl DbusService {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:     logger.clone(),
+            enabled:    true,
+            connection: None,
+            snapshot:   Arc::new(Mutex::new(Snapshot::default())),
+        }
+    }
+
+    /// Take the service name on the session bus and start answering calls.
+    pub fn init (&mut self) -> StdResult<()> {
+        if !self.enabled {
+            return Ok(())
+        }
+        let service = Service { snapshot: self.snapshot.clone() };
+        let connection = ConnectionBuilder::session()?
+            .name(BUS_NAME)?
+            .serve_at(BUS_PATH, service)?
+            .build()?;
+        info!(self.logger, "Serving compositor state as {BUS_NAME}");
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    fn emit <T: zbus::export::serde::Serialize + zbus::zvariant::DynamicType> (&self, signal: &str, body: &T) {
+        if let Some(connection) = self.connection.as_ref() {
+            if let Err(e) = connection.emit_signal(None::<&str>, BUS_PATH, BUS_NAME, signal, body) {
+                warn!(self.logger, "Could not emit {signal}: {e}");
+            }
+        }
+    }
+
+    /// Take a new snapshot into use, announcing what changed.
+    fn update (&mut self, next: Snapshot) {
+        let last = std::mem::replace(&mut *self.snapshot.lock().unwrap(), next.clone());
+        if last.outputs != next.outputs {
+            self.emit("OutputsChanged", &(next.outputs,));
+        }
+        if last.workspaces != next.workspaces {
+            self.emit("WorkspacesChanged", &(next.workspaces,));
+        }
+        if last.workspace != next.workspace {
+            self.emit("WorkspaceChanged", &(next.workspace,));
+        }
+        if last.focused != next.focused {
+            self.emit("FocusChanged", &next.focused);
+        }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Serve the state of the compositor over D-Bus, or don't.
+    pub fn dbus_service (mut self, enabled: bool) -> CharlieResult<Self> {
+        self.dbus.enabled = enabled;
+        Ok(self)
+    }
+
+    /// Bring what the D-Bus service tells up to date, if it's running.
+    pub fn dbus_update (&mut self) {
+        if self.dbus.connection.is_none() {
+            return
+        }
+        let outputs = (0..self.desktop.screens.len())
+            .filter_map(|screen| self.engine.output_get(screen))
+            .map(|output| {
+                let (w, h) = output.current_mode().map(|mode| (mode.size.w, mode.size.h)).unwrap_or((0, 0));
+                (output.name(), w, h, output.current_scale().fractional_scale())
+            })
+            .collect();
+        let workspaces = &self.desktop.workspaces;
+        let focused = self.desktop.focused.as_ref()
+            .and_then(|surface| self.desktop.window_state(surface))
+            .map(|window| {
+                let (app_id, title) = window.app_id_and_title();
+                (window.id, app_id.unwrap_or_default(), title.unwrap_or_default())
+            })
+            .unwrap_or_default();
+        let snapshot = Snapshot {
+            outputs,
+            workspaces: workspaces.list.iter().map(|workspace| workspace.name.clone()).collect(),
+            workspace:  workspaces.current_name().to_string(),
+            focused,
+        };
+        self.dbus.update(snapshot);
+    }
+
+}
@@ -0,0 +1,266 @@
+use super::prelude::*;
+
+use smithay::backend::renderer::ImportMem;
+
+/// A soft shadow cast by windows onto what's below them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    /// How far the shadow is shifted from the window, in logical pixels
+    pub offset:   Point<i32, Logical>,
+    /// How far the shadow fades out past the window's edges, in logical pixels
+    pub blur:     u32,
+    /// Opacity of the shadow right under the window, from 0 to 1
+    pub strength: f32,
+}
+
+impl Default for Shadow {
+    fn default () -> Self {
+        Self { offset: (0, 4).into(), blur: 12, strength: 0.4 }
+    }
+}
+
+/// A line around windows, colored by whether they have focus or want attention.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Border {
+    /// Width of the border outside the window, in logical pixels
+    pub width:     u32,
+    pub focused:   [f32; 4],
+    pub unfocused: [f32; 4],
+    /// Color of windows that want attention, whether or not they have focus
+    pub urgent:    [f32; 4],
+}
+
+impl Default for Border {
+    fn default () -> Self {
+        Self {
+            width:     2,
+            focused:   [0.32, 0.58, 0.89, 1.0],
+            unfocused: [0.27, 0.27, 0.27, 1.0],
+            urgent:    [0.89, 0.32, 0.32, 1.0],
+        }
+    }
+}
+
+/// Parse a color written as `#rrggbb` or `#rrggbbaa`.
+pub fn parse_color (color: &str) -> StdResult<[f32; 4]> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return Err(format!("expected #rrggbb or #rrggbbaa, got {color}").into())
+    }
+    let channel = |index: usize| -> StdResult<f32> {
+        Ok(match hex.get(index * 2..index * 2 + 2) {
+            Some(digits) => u8::from_str_radix(digits, 16)? as f32 / 255.0,
+            None => 1.0
+        })
+    };
+    Ok([channel(0)?, channel(1)?, channel(2)?, channel(3)?])
+}
+
+/// How far in from the side of a window its visible area starts on a row
+/// within a rounded corner, `y` being the distance from the top or bottom edge.
+pub fn corner_inset (radius: f64, y: f64) -> f64 {
+    if y >= radius {
+        return 0.0
+    }
+    let dy = radius - y;
+    radius - (radius * radius - dy * dy).max(0.0).sqrt()
+}
+
+/// Split a window's area into horizontal strips that together make up its rounded shape:
+/// one row per logical pixel in each corner, and one strip for the straight middle.
+pub fn rounded_rows (size: Size<f64, Logical>, radius: f64) -> Vec<Rectangle<f64, Logical>> {
+    let radius = radius.min(size.w / 2.0).min(size.h / 2.0).max(0.0).floor();
+    if radius < 1.0 {
+        return vec![Rectangle::from_loc_and_size((0.0, 0.0), size)]
+    }
+    let mut rows = Vec::with_capacity(radius as usize * 2 + 1);
+    let row = |y: f64, h: f64, inset: f64| Rectangle::from_loc_and_size(
+        (inset, y), (size.w - 2.0 * inset, h)
+    );
+    for y in 0..radius as usize {
+        // Sample the curve in the middle of the row
+        let inset = corner_inset(radius, y as f64 + 0.5).round();
+        rows.push(row(y as f64, 1.0, inset));
+        rows.push(row(size.h - y as f64 - 1.0, 1.0, inset));
+    }
+    rows.push(row(radius, size.h - 2.0 * radius, 0.0));
+    rows
+}
+
+/// Rounded corners, borders and drop shadows of windows.
+pub struct Decorations<R: EngineRenderer> {
+    logger:            Logger,
+    /// Radius of window corners, in logical pixels
+    pub corner_radius: u32,
+    /// Shadow behind windows, if any
+    pub shadow:        Option<Shadow>,
+    /// Border around windows, if any
+    pub border:        Option<Border>,
+    /// Nine-slice images of the shadow, by output scale in percent
+    textures:          HashMap<u32, R::TextureId>,
+    /// The shadow and corner radius the images were drawn with
+    drawn:             Option<(Shadow, u32)>,
+}
+
+/// Key for caching images per output scale
+fn scale_key (scale: f64) -> u32 {
+    (scale * 100.0).round() as u32
+}
+
+impl<R: EngineRenderer> Decorations<R> {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:        logger.clone(),
+            corner_radius: 0,
+            shadow:        None,
+            border:        None,
+            textures:      HashMap::new(),
+            drawn:         None,
+        }
+    }
+
+    /// Whether a point, relative to a window's top left corner, is inside the window's rounded shape.
+    pub fn contains (&self, size: Size<f64, Logical>, point: Point<f64, Logical>) -> bool {
+        if point.x < 0.0 || point.y < 0.0 || point.x >= size.w || point.y >= size.h {
+            return false
+        }
+        let radius = (self.corner_radius as f64).min(size.w / 2.0).min(size.h / 2.0);
+        let y = point.y.min(size.h - point.y);
+        let inset = corner_inset(radius, y);
+        point.x >= inset && point.x < size.w - inset
+    }
+
+    /// Draw the nine-slice shadow image for the current settings at this scale, unless already done.
+    pub fn prepare (&mut self, renderer: &mut R, scale: f64) -> StdResult<()> {
+        let shadow = match self.shadow {
+            Some(shadow) => shadow,
+            None => return Ok(())
+        };
+        if self.drawn != Some((shadow, self.corner_radius)) {
+            self.textures.clear();
+            self.drawn = Some((shadow, self.corner_radius));
+        }
+        if self.textures.contains_key(&scale_key(scale)) {
+            return Ok(())
+        }
+        debug!(self.logger, "Drawing shadow {shadow:?} at scale {scale}");
+        let blur   = (shadow.blur as f64 * scale).ceil().max(1.0);
+        let radius = (self.corner_radius as f64 * scale).ceil();
+        let corner = (blur * 2.0 + radius) as usize;
+        let side   = corner * 2 + 1;
+        let mut pixels = vec![0u8; side * side * 4];
+        for y in 0..side {
+            for x in 0..side {
+                // Distance from the rounded rectangle that starts `blur` in from the edges
+                let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+                let (lo, hi) = (blur + radius, side as f64 - blur - radius);
+                let dx = (lo - px).max(px - hi).max(0.0);
+                let dy = (lo - py).max(py - hi).max(0.0);
+                let distance = (dx * dx + dy * dy).sqrt() - radius;
+                let fade  = (0.5 - distance / (2.0 * blur)).clamp(0.0, 1.0);
+                let alpha = (shadow.strength.clamp(0.0, 1.0) as f64 * fade * fade * (3.0 - 2.0 * fade) * 255.0) as u8;
+                // Black, premultiplied
+                pixels[(y * side + x) * 4 + 3] = alpha;
+            }
+        }
+        let texture = renderer.import_memory(&pixels, (side as i32, side as i32).into(), false)?;
+        self.textures.insert(scale_key(scale), texture);
+        Ok(())
+    }
+
+    /// Draw the shadow of a window at this location and size on the output.
+    pub fn render_shadow (&self, frame: &mut R::Frame<'_>, window: Rectangle<i32, Physical>, scale: f64)
+        -> StdResult<()>
+    {
+        let (texture, shadow) = match (self.textures.get(&scale_key(scale)), self.shadow) {
+            (Some(texture), Some(shadow)) => (texture, shadow),
+            _ => return Ok(())
+        };
+        let side   = texture.size().w;
+        let corner = side / 2;
+        let blur   = (shadow.blur as f64 * scale).ceil() as i32;
+        let offset = shadow.offset.to_f64().to_physical(scale).to_i32_round();
+        // The image has the window's edges `blur` in from its own, so it extends that far out
+        let area = Rectangle::<i32, Physical>::from_loc_and_size(
+            (window.loc.x + offset.x - blur, window.loc.y + offset.y - blur),
+            (window.size.w + 2 * blur, window.size.h + 2 * blur)
+        );
+        let corner_dest = corner.min(area.size.w / 2).min(area.size.h / 2);
+        // Columns and rows of the nine slices, in the image and on the output
+        let src_x  = [0, corner, corner + 1, side];
+        let dest_x = [area.loc.x, area.loc.x + corner_dest, area.loc.x + area.size.w - corner_dest, area.loc.x + area.size.w];
+        let dest_y = [area.loc.y, area.loc.y + corner_dest, area.loc.y + area.size.h - corner_dest, area.loc.y + area.size.h];
+        for row in 0..3 {
+            for col in 0..3 {
+                let src = Rectangle::<f64, Buffer>::from_loc_and_size(
+                    (src_x[col] as f64, src_x[row] as f64),
+                    ((src_x[col + 1] - src_x[col]) as f64, (src_x[row + 1] - src_x[row]) as f64)
+                );
+                let dest = Rectangle::<i32, Physical>::from_extemities(
+                    (dest_x[col], dest_y[row]), (dest_x[col + 1], dest_y[row + 1])
+                );
+                if dest.size.w <= 0 || dest.size.h <= 0 {
+                    continue
+                }
+                frame.render_texture_from_to(
+                    texture, src, dest, &[Rectangle::from_loc_and_size((0, 0), dest.size)],
+                    Transform::Normal, 1.0
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the border around a window at this location and size on the screen, in this color.
+    /// The border follows the rounded corners, and is drawn in solid rows under the window.
+    pub fn render_border (
+        &self,
+        frame:  &mut R::Frame<'_>,
+        window: Rectangle<i32, Logical>,
+        scale:  f64,
+        color:  [f32; 4]
+    ) -> StdResult<()> {
+        let width = match self.border {
+            Some(border) if border.width > 0 => border.width as i32,
+            _ => return Ok(())
+        };
+        let outer = Rectangle::<f64, Logical>::from_loc_and_size(
+            (window.loc.x - width, window.loc.y - width),
+            (window.size.w + 2 * width, window.size.h + 2 * width)
+        );
+        let radius = if self.corner_radius > 0 { (self.corner_radius as i32 + width) as f64 } else { 0.0 };
+        let rows: Vec<Rectangle<i32, Physical>> = rounded_rows(outer.size, radius).into_iter()
+            .map(|row| Rectangle::from_loc_and_size(
+                (outer.loc + row.loc).to_physical(scale).to_i32_round(),
+                row.size.to_physical(scale).to_i32_round()
+            ))
+            .filter(|row| row.size.w > 0 && row.size.h > 0)
+            .collect();
+        frame.clear(color, &rows)?;
+        Ok(())
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Round the corners of windows.
+    pub fn corner_radius (mut self, radius: u32) -> CharlieResult<Self> {
+        self.desktop.decorations.corner_radius = radius;
+        Ok(self)
+    }
+
+    /// Draw a shadow behind windows.
+    pub fn shadow (mut self, shadow: Shadow) -> CharlieResult<Self> {
+        self.desktop.decorations.shadow = Some(shadow);
+        Ok(self)
+    }
+
+    /// Draw a border around windows.
+    pub fn border (mut self, border: Border) -> CharlieResult<Self> {
+        self.desktop.decorations.border = Some(border);
+        Ok(self)
+    }
+
+}
@@ -1,17 +1,82 @@
 use super::prelude::*;
+use super::workspace::Workspaces;
+use super::rules::WindowRule;
+use super::fullscreen::Fullscreen;
+use super::stacking::StackLayer;
+use super::decoration::{Decorations, rounded_rows};
+use super::transform::transform_size;
+use super::resize::ResizePolicy;
+use super::manage::Gaps;
+use super::scaling::Scaling;
+use super::pip::Pip;
+use super::crash::Ghost;
+use super::shm::shm_convert;
+use super::texture::{TextureCache, ImportedSize, ShownTexture, is_shm};
+use super::identity::{ClientState, Identity};
+use super::content::ContentType;
+use super::occlusion::covered;
+use super::surface::with_surface;
+use super::command::Command;
+use super::placement::WindowPlacement;
 
-pub struct Desktop {
+use smithay::backend::renderer::ImportMem;
+
+use smithay::desktop::space::SpaceElement;
+use smithay::desktop::utils::surface_primary_scanout_output;
+use smithay::wayland::shell::xdg::ShellClient;
+use smithay::wayland::compositor::BufferAssignment;
+use smithay::reexports::wayland_server::{Resource, backend::ClientId};
+
+use std::collections::HashSet;
+
+/// Color that windows of unresponsive clients are faded into
+const UNRESPONSIVE_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+/// How much of a window of an unresponsive client shows through the fade
+const UNRESPONSIVE_ALPHA: f32 = 0.4;
+
+/// Color that windows behind a modal dialog are darkened into
+const MODAL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// How much of a window behind a modal dialog shows through the darkening
+const MODAL_ALPHA: f32 = 0.6;
+
+pub struct Desktop<R: EngineRenderer> {
     logger: Logger,
     clock:  Clock<Monotonic>,
     /// A collection of windows that are mapped across the screens
     windows: Vec<WindowState>,
     /// A collection of views into the workspace, bound to engine outputs
     pub screens: Vec<ScreenState>,
+    /// The named workspaces that windows are grouped into
+    pub workspaces: Workspaces,
+    /// Properties to apply to matching windows
+    pub rules: Vec<WindowRule>,
+    /// Whether fullscreen windows may change the output mode to match their size
+    pub fullscreen_mode_switch: bool,
+    /// The window that has keyboard focus
+    pub focused: Option<WlSurface>,
+    /// The screen that last received input
+    pub active_screen: ScreenId,
+    /// Rounded corners and shadows of windows
+    pub decorations: Decorations<R>,
+    /// Space kept around windows that fill their screens
+    pub gaps:    Gaps,
+    /// Where new windows go, unless a rule says otherwise
+    pub placement: WindowPlacement,
+    /// Textures of client buffers, shared by the surfaces that show the same buffer
+    pub textures: TextureCache<R>,
+    /// Most frame callbacks per second sent to windows that can't be seen; None for no limit
+    pub occluded_fps: Option<u32>,
+    /// Where the windows of crashed clients were, while they're shown
+    pub ghosts:  Vec<Ghost>,
+    /// Id to give to the next window
+    next_id: u64,
     compositor: CompositorState,
     xdg_shell: XdgShellState,
 }
 
-impl Desktop {
+impl<R: EngineRenderer> Desktop<R> {
 
     pub fn new <E: Engine> (logger: &Logger, handle: &DisplayHandle)
         -> Result<Self, Box<dyn Error>>
@@ -23,6 +88,18 @@ impl Desktop {
             xdg_shell:  XdgShellState::new::<Charlie<E>, _>(&handle, logger.clone()),
             windows:    vec![],
             screens:    vec![],
+            workspaces: Workspaces::new(logger),
+            rules:      vec![],
+            fullscreen_mode_switch: false,
+            focused:    None,
+            active_screen: 0,
+            decorations: Decorations::new(logger),
+            gaps:       Gaps::default(),
+            placement:  WindowPlacement::Smart,
+            textures:   TextureCache::new(logger),
+            occluded_fps: Some(1),
+            ghosts:     vec![],
+            next_id:    1,
         })
     }
 
@@ -32,12 +109,251 @@ impl Desktop {
         self.screens.len() - 1
     }
 
-    /// Add a window to the workspace.
+    /// Add a window to the current workspace.
     pub fn window_add (&mut self, window: Window) -> usize {
-        self.windows.push(WindowState::new(window));
+        self.windows.push(WindowState::new(self.next_id, window, self.workspaces.current));
+        self.next_id += 1;
         self.windows.len() - 1
     }
 
+    /// Stop keeping track of a window, e.g. once it's destroyed.
+    pub fn window_remove (&mut self, surface: &WlSurface) -> Option<WindowState> {
+        let index = self.windows.iter().position(|w| w.window.toplevel().wl_surface() == surface)?;
+        let window = self.windows.remove(index);
+        if let Some(workspace) = self.workspaces.list.get_mut(window.workspace) {
+            workspace.minimized.retain(|s| s != surface);
+        }
+        Some(window)
+    }
+
+    /// Update a window after its root surface has been committed.
+    /// Returns false if no window has this surface.
+    pub fn window_commit (&mut self, surface: &WlSurface) -> bool {
+        let rules = &self.rules;
+        match self.windows.iter_mut().find(|w| w.window.toplevel().wl_surface() == surface) {
+            Some(window) => {
+                window.window.on_commit();
+                if !window.rules_applied {
+                    window.apply_rules(rules);
+                }
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Find the state of a window by its top level surface.
+    pub fn window_state (&self, surface: &WlSurface) -> Option<&WindowState> {
+        self.windows.iter().find(|w| w.window.toplevel().wl_surface() == surface)
+    }
+
+    /// Find the state of a window by its id.
+    pub fn window_by_id (&mut self, id: u64) -> Option<&mut WindowState> {
+        self.windows.iter_mut().find(|w| w.id == id)
+    }
+
+    /// Find the state of a window by its top level surface.
+    pub fn window_state_mut (&mut self, surface: &WlSurface) -> Option<&mut WindowState> {
+        self.windows.iter_mut().find(|w| w.window.toplevel().wl_surface() == surface)
+    }
+
+    /// Find the screen displayed on the named output.
+    pub fn screen_find (&self, output: &str) -> Option<ScreenId> {
+        self.screens.iter().position(|s| s.output.as_deref() == Some(output))
+    }
+
+    /// Add a window rule and reapply rules to existing windows.
+    pub fn rule_add (&mut self, rule: WindowRule) {
+        self.rules.push(rule);
+        for window in self.windows.iter_mut() {
+            window.rules_applied = false;
+        }
+    }
+
+    /// Iterate over the windows of all workspaces.
+    pub fn windows_all (&self) -> impl Iterator<Item = &WindowState> {
+        self.windows.iter()
+    }
+
+    /// Iterate over the windows of the current workspace, including minimized ones,
+    /// and the picture-in-picture windows, which are on every workspace.
+    pub fn windows_current (&self) -> impl Iterator<Item = &WindowState> {
+        let current = self.workspaces.current;
+        self.windows.iter().filter(move |w| w.workspace == current || w.pip.is_some())
+    }
+
+    /// Iterate over the windows of the current workspace that aren't minimized.
+    pub fn windows_visible (&self) -> impl Iterator<Item = &WindowState> {
+        self.windows_current().filter(|w| !w.minimized)
+    }
+
+    /// Iterate over the visible windows of the current workspace from bottom to top:
+    /// below, normal and above layers in turn, then fullscreen windows.
+    /// Dialogs are kept right above their parents, whichever client they belong to.
+    pub fn windows_stacked (&self) -> impl Iterator<Item = &WindowState> {
+        let mut windows: Vec<&WindowState> = self.windows_visible().collect();
+        windows.sort_by_key(|w| (w.fullscreen.is_some(), w.layer));
+        let mut stacked = Vec::with_capacity(windows.len());
+        for window in windows.iter() {
+            let parent = window.parent_surface();
+            let has_parent = parent.map(|parent| windows.iter().any(|w| w.window.toplevel().wl_surface() == &parent))
+                .unwrap_or(false);
+            if !has_parent {
+                Self::stack_with_children(&windows, window, &mut stacked);
+            }
+        }
+        // Windows whose parents are their own descendants would be left out otherwise
+        for window in windows.iter() {
+            if !stacked.iter().any(|w: &&WindowState| w.id == window.id) {
+                stacked.push(window);
+            }
+        }
+        stacked.into_iter()
+    }
+
+    /// Put a window on a stack, followed by its children and theirs.
+    fn stack_with_children <'a> (windows: &[&'a WindowState], window: &'a WindowState, stacked: &mut Vec<&'a WindowState>) {
+        if stacked.iter().any(|w| w.id == window.id) {
+            return
+        }
+        stacked.push(window);
+        let surface = window.window.toplevel().wl_surface();
+        for child in windows.iter().filter(|w| w.parent_surface().as_ref() == Some(surface)) {
+            Self::stack_with_children(windows, child, stacked);
+        }
+    }
+
+    /// Put a window on top of the others in its layer, together with its dialogs.
+    pub fn raise (&mut self, surface: &WlSurface) {
+        if let Some(index) = self.windows.iter().position(|w| w.window.toplevel().wl_surface() == surface) {
+            let window = self.windows.remove(index);
+            self.windows.push(window);
+        }
+        for child in self.children_of(surface) {
+            if !self.descends_from(surface, &child) {
+                self.raise(&child);
+            }
+        }
+    }
+
+    /// The windows that are dialogs of a window.
+    pub fn children_of (&self, surface: &WlSurface) -> Vec<WlSurface> {
+        self.windows.iter()
+            .filter(|w| w.parent_surface().as_ref() == Some(surface))
+            .map(|w| w.window.toplevel().wl_surface().clone())
+            .collect()
+    }
+
+    /// Whether a window is a dialog of another one, or of one of its dialogs.
+    pub fn descends_from (&self, surface: &WlSurface, ancestor: &WlSurface) -> bool {
+        let mut current = surface.clone();
+        // Going no further than there are windows, in case parents go around in a circle
+        for _ in 0..self.windows.len() {
+            match self.window_state(&current).and_then(|w| w.parent_surface()) {
+                Some(parent) if &parent == ancestor => return true,
+                Some(parent) => current = parent,
+                None => return false
+            }
+        }
+        false
+    }
+
+    /// The modal dialog that keeps a window from taking input: the topmost one open over it,
+    /// or over that one in turn, and so on.
+    pub fn modal_child (&self, surface: &WlSurface) -> Option<WlSurface> {
+        let mut current = surface.clone();
+        let mut found = None;
+        // Going no further than there are windows, in case parents go around in a circle
+        for _ in 0..self.windows.len() {
+            let child = self.windows.iter().rev()
+                .filter(|w| w.modal() && !w.minimized)
+                .find(|w| w.parent_surface().as_ref() == Some(&current))
+                .map(|w| w.window.toplevel().wl_surface().clone());
+            match child {
+                Some(child) if &child != surface => {
+                    current = child.clone();
+                    found = Some(child);
+                },
+                _ => break
+            }
+        }
+        found
+    }
+
+    /// Let go of the dialogs that were made children of a window from another client.
+    pub fn orphan (&mut self, parent: &WlSurface) {
+        for window in self.windows.iter_mut().filter(|w| w.parent.as_ref() == Some(parent)) {
+            window.parent = None;
+        }
+    }
+
+    /// Center a dialog on its parent window.
+    pub fn center_on_parent (&mut self, surface: &WlSurface) {
+        let parent = match self.window_state(surface).and_then(|w| w.parent_surface()) {
+            Some(parent) => parent,
+            None => return
+        };
+        let (center, size) = match self.window_state(&parent) {
+            Some(parent) => (parent.center, parent.window.geometry().size.to_f64()),
+            None => return
+        };
+        if let Some(window) = self.window_state_mut(surface) {
+            let own = window.window.geometry().size.to_f64();
+            window.center = (center.x + (size.w - own.w) / 2.0, center.y + (size.h - own.h) / 2.0).into();
+        }
+    }
+
+    /// Put a window below the others in its layer.
+    pub fn lower (&mut self, surface: &WlSurface) {
+        if let Some(index) = self.windows.iter().position(|w| w.window.toplevel().wl_surface() == surface) {
+            let window = self.windows.remove(index);
+            self.windows.insert(0, window);
+        }
+    }
+
+    /// Move a window to another layer of the stack.
+    pub fn set_layer (&mut self, surface: &WlSurface, layer: StackLayer) {
+        if let Some(window) = self.window_state_mut(surface) {
+            window.layer = layer;
+        }
+    }
+
+    /// Hide a window from rendering and input without closing it.
+    pub fn minimize (&mut self, surface: &WlSurface) {
+        let workspace = match self.window_state_mut(surface) {
+            Some(window) if !window.minimized => {
+                window.minimized = true;
+                window.workspace
+            },
+            _ => return
+        };
+        self.workspaces.list[workspace].minimized.push(surface.clone());
+    }
+
+    /// Show a minimized window again.
+    pub fn unminimize (&mut self, surface: &WlSurface) {
+        let workspace = match self.window_state_mut(surface) {
+            Some(window) if window.minimized => {
+                window.minimized = false;
+                window.workspace
+            },
+            _ => return
+        };
+        self.workspaces.list[workspace].minimized.retain(|s| s != surface);
+    }
+
+    /// The window that comes after the given one in the current workspace, wrapping around.
+    pub fn window_next (&self, after: Option<&WlSurface>) -> Option<WlSurface> {
+        let windows: Vec<&WlSurface> = self.windows_current()
+            .map(|w| w.window.toplevel().wl_surface())
+            .collect();
+        let index = after
+            .and_then(|after| windows.iter().position(|s| *s == after))
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        windows.get(index % windows.len().max(1)).map(|s| (*s).clone())
+    }
+
     /// Find a window by its top level surface.
     pub fn window_find (&self, surface: &WlSurface) -> Option<&Window> {
         self.windows.iter()
@@ -45,27 +361,210 @@ impl Desktop {
             .map(|w|&w.window)
     }
 
-    pub fn import (&self, renderer: &mut Gles2Renderer) -> Result<(), Box<dyn Error>> {
+    /// Ask all clients to close their windows.
+    pub fn close_all (&self) {
         for window in self.windows.iter() {
-            window.import(&self.logger, renderer)?;
+            match window.window.toplevel() {
+                Kind::Xdg(toplevel) => toplevel.send_close(),
+                // X11 windows go away together with XWayland
+                Kind::X11(_) => {}
+            }
         }
-        Ok(())
     }
 
-    pub fn render (&self, frame: &mut Gles2Frame, screen_id: usize, size: Size<i32, Physical>) -> Result<(), Box<dyn Error>> {
+    /// Import the buffers of the windows, except those of clients that are over the memory limit.
+    pub fn import (&mut self, renderer: &mut R, refused: &HashSet<ClientId>)
+        -> Result<(), Box<dyn Error>>
+    {
+        self.textures.frame_start();
         for window in self.windows.iter() {
-            window.render(&self.logger, frame, self.screens[screen_id].center, size)?;
+            let client = window.window.toplevel().wl_surface().client().map(|client| client.id());
+            if client.map(|client| refused.contains(&client)).unwrap_or(false) {
+                continue
+            }
+            window.import(&self.logger, renderer, &mut self.textures)?;
         }
         Ok(())
     }
 
-    pub fn send_frames (&self, output: &Output) {
+    pub fn render (
+        &self,
+        frame:     &mut R::Frame<'_>,
+        screen_id: usize,
+        size:      Size<i32, Physical>,
+        scale:     f64
+    ) -> Result<(), Box<dyn Error>> {
+        let offset = self.screens[screen_id].center;
+        // A fullscreen window covers everything else on its screen
+        if let Some(window) = self.windows_visible().find(|w| w.fullscreen_on(screen_id)) {
+            frame.clear([0.0, 0.0, 0.0, 1.0], &[Rectangle::from_loc_and_size((0, 0), size)])?;
+            let dimmed = self.modal_child(window.window.toplevel().wl_surface()).is_some();
+            return window.render::<R>(&self.logger, frame, offset, size, scale, 0.0, dimmed)
+        }
+        let radius = self.decorations.corner_radius as f64;
+        self.render_ghosts(frame, &self.screens[screen_id], scale)?;
+        for window in self.windows_stacked() {
+            if window.fullscreen.is_none() {
+                let geometry = window.geometry_on(&self.screens[screen_id]);
+                let area = Rectangle::<i32, Physical>::from_loc_and_size(
+                    geometry.loc.to_f64().to_physical(scale).to_i32_round(),
+                    geometry.size.to_f64().to_physical(scale).to_i32_round()
+                );
+                self.decorations.render_shadow(frame, area, scale)?;
+                if let Some(border) = self.decorations.border {
+                    let focused = self.focused.as_ref() == Some(window.window.toplevel().wl_surface());
+                    let color = if window.urgent {
+                        border.urgent
+                    } else if focused {
+                        border.focused
+                    } else {
+                        border.unfocused
+                    };
+                    self.decorations.render_border(frame, geometry, scale, color)?;
+                }
+            }
+            let dimmed = self.modal_child(window.window.toplevel().wl_surface()).is_some();
+            window.render::<R>(&self.logger, frame, offset, size, scale, radius, dimmed)?;
+        }
+        Ok(())
+    }
+
+    /// How windows on a screen are sampled: as the fullscreen window on it asks, if any.
+    pub fn scaling_on (&self, screen_id: ScreenId) -> Scaling {
+        self.windows_visible().find(|w| w.fullscreen_on(screen_id))
+            .map(|window| window.scaling)
+            .unwrap_or_default()
+    }
+
+    /// The topmost window at a point on a screen, going by the windows' rounded shapes.
+    pub fn window_under (&self, screen_id: ScreenId, point: Point<f64, Logical>) -> Option<WlSurface> {
+        self.surface_under(screen_id, point).map(|(surface, _)| surface)
+    }
+
+    /// The surface of the topmost window at a point on a screen,
+    /// and where the surface's origin is on the screen.
+    pub fn surface_under (&self, screen_id: ScreenId, point: Point<f64, Logical>)
+        -> Option<(WlSurface, Point<i32, Logical>)>
+    {
+        let screen = self.screens.get(screen_id)?;
+        let surface = |w: &WindowState| (
+            w.window.toplevel().wl_surface().clone(),
+            w.geometry_on(screen).loc
+        );
+        if let Some(window) = self.windows_visible().find(|w| w.fullscreen_on(screen_id)) {
+            return Some(surface(window))
+        }
+        let windows: Vec<&WindowState> = self.windows_stacked().collect();
+        windows.into_iter().rev().find(|window| {
+            let area = window.geometry_on(screen).to_f64();
+            self.decorations.contains(area.size, point - area.loc)
+        }).map(surface)
+    }
+
+    /// Send surface enter and leave events for the windows that start or stop
+    /// overlapping the output that displays this screen, so that clients
+    /// can pick a buffer scale to match it.
+    pub fn update_output (&self, output: &Output, screen_id: ScreenId) {
+        let screen = &self.screens[screen_id];
+        let area = match output.current_mode() {
+            Some(mode) => {
+                let size = transform_size(output.current_transform(), mode.size);
+                let size = size.to_f64().to_logical(output.current_scale().fractional_scale());
+                Rectangle::<i32, Logical>::from_loc_and_size((0, 0), size.to_i32_round())
+            },
+            None => return
+        };
+        let current = self.workspaces.current;
         for window in self.windows.iter() {
+            let overlap = if window.workspace == current && !window.minimized {
+                window.geometry_on(screen).intersection(area)
+            } else {
+                None
+            };
+            match overlap {
+                Some(overlap) => window.window.output_enter(output, overlap),
+                None => window.window.output_leave(output)
+            }
+        }
+    }
+
+    /// Milliseconds on the compositor clock, for timestamping synthesized events.
+    pub fn now_ms (&self) -> u32 {
+        Duration::from(self.clock.now()).as_millis() as u32
+    }
+
+    /// When the earliest of the frame callbacks held back by frame rate limits is due.
+    pub fn frames_held (&self) -> Option<Instant> {
+        self.windows_visible().filter_map(|w| w.frame_held(self.occluded_fps)).min()
+    }
+
+    /// Find out which windows can't be seen, because they're covered by the windows above
+    /// them or are off the given areas of the screens. Windows are taken to be opaque
+    /// within their geometry, except those faded for being unresponsive.
+    pub fn occlusion_update (&mut self, areas: &[(ScreenId, Rectangle<i32, Logical>)]) {
+        let stacked: Vec<u64> = self.windows_stacked().map(|w| w.id).collect();
+        let mut visible = HashSet::new();
+        for (screen, area) in areas.iter() {
+            let state = &self.screens[*screen];
+            let mut covers: Vec<Rectangle<i32, Logical>> = vec![];
+            for id in stacked.iter().rev() {
+                let window = match self.windows.iter().find(|w| w.id == *id) {
+                    Some(window) => window,
+                    None => continue
+                };
+                let geometry = if window.fullscreen_on(*screen) { *area } else { window.geometry_on(state) };
+                if let Some(shown) = geometry.intersection(*area) {
+                    if !covered(shown, &covers) {
+                        visible.insert(*id);
+                    }
+                    if !window.unresponsive {
+                        covers.push(shown);
+                    }
+                }
+            }
+        }
+        for window in self.windows.iter_mut() {
+            let occluded = !visible.contains(&window.id);
+            if window.occluded != occluded {
+                debug!(self.logger, "Window {} is {}", window.id, if occluded { "occluded" } else { "visible" });
+                window.occluded = occluded;
+            }
+        }
+    }
+
+    /// The screen that shows the most of a window, or the one it's fullscreen on.
+    pub fn window_screen (&self, window: &WindowState) -> Option<ScreenId> {
+        if let Some(fullscreen) = window.fullscreen.as_ref() {
+            return Some(fullscreen.screen)
+        }
+        self.screens.iter().enumerate()
+            .filter_map(|(id, screen)| {
+                let area = Rectangle::from_loc_and_size((0, 0), screen.size.to_i32_round());
+                let shown = window.geometry_on(screen).intersection(area)?;
+                Some((id, shown.size.w * shown.size.h))
+            })
+            .max_by_key(|(_, shown)| *shown)
+            .map(|(id, _)| id)
+    }
+
+    pub fn send_frames (&self, output: &Output) {
+        let now = Instant::now();
+        let screen = self.screen_find(&output.name());
+        for window in self.windows_visible() {
+            // Callbacks go out in the pass for the screen the window is mostly on, so only that
+            // one may count against the window's frame rate
+            match (screen, self.window_screen(window)) {
+                (Some(screen), Some(owner)) if owner != screen => continue,
+                _ => {}
+            }
+            if !window.frame_due(now, self.occluded_fps) {
+                continue
+            }
             window.window.send_frame(
                 output,
                 Duration::from(self.clock.now()),
                 Some(Duration::from_secs(1)),
-                smithay::desktop::utils::surface_primary_scanout_output
+                surface_primary_scanout_output
             );
         }
     }
@@ -83,6 +582,13 @@ impl<E: Engine> CompositorHandler for Charlie<E> {
     /// AFAIK This buffer contains the texture which is imported before each render.
     fn commit (&mut self, surface: &WlSurface) {
         //debug!(self.logger, "Commit {surface:?}");
+        self.redraw.request();
+        // The focused window may be showing the effect of the latest input
+        let focused_client = self.desktop.focused.as_ref()
+            .and_then(|focused| focused.client()).map(|client| client.id());
+        if focused_client.is_some() && focused_client == surface.client().map(|client| client.id()) {
+            self.latency.committed();
+        }
         use smithay::backend::renderer::utils::{
             RendererSurfaceState         as State,
             RendererSurfaceStateUserData as StateData
@@ -96,12 +602,36 @@ impl<E: Engine> CompositorHandler for Charlie<E> {
 
             warn!(self.logger, "Init surface: {surface:?}");
 
-            with_states(&surface, |surface_data| {
+            // A buffer with an acquire fence is taken into use once the fence signals
+            let fence = self.explicit_sync_commit(&surface);
+
+            let textures = &mut self.desktop.textures;
+            let accessed = with_surface(&surface, |surface_data| {
                 is_new = surface_data.data_map.insert_if_missing(||RefCell::new(State::default()));
-                let mut data = surface_data.data_map.get::<StateData>().unwrap().borrow_mut();
-                data.update_buffer(surface_data);
+                // A buffer attached again with damage has new contents, so its texture is stale
+                {
+                    let attributes = surface_data.cached_state.current::<SurfaceAttributes>();
+                    if let Some(BufferAssignment::NewBuffer(buffer)) = attributes.buffer.as_ref() {
+                        if !attributes.damage.is_empty() {
+                            textures.invalidate(buffer);
+                        }
+                    }
+                }
+                if fence.is_none() {
+                    let mut data = surface_data.data_map.get::<StateData>().unwrap().borrow_mut();
+                    data.update_buffer(surface_data);
+                }
             });
 
+            // Nothing else about the commit can be looked at either, and it's been logged
+            if accessed.is_err() {
+                break
+            }
+
+            if let Some(fence) = fence {
+                self.explicit_sync_wait(surface.clone(), fence);
+            }
+
             if is_new {
                 add_destruction_hook(&surface, |data| {
                     let data = data.data_map.get::<StateData>();
@@ -118,12 +648,34 @@ impl<E: Engine> CompositorHandler for Charlie<E> {
 
         }
 
-        if let Some(window) = self.desktop.window_find(&surface) {
-            window.on_commit();
-        } else {
+        // Surfaces of X11 windows may be created after XWayland told us about the windows
+        self.x11_commit(&surface);
+
+        if !self.desktop.window_commit(&surface) {
             warn!(self.logger, "could not find window for root toplevel surface {surface:?}");
         };
 
+        // Follow what the window says it shows
+        self.content_type_commit(&surface);
+
+        // Keep a window that's being resized anchored at the edges that aren't dragged
+        self.resize_commit(&surface);
+
+        // Let transactions know that the window caught up
+        self.transaction_commit(&surface);
+
+        // Hold back clients whose buffers take too much memory
+        self.memory_commit(&surface);
+
+        // Place new windows where they were before, and let scripts know about them
+        if let Some(window) = self.desktop.window_state_mut(&surface) {
+            self.thumbnails.damage(window.id);
+            if !window.mapped {
+                window.mapped = true;
+                self.commands.push(Command::MapWindow(surface));
+            }
+        }
+
     }
 
 }
@@ -137,8 +689,14 @@ impl<E: Engine> XdgShellHandler for Charlie<E> {
 
     fn new_toplevel (&mut self, surface: ToplevelSurface) {
         debug!(self.logger, "New toplevel surface: {surface:?}");
-        surface.send_configure();
+        let wl_surface = surface.wl_surface().clone();
+        self.commands.push(Command::Configure(surface.clone()));
         self.desktop.window_add(Window::new(Kind::Xdg(surface)));
+        self.commands.push(Command::Focus(Some(wl_surface)));
+    }
+
+    fn toplevel_destroyed (&mut self, surface: ToplevelSurface) {
+        self.window_destroyed(surface.wl_surface());
     }
 
     fn new_popup (&mut self, surface: PopupSurface, positioner: PositionerState) {
@@ -157,56 +715,73 @@ impl<E: Engine> XdgShellHandler for Charlie<E> {
         surface.send_repositioned(token);
     }
 
-    fn move_request (&mut self, surface: ToplevelSurface, seat: WlSeat, serial: Serial) {
-        //let seat = Seat::from_resource(&seat).unwrap();
-        //let wl_surface = surface.wl_surface();
-        //if let Some(start_data) = check_grab(&seat, wl_surface, serial) {
-            //let pointer = seat.get_pointer().unwrap();
-            //let window = self.window_find(wl_surface).unwrap();
-            //let initial_window_location = Default::default();//self.space.element_location(&window).unwrap();
-            //let grab = MoveSurfaceGrab { start_data, window: window.clone(), initial_window_location, };
-            //pointer.set_grab(self, grab, serial, Focus::Clear);
-        //}
+    fn move_request (&mut self, surface: ToplevelSurface, seat: WlSeat, _serial: Serial) {
+        if let Some(index) = self.seat_index(&seat) {
+            self.move_start(surface.wl_surface(), index);
+        }
     }
 
     fn resize_request (
         &mut self,
         surface: ToplevelSurface,
         seat: WlSeat,
-        serial: Serial,
+        _serial: Serial,
         edges: XdgToplevelResizeEdge,
     ) {
-        //let seat = Seat::from_resource(&seat).unwrap();
-        //let wl_surface = surface.wl_surface();
-        //if let Some(start_data) = check_grab(&seat, wl_surface, serial) {
-            //let pointer = seat.get_pointer().unwrap();
-            //let window = self.window_find(wl_surface).unwrap();
-            ////let initial_window_location = Default::default();//self.space.element_location(&window).unwrap();
-            ////let initial_window_size = (*window).geometry().size;
-            //surface.with_pending_state(|state| { state.states.set(XdgToplevelState::Resizing); });
-            //surface.send_configure();
-            ////let grab = ResizeSurfaceGrab::start(
-                ////start_data,
-                ////window.clone(),
-                ////edges.into(),
-                ////Rectangle::from_loc_and_size(initial_window_location, initial_window_size),
-            ////);
-            ////pointer.set_grab(self, grab, serial, Focus::Clear);
-        //}
+        if let Some(index) = self.seat_index(&seat) {
+            self.resize_start(surface, index, edges);
+        }
     }
 
     fn grab (&mut self, _surface: PopupSurface, _seat: WlSeat, _serial: Serial) {
         // TODO popup grabs
     }
 
+    fn fullscreen_request (&mut self, surface: ToplevelSurface, output: Option<WlOutput>) {
+        let screen = output
+            .and_then(|output| Output::from_resource(&output))
+            .and_then(|output| self.desktop.screen_find(&output.name()))
+            .unwrap_or(0);
+        if let Err(e) = self.fullscreen(&surface, screen) {
+            warn!(self.logger, "Could not make {surface:?} fullscreen: {e}");
+        }
+    }
+
+    fn unfullscreen_request (&mut self, surface: ToplevelSurface) {
+        // Windows of a kiosk stay fullscreen
+        if self.kiosk.enabled {
+            return
+        }
+        if let Err(e) = self.unfullscreen(&surface) {
+            warn!(self.logger, "Could not restore {surface:?} from fullscreen: {e}");
+        }
+    }
+
+    fn minimize_request (&mut self, surface: ToplevelSurface) {
+        if self.kiosk.enabled {
+            return
+        }
+        self.minimize(surface.wl_surface())
+    }
+
+    fn client_pong (&mut self, client: ShellClient) {
+        self.watchdog_pong(client)
+    }
+
     fn ack_configure(&mut self, surface: WlSurface, configure: smithay::wayland::shell::xdg::Configure) {
         debug!(self.logger, "ack_configure {surface:?} -> {configure:?}");
+        if let smithay::wayland::shell::xdg::Configure::Toplevel(configure) = &configure {
+            self.resize_ack(&surface, configure);
+            self.transaction_ack(&surface, configure);
+        }
     }
 }
 
 pub struct ScreenState {
     pub center: Point<f64, Logical>,
-    size: Size<f64, Logical>
+    pub size:   Size<f64, Logical>,
+    /// Name of the output that displays this screen
+    pub output: Option<String>,
 }
 
 impl ScreenState {
@@ -214,7 +789,7 @@ impl ScreenState {
         center: impl Into<Point<f64, Logical>>,
         size:   impl Into<Size<f64, Logical>>
     ) -> Self {
-        Self { center: center.into(), size: size.into() }
+        Self { center: center.into(), size: size.into(), output: None }
     }
     #[inline]
     pub fn center (&self) -> &Point<f64, Logical> {
@@ -223,19 +798,264 @@ impl ScreenState {
 }
 
 pub struct WindowState {
-    pub window: Window,
-    center: Point<f64, Logical>,
-    size:   Size<f64, Logical>
+    /// Unique id of the window, for referring to it from outside
+    pub id:        u64,
+    pub window:    Window,
+    /// The client of the window, kept for when the window outlives it
+    pub client:    Option<ClientId>,
+    /// Index of the workspace this window belongs to
+    pub workspace: usize,
+    /// Whether the window rules have been applied since the last rule change
+    pub rules_applied: bool,
+    /// Maximum rate at which to send frame callbacks
+    pub max_fps:   Option<u32>,
+    /// When the last frame callback was sent
+    last_frame:    Cell<Option<Instant>>,
+    /// Set while the window is fullscreen
+    pub fullscreen: Option<Fullscreen>,
+    /// Whether the window is hidden from rendering and input
+    pub minimized: bool,
+    /// Whether the window has been committed for the first time
+    pub mapped:    bool,
+    /// Which band of the stack the window is kept in
+    pub layer:     StackLayer,
+    /// Whether the window's geometry is restored and saved across restarts
+    pub remember_geometry: bool,
+    /// How the window's buffer is sampled when it's drawn scaled
+    pub scaling:   Scaling,
+    /// Whether the window's client stopped answering pings
+    pub unresponsive: bool,
+    /// Whether the window wants attention, until it gets focus
+    pub urgent:    bool,
+    /// The size the window is being resized to, while it hasn't caught up, and how to draw it at that size
+    pub resizing:  Option<(Size<i32, Logical>, ResizePolicy)>,
+    /// The window of another client that this one is a dialog of (`xdg_foreign`)
+    pub parent:    Option<WlSurface>,
+    /// What the window's client says it shows
+    pub content_type: ContentType,
+    /// Whether the window is covered by others or off the outputs, as of the last frame
+    pub occluded:  bool,
+    /// Where the window was before it was made picture-in-picture, while it is
+    pub pip:       Option<Pip>,
+    /// Where the window was before it was made to fill its screen, while it does
+    pub filled:    Option<(Point<f64, Logical>, Size<f64, Logical>)>,
+    /// Whether the window is hidden while windows started from it are open
+    pub swallows:  bool,
+    /// Whether the window hides the terminal it was started from
+    pub swallowable: bool,
+    /// The terminal hidden while this window is open
+    pub swallowed: Option<WlSurface>,
+    /// Where the window goes when it's mapped, if a rule says
+    pub placement: Option<WindowPlacement>,
+    /// Whether the window's client says that it's a modal dialog, through `xdg_dialog_v1`
+    pub modal_hint: bool,
+    /// Whether a rule says that the window is a modal dialog or isn't, whatever its client says
+    pub modal_rule: Option<bool>,
+    pub center:    Point<f64, Logical>,
+    pub size:      Size<f64, Logical>
 }
 
 impl WindowState {
 
-    pub fn new (window: Window) -> Self {
-        Self { window, center: (0.0, 0.0).into(), size: (0.0, 0.0).into() }
+    pub fn new (id: u64, window: Window, workspace: usize) -> Self {
+        let client = window.toplevel().wl_surface().client().map(|client| client.id());
+        Self {
+            id,
+            window,
+            client,
+            workspace,
+            rules_applied: false,
+            max_fps:       None,
+            last_frame:    Cell::new(None),
+            fullscreen:    None,
+            minimized:     false,
+            mapped:        false,
+            layer:         StackLayer::Normal,
+            remember_geometry: true,
+            scaling:       Scaling::Linear,
+            unresponsive:  false,
+            urgent:        false,
+            resizing:      None,
+            parent:        None,
+            content_type:  ContentType::None,
+            occluded:      false,
+            pip:           None,
+            filled:        None,
+            swallows:      false,
+            swallowable:   true,
+            swallowed:     None,
+            placement:     None,
+            modal_hint:    false,
+            modal_rule:    None,
+            center:        (0.0, 0.0).into(),
+            size:          (0.0, 0.0).into()
+        }
     }
 
-    /// Import the window's surface into the renderer as a texture
-    pub fn import (&self, logger: &Logger, renderer: &mut Gles2Renderer)
+    /// Get the app id and title of the window, if it has them.
+    pub fn app_id_and_title (&self) -> (Option<String>, Option<String>) {
+        match self.window.toplevel() {
+            Kind::Xdg(toplevel) => with_surface(toplevel.wl_surface(), |states| {
+                let attributes = states.data_map.get::<XdgToplevelSurfaceData>()
+                    .unwrap().lock().unwrap();
+                (attributes.app_id.clone(), attributes.title.clone())
+            }).unwrap_or_default(),
+            Kind::X11(_) => (None, None)
+        }
+    }
+
+    /// The window this one is a dialog of, set by another client through `xdg_foreign`,
+    /// or by the window's own client.
+    pub fn parent_surface (&self) -> Option<WlSurface> {
+        self.parent.clone().or_else(|| match self.window.toplevel() {
+            Kind::Xdg(toplevel) => toplevel.parent(),
+            Kind::X11(_) => None
+        })
+    }
+
+    /// Whether the window, if it's a dialog, keeps its parent from taking input while it's open.
+    pub fn modal (&self) -> bool {
+        self.modal_rule.unwrap_or(self.modal_hint)
+    }
+
+    /// Who the client of the window is, if it connected through one of our sockets.
+    pub fn identity (&self) -> Option<Identity> {
+        let client = self.window.toplevel().wl_surface().client()?;
+        let state = client.get_data::<ClientState>()?;
+        Some(state.identity.clone())
+    }
+
+    /// Reset the window's properties to the ones given by the matching rules.
+    pub fn apply_rules (&mut self, rules: &[WindowRule]) {
+        let (app_id, title) = self.app_id_and_title();
+        let identity = self.identity();
+        self.max_fps = None;
+        self.remember_geometry = true;
+        self.scaling = Scaling::Linear;
+        self.swallows = false;
+        self.swallowable = true;
+        self.placement = None;
+        self.modal_rule = None;
+        for rule in rules.iter().filter(|r| r.matches(app_id.as_deref(), title.as_deref(), identity.as_ref(), self.content_type)) {
+            if rule.max_fps.is_some() {
+                self.max_fps = rule.max_fps;
+            }
+            if let Some(remember) = rule.remember_geometry {
+                self.remember_geometry = remember;
+            }
+            if let Some(scaling) = rule.scaling {
+                self.scaling = scaling;
+            }
+            if let Some(swallow) = rule.swallow {
+                self.swallows = swallow;
+            }
+            if let Some(swallowable) = rule.swallowable {
+                self.swallowable = swallowable;
+            }
+            if rule.placement.is_some() {
+                self.placement = rule.placement;
+            }
+            if rule.modal.is_some() {
+                self.modal_rule = rule.modal;
+            }
+        }
+        self.rules_applied = true;
+    }
+
+    /// Where the window is on a screen, in the screen's logical coordinates.
+    pub fn geometry_on (&self, screen: &ScreenState) -> Rectangle<i32, Logical> {
+        let location = Point::<f64, Logical>::from((
+            self.center.x + screen.center.x,
+            self.center.y + screen.center.y
+        ));
+        Rectangle::from_loc_and_size(location.to_i32_round(), self.window.geometry().size)
+    }
+
+    /// Where a fullscreen window that's scaled by whole numbers is drawn on a screen of this
+    /// logical size, and how many physical pixels each of its logical pixels takes up.
+    pub fn integer_zoom (&self, area: Size<f64, Logical>, scale: f64) -> Option<(Point<f64, Logical>, f64)> {
+        if self.scaling != Scaling::Integer || self.fullscreen.is_none() {
+            return None
+        }
+        let size = self.window.geometry().size.to_f64();
+        if size.w <= 0.0 || size.h <= 0.0 {
+            return None
+        }
+        let zoom = ((area.w / size.w).min(area.h / size.h) * scale).floor().max(1.0);
+        let origin = (
+            (area.w - size.w * zoom / scale) / 2.0,
+            (area.h - size.h * zoom / scale) / 2.0
+        ).into();
+        Some((origin, zoom))
+    }
+
+    /// Whether this window is fullscreen on the given screen.
+    pub fn fullscreen_on (&self, screen: ScreenId) -> bool {
+        self.fullscreen.as_ref().map(|f| f.screen == screen).unwrap_or(false)
+    }
+
+    /// How many frame callbacks per second the window may get: up to its own limit,
+    /// and up to the one for windows that can't be seen while it can't.
+    pub fn fps_limit (&self, occluded_fps: Option<u32>) -> Option<u32> {
+        let own = self.max_fps.filter(|fps| *fps > 0);
+        match occluded_fps.filter(|_| self.occluded) {
+            Some(occluded) => Some(own.map(|own| own.min(occluded)).unwrap_or(occluded).max(1)),
+            None => own
+        }
+    }
+
+    /// Whether enough time has passed since the last frame callback to send another one.
+    /// Windows held back here keep their callbacks pending until a later frame.
+    pub fn frame_due (&self, now: Instant, occluded_fps: Option<u32>) -> bool {
+        if let Some(fps) = self.fps_limit(occluded_fps) {
+            if let Some(last) = self.last_frame.get() {
+                if now.duration_since(last) < Duration::from_secs(1) / fps {
+                    return false
+                }
+            }
+        }
+        self.last_frame.set(Some(now));
+        true
+    }
+
+    /// When the frame callbacks that the window is waiting for are due,
+    /// if it's waiting for any and they're held back by its frame rate limit.
+    pub fn frame_held (&self, occluded_fps: Option<u32>) -> Option<Instant> {
+        let fps  = self.fps_limit(occluded_fps)?;
+        let last = self.last_frame.get()?;
+        let waiting = with_surface(self.window.toplevel().wl_surface(), |states| {
+            !states.cached_state.current::<SurfaceAttributes>().frame_callbacks.is_empty()
+        }).unwrap_or(false);
+        waiting.then(|| last + Duration::from_secs(1) / fps)
+    }
+
+    /// How big to draw a surface of this size, and how many of its logical pixels go into each
+    /// one drawn: while catching up with a resize, it's clipped or scaled to the requested size.
+    fn shown_size (&self, surface: Size<f64, Logical>) -> (Size<f64, Logical>, (f64, f64)) {
+        let geometry = self.window.geometry().size.to_f64();
+        match self.resizing {
+            Some((target, policy)) if geometry.w > 0.0 && geometry.h > 0.0 => {
+                // The surface may be bigger than its geometry, e.g. to draw shadows
+                let target = target.to_f64();
+                match policy {
+                    ResizePolicy::Clip => ((
+                        surface.w.min(surface.w - geometry.w + target.w).max(0.0),
+                        surface.h.min(surface.h - geometry.h + target.h).max(0.0)
+                    ).into(), (1.0, 1.0)),
+                    ResizePolicy::Scale => {
+                        let factor = (geometry.w / target.w, geometry.h / target.h);
+                        ((surface.w / factor.0, surface.h / factor.1).into(), factor)
+                    }
+                }
+            },
+            _ => (surface, (1.0, 1.0))
+        }
+    }
+
+    /// Import the window's surface into the renderer as a texture, or reuse the texture
+    /// its buffer was already imported into. Shared memory buffers are released right away,
+    /// since the texture has a copy; dmabufs are kept until the client attaches another.
+    pub fn import <R: EngineRenderer> (&self, logger: &Logger, renderer: &mut R, cache: &mut TextureCache<R>)
         -> Result<(), Box<dyn Error>>
     {
         let surface = match self.window.toplevel() {
@@ -243,38 +1063,83 @@ impl WindowState {
             Kind::X11(x11surface) => &x11surface.surface
         };
 
-        with_states(surface, |surface_data| {
+        with_surface(surface, |surface_data| {
             if let Some(data) = surface_data.data_map.get::<RendererSurfaceStateUserData>() {
 
                 let data = &mut *data.borrow_mut();
 
                 let texture_id = (
-                    TypeId::of::<<Gles2Renderer as Renderer>::TextureId>(),
+                    TypeId::of::<R::TextureId>(),
                     renderer.id().clone()
                 );
 
                 if let Entry::Vacant(entry) = data.textures.entry(texture_id) {
-                    if let Some(buffer) = data.buffer.as_ref() {
-                        match renderer.import_buffer(
-                            buffer, Some(surface_data), &match buffer_dimensions(buffer) {
-                                Some(size) => vec![Rectangle::from_loc_and_size((0, 0), size)],
-                                None       => vec![]
-                            }
-                        ) {
-                            Some(Ok(m)) => {
-                                warn!(logger, "Loading {m:?}");
-                                entry.insert(Box::new(m));
-                            }
-                            Some(Err(err)) => {
-                                warn!(logger, "Error loading buffer: {}", err);
-                                return Err(err);
+                    let buffer = match data.buffer.clone() {
+                        Some(buffer) => buffer,
+                        None => {
+                            warn!(logger, "No buffer in {surface_data:?}");
+                            return Ok(())
+                        }
+                    };
+                    let cached = cache.get(&buffer);
+                    // Large copies past the frame's budget wait for the next frame,
+                    // while the surface keeps showing its previous texture
+                    if cached.is_none() && is_shm(&buffer) {
+                        let bytes = buffer_dimensions(&buffer)
+                            .map(|size| size.w.max(0) as usize * size.h.max(0) as usize * 4)
+                            .unwrap_or(0);
+                        if !cache.may_upload(bytes) {
+                            return Ok(())
+                        }
+                    }
+                    let texture = match cached {
+                        // Another surface already imported this buffer
+                        Some(texture) => texture,
+                        // Shared memory formats that the renderer can't sample are converted first
+                        None => if let Some(converted) = shm_convert(&buffer) {
+                            match converted {
+                                Ok((pixels, size)) => renderer.import_memory(&pixels, size, false)?,
+                                Err(e) => {
+                                    warn!(logger, "Error converting buffer: {e}");
+                                    return Ok(())
+                                }
                             }
-                            None => {
-                                error!(logger, "Unknown buffer format for: {:?}", buffer);
+                        } else {
+                            match renderer.import_buffer(
+                                &buffer, Some(surface_data), &match buffer_dimensions(&buffer) {
+                                    Some(size) => vec![Rectangle::from_loc_and_size((0, 0), size)],
+                                    None       => vec![]
+                                }
+                            ) {
+                                Some(Ok(m)) => {
+                                    warn!(logger, "Loading {m:?}");
+                                    m
+                                }
+                                Some(Err(err)) => {
+                                    warn!(logger, "Error loading buffer: {}", err);
+                                    return Err(err);
+                                }
+                                None => {
+                                    error!(logger, "Unknown buffer format for: {:?}", buffer);
+                                    return Ok(())
+                                }
                             }
                         }
-                    } else {
-                        warn!(logger, "No buffer in {surface_data:?}")
+                    };
+                    surface_data.data_map.insert_if_missing(ImportedSize::default);
+                    if let Some(size) = surface_data.data_map.get::<ImportedSize>() {
+                        size.0.set(Some(texture.size()));
+                    }
+                    surface_data.data_map.insert_if_missing(ShownTexture::<R::TextureId>::default);
+                    if let Some(shown) = surface_data.data_map.get::<ShownTexture<R::TextureId>>() {
+                        *shown.0.borrow_mut() = Some(texture.clone());
+                    }
+                    cache.insert(&buffer, texture.clone());
+                    entry.insert(Box::new(texture));
+                    // The texture has a copy, so the client can have the memory back
+                    if is_shm(&buffer) {
+                        data.buffer = None;
+                        buffer.release();
                     }
                 }
 
@@ -284,42 +1149,129 @@ impl WindowState {
 
             Ok(())
 
-        })?;
+        })??;
 
         Ok(())
     }
 
-    /// Render the window's imported texture into the current frame
-    pub fn render (
+    /// Render the window's imported texture scaled down to fit an area, keeping its aspect ratio.
+    pub fn render_thumbnail <R: EngineRenderer> (&self, frame: &mut R::Frame<'_>, size: Size<i32, Physical>)
+        -> Result<(), Box<dyn Error>>
+    {
+        let surface = match self.window.toplevel() {
+            Kind::Xdg(xdgsurface) => xdgsurface.wl_surface(),
+            Kind::X11(x11surface) => &x11surface.surface
+        };
+        with_surface(surface, |surface_data| {
+            let buffer_transform: Transform = surface_data.cached_state
+                .current::<SurfaceAttributes>().buffer_transform.into();
+            let data = match surface_data.data_map.get::<RendererSurfaceStateUserData>() {
+                Some(data) => data,
+                None => return Ok(())
+            };
+            let data = data.borrow();
+            let texture = match data.texture::<R>(frame.id()) {
+                Some(texture) => texture,
+                None => return Ok(())
+            };
+            let buffer_size = texture.size();
+            let src = Rectangle::<f64, Buffer>::from_loc_and_size((0.0, 0.0), buffer_size.to_f64());
+            let content = transform_size(buffer_transform, buffer_size).to_f64();
+            let scale = (size.w as f64 / content.w).min(size.h as f64 / content.h).min(1.0);
+            let dest_size = Size::<f64, Physical>::from((content.w * scale, content.h * scale));
+            let dest = Rectangle::<i32, Physical>::from_loc_and_size(
+                (((size.w as f64 - dest_size.w) / 2.0) as i32, ((size.h as f64 - dest_size.h) / 2.0) as i32),
+                dest_size.to_i32_round()
+            );
+            frame.render_texture_from_to(
+                texture, src, dest, &[Rectangle::from_loc_and_size((0, 0), dest.size)],
+                buffer_transform, 1.0
+            )?;
+            Ok(())
+        })?
+    }
+
+    /// Render the window's imported texture into the current frame,
+    /// darkened if it's behind a modal dialog
+    pub fn render <R: EngineRenderer> (
         &self,
         logger: &Logger,
-        frame:  &mut Gles2Frame,
+        frame:  &mut R::Frame<'_>,
         offset: Point<f64, Logical>,
-        size:   Size<i32, Physical>
+        size:   Size<i32, Physical>,
+        scale:  f64,
+        radius: f64,
+        dimmed: bool
     )
         -> Result<(), Box<dyn Error>>
     {
 
-        let (src, dest, damage): (Rectangle<f64, Buffer>, Rectangle<i32, Physical>, Rectangle<i32, Physical>) = (
-            Rectangle::from_loc_and_size((0.0, 0.0), (size.w as f64, size.h as f64)),
-            Rectangle::from_loc_and_size((
-                self.center.x as i32 + offset.x as i32,
-                self.center.y as i32 + offset.y as i32
-            ), size),
-            Rectangle::from_loc_and_size((0, 0), size)
-        );
-
         let surface = match self.window.toplevel() {
             Kind::Xdg(xdgsurface) => xdgsurface.wl_surface(),
             Kind::X11(x11surface) => &x11surface.surface
         };
 
-        with_states(surface, |surface_data| {
+        let location = Point::<f64, Logical>::from((
+            self.center.x + offset.x,
+            self.center.y + offset.y
+        )).to_physical(scale).to_i32_round();
+        let damage = Rectangle::<i32, Physical>::from_loc_and_size((0, 0), size);
+
+        // Fullscreen windows scaled by whole numbers are drawn in the middle of their screen
+        let (location, zoom) = match self.integer_zoom(size.to_f64().to_logical(scale), scale) {
+            Some((origin, zoom)) => (origin.to_physical(scale).to_i32_round(), zoom),
+            None => (location, scale)
+        };
+
+        // A window that isn't responding is drawn faded into gray,
+        // and one behind a modal dialog darkened
+        let fade = if self.unresponsive {
+            Some((UNRESPONSIVE_COLOR, UNRESPONSIVE_ALPHA))
+        } else if dimmed {
+            Some((MODAL_COLOR, MODAL_ALPHA))
+        } else {
+            None
+        };
+        let alpha = fade.map(|(_, alpha)| alpha).unwrap_or(1.0);
+
+        with_surface(surface, |surface_data| {
+            // The client may have drawn its buffer rotated or flipped
+            let (buffer_transform, buffer_scale) = {
+                let attributes = surface_data.cached_state.current::<SurfaceAttributes>();
+                (Transform::from(attributes.buffer_transform), attributes.buffer_scale.max(1))
+            };
             if let Some(data) = surface_data.data_map.get::<RendererSurfaceStateUserData>() {
-                if let Some(texture) = data.borrow().texture::<Gles2Renderer>(frame.id()) {
-                    frame.render_texture_from_to(
-                        texture, src, dest, &[damage], Transform::Normal, 1.0f32
-                    ).unwrap();
+                // While the upload of a new buffer waits, the previous texture is drawn instead
+                let shown = surface_data.data_map.get::<ShownTexture<R::TextureId>>()
+                    .and_then(|shown| shown.0.borrow().clone());
+                if let Some(texture) = data.borrow().texture::<R>(frame.id()).or(shown.as_ref()) {
+                    let buffer_size = texture.size();
+                    // The client drew at buffer_scale; draw it at the size it means on this output
+                    let surface_size = transform_size(buffer_transform, buffer_size).to_f64();
+                    let surface_size = Size::<f64, Logical>::from((
+                        surface_size.w / buffer_scale as f64,
+                        surface_size.h / buffer_scale as f64
+                    ));
+                    let (shown_size, factor) = self.shown_size(surface_size);
+                    // Draw the window row by row in the corners to round them off
+                    for row in rounded_rows(shown_size, radius) {
+                        let src = Rectangle::<f64, Logical>::from_loc_and_size(
+                            (row.loc.x * factor.0, row.loc.y * factor.1),
+                            (row.size.w * factor.0, row.size.h * factor.1)
+                        ).to_buffer(buffer_scale as f64, buffer_transform, &surface_size);
+                        let dest = Rectangle::<i32, Physical>::from_loc_and_size(
+                            location + row.loc.to_physical(zoom).to_i32_round(),
+                            row.size.to_physical(zoom).to_i32_round()
+                        );
+                        if let Some((color, _)) = fade {
+                            if let Err(e) = frame.clear(color, &[dest]) {
+                                warn!(logger, "Could not fade window: {e}");
+                            }
+                        }
+                        frame.render_texture_from_to(
+                            texture, src, dest, &[damage], buffer_transform, alpha
+                        ).unwrap();
+                    }
                 } else {
                     warn!(logger, "No texture in this renderer for {data:?}");
                     //frame.render_texture_from_to(
@@ -329,7 +1281,7 @@ impl WindowState {
             } else {
                 warn!(logger, "No RendererSurfaceState for {surface:?}")
             }
-        });
+        })?;
 
         Ok(())
 
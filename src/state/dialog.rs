@@ -0,0 +1,169 @@
+use super::prelude::*;
+
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource,
+    backend::{ClientId, GlobalId, ObjectId},
+};
+
+use self::protocol::{
+    xdg_wm_dialog_v1::{self, XdgWmDialogV1},
+    xdg_dialog_v1::{self, XdgDialogV1},
+};
+
+/// Code generated from `protocols/xdg-dialog-v1.xml`.
+pub mod protocol {
+    #![allow(non_upper_case_globals, non_camel_case_types, unused)]
+    use smithay::reexports::wayland_server;
+    use smithay::reexports::wayland_server::protocol::*;
+    use wayland_protocols::xdg::shell::server::*;
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        use wayland_protocols::xdg::shell::server::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/xdg-dialog-v1.xml");
+    }
+    use self::__interfaces::*;
+    wayland_scanner::generate_server_code!("protocols/xdg-dialog-v1.xml");
+}
+
+/// Whether a toplevel has a dialog object, kept with its surface.
+#[derive(Default)]
+struct SurfaceDialog {
+    taken: Cell<bool>,
+}
+
+/// Lets clients say which of their dialogs are modal (`xdg_wm_dialog_v1`).
+///
+/// A modal dialog keeps its parent from being focused while it's open, and darkens it.
+/// Dialogs are only modal if their client says so, or a window rule does.
+pub struct Dialogs {
+    logger: Logger,
+    global: GlobalId,
+}
+
+impl Dialogs {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle) -> Self {
+        Self {
+            logger: logger.clone(),
+            global: display.create_global::<Charlie<E>, XdgWmDialogV1, _>(1, ()),
+        }
+    }
+
+    /// Stop advertising the protocol.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.global.clone());
+    }
+
+}
+
+impl<E: Engine> GlobalDispatch<XdgWmDialogV1, ()> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<XdgWmDialogV1>,
+        _data:     &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<E: Engine> Dispatch<XdgWmDialogV1, ()> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        _client:   &Client,
+        resource:  &XdgWmDialogV1,
+        request:   xdg_wm_dialog_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            xdg_wm_dialog_v1::Request::GetXdgDialog { id, toplevel } => {
+                let surface = state.desktop.windows_all()
+                    .find_map(|window| match window.window.toplevel() {
+                        Kind::Xdg(xdg) if xdg.xdg_toplevel() == &toplevel => Some(xdg.wl_surface().clone()),
+                        _ => None
+                    });
+                let surface = match surface {
+                    Some(surface) => surface,
+                    // The toplevel is gone, so the dialog object is inert
+                    None => {
+                        data_init.init(id, None);
+                        return
+                    }
+                };
+                let taken = with_states(&surface, |states| {
+                    states.data_map.insert_if_missing(SurfaceDialog::default);
+                    states.data_map.get::<SurfaceDialog>().unwrap().taken.replace(true)
+                });
+                if taken {
+                    resource.post_error(
+                        xdg_wm_dialog_v1::Error::AlreadyUsed,
+                        "toplevel already has a dialog object"
+                    );
+                    return
+                }
+                data_init.init(id, Some(surface));
+            },
+            xdg_wm_dialog_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<XdgDialogV1, Option<WlSurface>> for Charlie<E> {
+    fn request (
+        state:      &mut Self,
+        _client:    &Client,
+        _resource:  &XdgDialogV1,
+        request:    xdg_dialog_v1::Request,
+        surface:    &Option<WlSurface>,
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let surface = match surface {
+            Some(surface) => surface,
+            None => return
+        };
+        match request {
+            xdg_dialog_v1::Request::SetModal => state.dialog_modal(surface, true),
+            xdg_dialog_v1::Request::UnsetModal => state.dialog_modal(surface, false),
+            xdg_dialog_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+    fn destroyed (
+        state:   &mut Self,
+        _client: ClientId,
+        _object: ObjectId,
+        surface: &Option<WlSurface>,
+    ) {
+        // The dialog stops being modal along with its dialog object
+        if let Some(surface) = surface.as_ref().filter(|surface| surface.is_alive()) {
+            with_states(surface, |states| {
+                if let Some(data) = states.data_map.get::<SurfaceDialog>() {
+                    data.taken.set(false);
+                }
+            });
+            state.dialog_modal(surface, false);
+        }
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Follow what a client says about whether its dialog is modal.
+    pub fn dialog_modal (&mut self, surface: &WlSurface, modal: bool) {
+        let window = match self.desktop.window_state_mut(surface) {
+            Some(window) if window.modal_hint != modal => window,
+            _ => return
+        };
+        debug!(self.dialogs.logger, "Window {} is {}modal", window.id, if modal { "" } else { "not " });
+        window.modal_hint = modal;
+        // Its parent is darkened or not
+        self.redraw.request();
+    }
+
+}
@@ -0,0 +1,35 @@
+use super::prelude::*;
+
+use smithay::desktop::utils::surface_primary_scanout_output;
+
+use super::content::ContentType;
+
+impl<E: Engine> Charlie<E> {
+
+    /// Tell a window which buffers it should allocate, now that it has entered
+    /// or left fullscreen, or started or stopped showing video. Fullscreen and video windows
+    /// get the scanout tranches of their output, so the engine may put their buffers
+    /// on screen, or on an overlay plane, without compositing them.
+    pub fn dmabuf_feedback_update (&mut self, surface: &WlSurface) {
+        let window = match self.desktop.window_state(surface) {
+            Some(window) => window,
+            None => return
+        };
+        let (screen, scanout) = match &window.fullscreen {
+            Some(fullscreen) => (fullscreen.screen, true),
+            // Video may go on an overlay plane
+            None => (self.desktop.active_screen, window.content_type == ContentType::Video)
+        };
+        let output = match self.engine.output_get(screen) {
+            Some(output) => output,
+            None => return
+        };
+        let feedback = match self.engine.dmabuf_feedback(screen, scanout) {
+            Some(feedback) => feedback,
+            None => return
+        };
+        debug!(self.logger, "Sending dmabuf feedback to window {} (scanout: {scanout})", window.id);
+        window.window.send_dmabuf_feedback(&output, surface_primary_scanout_output, |_, _| feedback);
+    }
+
+}
@@ -0,0 +1,90 @@
+use super::prelude::*;
+
+/// A window being moved by dragging it.
+struct Drag {
+    surface: WlSurface,
+    /// The screen the move started on; motion on other screens is ignored
+    screen:  ScreenId,
+    /// Where the pointer was when the move started
+    origin:  Point<f64, Logical>,
+    /// Where the window was when the move started
+    start:   Point<f64, Logical>,
+}
+
+/// Interactive moving of windows, whether the client asked for it by its title bar
+/// or the window was grabbed with the logo key held.
+///
+/// The window follows the pointer directly; clients don't see the motion until the button
+/// is released, and the window's new place is remembered then.
+pub struct Moving {
+    logger: Logger,
+    /// The move in progress, if any
+    active: Option<Drag>,
+}
+
+impl Moving {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), active: None }
+    }
+
+    /// The window being moved, if any.
+    pub fn window (&self) -> Option<&WlSurface> {
+        self.active.as_ref().map(|drag| &drag.surface)
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Which pointer belongs to a seat that a client sent.
+    pub fn seat_index (&self, seat: &WlSeat) -> Option<usize> {
+        let seat = Seat::<Self>::from_resource(seat)?;
+        self.input.seats.iter().position(|s| *s == seat)
+    }
+
+    /// Start moving a window, following a pointer.
+    pub fn move_start (&mut self, surface: &WlSurface, index: usize) {
+        let (origin, screen) = {
+            let pointer = &self.input.pointers[index];
+            (pointer.location, pointer.screen)
+        };
+        let window = match self.desktop.window_state(surface) {
+            Some(window) if window.fullscreen.is_none() => window,
+            _ => return
+        };
+        debug!(self.moving.logger, "Moving window {}", window.id);
+        self.moving.active = Some(Drag { surface: surface.clone(), screen, origin, start: window.center });
+    }
+
+    /// Follow the pointer while moving. Returns false if no move is in progress.
+    pub fn move_motion (&mut self, screen: ScreenId, location: Point<f64, Logical>) -> bool {
+        let drag = match self.moving.active.as_ref() {
+            Some(drag) => drag,
+            None => return false
+        };
+        if drag.screen == screen {
+            let center = drag.start + (location - drag.origin);
+            match self.desktop.window_state_mut(&drag.surface) {
+                Some(window) => window.center = center,
+                None => self.moving.active = None
+            }
+            self.redraw.request();
+        }
+        true
+    }
+
+    /// Finish moving when the button is released, remembering where the window ended up.
+    /// Returns false if no move is in progress.
+    pub fn move_end (&mut self) -> bool {
+        match self.moving.active.take() {
+            Some(drag) => {
+                self.geometry_remember(&drag.surface);
+                self.pip_snap(&drag.surface);
+                true
+            },
+            None => false
+        }
+    }
+
+}
@@ -0,0 +1,240 @@
+use super::prelude::*;
+use super::shader::{Program, FULLSCREEN_VERTEX};
+
+use smithay::backend::renderer::gles2::ffi;
+
+/// Applies all effects in one pass over a copy of the finished frame
+const EFFECTS_FRAGMENT: &str = r#"
+#version 100
+precision mediump float;
+uniform sampler2D tex;
+uniform mat3 simulate;
+uniform float grayscale;
+uniform float invert;
+uniform float vignette;
+uniform float dim;
+varying vec2 v_coords;
+void main() {
+    vec3 color = texture2D(tex, v_coords).rgb;
+    // Shift what a color vision deficiency hides into channels that can be told apart
+    vec3 error = color - simulate * color;
+    color = clamp(color + vec3(0.0, 0.7 * error.r + error.g, 0.7 * error.r + error.b), 0.0, 1.0);
+    color = mix(color, vec3(dot(color, vec3(0.2126, 0.7152, 0.0722))), grayscale);
+    color = mix(color, 1.0 - color, invert);
+    color *= 1.0 - vignette * smoothstep(0.3, 0.75, length(v_coords - 0.5));
+    color *= 1.0 - dim;
+    gl_FragColor = vec4(color, 1.0);
+}
+"#;
+
+/// Color correction for a kind of color blindness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorFilter {
+    None,
+    /// For red-blindness
+    Protanopia,
+    /// For green-blindness
+    Deuteranopia,
+    /// For blue-blindness
+    Tritanopia,
+}
+
+impl ColorFilter {
+
+    pub fn name (&self) -> &'static str {
+        match self {
+            Self::None         => "none",
+            Self::Protanopia   => "protanopia",
+            Self::Deuteranopia => "deuteranopia",
+            Self::Tritanopia   => "tritanopia",
+        }
+    }
+
+    /// How colors look with this deficiency, as a column-major RGB matrix.
+    fn simulation (&self) -> [f32; 9] {
+        const RGB_TO_LMS: [[f64; 3]; 3] = [
+            [17.8824,    43.5161,  4.11935],
+            [3.45565,    27.1554,  3.86714],
+            [0.0299566,  0.184309, 1.46709],
+        ];
+        const LMS_TO_RGB: [[f64; 3]; 3] = [
+            [0.0809444479,   -0.130504409,   0.116721066],
+            [-0.0102485335,   0.0540193266, -0.113614708],
+            [-0.000365296938, -0.00412161469, 0.693511405],
+        ];
+        let deficiency = match self {
+            Self::None         => return [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            Self::Protanopia   => [[0.0, 2.02344, -2.52581], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            Self::Deuteranopia => [[1.0, 0.0, 0.0], [0.494207, 0.0, 1.24827], [0.0, 0.0, 1.0]],
+            Self::Tritanopia   => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [-0.395913, 0.801109, 0.0]],
+        };
+        let multiply = |a: [[f64; 3]; 3], b: [[f64; 3]; 3]| {
+            let mut product = [[0.0; 3]; 3];
+            for row in 0..3 {
+                for col in 0..3 {
+                    product[row][col] = (0..3).map(|i| a[row][i] * b[i][col]).sum();
+                }
+            }
+            product
+        };
+        let matrix = multiply(LMS_TO_RGB, multiply(deficiency, RGB_TO_LMS));
+        let mut columns = [0.0; 9];
+        for col in 0..3 {
+            for row in 0..3 {
+                columns[col * 3 + row] = matrix[row][col] as f32;
+            }
+        }
+        columns
+    }
+
+}
+
+impl std::str::FromStr for ColorFilter {
+    type Err = Box<dyn Error>;
+    fn from_str (filter: &str) -> StdResult<Self> {
+        Ok(match filter {
+            "none"         => Self::None,
+            "protanopia"   => Self::Protanopia,
+            "deuteranopia" => Self::Deuteranopia,
+            "tritanopia"   => Self::Tritanopia,
+            _ => return Err(format!("unknown color filter: {filter}").into())
+        })
+    }
+}
+
+/// Effects applied to whole outputs after everything else is drawn.
+///
+/// The finished frame is copied into a texture, which is then drawn back
+/// over the frame through a shader that applies the enabled effects.
+pub struct Effects {
+    logger:            Logger,
+    /// Show everything in shades of gray
+    pub grayscale:     bool,
+    /// Invert all colors
+    pub invert:        bool,
+    /// Correct colors for color blindness
+    pub filter:        ColorFilter,
+    /// How much to darken the corners, from 0 to 1
+    pub vignette:      f32,
+    /// How much to darken outputs other than the one that last received input, from 0 to 1
+    pub dim_unfocused: f32,
+    program:           Option<Program>,
+    /// Texture holding the copy of each screen's frame, and its size
+    copies:            HashMap<ScreenId, (u32, Size<i32, Physical>)>,
+}
+
+impl Effects {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:        logger.clone(),
+            grayscale:     false,
+            invert:        false,
+            filter:        ColorFilter::None,
+            vignette:      0.0,
+            dim_unfocused: 0.0,
+            program:       None,
+            copies:        HashMap::new(),
+        }
+    }
+
+    /// Whether any effect is on, and so whether the extra pass is needed at all.
+    pub fn enabled (&self) -> bool {
+        self.grayscale || self.invert || self.filter != ColorFilter::None
+            || self.vignette > 0.0 || self.dim_unfocused > 0.0
+    }
+
+    /// Describe the current settings.
+    pub fn describe (&self) -> String {
+        format!("grayscale {}\ninvert {}\nfilter {}\nvignette {}\ndim {}",
+            self.grayscale, self.invert, self.filter.name(), self.vignette, self.dim_unfocused)
+    }
+
+    /// Compile the shader, unless already done.
+    pub fn prepare (&mut self, renderer: &mut Gles2Renderer) -> StdResult<()> {
+        if self.enabled() && self.program.is_none() {
+            debug!(self.logger, "Compiling effects shader");
+            let program = renderer.with_context(|gl| unsafe {
+                Program::new(gl, FULLSCREEN_VERTEX, EFFECTS_FRAGMENT, &[
+                    "tex", "simulate", "grayscale", "invert", "vignette", "dim"
+                ])
+            })??;
+            self.program = Some(program);
+        }
+        Ok(())
+    }
+
+    /// Apply the effects to the finished frame of a screen.
+    pub fn render (
+        &mut self,
+        frame:   &mut Gles2Frame,
+        screen:  ScreenId,
+        size:    Size<i32, Physical>,
+        focused: bool
+    ) -> StdResult<()> {
+        if !self.enabled() {
+            return Ok(())
+        }
+        let program = match self.program.as_ref() {
+            Some(program) => program,
+            None => return Ok(())
+        };
+        let simulate = self.filter.simulation();
+        let grayscale = if self.grayscale { 1.0 } else { 0.0 };
+        let invert    = if self.invert { 1.0 } else { 0.0 };
+        let vignette  = self.vignette.clamp(0.0, 1.0);
+        let dim       = if focused { 0.0 } else { self.dim_unfocused.clamp(0.0, 1.0) };
+        let copy      = self.copies.get(&screen).copied();
+        let texture = frame.with_context(|gl| unsafe {
+            // Keep one texture per screen, reallocated when the screen changes size
+            let texture = match copy {
+                Some((texture, copy_size)) if copy_size == size => texture,
+                previous => {
+                    let mut texture = previous.map(|(texture, _)| texture).unwrap_or(0);
+                    if texture == 0 {
+                        gl.GenTextures(1, &mut texture);
+                    }
+                    gl.BindTexture(ffi::TEXTURE_2D, texture);
+                    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MIN_FILTER, ffi::LINEAR as i32);
+                    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_MAG_FILTER, ffi::LINEAR as i32);
+                    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_WRAP_S, ffi::CLAMP_TO_EDGE as i32);
+                    gl.TexParameteri(ffi::TEXTURE_2D, ffi::TEXTURE_WRAP_T, ffi::CLAMP_TO_EDGE as i32);
+                    gl.TexImage2D(
+                        ffi::TEXTURE_2D, 0, ffi::RGB as i32, size.w, size.h, 0,
+                        ffi::RGB, ffi::UNSIGNED_BYTE, std::ptr::null()
+                    );
+                    texture
+                }
+            };
+            // Copy the frame into the texture and draw it back through the shader
+            gl.ActiveTexture(ffi::TEXTURE0);
+            gl.BindTexture(ffi::TEXTURE_2D, texture);
+            gl.CopyTexSubImage2D(ffi::TEXTURE_2D, 0, 0, 0, 0, 0, size.w, size.h);
+            gl.Disable(ffi::BLEND);
+            program.draw_fullscreen(gl, |gl, program| {
+                gl.Uniform1i(program.uniform("tex"), 0);
+                gl.UniformMatrix3fv(program.uniform("simulate"), 1, ffi::FALSE, simulate.as_ptr());
+                gl.Uniform1f(program.uniform("grayscale"), grayscale);
+                gl.Uniform1f(program.uniform("invert"), invert);
+                gl.Uniform1f(program.uniform("vignette"), vignette);
+                gl.Uniform1f(program.uniform("dim"), dim);
+            });
+            gl.Enable(ffi::BLEND);
+            gl.BindTexture(ffi::TEXTURE_2D, 0);
+            texture
+        })?;
+        self.copies.insert(screen, (texture, size));
+        Ok(())
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Set the effects applied to all outputs.
+    pub fn effects (mut self, setup: impl FnOnce(&mut Effects)) -> CharlieResult<Self> {
+        setup(&mut self.effects);
+        Ok(self)
+    }
+
+}
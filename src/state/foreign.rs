@@ -0,0 +1,230 @@
+use super::prelude::*;
+
+use rand::{Rng, distributions::Alphanumeric};
+
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource,
+    backend::{ClientId, GlobalId, ObjectId},
+};
+
+use wayland_protocols::xdg::foreign::zv2::server::{
+    zxdg_exporter_v2::{self, ZxdgExporterV2},
+    zxdg_exported_v2::{self, ZxdgExportedV2},
+    zxdg_importer_v2::{self, ZxdgImporterV2},
+    zxdg_imported_v2::{self, ZxdgImportedV2},
+};
+
+/// Length of the handles that exported windows are known by
+const HANDLE_LENGTH: usize = 32;
+
+/// Lets a client make its dialog a child of another client's window (`zxdg_foreign_v2`),
+/// such as a file picker opened by a portal on behalf of a sandboxed app. One client
+/// exports its window and passes the handle it gets to the other, which imports it.
+pub struct ForeignState {
+    logger:       Logger,
+    exporter:     GlobalId,
+    importer:     GlobalId,
+    /// Exported windows, by handle
+    pub exported: HashMap<String, WlSurface>,
+    /// Imports of the exported windows, to tell when they go away
+    imported:     Vec<(String, ZxdgImportedV2)>,
+}
+
+impl ForeignState {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle) -> Self {
+        Self {
+            logger:   logger.clone(),
+            exporter: display.create_global::<Charlie<E>, ZxdgExporterV2, _>(1, ()),
+            importer: display.create_global::<Charlie<E>, ZxdgImporterV2, _>(1, ()),
+            exported: HashMap::new(),
+            imported: vec![],
+        }
+    }
+
+    /// Stop advertising the protocol.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.exporter.clone());
+        display.remove_global::<Charlie<E>>(self.importer.clone());
+    }
+
+}
+
+impl<E: Engine> GlobalDispatch<ZxdgExporterV2, ()> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<ZxdgExporterV2>,
+        _data:     &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<E: Engine> Dispatch<ZxdgExporterV2, ()> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        _client:   &Client,
+        _resource: &ZxdgExporterV2,
+        request:   zxdg_exporter_v2::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zxdg_exporter_v2::Request::ExportToplevel { id, surface } => {
+                let handle: String = rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(HANDLE_LENGTH)
+                    .map(char::from)
+                    .collect();
+                debug!(state.foreign.logger, "Exporting {surface:?} as {handle}");
+                let exported = data_init.init(id, handle.clone());
+                exported.handle(handle.clone());
+                state.foreign.exported.insert(handle, surface);
+            },
+            zxdg_exporter_v2::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<ZxdgExportedV2, String> for Charlie<E> {
+    fn request (
+        _state:     &mut Self,
+        _client:    &Client,
+        _resource:  &ZxdgExportedV2,
+        _request:   zxdg_exported_v2::Request,
+        _handle:    &String,
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+    fn destroyed (
+        state:   &mut Self,
+        _client: ClientId,
+        _object: ObjectId,
+        handle:  &String,
+    ) {
+        state.foreign_unexport(handle);
+    }
+}
+
+impl<E: Engine> GlobalDispatch<ZxdgImporterV2, ()> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<ZxdgImporterV2>,
+        _data:     &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<E: Engine> Dispatch<ZxdgImporterV2, ()> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        _client:   &Client,
+        _resource: &ZxdgImporterV2,
+        request:   zxdg_importer_v2::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zxdg_importer_v2::Request::ImportToplevel { id, handle } => {
+                let imported = data_init.init(id, handle.clone());
+                if state.foreign.exported.contains_key(&handle) {
+                    state.foreign.imported.push((handle, imported));
+                } else {
+                    debug!(state.foreign.logger, "Client imported unknown handle {handle}");
+                    imported.destroyed();
+                }
+            },
+            zxdg_importer_v2::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<ZxdgImportedV2, String> for Charlie<E> {
+    fn request (
+        state:      &mut Self,
+        _client:    &Client,
+        _resource:  &ZxdgImportedV2,
+        request:    zxdg_imported_v2::Request,
+        handle:     &String,
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zxdg_imported_v2::Request::SetParentOf { surface } => {
+                let parent = match state.foreign.exported.get(handle) {
+                    Some(parent) => parent.clone(),
+                    None => return
+                };
+                state.window_set_parent(&surface, Some(parent));
+            },
+            zxdg_imported_v2::Request::Destroy => {},
+            _ => {}
+        }
+    }
+    fn destroyed (
+        state:   &mut Self,
+        _client: ClientId,
+        object:  ObjectId,
+        _handle: &String,
+    ) {
+        state.foreign.imported.retain(|(_, imported)| imported.id() != object);
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Forget an exported window, letting its importers know, and let go of the dialogs
+    /// that were made its children through it.
+    fn foreign_unexport (&mut self, handle: &str) {
+        let surface = match self.foreign.exported.remove(handle) {
+            Some(surface) => surface,
+            None => return
+        };
+        debug!(self.foreign.logger, "Unexporting {handle}");
+        self.foreign.imported.retain(|(imported_handle, imported)| {
+            if imported_handle == handle {
+                imported.destroyed();
+                false
+            } else {
+                true
+            }
+        });
+        self.desktop.orphan(&surface);
+    }
+
+    /// Make a window the child of another one, keeping it above its parent
+    /// and centered on it, or make it a window of its own again.
+    pub fn window_set_parent (&mut self, surface: &WlSurface, parent: Option<WlSurface>) {
+        if let Some(parent) = parent.as_ref() {
+            if parent == surface || self.desktop.descends_from(parent, surface) {
+                warn!(self.foreign.logger, "Not making {surface:?} a dialog of its own dialog");
+                return
+            }
+        }
+        let window = match self.desktop.window_state_mut(surface) {
+            Some(window) => window,
+            None => {
+                warn!(self.foreign.logger, "Can't set parent of {surface:?}, which is not a window");
+                return
+            }
+        };
+        window.parent = parent;
+        if window.mapped {
+            self.desktop.center_on_parent(surface);
+        }
+        self.desktop.raise(surface);
+    }
+
+}
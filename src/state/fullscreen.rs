@@ -0,0 +1,88 @@
+use super::prelude::*;
+
+/// What a fullscreen window covers and what to restore when it leaves fullscreen.
+#[derive(Debug)]
+pub struct Fullscreen {
+    /// The screen covered by the window
+    pub screen:       ScreenId,
+    /// Where the window was before going fullscreen
+    pub restore_center: Point<f64, Logical>,
+    /// How big the window was before going fullscreen
+    pub restore_size: Size<f64, Logical>,
+    /// The output mode to switch back to, if it was changed
+    pub restore_mode: Option<Size<i32, Physical>>,
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Make a window cover a screen, optionally switching the output to a matching mode.
+    pub fn fullscreen (&mut self, surface: &ToplevelSurface, screen: ScreenId) -> CharlieResult<()> {
+        let screen_state = self.desktop.screens.get(screen)
+            .ok_or_else(|| CharlieError::Config(format!("no screen {screen}")))?;
+        let (offset, mut size) = (screen_state.center, screen_state.size);
+        let mode_switch = self.desktop.fullscreen_mode_switch;
+        let window = match self.desktop.window_state_mut(surface.wl_surface()) {
+            Some(window) => window,
+            None => return Ok(())
+        };
+        if window.fullscreen.is_some() {
+            return Ok(())
+        }
+
+        // Try to give the client the resolution it's already drawing at
+        let mut restore_mode = None;
+        if mode_switch {
+            let wanted = window.window.geometry().size.to_f64().to_physical(1.0).to_i32_round();
+            match self.engine.output_set_mode(screen, wanted) {
+                Ok(previous) => {
+                    restore_mode = Some(previous);
+                    size = wanted.to_f64().to_logical(1.0);
+                },
+                Err(e) => debug!(self.logger, "Not switching mode for fullscreen: {e}")
+            }
+        }
+
+        window.fullscreen = Some(Fullscreen {
+            screen,
+            restore_center: window.center,
+            restore_size:   window.size,
+            restore_mode,
+        });
+        // Windows are drawn relative to the screen center, so this puts it at the origin
+        window.center = (-offset.x, -offset.y).into();
+        window.size   = size;
+
+        surface.with_pending_state(|state| {
+            state.states.set(XdgToplevelState::Fullscreen);
+            state.size = Some(size.to_i32_round());
+        });
+        surface.send_configure();
+        self.dmabuf_feedback_update(surface.wl_surface());
+        Ok(())
+    }
+
+    /// Put a fullscreen window back where it was, and restore the output mode.
+    pub fn unfullscreen (&mut self, surface: &ToplevelSurface) -> CharlieResult<()> {
+        let window = match self.desktop.window_state_mut(surface.wl_surface()) {
+            Some(window) => window,
+            None => return Ok(())
+        };
+        let fullscreen = match window.fullscreen.take() {
+            Some(fullscreen) => fullscreen,
+            None => return Ok(())
+        };
+        window.center = fullscreen.restore_center;
+        window.size   = fullscreen.restore_size;
+        surface.with_pending_state(|state| {
+            state.states.unset(XdgToplevelState::Fullscreen);
+            state.size = Some(fullscreen.restore_size.to_i32_round());
+        });
+        surface.send_configure();
+        self.dmabuf_feedback_update(surface.wl_surface());
+        if let Some(mode) = fullscreen.restore_mode {
+            self.engine.output_set_mode(fullscreen.screen, mode)?;
+        }
+        Ok(())
+    }
+
+}
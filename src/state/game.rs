@@ -0,0 +1,82 @@
+use super::prelude::*;
+
+/// A mode for the focused fullscreen window that trades niceties for latency.
+///
+/// While it's on, thumbnails aren't rendered, the effects pass is skipped, and the main loop
+/// waits less for events between frames. Tearing page flips are up to the client, through
+/// [tearing control](super::tearing). It turns itself off when the window loses focus
+/// or stops being fullscreen.
+pub struct GameMode {
+    logger:       Logger,
+    /// The window that the mode is on for
+    pub window:   Option<WlSurface>,
+    /// How long the main loop waits for events between frames while the mode is on
+    pub deadline: Duration,
+}
+
+impl GameMode {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), window: None, deadline: Duration::ZERO }
+    }
+
+    /// Whether the mode is on.
+    pub fn active (&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// How long the main loop may wait for events between frames, if it may not wait
+    /// for as long as there are none.
+    pub fn frame_wait (&self) -> Option<Duration> {
+        self.active().then_some(self.deadline)
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Set how long the main loop waits for events between frames in game mode.
+    pub fn game_mode_deadline (mut self, deadline: Duration) -> CharlieResult<Self> {
+        self.game.deadline = deadline;
+        Ok(self)
+    }
+
+    /// Turn game mode on for the focused window, if it's fullscreen.
+    pub fn game_mode_enter (&mut self) -> StdResult<()> {
+        let surface = self.desktop.focused.clone().ok_or("no window is focused")?;
+        let window = self.desktop.window_state(&surface).ok_or("the focused surface is not a window")?;
+        if window.fullscreen.is_none() {
+            return Err("the focused window is not fullscreen".into())
+        }
+        let id = window.id;
+        self.game_mode_exit();
+        info!(self.game.logger, "Game mode on for window {id}");
+        self.game.window = Some(surface);
+        self.ipc_event(&format!("game mode on {id}"));
+        Ok(())
+    }
+
+    /// Turn game mode off.
+    pub fn game_mode_exit (&mut self) {
+        if self.game.window.take().is_none() {
+            return
+        }
+        info!(self.game.logger, "Game mode off");
+        self.ipc_event("game mode off");
+    }
+
+    /// Turn game mode off if its window lost focus, stopped being fullscreen, or went away.
+    pub fn game_mode_check (&mut self) {
+        let surface = match self.game.window.as_ref() {
+            Some(surface) => surface,
+            None => return
+        };
+        let fullscreen = self.desktop.window_state(surface)
+            .map(|window| window.fullscreen.is_some())
+            .unwrap_or(false);
+        if !fullscreen || self.desktop.focused.as_ref() != Some(surface) {
+            self.game_mode_exit();
+        }
+    }
+
+}
@@ -0,0 +1,163 @@
+use super::prelude::*;
+
+use std::{fs::File, io::Read, os::fd::{FromRawFd, RawFd}};
+
+use super::security::Gate;
+
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource,
+    backend::{ClientId, GlobalId, ObjectId},
+};
+
+use wayland_protocols_wlr::gamma_control::v1::server::{
+    zwlr_gamma_control_manager_v1::{self, ZwlrGammaControlManagerV1},
+    zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+};
+
+/// Lets clients such as redshift and gammastep set the gamma ramps of outputs
+/// (`zwlr_gamma_control_v1`). Only outputs with hardware gamma support it,
+/// and only one client at a time per output. Which clients see it is up to the
+/// [Protocol::GammaControl](super::security::Protocol::GammaControl) policy.
+pub struct GammaControlState {
+    logger:       Logger,
+    global:       GlobalId,
+    /// The client gamma control of each screen
+    pub controls: HashMap<ScreenId, ZwlrGammaControlV1>,
+}
+
+impl GammaControlState {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle, gate: Gate) -> Self {
+        Self {
+            logger:   logger.clone(),
+            global:   display.create_global::<Charlie<E>, ZwlrGammaControlManagerV1, _>(1, gate),
+            controls: HashMap::new(),
+        }
+    }
+
+    /// Stop advertising the protocol.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.global.clone());
+    }
+
+    /// Whether a client has taken over the gamma of this screen.
+    pub fn controlled (&self, screen: ScreenId) -> bool {
+        self.controls.contains_key(&screen)
+    }
+
+}
+
+impl<E: Engine> GlobalDispatch<ZwlrGammaControlManagerV1, Gate> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<ZwlrGammaControlManagerV1>,
+        _gate:     &Gate,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+
+    fn can_view (client: Client, gate: &Gate) -> bool {
+        gate.admits(&client)
+    }
+}
+
+impl<E: Engine> Dispatch<ZwlrGammaControlManagerV1, ()> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        _client:   &Client,
+        _resource: &ZwlrGammaControlManagerV1,
+        request:   zwlr_gamma_control_manager_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_gamma_control_manager_v1::Request::GetGammaControl { id, output } => {
+                let screen = Output::from_resource(&output)
+                    .and_then(|output| state.desktop.screen_find(&output.name()));
+                let size = screen.and_then(|screen| state.engine.output_gamma_size(screen));
+                match (screen, size) {
+                    (Some(screen), Some(size)) if !state.gamma.controlled(screen) => {
+                        let control = data_init.init(id, Some(screen));
+                        control.gamma_size(size);
+                        state.gamma.controls.insert(screen, control);
+                    },
+                    _ => {
+                        // No hardware gamma, or another client already has it
+                        let control = data_init.init(id, None);
+                        control.failed();
+                    }
+                }
+            },
+            zwlr_gamma_control_manager_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<ZwlrGammaControlV1, Option<ScreenId>> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        _client:   &Client,
+        resource:  &ZwlrGammaControlV1,
+        request:   zwlr_gamma_control_v1::Request,
+        screen:    &Option<ScreenId>,
+        _display:  &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let screen = match screen {
+            Some(screen) => *screen,
+            None => return
+        };
+        match request {
+            zwlr_gamma_control_v1::Request::SetGamma { fd } => {
+                if let Err(e) = state.gamma_set(screen, fd) {
+                    warn!(state.logger, "Could not set gamma of screen {screen}: {e}");
+                    state.gamma_release(screen);
+                    resource.failed();
+                }
+            },
+            zwlr_gamma_control_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+    fn destroyed (
+        state:   &mut Self,
+        _client: ClientId,
+        _object: ObjectId,
+        screen:  &Option<ScreenId>,
+    ) {
+        if let Some(screen) = screen {
+            state.gamma_release(*screen);
+        }
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Load the gamma ramps that a client has written into a file descriptor.
+    fn gamma_set (&mut self, screen: ScreenId, fd: RawFd) -> StdResult<()> {
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        let size = self.engine.output_gamma_size(screen).ok_or("output has no gamma")? as usize;
+        let mut bytes = vec![0u8; size * 3 * 2];
+        file.read_exact(&mut bytes)?;
+        let ramps: Vec<u16> = bytes.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect();
+        debug!(self.gamma.logger, "Client set gamma of screen {screen}");
+        self.engine.output_set_gamma(screen, Some(&ramps))?;
+        Ok(())
+    }
+
+    /// Give the gamma of a screen back to the compositor after its client control is gone.
+    fn gamma_release (&mut self, screen: ScreenId) {
+        if self.gamma.controls.remove(&screen).is_some() {
+            if let Err(e) = self.engine.output_set_gamma(screen, None) {
+                warn!(self.logger, "Could not reset gamma of screen {screen}: {e}");
+            }
+            self.night_light.reset();
+        }
+    }
+
+}
@@ -0,0 +1,188 @@
+use super::prelude::*;
+
+use std::path::PathBuf;
+
+/// Where windows were last seen, remembered across restarts.
+///
+/// Windows are told apart by a hash of their app id and title, so that a window
+/// which maps again with the same identity is put back where it was.
+/// Entries are kept one per line, as `hash x y w h`.
+pub struct GeometryStore {
+    logger:      Logger,
+    /// File that the geometries are loaded from and saved to
+    pub path:    Option<PathBuf>,
+    /// Window center and size by identity hash
+    entries:     HashMap<u64, (Point<f64, Logical>, Size<f64, Logical>)>,
+    /// Whether there are changes that haven't been saved
+    dirty:       bool,
+}
+
+impl GeometryStore {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), path: None, entries: HashMap::new(), dirty: false }
+    }
+
+    /// Where geometries are stored by default: `$XDG_STATE_HOME/charlie/geometry`.
+    pub fn default_path () -> Option<PathBuf> {
+        std::env::var_os("XDG_STATE_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+            .map(|state| state.join("charlie").join("geometry"))
+    }
+
+    /// Identify a window across restarts. This must not change between builds,
+    /// so it's FNV-1a rather than the standard library's hasher.
+    pub fn key (app_id: &str, title: &str) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in app_id.bytes().chain(std::iter::once(0)).chain(title.bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+
+    /// Use the given file, reading the geometries already stored in it.
+    /// A missing file is not an error, since it's created on first save.
+    pub fn load (&mut self, path: impl Into<PathBuf>) -> StdResult<()> {
+        let path = path.into();
+        self.entries.clear();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                match Self::parse(line) {
+                    Ok((key, center, size)) => { self.entries.insert(key, (center, size)); },
+                    Err(e) => warn!(self.logger, "Skipping geometry entry {line:?}: {e}")
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e.into())
+        }
+        debug!(self.logger, "Loaded {} window geometries from {}", self.entries.len(), path.display());
+        self.path = Some(path);
+        Ok(())
+    }
+
+    fn parse (line: &str) -> StdResult<(u64, Point<f64, Logical>, Size<f64, Logical>)> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [key, x, y, w, h] => Ok((
+                u64::from_str_radix(key, 16)?,
+                (x.parse::<f64>()?, y.parse::<f64>()?).into(),
+                (w.parse::<f64>()?, h.parse::<f64>()?).into()
+            )),
+            _ => Err("expected: hash x y w h".into())
+        }
+    }
+
+    /// Write the stored geometries back to the file, if anything changed.
+    pub fn save (&mut self) -> StdResult<()> {
+        let path = match (&self.path, self.dirty) {
+            (Some(path), true) => path,
+            _ => return Ok(())
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for (key, (center, size)) in self.entries.iter() {
+            text.push_str(&format!("{key:016x} {} {} {} {}\n", center.x, center.y, size.w, size.h));
+        }
+        // Replace the file at once, so a crash can't leave it half written
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, text)?;
+        std::fs::rename(&temp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// The last known geometry of a window.
+    pub fn get (&self, key: u64) -> Option<(Point<f64, Logical>, Size<f64, Logical>)> {
+        self.entries.get(&key).copied()
+    }
+
+    /// Update the last known geometry of a window.
+    pub fn set (&mut self, key: u64, center: Point<f64, Logical>, size: Size<f64, Logical>) {
+        if self.entries.insert(key, (center, size)) != Some((center, size)) {
+            self.dirty = true;
+        }
+    }
+
+    /// Forget the geometry of all windows.
+    pub fn clear (&mut self) {
+        self.dirty |= !self.entries.is_empty();
+        self.entries.clear();
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Remember window geometries in this file instead of the default one.
+    pub fn geometry_store (mut self, path: impl Into<PathBuf>) -> CharlieResult<Self> {
+        let path = path.into();
+        self.geometry.load(&path).context(format!("Could not load {}", path.display()))?;
+        Ok(self)
+    }
+
+    /// The identity of a window in the geometry store, unless a rule opted it out.
+    fn geometry_key (&self, surface: &WlSurface) -> Option<u64> {
+        let window = self.desktop.window_state(surface).filter(|w| w.remember_geometry)?;
+        match window.app_id_and_title() {
+            (Some(app_id), title) => Some(GeometryStore::key(&app_id, &title.unwrap_or_default())),
+            // Without an app id, unrelated windows would share an entry
+            (None, _) => None
+        }
+    }
+
+    /// Put a newly mapped window where it was the last time it was seen, if it was.
+    /// Returns whether it was.
+    pub fn geometry_restore (&mut self, surface: &WlSurface) -> bool {
+        let (center, size) = match self.geometry_key(surface).and_then(|key| self.geometry.get(key)) {
+            Some(geometry) => geometry,
+            None => return false
+        };
+        let window = match self.desktop.window_state_mut(surface) {
+            Some(window) => window,
+            None => return false
+        };
+        debug!(self.logger, "Restoring window {} to {center:?} {size:?}", window.id);
+        window.center = center;
+        window.size   = size;
+        if let Kind::Xdg(toplevel) = window.window.toplevel() {
+            toplevel.with_pending_state(|state| state.size = Some(size.to_i32_round()));
+            toplevel.send_configure();
+        }
+        true
+    }
+
+    /// Record where a window is now, to be saved on shutdown.
+    pub fn geometry_remember (&mut self, surface: &WlSurface) {
+        let key = match self.geometry_key(surface) {
+            Some(key) => key,
+            None => return
+        };
+        if let Some(window) = self.desktop.window_state(surface) {
+            // Don't remember the screen-filling geometry of fullscreen windows,
+            // nor the corner that picture-in-picture windows are shrunk into
+            let (center, size) = match (&window.fullscreen, &window.pip) {
+                (Some(fullscreen), _) => (fullscreen.restore_center, fullscreen.restore_size),
+                (None, Some(pip)) => (pip.restore_center, pip.restore_size),
+                (None, None) => (window.center, window.size)
+            };
+            self.geometry.set(key, center, size);
+        }
+    }
+
+    /// Record the geometry of all windows and write it to disk.
+    pub fn geometry_save (&mut self) {
+        let surfaces: Vec<WlSurface> = self.desktop.windows_all()
+            .map(|w| w.window.toplevel().wl_surface().clone())
+            .collect();
+        for surface in surfaces.iter() {
+            self.geometry_remember(surface);
+        }
+        if let Err(e) = self.geometry.save() {
+            warn!(self.logger, "Could not save window geometry: {e}");
+        }
+    }
+
+}
@@ -0,0 +1,161 @@
+use super::prelude::*;
+use super::desktop::{Desktop, WindowState};
+use super::text::Text;
+
+/// Letters that windows are labeled with in hint mode, home row first
+const HINT_KEYS: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+/// Size of label text at scale 1
+const FONT_SIZE: f32 = 20.0;
+
+/// Space between the edge of a label and its text at scale 1
+const PADDING: f64 = 6.0;
+
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.1, 0.85];
+const HINT:       [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+const ID:         [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+/// Labels drawn over windows: their ids, for addressing them over IPC,
+/// and in hint mode a letter for each, which focuses the window when pressed.
+pub struct Hints<R: EngineRenderer> {
+    logger:     Logger,
+    text:       Text<R>,
+    /// Whether every window is labeled with its id
+    pub labels: bool,
+    /// The letters handed out to windows while hint mode is on
+    active:     Option<Vec<(char, WlSurface)>>,
+}
+
+impl<R: EngineRenderer> Hints<R> {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), text: Text::from_env(logger), labels: false, active: None }
+    }
+
+    /// Whether hint mode is on, and so keys pick windows.
+    pub fn active (&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// The letter of a window in hint mode, and its id if ids are shown, with their colors.
+    fn label (&self, window: &WindowState) -> Vec<(String, [f32; 4])> {
+        let surface = window.window.toplevel().wl_surface();
+        let mut label = vec![];
+        if let Some(hints) = self.active.as_ref() {
+            if let Some((key, _)) = hints.iter().find(|(_, s)| s == surface) {
+                label.push((key.to_string(), HINT));
+            }
+        }
+        if self.labels {
+            label.push((window.id.to_string(), ID));
+        }
+        label
+    }
+
+    /// Rasterize the labels of the visible windows for this scale.
+    pub fn prepare (&mut self, renderer: &mut R, desktop: &Desktop<R>, scale: f64) -> StdResult<()> {
+        if !self.labels && self.active.is_none() {
+            return Ok(())
+        }
+        self.text.begin();
+        let size = (FONT_SIZE as f64 * scale) as f32;
+        for window in desktop.windows_visible() {
+            for (text, color) in self.label(window) {
+                self.text.prepare(renderer, &text, size, color)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the labels in the middle of the visible windows on a screen.
+    pub fn render (&self, frame: &mut R::Frame<'_>, desktop: &Desktop<R>, screen: ScreenId, scale: f64)
+        -> StdResult<()>
+    {
+        if !self.labels && self.active.is_none() {
+            return Ok(())
+        }
+        let screen = match desktop.screens.get(screen) {
+            Some(screen) => screen,
+            None => return Ok(())
+        };
+        let font_size = (FONT_SIZE as f64 * scale) as f32;
+        for window in desktop.windows_stacked() {
+            let label = self.label(window);
+            if label.is_empty() {
+                continue
+            }
+            let sizes: Vec<_> = label.iter()
+                .map(|(text, _)| self.text.measure(text, font_size).to_f64().to_logical(scale))
+                .collect();
+            let w = sizes.iter().map(|size| size.w).sum::<f64>() + PADDING * (sizes.len() + 1) as f64;
+            let h = sizes.iter().map(|size| size.h).fold(0.0, f64::max) + PADDING * 2.0;
+            let area = window.geometry_on(screen).to_f64();
+            let area = Rectangle::<f64, Logical>::from_loc_and_size((
+                area.loc.x + (area.size.w - w) / 2.0,
+                area.loc.y + (area.size.h - h) / 2.0
+            ), (w, h));
+            frame.clear(BACKGROUND, &[area.to_physical(scale).to_i32_round()])?;
+            let mut x = area.loc.x + PADDING;
+            for ((text, color), size) in label.iter().zip(sizes) {
+                let origin = Point::<f64, Logical>::from((x, area.loc.y + PADDING))
+                    .to_physical(scale).to_i32_round();
+                self.text.draw_text(frame, origin, text, font_size, *color)?;
+                x += size.w + PADDING;
+            }
+        }
+        Ok(())
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Label the visible windows with letters, topmost first, and wait for one to be pressed.
+    pub fn hints_start (&mut self) {
+        let hints: Vec<_> = HINT_KEYS.chars()
+            .zip(self.desktop.windows_stacked().collect::<Vec<_>>().into_iter().rev())
+            .map(|(key, window)| (key, window.window.toplevel().wl_surface().clone()))
+            .collect();
+        if hints.is_empty() {
+            return
+        }
+        debug!(self.hints.logger, "Hinting {} windows", hints.len());
+        self.hints.active = Some(hints);
+        self.redraw();
+    }
+
+    /// Focus the window labeled with the pressed key, if any, and leave hint mode.
+    pub fn hints_pick (&mut self, keysym: u32) {
+        let hints = match self.hints.active.take() {
+            Some(hints) => hints,
+            None => return
+        };
+        self.redraw();
+        let key = match char::from_u32(keysym) {
+            Some(key) => key.to_ascii_lowercase(),
+            None => return
+        };
+        let surface = hints.into_iter().find(|(k, _)| *k == key).map(|(_, surface)| surface);
+        let id = surface.and_then(|surface| self.desktop.window_state(&surface)).map(|window| window.id);
+        if let Some(id) = id {
+            if let Err(e) = self.window_jump(id) {
+                warn!(self.hints.logger, "Could not jump to window {id}: {e}");
+            }
+        }
+    }
+
+    /// Show a window wherever it is: switch to its workspace, restore it, raise it and focus it.
+    pub fn window_jump (&mut self, id: u64) -> StdResult<()> {
+        let window = self.desktop.window_by_id(id).ok_or("no such window")?;
+        let (surface, workspace) = (window.window.toplevel().wl_surface().clone(), window.workspace);
+        if workspace != self.desktop.workspaces.current {
+            self.desktop.workspaces.switch(workspace);
+        }
+        self.desktop.unminimize(&surface);
+        self.desktop.raise(&surface);
+        self.focus(Some(surface));
+        self.redraw();
+        Ok(())
+    }
+
+}
@@ -0,0 +1,60 @@
+use super::prelude::*;
+
+use std::os::fd::{FromRawFd, RawFd};
+
+use smithay::wayland::data_device::set_data_device_selection;
+
+/// Types under which text from the host clipboard is offered to clients
+pub const TEXT_MIME_TYPES: [&str; 4] = [
+    "text/plain;charset=utf-8",
+    "text/plain",
+    "UTF8_STRING",
+    "STRING",
+];
+
+impl<E: Engine> Charlie<E> {
+
+    /// Follow changes in what the session hosting the compositor provides:
+    /// the kinds of input it delivers, and the text on its clipboard.
+    pub fn host_sync (&mut self) {
+        let capabilities = self.engine.input_capabilities();
+        if capabilities != self.input.capabilities {
+            info!(self.logger, "Input capabilities changed: {capabilities:?}");
+            if let Err(e) = self.input.set_capabilities(capabilities) {
+                warn!(self.logger, "Could not update seat capabilities: {e}");
+            }
+        }
+        if let Some(text) = self.engine.host_selection() {
+            self.selection_offer(text);
+        }
+    }
+
+    /// Make text the current selection of every seat.
+    pub fn selection_offer (&mut self, text: String) {
+        debug!(self.logger, "Offering {} bytes from the host clipboard", text.len());
+        self.input.selection = Some(text);
+        self.clipboard.cancel(&self.handle);
+        self.x11_selection_claim(None);
+        let handle = self.display.borrow().handle();
+        let mime_types: Vec<String> = TEXT_MIME_TYPES.iter().map(|t| t.to_string()).collect();
+        for seat in self.input.seats.iter() {
+            set_data_device_selection(&handle, seat, mime_types.clone());
+        }
+    }
+
+    /// Write the offered selection to a client that asked to paste it.
+    /// The client reads at its own pace, so this happens a chunk at a time.
+    pub fn selection_send (&mut self, mime_type: String, fd: RawFd) {
+        // The selection may be the clipboard of an X11 client
+        if self.xwm.as_ref().map(|xwm| xwm.selection.offer.is_some()).unwrap_or(false) {
+            return self.x11_selection_paste(&mime_type, fd)
+        }
+        match self.input.selection.clone() {
+            Some(text) if TEXT_MIME_TYPES.contains(&mime_type.as_str()) =>
+                self.selection_transfer(&mime_type, Some(text), fd),
+            Some(_) => drop(unsafe { std::fs::File::from_raw_fd(fd) }),
+            None => self.selection_transfer(&mime_type, None, fd)
+        }
+    }
+
+}
@@ -0,0 +1,201 @@
+use super::prelude::*;
+
+use std::collections::HashSet;
+
+use smithay::reexports::calloop::timer::{Timer, TimeoutAction};
+
+/// How close to the edge, in logical pixels, the pointer has to be to touch it
+const EDGE_THRESHOLD: f64 = 1.0;
+
+/// Part of a screen's border that can trigger an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HotEdge {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl HotEdge {
+
+    /// Which edge or corner a point is touching, if any. Corners take precedence.
+    pub fn at (location: Point<f64, Logical>, area: Size<f64, Logical>) -> Option<Self> {
+        let left   = location.x <= EDGE_THRESHOLD;
+        let right  = location.x >= area.w - 1.0 - EDGE_THRESHOLD;
+        let top    = location.y <= EDGE_THRESHOLD;
+        let bottom = location.y >= area.h - 1.0 - EDGE_THRESHOLD;
+        Some(match (left, right, top, bottom) {
+            (true, _, true, _)  => Self::TopLeft,
+            (_, true, true, _)  => Self::TopRight,
+            (true, _, _, true)  => Self::BottomLeft,
+            (_, true, _, true)  => Self::BottomRight,
+            (_, _, true, _)     => Self::Top,
+            (_, _, _, true)     => Self::Bottom,
+            (true, _, _, _)     => Self::Left,
+            (_, true, _, _)     => Self::Right,
+            _ => return None
+        })
+    }
+
+    pub fn name (&self) -> &'static str {
+        match self {
+            Self::TopLeft     => "top-left",
+            Self::TopRight    => "top-right",
+            Self::BottomLeft  => "bottom-left",
+            Self::BottomRight => "bottom-right",
+            Self::Top         => "top",
+            Self::Bottom      => "bottom",
+            Self::Left        => "left",
+            Self::Right       => "right",
+        }
+    }
+
+}
+
+impl std::str::FromStr for HotEdge {
+    type Err = Box<dyn Error>;
+    fn from_str (edge: &str) -> StdResult<Self> {
+        Ok(match edge {
+            "top-left"     => Self::TopLeft,
+            "top-right"    => Self::TopRight,
+            "bottom-left"  => Self::BottomLeft,
+            "bottom-right" => Self::BottomRight,
+            "top"          => Self::Top,
+            "bottom"       => Self::Bottom,
+            "left"         => Self::Left,
+            "right"        => Self::Right,
+            _ => return Err(format!("unknown edge: {edge}").into())
+        })
+    }
+}
+
+/// Commands that run when the pointer is held against a screen edge or corner.
+pub struct HotCorners {
+    logger:         Logger,
+    /// IPC command to run for each edge
+    pub actions:    HashMap<HotEdge, String>,
+    /// How long the pointer has to stay at the edge
+    pub delay:      Duration,
+    /// How far relative motion has to push past the edge, in logical pixels
+    pub resistance: f64,
+    /// Screens on which edges do nothing
+    pub disabled:   HashSet<ScreenId>,
+    /// The edge the pointer is at, and since when
+    touching:       Option<(ScreenId, HotEdge, Instant)>,
+    /// How far the pointer has been pushed past the edge it's at
+    pressure:       f64,
+    /// Whether the edge the pointer is at has already been triggered
+    fired:          bool,
+}
+
+impl HotCorners {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:     logger.clone(),
+            actions:    HashMap::new(),
+            delay:      Duration::from_millis(150),
+            resistance: 0.0,
+            disabled:   HashSet::new(),
+            touching:   None,
+            pressure:   0.0,
+            fired:      false,
+        }
+    }
+
+    /// Count relative motion that was stopped by the edge of the screen.
+    pub fn push (&mut self, overshoot: Point<f64, Logical>) {
+        self.pressure += overshoot.x.abs() + overshoot.y.abs();
+    }
+
+    /// Track the pointer. Returns true if it just started touching an edge that has an action.
+    fn motion (&mut self, screen: ScreenId, location: Point<f64, Logical>, area: Size<f64, Logical>) -> bool {
+        let edge = if self.disabled.contains(&screen) {
+            None
+        } else {
+            HotEdge::at(location, area).filter(|edge| self.actions.contains_key(edge))
+        };
+        match (edge, self.touching) {
+            (Some(edge), Some((s, e, _))) if s == screen && e == edge => false,
+            (Some(edge), _) => {
+                self.touching = Some((screen, edge, Instant::now()));
+                self.pressure = 0.0;
+                self.fired    = false;
+                true
+            },
+            (None, _) => {
+                self.touching = None;
+                self.pressure = 0.0;
+                false
+            }
+        }
+    }
+
+    /// The edge that should trigger now, if any. It won't trigger again until the pointer leaves it.
+    fn trigger (&mut self) -> Option<(ScreenId, HotEdge)> {
+        let (screen, edge, since) = self.touching?;
+        if self.fired || since.elapsed() < self.delay || self.pressure < self.resistance {
+            return None
+        }
+        self.fired = true;
+        debug!(self.logger, "Hot edge {} on screen {screen}", edge.name());
+        Some((screen, edge))
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Run a command when the pointer is held against an edge or corner of a screen.
+    pub fn hot_corner (mut self, edge: HotEdge, command: impl Into<String>) -> CharlieResult<Self> {
+        self.hot_corners.actions.insert(edge, command.into());
+        Ok(self)
+    }
+
+    /// How long the pointer has to be held against an edge, and how far past it
+    /// relative motion has to push, before the edge's command runs.
+    pub fn hot_corner_activation (mut self, delay: Duration, resistance: f64) -> CharlieResult<Self> {
+        self.hot_corners.delay      = delay;
+        self.hot_corners.resistance = resistance;
+        Ok(self)
+    }
+
+    /// Check whether the pointer has activated an edge after moving.
+    pub fn hot_corners_motion (&mut self, screen: ScreenId, location: Point<f64, Logical>) {
+        let area = match self.screen_area(screen) {
+            Some(area) => area,
+            None => return
+        };
+        if self.hot_corners.motion(screen, location, area) && !self.hot_corners.delay.is_zero() {
+            // Check again once the delay has passed, in case the pointer stops moving
+            let delay = self.hot_corners.delay;
+            if let Err(e) = self.handle.insert_source(Timer::from_duration(delay), |_, _, state| {
+                state.hot_corners_check();
+                TimeoutAction::Drop
+            }) {
+                warn!(self.logger, "Could not schedule hot corner check: {e}");
+            }
+        }
+        self.hot_corners_check();
+    }
+
+    /// Run the command of the touched edge, if it's ready to trigger.
+    pub fn hot_corners_check (&mut self) {
+        if self.input_inhibitor.active() || self.kiosk.enabled {
+            return
+        }
+        if let Some((screen, edge)) = self.hot_corners.trigger() {
+            self.scripts.hook("on_hot_corner", vec![edge.name().into(), (screen as i64).into()]);
+            if let Some(command) = self.hot_corners.actions.get(&edge).cloned() {
+                if let Err(e) = self.ipc_line(&command) {
+                    warn!(self.logger, "Hot edge {} command {command} failed: {e}", edge.name());
+                }
+            }
+        }
+    }
+
+}
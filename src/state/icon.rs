@@ -0,0 +1,220 @@
+use super::prelude::*;
+
+use std::path::{Path, PathBuf};
+
+/// Icon theme that every other theme falls back to
+const FALLBACK_THEME: &str = "hicolor";
+
+/// The directories that desktop entries and icons are looked up in, most important first.
+fn data_dirs () -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(home) => dirs.push(PathBuf::from(home)),
+        None => if let Some(home) = std::env::var_os("HOME") {
+            dirs.push(PathBuf::from(home).join(".local/share"))
+        }
+    }
+    let system = std::env::var("XDG_DATA_DIRS").unwrap_or_else(|_| "/usr/local/share:/usr/share".into());
+    dirs.extend(system.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from));
+    dirs
+}
+
+/// The value of a key in the `[Desktop Entry]` group of a desktop entry.
+fn desktop_entry_key <'a> (entry: &'a str, key: &str) -> Option<&'a str> {
+    let mut in_group = false;
+    for line in entry.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+        } else if in_group {
+            if let Some((k, value)) = line.split_once('=') {
+                if k.trim() == key {
+                    return Some(value.trim())
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The icon named by the desktop entry of an app. The entry is the one whose file name
+/// is the app id, or whose `StartupWMClass` is, ignoring case; X11 windows go by their class.
+fn desktop_icon (app_id: &str) -> Option<String> {
+    let app_id = app_id.to_lowercase();
+    let mut by_class = None;
+    for dir in data_dirs() {
+        let entries = match std::fs::read_dir(dir.join("applications")) {
+            Ok(entries) => entries,
+            Err(_) => continue
+        };
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if path.extension().map(|ext| ext != "desktop").unwrap_or(true) {
+                continue
+            }
+            let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_lowercase()).unwrap_or_default();
+            let named = stem == app_id;
+            if !named && by_class.is_some() {
+                continue
+            }
+            let entry = match std::fs::read_to_string(&path) {
+                Ok(entry) => entry,
+                Err(_) => continue
+            };
+            let icon = desktop_entry_key(&entry, "Icon").map(String::from);
+            if named && icon.is_some() {
+                return icon
+            }
+            let class = desktop_entry_key(&entry, "StartupWMClass").map(str::to_lowercase);
+            if class.as_deref() == Some(app_id.as_str()) {
+                by_class = icon;
+            }
+        }
+    }
+    by_class
+}
+
+/// Find the PNG file of a named icon, as close as possible to a size, in a theme or in the
+/// fallback theme. Scalable icons are skipped, since they can't be loaded.
+fn theme_icon (name: &str, theme: &str, size: u32) -> Option<PathBuf> {
+    let file = format!("{name}.png");
+    let mut bases: Vec<PathBuf> = data_dirs().into_iter().map(|dir| dir.join("icons")).collect();
+    if let Some(home) = std::env::var_os("HOME") {
+        bases.insert(0, PathBuf::from(home).join(".icons"));
+    }
+    for theme in [theme, FALLBACK_THEME] {
+        // Prefer the smallest icon that's at least the size, then the biggest one that's smaller
+        let mut best: Option<(u32, PathBuf)> = None;
+        for base in bases.iter() {
+            let sizes = match std::fs::read_dir(base.join(theme)) {
+                Ok(sizes) => sizes,
+                Err(_) => continue
+            };
+            for dir in sizes.filter_map(|entry| entry.ok()) {
+                let dir_size = match dir.file_name().to_string_lossy().split(['x', '@']).next()
+                    .and_then(|size| size.parse::<u32>().ok())
+                {
+                    Some(dir_size) => dir_size,
+                    None => continue
+                };
+                let path = dir.path().join("apps").join(&file);
+                if !path.is_file() {
+                    continue
+                }
+                let better = match &best {
+                    None => true,
+                    Some((best_size, _)) => match (dir_size >= size, *best_size >= size) {
+                        (true, true)   => dir_size < *best_size,
+                        (false, false) => dir_size > *best_size,
+                        (fits, _)      => fits
+                    }
+                };
+                if better {
+                    best = Some((dir_size, path));
+                }
+            }
+        }
+        if let Some((_, path)) = best {
+            return Some(path)
+        }
+    }
+    data_dirs().into_iter()
+        .map(|dir| dir.join("pixmaps").join(&file))
+        .find(|path| path.is_file())
+}
+
+/// Icons of apps, found through their desktop entries and the icon theme, for identifying
+/// windows in compositor UI such as the window switcher.
+///
+/// Lookups and textures are cached by app id, including failed ones,
+/// since looking through the icon theme means reading lots of directories.
+pub struct Icons<R: EngineRenderer> {
+    logger:     Logger,
+    /// Name of the icon theme to look in before the fallback theme
+    pub theme:  String,
+    /// Size of the icons to pick, in pixels
+    pub size:   u32,
+    /// Where the icon of each app id was found
+    paths:      HashMap<String, Option<PathBuf>>,
+    /// The icon of each app id, uploaded to the renderer
+    textures:   HashMap<String, Option<R::TextureId>>,
+}
+
+impl<R: EngineRenderer> Icons<R> {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:   logger.clone(),
+            theme:    FALLBACK_THEME.into(),
+            size:     48,
+            paths:    HashMap::new(),
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Forget everything that was looked up, e.g. because the theme changed.
+    pub fn clear (&mut self) {
+        self.paths.clear();
+        self.textures.clear();
+    }
+
+    /// Where the icon of an app is.
+    pub fn path (&mut self, app_id: &str) -> Option<PathBuf> {
+        if let Some(path) = self.paths.get(app_id) {
+            return path.clone()
+        }
+        let name = desktop_icon(app_id).unwrap_or_else(|| app_id.into());
+        let path = if Path::new(&name).is_absolute() {
+            Some(PathBuf::from(name))
+        } else {
+            theme_icon(&name, &self.theme, self.size)
+                .or_else(|| theme_icon(&name.to_lowercase(), &self.theme, self.size))
+        };
+        match &path {
+            Some(path) => debug!(self.logger, "Icon of {app_id} is {}", path.display()),
+            None => debug!(self.logger, "No icon for {app_id}")
+        }
+        self.paths.insert(app_id.into(), path.clone());
+        path
+    }
+
+    /// The icon of an app as a texture, loaded on first use.
+    pub fn texture (&mut self, renderer: &mut R, app_id: &str) -> Option<R::TextureId> {
+        if let Some(texture) = self.textures.get(app_id) {
+            return texture.clone()
+        }
+        let texture = self.path(app_id).and_then(|path| match import_bitmap(renderer, &path) {
+            Ok(texture) => Some(texture),
+            Err(e) => {
+                warn!(self.logger, "Could not load icon {}: {e}", path.display());
+                None
+            }
+        });
+        self.textures.insert(app_id.into(), texture.clone());
+        texture
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Look for app icons in this theme, at about this size.
+    pub fn icon_theme (mut self, theme: impl Into<String>, size: u32) -> CharlieResult<Self> {
+        self.icons.theme = theme.into();
+        self.icons.size  = size;
+        self.icons.clear();
+        Ok(self)
+    }
+
+    /// Where the icon of a window is, going by its app id.
+    pub fn window_icon_path (&mut self, id: u64) -> Option<PathBuf> {
+        let (app_id, _) = self.desktop.windows_all().find(|w| w.id == id)?.app_id_and_title();
+        self.icons.path(&app_id?)
+    }
+
+    /// The icon of a window as a texture, for drawing it in compositor UI.
+    pub fn window_icon (&mut self, id: u64) -> Option<EngineTexture<E>> {
+        let (app_id, _) = self.desktop.windows_all().find(|w| w.id == id)?.app_id_and_title();
+        let mut renderer = self.engine.renderer();
+        self.icons.texture(&mut *renderer, &app_id?)
+    }
+
+}
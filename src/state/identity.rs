@@ -0,0 +1,171 @@
+use super::prelude::*;
+
+use std::{
+    os::{fd::AsRawFd, unix::net::UnixStream},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use smithay::reexports::wayland_server::backend::{ClientData, ClientId, DisconnectReason};
+
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+
+use super::clipboard::Domain;
+use super::crash::{Disconnect, Disconnects};
+
+/// The sandbox a client runs in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sandbox {
+    /// A Flatpak app, with its app id, e.g. `org.mozilla.firefox`
+    Flatpak(String),
+    /// A Snap, with its name
+    Snap(String),
+}
+
+/// Who is on the other end of a connection, as far as the kernel and the sandbox can tell.
+/// Everything is looked up once, when the client connects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Identity {
+    pub pid:     Option<i32>,
+    pub uid:     Option<u32>,
+    /// The executable of the process
+    pub exe:     Option<PathBuf>,
+    /// The systemd unit the process runs in, e.g. `app-firefox.scope` or `foo.service`
+    pub unit:    Option<String>,
+    pub sandbox: Option<Sandbox>,
+}
+
+impl Identity {
+
+    /// Identify the process on the other end of a socket, by its peer credentials.
+    pub fn of_stream (stream: &UnixStream) -> Self {
+        match getsockopt(stream.as_raw_fd(), PeerCredentials) {
+            Ok(credentials) => Self::of_process(credentials.pid(), credentials.uid()),
+            Err(_) => Self::default()
+        }
+    }
+
+    /// Identify a process by what `/proc` says about it.
+    pub fn of_process (pid: i32, uid: u32) -> Self {
+        let cgroup = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).unwrap_or_default();
+        // The unit is the innermost path component of the unified hierarchy that names one
+        let unit = cgroup.lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .and_then(|path| path.rsplit('/').find(|name| name.ends_with(".service") || name.ends_with(".scope")))
+            .map(String::from);
+        Self {
+            pid:     Some(pid),
+            uid:     Some(uid),
+            exe:     std::fs::read_link(format!("/proc/{pid}/exe")).ok(),
+            sandbox: flatpak_app_id(pid).map(Sandbox::Flatpak).or_else(|| snap_name(&cgroup).map(Sandbox::Snap)),
+            unit,
+        }
+    }
+
+    /// Which side of the sandbox the client runs on.
+    pub fn domain (&self) -> Domain {
+        if self.sandbox.is_some() { Domain::Sandboxed } else { Domain::Host }
+    }
+
+    /// Whether the client runs as the same user as the compositor.
+    pub fn same_user (&self) -> bool {
+        self.uid == Some(nix::unistd::getuid().as_raw())
+    }
+
+    /// The app id the sandbox knows the client by, which unlike the one
+    /// the client gives its windows, the client can't make up.
+    pub fn sandbox_app_id (&self) -> Option<&str> {
+        match &self.sandbox {
+            Some(Sandbox::Flatpak(app_id)) | Some(Sandbox::Snap(app_id)) => Some(app_id),
+            None => None
+        }
+    }
+
+    /// Describe the client on one line, for listing it.
+    pub fn describe (&self) -> String {
+        let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "-".into());
+        format!(
+            "pid {} uid {} exe {} unit {} sandbox {}",
+            or_unknown(self.pid.map(|pid| pid.to_string())),
+            or_unknown(self.uid.map(|uid| uid.to_string())),
+            or_unknown(self.exe.as_ref().map(|exe| exe.display().to_string())),
+            or_unknown(self.unit.clone()),
+            match &self.sandbox {
+                Some(Sandbox::Flatpak(app_id)) => format!("flatpak:{app_id}"),
+                Some(Sandbox::Snap(name)) => format!("snap:{name}"),
+                None => "-".into()
+            }
+        )
+    }
+
+}
+
+/// The app id of a Flatpak app, from the `.flatpak-info` at the root of its sandbox.
+fn flatpak_app_id (pid: i32) -> Option<String> {
+    let info = std::fs::read_to_string(format!("/proc/{pid}/root/.flatpak-info")).ok()?;
+    let mut in_group = false;
+    for line in info.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Application]";
+        } else if in_group {
+            if let Some(name) = line.strip_prefix("name=") {
+                return Some(name.trim().into())
+            }
+        }
+    }
+    None
+}
+
+/// The name of a Snap, from the `snap.NAME.APP` scope or service it runs in.
+fn snap_name (cgroup: &str) -> Option<String> {
+    cgroup.lines()
+        .flat_map(|line| line.split('/'))
+        .find_map(|name| name.strip_prefix("snap."))
+        .and_then(|name| name.split('.').next())
+        .map(String::from)
+}
+
+/// Identities of the connected clients, by client id.
+pub type Identities = Arc<Mutex<HashMap<ClientId, Identity>>>;
+
+/// Data kept with each Wayland client: who it is, and how it connected.
+#[derive(Debug)]
+pub struct ClientState {
+    pub identity:   Identity,
+    /// Whether the client connected to the privileged socket
+    pub privileged: bool,
+    /// Where the client is listed while it's connected
+    identities:     Identities,
+    /// Where the client is reported once it's gone
+    disconnects:    Disconnects,
+}
+
+impl ClientState {
+
+    /// Identify the client on the other end of a socket.
+    pub fn new (stream: &UnixStream, privileged: bool, identities: &Identities, disconnects: &Disconnects) -> Self {
+        Self {
+            identity:    Identity::of_stream(stream),
+            privileged,
+            identities:  identities.clone(),
+            disconnects: disconnects.clone(),
+        }
+    }
+
+}
+
+impl ClientData for ClientState {
+    fn initialized (&self, client_id: ClientId) {
+        if let Ok(mut identities) = self.identities.lock() {
+            identities.insert(client_id, self.identity.clone());
+        }
+    }
+    fn disconnected (&self, client_id: ClientId, reason: DisconnectReason) {
+        if let Ok(mut identities) = self.identities.lock() {
+            identities.remove(&client_id);
+        }
+        if let Ok(mut disconnects) = self.disconnects.lock() {
+            disconnects.push(Disconnect::new(client_id, self.identity.clone(), reason));
+        }
+    }
+}
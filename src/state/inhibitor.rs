@@ -0,0 +1,160 @@
+use super::prelude::*;
+
+use super::input::Pointer;
+use super::security::Gate;
+
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource,
+    backend::{ClientId, GlobalId, ObjectId},
+};
+
+use wayland_protocols_wlr::input_inhibitor::v1::server::{
+    zwlr_input_inhibit_manager_v1::{self, ZwlrInputInhibitManagerV1},
+    zwlr_input_inhibitor_v1::{self, ZwlrInputInhibitorV1},
+};
+
+/// Lets a screen locker or kiosk launcher take all input for itself
+/// (`zwlr_input_inhibit_manager_v1`). While it has, other clients get no keyboard or pointer
+/// focus, and key bindings other than switching VTs don't apply. It ends when the client
+/// destroys the inhibitor or goes away. Which clients see it is up to the
+/// [Protocol::InputInhibitor](super::security::Protocol::InputInhibitor) policy.
+pub struct InputInhibitorState {
+    logger:    Logger,
+    global:    GlobalId,
+    /// The client that has taken all input, if any
+    pub owner: Option<ClientId>,
+}
+
+impl InputInhibitorState {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle, gate: Gate) -> Self {
+        Self {
+            logger: logger.clone(),
+            global: display.create_global::<Charlie<E>, ZwlrInputInhibitManagerV1, _>(1, gate),
+            owner:  None,
+        }
+    }
+
+    /// Stop advertising the protocol.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.global.clone());
+    }
+
+    /// Whether a client has taken all input.
+    pub fn active (&self) -> bool {
+        self.owner.is_some()
+    }
+
+    /// Whether a surface may have input, which is any surface unless a client has taken it.
+    pub fn admits (&self, surface: &WlSurface) -> bool {
+        match self.owner.as_ref() {
+            Some(owner) => surface.client().map(|client| &client.id() == owner).unwrap_or(false),
+            None => true
+        }
+    }
+
+}
+
+impl<E: Engine> GlobalDispatch<ZwlrInputInhibitManagerV1, Gate> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<ZwlrInputInhibitManagerV1>,
+        _gate:     &Gate,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+
+    fn can_view (client: Client, gate: &Gate) -> bool {
+        gate.admits(&client)
+    }
+}
+
+impl<E: Engine> Dispatch<ZwlrInputInhibitManagerV1, ()> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        client:    &Client,
+        resource:  &ZwlrInputInhibitManagerV1,
+        request:   zwlr_input_inhibit_manager_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_input_inhibit_manager_v1::Request::GetInhibitor { id } = request {
+            if state.input_inhibitor.active() {
+                resource.post_error(
+                    zwlr_input_inhibit_manager_v1::Error::AlreadyInhibited,
+                    "input is already inhibited"
+                );
+                return
+            }
+            data_init.init(id, ());
+            state.input_inhibit(client.id());
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<ZwlrInputInhibitorV1, ()> for Charlie<E> {
+    fn request (
+        _state:     &mut Self,
+        _client:    &Client,
+        _resource:  &ZwlrInputInhibitorV1,
+        _request:   zwlr_input_inhibitor_v1::Request,
+        _data:      &(),
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // The only request is destroy, which ends inhibition in `destroyed`
+    }
+    fn destroyed (
+        state:   &mut Self,
+        client:  ClientId,
+        _object: ObjectId,
+        _data:   &(),
+    ) {
+        // Also called when the client disconnects, crashed or not
+        if state.input_inhibitor.owner.as_ref() == Some(&client) {
+            state.input_release();
+        }
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Give all input to a client, taking focus away from the others.
+    pub fn input_inhibit (&mut self, client: ClientId) {
+        info!(self.input_inhibitor.logger, "Client {client:?} took all input");
+        self.input_inhibitor.owner = Some(client);
+        // The client's own topmost window keeps or gets focus
+        let own = self.desktop.windows_visible()
+            .map(|w| w.window.toplevel().wl_surface().clone())
+            .find(|surface| self.input_inhibitor.admits(surface));
+        let focused = self.desktop.focused.clone().filter(|surface| self.input_inhibitor.admits(surface));
+        self.focus(focused.or(own));
+        self.input_reenter();
+    }
+
+    /// Give input back to all clients.
+    pub fn input_release (&mut self) {
+        if self.input_inhibitor.owner.take().is_none() {
+            return
+        }
+        info!(self.input_inhibitor.logger, "Input is no longer inhibited");
+        let next = self.desktop.windows_visible().next()
+            .map(|w| w.window.toplevel().wl_surface().clone());
+        self.focus(next);
+        self.input_reenter();
+    }
+
+    /// Have the pointers leave or enter surfaces, now that different surfaces may have input.
+    fn input_reenter (&mut self) {
+        let time = self.desktop.now_ms();
+        for index in 0..self.input.pointers.len() {
+            Pointer::refresh(self, index, time);
+        }
+        self.redraw.request();
+    }
+
+}
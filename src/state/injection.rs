@@ -0,0 +1,298 @@
+use super::prelude::*;
+
+use std::{fs::File, os::fd::{FromRawFd, RawFd}};
+
+use super::record::Recorded;
+use super::security::Gate;
+
+use smithay::backend::input::AxisSource;
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum,
+    backend::GlobalId,
+    protocol::wl_pointer,
+};
+
+use wayland_protocols_wlr::virtual_pointer::v1::server::{
+    zwlr_virtual_pointer_manager_v1::{self, ZwlrVirtualPointerManagerV1},
+    zwlr_virtual_pointer_v1::{self, ZwlrVirtualPointerV1},
+};
+
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::server::{
+    zwp_virtual_keyboard_manager_v1::{self, ZwpVirtualKeyboardManagerV1},
+    zwp_virtual_keyboard_v1::{self, ZwpVirtualKeyboardV1},
+};
+
+/// Lets clients such as wtype, wlrctl and test harnesses drive the session by sending
+/// pointer and keyboard events (`zwlr_virtual_pointer_v1` and `zwp_virtual_keyboard_v1`).
+/// The events go through the same path as those of real devices, so bindings apply to them.
+/// Which clients see the protocols is up to the
+/// [Protocol::VirtualPointer](super::security::Protocol::VirtualPointer) and
+/// [Protocol::VirtualKeyboard](super::security::Protocol::VirtualKeyboard) policies.
+///
+/// Keys are read with the keymap of the seat: the keymap that a virtual keyboard uploads
+/// is accepted but not used, so clients that make up keycodes for arbitrary keysyms
+/// type whatever those keycodes mean in the seat's keymap.
+pub struct VirtualInput {
+    logger:   Logger,
+    pointer:  GlobalId,
+    keyboard: GlobalId,
+}
+
+impl VirtualInput {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle, pointer: Gate, keyboard: Gate) -> Self {
+        Self {
+            logger:   logger.clone(),
+            pointer:  display.create_global::<Charlie<E>, ZwlrVirtualPointerManagerV1, _>(2, pointer),
+            keyboard: display.create_global::<Charlie<E>, ZwpVirtualKeyboardManagerV1, _>(1, keyboard),
+        }
+    }
+
+    /// Stop advertising the protocols.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.pointer.clone());
+        display.remove_global::<Charlie<E>>(self.keyboard.clone());
+    }
+
+}
+
+/// A virtual pointer: the screen it's bound to, if any, and the scrolling
+/// it has sent since its last frame.
+#[derive(Default)]
+pub struct VirtualPointer {
+    screen: Option<ScreenId>,
+    axis:   Mutex<Option<Recorded>>,
+}
+
+/// A virtual keyboard, which may only send keys once it has sent a keymap.
+#[derive(Default)]
+pub struct VirtualKeyboard {
+    keymap: Mutex<bool>,
+}
+
+impl<E: Engine> GlobalDispatch<ZwlrVirtualPointerManagerV1, Gate> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<ZwlrVirtualPointerManagerV1>,
+        _gate:     &Gate,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+
+    fn can_view (client: Client, gate: &Gate) -> bool {
+        gate.admits(&client)
+    }
+}
+
+impl<E: Engine> Dispatch<ZwlrVirtualPointerManagerV1, ()> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        _client:   &Client,
+        _resource: &ZwlrVirtualPointerManagerV1,
+        request:   zwlr_virtual_pointer_manager_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointer { id, .. } => {
+                debug!(state.virtual_input.logger, "Client created a virtual pointer");
+                data_init.init(id, VirtualPointer::default());
+            },
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointerWithOutput { id, output, .. } => {
+                let screen = output.as_ref()
+                    .and_then(Output::from_resource)
+                    .and_then(|output| state.desktop.screen_find(&output.name()));
+                debug!(state.virtual_input.logger, "Client created a virtual pointer on screen {screen:?}");
+                data_init.init(id, VirtualPointer { screen, ..Default::default() });
+            },
+            zwlr_virtual_pointer_manager_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<ZwlrVirtualPointerV1, VirtualPointer> for Charlie<E> {
+    fn request (
+        state:      &mut Self,
+        _client:    &Client,
+        _resource:  &ZwlrVirtualPointerV1,
+        request:    zwlr_virtual_pointer_v1::Request,
+        pointer:    &VirtualPointer,
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let screen = pointer.screen.unwrap_or(state.desktop.active_screen);
+        let event = match request {
+            zwlr_virtual_pointer_v1::Request::Motion { dx, dy, .. } => {
+                Recorded::Motion { dx, dy, dx_unaccel: dx, dy_unaccel: dy }
+            },
+            zwlr_virtual_pointer_v1::Request::MotionAbsolute { x, y, x_extent, y_extent, .. } => {
+                if x_extent == 0 || y_extent == 0 {
+                    return
+                }
+                let area = match state.screen_area(screen) {
+                    Some(area) => area,
+                    None => return
+                };
+                Recorded::MotionAbsolute {
+                    x: x as f64 / x_extent as f64 * area.w,
+                    y: y as f64 / y_extent as f64 * area.h,
+                }
+            },
+            zwlr_virtual_pointer_v1::Request::Button { button, state: WEnum::Value(pressed), .. } => {
+                Recorded::Button { code: button, pressed: pressed == wl_pointer::ButtonState::Pressed }
+            },
+            // Scrolling is collected until the frame that ends it
+            zwlr_virtual_pointer_v1::Request::Axis { axis: WEnum::Value(axis), value, .. } => {
+                axis_update(pointer, |horizontal, vertical, _, _| match axis {
+                    wl_pointer::Axis::HorizontalScroll => *horizontal = Some(value),
+                    _ => *vertical = Some(value),
+                });
+                return
+            },
+            zwlr_virtual_pointer_v1::Request::AxisDiscrete { axis: WEnum::Value(axis), value, discrete, .. } => {
+                axis_update(pointer, |horizontal, vertical, horizontal_discrete, vertical_discrete| match axis {
+                    wl_pointer::Axis::HorizontalScroll => {
+                        *horizontal = Some(value);
+                        *horizontal_discrete = Some(discrete as f64);
+                    },
+                    _ => {
+                        *vertical = Some(value);
+                        *vertical_discrete = Some(discrete as f64);
+                    }
+                });
+                return
+            },
+            zwlr_virtual_pointer_v1::Request::AxisSource { axis_source: WEnum::Value(source) } => {
+                let mut axis = pointer.axis.lock().unwrap();
+                let axis = axis.get_or_insert_with(axis_empty);
+                if let Recorded::Axis { source: current, .. } = axis {
+                    *current = match source {
+                        wl_pointer::AxisSource::Finger     => AxisSource::Finger,
+                        wl_pointer::AxisSource::Continuous => AxisSource::Continuous,
+                        wl_pointer::AxisSource::WheelTilt  => AxisSource::WheelTilt,
+                        _ => AxisSource::Wheel,
+                    };
+                }
+                return
+            },
+            zwlr_virtual_pointer_v1::Request::AxisStop { axis: WEnum::Value(axis), .. } => {
+                axis_update(pointer, |horizontal, vertical, _, _| match axis {
+                    wl_pointer::Axis::HorizontalScroll => *horizontal = Some(0.0),
+                    _ => *vertical = Some(0.0),
+                });
+                return
+            },
+            zwlr_virtual_pointer_v1::Request::Frame => {
+                match pointer.axis.lock().unwrap().take() {
+                    Some(axis) => axis,
+                    None => return
+                }
+            },
+            _ => return
+        };
+        if let Err(e) = state.input_emulate(screen, event) {
+            warn!(state.virtual_input.logger, "Could not handle virtual pointer event: {e}");
+        }
+    }
+}
+
+/// Scrolling with no amounts yet.
+fn axis_empty () -> Recorded {
+    Recorded::Axis {
+        source:     AxisSource::Wheel,
+        horizontal: None,
+        vertical:   None,
+        horizontal_discrete: None,
+        vertical_discrete:   None,
+    }
+}
+
+/// Add to the scrolling that a virtual pointer will send with its next frame.
+fn axis_update (
+    pointer: &VirtualPointer,
+    update:  impl FnOnce(&mut Option<f64>, &mut Option<f64>, &mut Option<f64>, &mut Option<f64>)
+) {
+    let mut axis = pointer.axis.lock().unwrap();
+    if let Recorded::Axis {
+        horizontal, vertical, horizontal_discrete, vertical_discrete, ..
+    } = axis.get_or_insert_with(axis_empty) {
+        update(horizontal, vertical, horizontal_discrete, vertical_discrete)
+    }
+}
+
+impl<E: Engine> GlobalDispatch<ZwpVirtualKeyboardManagerV1, Gate> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<ZwpVirtualKeyboardManagerV1>,
+        _gate:     &Gate,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+
+    fn can_view (client: Client, gate: &Gate) -> bool {
+        gate.admits(&client)
+    }
+}
+
+impl<E: Engine> Dispatch<ZwpVirtualKeyboardManagerV1, ()> for Charlie<E> {
+    fn request (
+        state:     &mut Self,
+        _client:   &Client,
+        _resource: &ZwpVirtualKeyboardManagerV1,
+        request:   zwp_virtual_keyboard_manager_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_virtual_keyboard_manager_v1::Request::CreateVirtualKeyboard { id, .. } = request {
+            debug!(state.virtual_input.logger, "Client created a virtual keyboard");
+            data_init.init(id, VirtualKeyboard::default());
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboard> for Charlie<E> {
+    fn request (
+        state:      &mut Self,
+        _client:    &Client,
+        resource:   &ZwpVirtualKeyboardV1,
+        request:    zwp_virtual_keyboard_v1::Request,
+        keyboard:   &VirtualKeyboard,
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_virtual_keyboard_v1::Request::Keymap { fd, .. } => {
+                close(fd);
+                *keyboard.keymap.lock().unwrap() = true;
+            },
+            zwp_virtual_keyboard_v1::Request::Key { key, state: pressed, .. } => {
+                if !*keyboard.keymap.lock().unwrap() {
+                    resource.post_error(zwp_virtual_keyboard_v1::Error::NoKeymap, "no keymap was set");
+                    return
+                }
+                let event = Recorded::Key { code: key, pressed: pressed != 0 };
+                if let Err(e) = state.input_emulate(state.desktop.active_screen, event) {
+                    warn!(state.virtual_input.logger, "Could not handle virtual key: {e}");
+                }
+            },
+            // Modifiers follow from the modifier keys that are sent
+            zwp_virtual_keyboard_v1::Request::Modifiers { .. } => {},
+            zwp_virtual_keyboard_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+/// Let go of a file descriptor that a client sent.
+fn close (fd: RawFd) {
+    drop(unsafe { File::from_raw_fd(fd) });
+}
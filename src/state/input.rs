@@ -1,32 +1,78 @@
 use super::prelude::*;
+use super::cursor::Cursor;
+use super::record::Recorder;
+use super::acceleration::{PointerConfig, ALL_DEVICES};
+use super::warp::Confine;
+use super::notify::CloseReason;
+use super::stacking::StackLayer;
+use super::keymap::Keymap;
+use super::manage::Edge;
+use super::action::Action;
+use super::osd::MediaKey;
+use super::resize::ResizeModifiers;
+
+use rhai::Dynamic;
+use super::transform::{transform_size, untransform_point};
 
 use smithay::{
     backend::input::{
+        Device,
         Event,
-        //KeyState,
+        KeyState,
         KeyboardKeyEvent,
         //AbsolutePositionEvent,
         PointerButtonEvent,
         PointerMotionEvent,
-        //PointerAxisEvent
+        PointerAxisEvent,
+        Axis,
+        AxisSource,
     },
     input::{
         pointer::{
             PointerHandle,
+            AxisFrame,
+            ButtonEvent,
             CursorImageStatus     as Status,
             CursorImageAttributes as Attributes
         },
         keyboard::{
-            //keysyms,
+            keysyms,
             KeyboardHandle,
             FilterResult,
+            ModifiersState,
         },
     },
-    wayland::input_method::InputMethodSeat
+    wayland::input_method::InputMethodSeat,
+    reexports::calloop::{RegistrationToken, timer::{Timer, TimeoutAction}},
+    reexports::wayland_server::{Resource, protocol::wl_data_source::WlDataSource},
 };
 
+/// How far one wheel step scrolls, for devices that only report steps
+const SCROLL_STEP: f64 = 3.0;
+
+/// How long a key is held before it starts repeating, in milliseconds
+pub const REPEAT_DELAY: i32 = 200;
+
+/// How many times per second a held key repeats
+pub const REPEAT_RATE: i32 = 25;
+
+/// Evdev code of the left mouse button
+const BTN_LEFT: u32 = 0x110;
+
+/// Evdev code of the right mouse button
+const BTN_RIGHT: u32 = 0x111;
+
+/// How much the scale of an output changes with each press of the scale bindings
+const SCALE_STEP: f64 = 0.25;
+
+/// How many logical pixels a window moves or grows with each press of the arrow bindings
+const ARRANGE_STEP: i32 = 32;
+
+/// How many logical pixels the gaps around windows change by with each press of the gap bindings
+const GAP_STEP: i32 = 4;
+
 impl<E: Engine, B: InputBackend> Update<(InputEvent<B>, ScreenId)> for Charlie<E> {
-    fn update (&mut self, (event, screen_id): (InputEvent<B>, ScreenId)) -> StdResult<()> {
+    fn update (&mut self, (event, screen_id): (InputEvent<B>, ScreenId)) -> CharlieResult<()> {
         handle_input(self, event, screen_id)
     }
 }
@@ -35,7 +81,11 @@ fn handle_input <E: Engine, B: InputBackend> (
     state: &mut Charlie<E>,
     event: InputEvent<B>,
     screen_id: ScreenId
-) -> StdResult<()> {
+) -> CharlieResult<()> {
+    if let Some(recorder) = state.input.recorder.as_mut() {
+        recorder.record(&event, screen_id);
+    }
+    state.desktop.active_screen = screen_id;
     Ok(match event {
         InputEvent::PointerMotion { event, .. }
             => Pointer::on_move_relative::<B>(state, 0, event, screen_id),
@@ -45,8 +95,17 @@ fn handle_input <E: Engine, B: InputBackend> (
             => Pointer::on_button::<B>(state, 0, event, screen_id),
         InputEvent::PointerAxis { event, .. }
             => Pointer::on_axis::<B>(state, 0, event, screen_id),
-        InputEvent::Keyboard { event, .. }
-            => Keyboard::on_key::<B>(state, 0, event, screen_id),
+        InputEvent::Keyboard { event, .. } => {
+            Keyboard::on_key::<B>(state, 0, event, screen_id);
+            state.keyboard_leds_update(false)
+        },
+        // New devices start with their defaults and their LEDs off
+        InputEvent::DeviceAdded { device } => {
+            state.pointer_config_added(&device.name());
+            state.keyboard_leds_update(true)
+        },
+        InputEvent::SwitchToggle { event }
+            => state.switch_toggle::<B>(event),
         _ => {}
     })
 }
@@ -60,6 +119,20 @@ pub struct Input<E: Engine> {
     pub pointers:  Vec<Pointer<E>>,
     /// State of the keyboard(s)
     pub keyboards: Vec<Keyboard<E>>,
+    /// Where input events are being recorded, if anywhere
+    pub recorder:  Option<Recorder>,
+    /// Acceleration settings of relative pointer devices, by device name
+    pub pointer_configs: HashMap<String, PointerConfig>,
+    /// The seats, in the same order as the pointers and keyboards
+    pub seats:     Vec<Seat<Charlie<E>>>,
+    /// Kinds of input the engine delivers, and the seats advertise
+    pub capabilities: InputCapabilities,
+    /// Text offered as the selection by the compositor itself
+    pub selection: Option<String>,
+    /// The keymap of all keyboards
+    pub keymap:    Keymap,
+    /// Whether Num Lock is turned on when keyboards are added
+    pub numlock:   bool,
 }
 
 impl<E: Engine> Input<E> {
@@ -72,10 +145,26 @@ impl<E: Engine> Input<E> {
             data_device: DataDeviceState::new::<Charlie<E>, _>(&handle, logger.clone()),
             pointers:    vec![],
             keyboards:   vec![],
+            recorder:    None,
+            pointer_configs: HashMap::new(),
+            seats:       vec![],
+            // There's no touch support in the seats yet, so it's never advertised
+            capabilities: InputCapabilities { keyboard: true, pointer: true, touch: false },
+            selection:   None,
+            keymap:      Keymap::default(),
+            numlock:     false,
         })
     }
 
-    pub fn seat_add (&mut self, name: impl Into<String>, pointer: Gles2Texture)
+    /// The acceleration settings of a device, or the default ones if it has none.
+    pub fn pointer_config (&self, device: &str) -> PointerConfig {
+        self.pointer_configs.get(device)
+            .or_else(|| self.pointer_configs.get(ALL_DEVICES))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn seat_add (&mut self, name: impl Into<String>, pointer: EngineTexture<E>)
         -> Result<Seat<Charlie<E>>, Box<dyn Error>>
     {
         let mut seat = self.seat.new_wl_seat(&self.handle, name.into(), self.logger.clone());
@@ -83,12 +172,35 @@ impl<E: Engine> Input<E> {
             Pointer::new(&self.logger, seat.add_pointer(), pointer)?
         );
         self.keyboards.push(
-            Keyboard::new(&self.logger, seat.add_keyboard(XkbConfig::default(), 200, 25)?)
+            Keyboard::new(&self.logger, seat.add_keyboard(self.keymap.xkb(), REPEAT_DELAY, REPEAT_RATE)?)
         );
-        seat.add_input_method(XkbConfig::default(), 200, 25);
+        seat.add_input_method(self.keymap.xkb(), REPEAT_DELAY, REPEAT_RATE);
+        self.seats.push(seat.clone());
         Ok(seat)
     }
 
+    /// Add or remove the keyboards and pointers of all seats, so that clients
+    /// only see the kinds of input that can actually arrive.
+    pub fn set_capabilities (&mut self, capabilities: InputCapabilities) -> StdResult<()> {
+        for (index, seat) in self.seats.iter_mut().enumerate() {
+            match (self.capabilities.keyboard, capabilities.keyboard) {
+                (true, false) => seat.remove_keyboard(),
+                (false, true) => {
+                    self.keyboards[index].handle = seat.add_keyboard(self.keymap.xkb(), REPEAT_DELAY, REPEAT_RATE)?;
+                },
+                _ => {}
+            }
+            match (self.capabilities.pointer, capabilities.pointer) {
+                (true, false) => seat.remove_pointer(),
+                (false, true) => self.pointers[index].handle = seat.add_pointer(),
+                _ => {}
+            }
+        }
+        // Touch is tracked, but the seats don't support it yet
+        self.capabilities = capabilities;
+        Ok(())
+    }
+
 }
 
 #[delegate_seat]
@@ -100,14 +212,31 @@ impl<E: Engine> SeatHandler for Charlie<E> {
         &mut self.input.seat
     }
 
+    /// Clients' own cursor surfaces aren't drawn; a client that sets one
+    /// instead of a named shape gets the default cursor.
     fn cursor_image (
         &mut self,
-        _seat: &Seat<Self>,
+        seat: &Seat<Self>,
         _image: smithay::input::pointer::CursorImageStatus,
     ) {
+        if let Some(index) = self.input.seats.iter().position(|s| s == seat) {
+            if let Some(pointer) = self.input.pointers.get_mut(index) {
+                pointer.cursor.reset_shape();
+            }
+        }
     }
 
-    fn focus_changed(&mut self, _seat: &Seat<Self>, _focused: Option<&WlSurface>) {
+    fn focus_changed(&mut self, _seat: &Seat<Self>, focused: Option<&WlSurface>) {
+        self.desktop.focused = focused.cloned();
+        // A window that wanted attention got it
+        if let Some(window) = focused.and_then(|surface| self.desktop.window_state_mut(surface)) {
+            window.urgent = false;
+        }
+        self.redraw.request();
+        // Game mode is only for the window that has focus
+        self.game_mode_check();
+        let window = focused.map(|surface| self.script_window(surface)).unwrap_or(Dynamic::UNIT);
+        self.scripts.hook("on_focus_changed", vec![window]);
     }
 }
 
@@ -116,6 +245,26 @@ impl<E: Engine> DataDeviceHandler for Charlie<E> {
     fn data_device_state(&self) -> &DataDeviceState {
         &self.input.data_device
     }
+
+    fn new_selection(&mut self, source: Option<WlDataSource>) {
+        // Serve the selection once the data device is done taking it,
+        // and offer it to X11 clients too
+        match source {
+            Some(source) => {
+                self.handle.insert_idle(move |state| {
+                    state.x11_selection_claim(Some(source.clone()));
+                    state.selection_adopt(source);
+                });
+            },
+            None => if let Some(xwm) = self.xwm.as_mut() {
+                xwm.selection.source = None;
+            }
+        }
+    }
+
+    fn send_selection(&mut self, mime_type: String, fd: std::os::fd::RawFd) {
+        self.selection_send(mime_type, fd)
+    }
 }
 
 impl<E: Engine> ClientDndGrabHandler for Charlie<E> {}
@@ -123,28 +272,33 @@ impl<E: Engine> ClientDndGrabHandler for Charlie<E> {}
 impl<E: Engine> ServerDndGrabHandler for Charlie<E> {}
 
 /// Possible results of a keyboard action
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum KeyAction {
-    /// Quit the compositor
-    Quit,
-    /// Trigger a vt-switch
-    VtSwitch(i32),
-    /// run a command
-    Run(String),
-    /// Switch the current screen
-    Screen(usize),
-    ScaleUp,
-    ScaleDown,
+    /// Perform an action bound to the key
+    Perform(Action),
+    /// Focus the window labeled with this key, leaving hint mode
+    Pick(u32),
     /// Forward the key to the client
     Forward,
     /// Do nothing more
     None,
 }
 
+impl KeyAction {
+    /// Whether the action is performed again while its key is held.
+    fn repeats (&self) -> bool {
+        matches!(self, Self::Perform(action) if action.repeats())
+    }
+}
+
 pub struct Keyboard<E: Engine> {
-    logger:  Logger,
-    handle:  KeyboardHandle<Charlie<E>>,
-    hotkeys: Vec<u32>,
+    logger:     Logger,
+    pub handle: KeyboardHandle<Charlie<E>>,
+    hotkeys:    Vec<u32>,
+    /// The bound key that's held down and repeating, and the timer that repeats it
+    repeat:     Option<(u32, RegistrationToken)>,
+    /// The locks last shown on the LEDs
+    pub leds:   KeyboardLeds,
 }
 
 impl<E: Engine> Keyboard<E> {
@@ -154,6 +308,8 @@ impl<E: Engine> Keyboard<E> {
             logger: logger.clone(),
             handle,
             hotkeys: vec![],
+            repeat:  None,
+            leds:    KeyboardLeds::default(),
         }
     }
 
@@ -166,15 +322,53 @@ impl<E: Engine> Keyboard<E> {
         let key_code   = event.key_code();
         let key_state  = event.state();
         let serial     = SERIAL_COUNTER.next_serial();
-        let logger     = state.logger.clone();
         let time       = Event::time(&event);
-        //let hotkeys    = &mut state.keyboards[index].hotkeys;
-        let mut action = KeyAction::None;
         debug!(state.logger, "key"; "keycode" => key_code, "state" => format!("{:?}", key_state));
-        let keyboard = &mut state.input.keyboards[index];
-        keyboard.handle.clone().input::<(), _>(state, key_code, key_state, serial, time, |_,_,_|{
-            FilterResult::Forward
+        let keyboard = state.input.keyboards[index].handle.clone();
+        let action = keyboard.input::<KeyAction, _>(state, key_code, key_state, serial, time, |state, modifiers, keysym| {
+            let keysym  = keysym.modified_sym();
+            if let KeyState::Pressed = key_state {
+                let action = match Self::bound_action(modifiers, keysym) {
+                    // While a client has taken all input, only switching VTs stays bound
+                    action @ KeyAction::Perform(Action::VtSwitch(_)) if state.input_inhibitor.active() => action,
+                    _ if state.input_inhibitor.active() => KeyAction::Forward,
+                    // A kiosk has one binding, which doesn't go to scripts either
+                    _ if state.kiosk.enabled => if state.kiosk.escape.matches(modifiers, keysym) {
+                        KeyAction::Perform(state.kiosk.action.clone())
+                    } else {
+                        KeyAction::Forward
+                    },
+                    // While windows are labeled with letters, keys pick them and go no further
+                    _ if state.hints.active() => KeyAction::Pick(keysym),
+                    // Keys that aren't bound may be consumed by scripts
+                    KeyAction::Forward if state.scripts.key(modifiers, keysym) => KeyAction::None,
+                    action => action
+                };
+                match action {
+                    KeyAction::Forward => FilterResult::Forward,
+                    action => {
+                        // Don't forward the release of a bound key either
+                        state.input.keyboards[index].hotkeys.push(keysym);
+                        if action.repeats() {
+                            Self::repeat_start(state, index, keysym, action.clone());
+                        }
+                        FilterResult::Intercept(action)
+                    }
+                }
+            } else if state.input.keyboards[index].hotkeys.contains(&keysym) {
+                if state.input.keyboards[index].repeat.as_ref().map(|(k, _)| *k) == Some(keysym) {
+                    Self::repeat_cancel(state, index);
+                }
+                let hotkeys = &mut state.input.keyboards[index].hotkeys;
+                hotkeys.retain(|k| *k != keysym);
+                FilterResult::Intercept(KeyAction::None)
+            } else {
+                FilterResult::Forward
+            }
         });
+        if let Some(action) = action {
+            state.key_action(action)
+        }
         //self.keyboard.input((), keycode, state, serial, time, |state, modifiers, keysym| {
             //debug!(log, "keysym";
                 //"state"  => format!("{:?}", state),
@@ -224,16 +418,227 @@ impl<E: Engine> Keyboard<E> {
         //};
     }
 
+    /// Perform a bound action again and again while its key is held,
+    /// after the same delay and at the same rate as clients repeat keys.
+    fn repeat_start (state: &mut Charlie<E>, index: usize, keysym: u32, action: KeyAction) {
+        Self::repeat_cancel(state, index);
+        let delay    = Duration::from_millis(REPEAT_DELAY as u64);
+        let interval = Duration::from_millis(1000 / REPEAT_RATE as u64);
+        let timer = state.handle.insert_source(Timer::from_duration(delay), move |_, _, state| {
+            state.key_action(action.clone());
+            TimeoutAction::ToDuration(interval)
+        });
+        match timer {
+            Ok(token) => state.input.keyboards[index].repeat = Some((keysym, token)),
+            Err(e) => warn!(state.logger, "Could not repeat key action: {e}")
+        }
+    }
+
+    /// Stop repeating the held key, if any.
+    fn repeat_cancel (state: &mut Charlie<E>, index: usize) {
+        if let Some((_, token)) = state.input.keyboards[index].repeat.take() {
+            state.handle.remove(token);
+        }
+    }
+
+    /// Determine which action, if any, is bound to a key combination.
+    fn bound_action (modifiers: &ModifiersState, keysym: u32) -> KeyAction {
+        KeyAction::Perform(if modifiers.ctrl && modifiers.alt && keysym == keysyms::KEY_BackSpace
+            || modifiers.logo && keysym == keysyms::KEY_q
+        {
+            Action::Quit
+        } else if (keysyms::KEY_XF86Switch_VT_1..=keysyms::KEY_XF86Switch_VT_12).contains(&keysym) {
+            Action::VtSwitch((keysym - keysyms::KEY_XF86Switch_VT_1 + 1) as i32)
+        } else if modifiers.logo && modifiers.alt && keysym >= keysyms::KEY_1 && keysym <= keysyms::KEY_9 {
+            Action::JumpTo((keysym - keysyms::KEY_0) as u64)
+        } else if modifiers.logo && keysym >= keysyms::KEY_1 && keysym <= keysyms::KEY_9 {
+            Action::WorkspaceSwitch((keysym - keysyms::KEY_0).to_string())
+        } else if modifiers.logo && keysym == keysyms::KEY_Tab {
+            Action::WorkspaceBackAndForth
+        } else if modifiers.logo && keysym == keysyms::KEY_m {
+            Action::Minimize(None)
+        } else if modifiers.alt && keysym == keysyms::KEY_Tab {
+            Action::Cycle
+        } else if modifiers.logo && keysym == keysyms::KEY_w {
+            Action::WarpToFocused
+        } else if modifiers.logo && keysym == keysyms::KEY_Page_Up {
+            Action::Raise(None)
+        } else if modifiers.logo && keysym == keysyms::KEY_Page_Down {
+            Action::Lower(None)
+        } else if modifiers.logo && keysym == keysyms::KEY_a {
+            Action::ToggleLayer(StackLayer::Above)
+        } else if modifiers.logo && keysym == keysyms::KEY_b {
+            Action::ToggleLayer(StackLayer::Below)
+        } else if modifiers.logo && keysym == keysyms::KEY_equal {
+            Action::ScaleStep(SCALE_STEP)
+        } else if modifiers.logo && keysym == keysyms::KEY_minus {
+            Action::ScaleStep(-SCALE_STEP)
+        } else if modifiers.logo && modifiers.shift && keysym == keysyms::KEY_Escape {
+            Action::Kill
+        } else if modifiers.logo && modifiers.shift && keysym == keysyms::KEY_D {
+            Action::Snapshot(None)
+        } else if let (true, Some((dx, dy))) = (modifiers.logo, arrow(keysym)) {
+            if modifiers.ctrl {
+                Action::MoveToEdge(match (dx, dy) {
+                    (-1, _) => Edge::Left,
+                    (1, _)  => Edge::Right,
+                    (_, -1) => Edge::Top,
+                    _       => Edge::Bottom,
+                })
+            } else if modifiers.shift {
+                Action::ResizeBy(dx * ARRANGE_STEP, dy * ARRANGE_STEP)
+            } else {
+                Action::MoveBy(dx * ARRANGE_STEP, dy * ARRANGE_STEP)
+            }
+        } else if modifiers.logo && keysym == keysyms::KEY_o {
+            Action::MoveToScreen(None)
+        } else if modifiers.logo && keysym == keysyms::KEY_c {
+            Action::Center
+        } else if modifiers.logo && keysym == keysyms::KEY_f {
+            Action::ToggleFloating
+        } else if modifiers.logo && keysym == keysyms::KEY_bracketright {
+            Action::GapsBy(GAP_STEP)
+        } else if modifiers.logo && keysym == keysyms::KEY_bracketleft {
+            Action::GapsBy(-GAP_STEP)
+        } else if modifiers.logo && keysym == keysyms::KEY_p {
+            Action::TogglePip(None)
+        } else if modifiers.logo && keysym == keysyms::KEY_i {
+            Action::ToggleIntegerScaling
+        } else if modifiers.logo && keysym == keysyms::KEY_j {
+            Action::Hints
+        } else if let Some(key) = MediaKey::from_keysym(keysym) {
+            Action::MediaKey(key)
+        } else if keysym == keysyms::KEY_XF86PowerOff {
+            Action::Power
+        } else {
+            return KeyAction::Forward
+        })
+    }
+
+}
+
+/// Which way an arrow key points, as steps along the x and y axes.
+fn arrow (keysym: u32) -> Option<(i32, i32)> {
+    match keysym {
+        keysyms::KEY_Left  => Some((-1, 0)),
+        keysyms::KEY_Right => Some((1, 0)),
+        keysyms::KEY_Up    => Some((0, -1)),
+        keysyms::KEY_Down  => Some((0, 1)),
+        _ => None
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Tell clients where the pointers have moved since the last turn of the main loop.
+    pub fn pointer_frame (&mut self) {
+        for index in 0..self.input.pointers.len() {
+            Pointer::flush(self, index);
+        }
+    }
+
+    /// Give keyboard focus to a window, or take it away from all windows.
+    pub fn focus (&mut self, surface: Option<WlSurface>) {
+        // While a client has taken all input, only its own surfaces can have focus
+        if !surface.as_ref().map(|surface| self.input_inhibitor.admits(surface)).unwrap_or(true) {
+            return
+        }
+        // A kiosk doesn't focus the windows that it doesn't show
+        if !surface.as_ref().map(|surface| self.kiosk_admits(surface)).unwrap_or(true) {
+            return
+        }
+        // A window with a modal dialog open passes focus on to the dialog
+        let surface = surface.map(|surface| match self.desktop.modal_child(&surface) {
+            Some(dialog) => {
+                self.desktop.raise(&dialog);
+                self.redraw.request();
+                dialog
+            },
+            None => surface
+        });
+        // Held bindings stop repeating once focus moves
+        for index in 0..self.input.keyboards.len() {
+            Keyboard::repeat_cancel(self, index);
+        }
+        let serial = SERIAL_COUNTER.next_serial();
+        let keyboards: Vec<_> = self.input.keyboards.iter().map(|k| k.handle.clone()).collect();
+        for keyboard in keyboards {
+            keyboard.set_focus(self, surface.clone(), serial);
+        }
+    }
+
+    /// Hide a window, passing focus on if it had it.
+    pub fn minimize (&mut self, surface: &WlSurface) {
+        self.desktop.minimize(surface);
+        if self.desktop.focused.as_ref() == Some(surface) {
+            let next = self.desktop.windows_visible().next()
+                .map(|w| w.window.toplevel().wl_surface().clone());
+            self.focus(next);
+        }
+    }
+
+    /// Focus the next window of the current workspace, restoring it if minimized.
+    pub fn cycle (&mut self) {
+        if let Some(next) = self.desktop.window_next(self.desktop.focused.as_ref()) {
+            self.desktop.unminimize(&next);
+            self.desktop.raise(&next);
+            self.focus(Some(next));
+        }
+    }
+
+    /// Change the scale of the active screen's output by a step, staying on multiples of it.
+    pub fn scale_step (&mut self, step: f64) {
+        let screen = self.desktop.active_screen;
+        let scale = match self.engine.output_get(screen) {
+            Some(output) => output.current_scale().fractional_scale(),
+            None => return
+        };
+        let scale = (((scale + step) / SCALE_STEP).round() * SCALE_STEP).clamp(SCALE_STEP * 2.0, 4.0);
+        if let Err(e) = self.output_scale(screen, scale) {
+            warn!(self.logger, "Could not scale screen {screen}: {e}");
+        }
+    }
+
+    /// Perform the action bound to a key.
+    fn key_action (&mut self, action: KeyAction) {
+        match action {
+            KeyAction::None | KeyAction::Forward => {},
+            KeyAction::Pick(keysym) => {
+                self.hints_pick(keysym)
+            },
+            KeyAction::Perform(action) => if let Err(e) = self.perform(action.clone()) {
+                debug!(self.logger, "Not performing {action}: {e}");
+            },
+        }
+    }
+
 }
 
 pub struct Pointer<E: Engine> {
     logger:        Logger,
     pub handle:    PointerHandle<Charlie<E>>,
-    pub texture:   Gles2Texture,
+    /// Fallback image for when no cursor theme could be loaded
+    pub texture:   EngineTexture<E>,
+    /// Cursor theme images, rasterized per output scale
+    pub cursor:    Cursor<E::Renderer>,
     status:        Arc<Mutex<Status>>,
-    location:      Point<f64, Logical>,
+    pub location:  Point<f64, Logical>,
     last_location: Point<f64, Logical>,
+    /// Time of the last relative motion event, for measuring speed
+    last_motion:   Option<u32>,
+    /// Where the pointer is kept, if anywhere
+    pub confine:   Option<Confine>,
+    /// The screen the pointer was last moved on
+    pub screen:    ScreenId,
+    /// Whether the pointer is dragging the screen, and so isn't over any surface
     held:          bool,
+    /// Whether the pointer is moving or resizing a window with the logo key,
+    /// and so clients see none of its buttons until it's released
+    dragging:      bool,
+    /// Time of the latest motion that clients haven't been told about yet
+    pending:       Option<u32>,
+    /// Parts of wheel steps not yet sent to clients, in 120ths of a step, per axis
+    wheel_v120:    [i32; 2],
 }
 
 impl<E: Engine> Pointer<E> {
@@ -241,24 +646,32 @@ impl<E: Engine> Pointer<E> {
     pub fn new (
         logger:  &Logger,
         handle:  PointerHandle<Charlie<E>>,
-        texture: Gles2Texture
+        texture: EngineTexture<E>
     ) -> Result<Self, Box<dyn Error>> {
         Ok(Self {
             logger:        logger.clone(),
             status:        Arc::new(Mutex::new(Status::Default)),
+            cursor:        Cursor::from_env(logger),
             location:      (100.0, 30.0).into(),
             last_location: (100.0, 30.0).into(),
+            last_motion:   None,
+            confine:       None,
+            screen:        0,
             handle,
             texture,
-            held: false
+            held:          false,
+            dragging:      false,
+            pending:       None,
+            wheel_v120:    [0, 0],
         })
     }
 
-    /// Render this pointer
+    /// Render this pointer at the given output scale
     pub fn render <'a> (
         &mut self,
-        frame:  &mut Gles2Frame<'a>,
+        frame:  &mut <E::Renderer as Renderer>::Frame<'a>,
         size:   &Size<i32, Physical>,
+        scale:  f64,
         screen: &ScreenState
     ) -> StdResult<()> {
         let damage = Rectangle::<i32, Physical>::from_loc_and_size(
@@ -267,10 +680,14 @@ impl<E: Engine> Pointer<E> {
         );
         let x = self.location.x;
         let y = self.location.y;
-        let location = Point::<f64, Logical>::from((x, y)).to_physical(1.0).to_i32_round();
-        //let size = self.texture.size();
+        let location = Point::<f64, Logical>::from((x, y)).to_physical(scale).to_i32_round();
+        // Use the theme cursor rasterized for this scale, if there is one
+        let (texture, location) = match self.cursor.get(scale) {
+            Some((texture, hotspot)) => (texture, location - *hotspot),
+            None => (&self.texture, location)
+        };
         Ok(frame.render_texture_at(
-            &self.texture,
+            texture,
             location,
             1,
             1.0,
@@ -301,14 +718,38 @@ impl<E: Engine> Pointer<E> {
         (visible, location)
     }
 
+    /// Relative motion moves the pointer from wherever it is, whichever screen the event came from.
     pub fn on_move_relative<B: InputBackend>(
         state: &mut Charlie<E>,
         index: usize,
         event: B::PointerMotionEvent,
-        screen_id: usize
+        _screen_id: usize
     ) {
-        let delta = event.delta();
-        panic!("{:?}", delta);
+        let config = state.input.pointer_config(&event.device().name());
+        let time = event.time();
+        let pointer = &mut state.input.pointers[index];
+        let interval = pointer.last_motion.map(|last| time.wrapping_sub(last));
+        pointer.last_motion = Some(time);
+        let delta = config.accelerate(
+            (event.delta_x_unaccel(), event.delta_y_unaccel()).into(), interval
+        );
+        let mut location = pointer.location + delta;
+        // Go over to the screen next to this one in the output layout,
+        // unless confined or held back by a barrier on the edge
+        let screen_id = pointer.screen;
+        if pointer.confine.is_none() && state.layout.pass(screen_id, location) {
+            if let Some((screen, location)) = state.layout.cross(screen_id, location) {
+                return Self::move_to(state, index, location, screen, time)
+            }
+        }
+        // Keep the pointer on the output, pushing against hot edges
+        if let Some(area) = state.screen_area(screen_id) {
+            let unclamped = location;
+            location.x = location.x.clamp(0.0, (area.w - 1.0).max(0.0));
+            location.y = location.y.clamp(0.0, (area.h - 1.0).max(0.0));
+            state.hot_corners.push(unclamped - location);
+        }
+        Self::move_to(state, index, location, screen_id, time)
     }
 
     pub fn on_move_absolute<B: InputBackend>(
@@ -317,9 +758,57 @@ impl<E: Engine> Pointer<E> {
         event: B::PointerMotionAbsoluteEvent,
         screen_id: usize
     ) {
+        // Map the device (e.g. the host window the event came from) onto the logical area
+        // of the output that shows this screen, whatever the size and scale of each,
+        // then back to the untransformed space we draw in
+        let mut location: Point<f64, Logical> = (event.x(), event.y()).into();
+        if let Some(output) = state.engine.output_get(screen_id) {
+            let (transform, scale) = (output.current_transform(), output.current_scale().fractional_scale());
+            if let Some(mode) = output.current_mode() {
+                let area = transform_size(transform, mode.size.to_f64().to_logical(scale));
+                location = (
+                    event.x_transformed(area.w.round() as i32),
+                    event.y_transformed(area.h.round() as i32)
+                ).into();
+                location = untransform_point(transform, location, area);
+            }
+        }
+        Self::move_to(state, index, location, screen_id, event.time())
+    }
+
+    /// Move the pointer to a point on a screen, dragging the screen if a button is held.
+    pub fn move_to (
+        state:     &mut Charlie<E>,
+        index:     usize,
+        location:  Point<f64, Logical>,
+        screen_id: usize,
+        time:      u32
+    ) {
+        let pointer = &mut state.input.pointers[index];
+        let location = match pointer.confine.as_ref() {
+            None => location,
+            Some(confine) => match confine.apply(screen_id, location) {
+                Some(location) => location,
+                // Motion on other screens doesn't move a confined pointer
+                None => return
+            }
+        };
+        // Entering another screen isn't motion within it
+        let crossed = pointer.screen != screen_id;
+        pointer.last_location = if crossed { location } else { pointer.location };
+        pointer.location = location;
+        pointer.screen = screen_id;
+        let modifiers = state.input.keyboards.get(index)
+            .map(|keyboard| {
+                let modifiers = keyboard.handle.modifier_state();
+                ResizeModifiers { proportional: modifiers.shift, centered: modifiers.ctrl }
+            })
+            .unwrap_or_default();
+        if state.resize_motion(screen_id, location, modifiers) || state.move_motion(screen_id, location) {
+            // Resizing and moving are grabs of our own, so clients don't see the motion
+            return
+        }
         let pointer = &mut state.input.pointers[index];
-        pointer.last_location = pointer.location;
-        pointer.location = (event.x(), event.y()).into();
         if pointer.held {
             crit!(state.logger, "CLECK! {screen_id}");
             let dx = pointer.location.x - pointer.last_location.x;
@@ -327,11 +816,9 @@ impl<E: Engine> Pointer<E> {
             state.desktop.screens[screen_id].center.x += dx as f64;
             state.desktop.screens[screen_id].center.y += dy as f64;
         } else {
-            pointer.handle.clone().motion(state, None, &MotionEvent {
-                location: (event.x(), event.y()).into(),
-                serial: SERIAL_COUNTER.next_serial(),
-                time: event.time()
-            })
+            // Clients are told once per turn of the main loop, in [Self::flush]
+            pointer.pending = Some(time);
+            state.hot_corners_motion(screen_id, location);
         }
         //self.pointer.motion(
             //self.location,
@@ -341,22 +828,158 @@ impl<E: Engine> Pointer<E> {
         //);
     }
 
+    /// Where the fullscreen window on a screen is drawn, if it's scaled by whole numbers,
+    /// and by how much, along with the scale of the screen's output.
+    fn integer_zoom (state: &Charlie<E>, screen_id: ScreenId) -> Option<(Point<f64, Logical>, f64, f64)> {
+        let scale = state.engine.output_get(screen_id)?.current_scale().fractional_scale();
+        let area = state.screen_area(screen_id)?;
+        let window = state.desktop.windows_visible().find(|w| w.fullscreen_on(screen_id))?;
+        let (origin, zoom) = window.integer_zoom(area, scale)?;
+        Some((origin, zoom, scale))
+    }
+
+    /// What the pointer is busy with, if anything: moving or resizing a window with the logo key,
+    /// dragging the screen, or a grab of a client's.
+    pub fn grab (&self) -> Option<&'static str> {
+        if self.dragging {
+            Some("window")
+        } else if self.held {
+            Some("screen")
+        } else if self.handle.is_grabbed() {
+            Some("client")
+        } else {
+            None
+        }
+    }
+
+    /// Enter whatever surface is under the pointer now, as if it had moved there.
+    pub fn refresh (state: &mut Charlie<E>, index: usize, time: u32) {
+        state.input.pointers[index].pending = Some(time);
+        Self::flush(state, index);
+    }
+
+    /// Send the motion that built up since the last frame, entering and leaving surfaces
+    /// as the pointer crosses their edges. However many motion events the device sent,
+    /// clients get one motion event and one frame per turn of the main loop.
+    pub fn flush (state: &mut Charlie<E>, index: usize) {
+        let pointer = &mut state.input.pointers[index];
+        let time = match pointer.pending.take() {
+            Some(time) => time,
+            None => return
+        };
+        let (location, screen_id) = (pointer.location, pointer.screen);
+        let handle = pointer.handle.clone();
+        let focus = state.desktop.surface_under(screen_id, location)
+            .filter(|(surface, _)| state.input_inhibitor.admits(surface));
+        // A fullscreen window drawn at a whole multiple of its size sees the pointer
+        // where it is on the window as drawn
+        let location = match (&focus, Self::integer_zoom(state, screen_id)) {
+            (Some((_, origin)), Some((drawn, zoom, scale))) =>
+                origin.to_f64() + (location - drawn).to_physical(scale).to_logical(zoom),
+            _ => location
+        };
+        // A shape that a client asked for is only shown over that client's surfaces
+        let client = focus.as_ref().and_then(|(surface, _)| surface.client()).map(|client| client.id());
+        let cursor = &mut state.input.pointers[index].cursor;
+        if cursor.owner.is_some() && cursor.owner != client {
+            cursor.reset_shape();
+        }
+        handle.motion(state, focus, &MotionEvent {
+            location,
+            serial: SERIAL_COUNTER.next_serial(),
+            time
+        });
+    }
+
     pub fn on_button<B: InputBackend>(
         state: &mut Charlie<E>,
         index: usize,
         event: B::PointerButtonEvent,
         screen_id: usize
     ) {
+        // Clients must see the pointer where the button was pressed
+        Self::flush(state, index);
+        let (serial, time) = (SERIAL_COUNTER.next_serial(), event.time());
+        let button = match (state.input.pointer_config(&event.device().name()).left_handed, event.button_code()) {
+            (true, BTN_LEFT)  => BTN_RIGHT,
+            (true, BTN_RIGHT) => BTN_LEFT,
+            (_, button)       => button
+        };
         match event.state() {
+            ButtonState::Pressed if state.input_inhibitor.active() || state.kiosk.enabled => {
+                // While a client has taken all input, clicks only focus its own windows,
+                // and a kiosk doesn't let the screen be dragged around
+                let location = state.input.pointers[index].location;
+                if let Some(surface) = state.desktop.window_under(screen_id, location) {
+                    state.focus(Some(surface));
+                }
+            },
             ButtonState::Pressed => {
                 crit!(state.logger, "CLICK! {screen_id}");
-                state.input.pointers[index].held = true;
+                // Clicking a notification dismisses it
+                if screen_id == state.desktop.active_screen {
+                    let location = state.input.pointers[index].location;
+                    if let Some(id) = state.notifications.at(location) {
+                        state.notification_close(id, CloseReason::Dismissed);
+                        return
+                    }
+                }
+                // Clicking a window focuses and raises it
+                let location = state.input.pointers[index].location;
+                if Self::drag_start(state, index, button, screen_id) {
+                    let handle = state.input.pointers[index].handle.clone();
+                    handle.motion(state, None, &MotionEvent { location, serial, time });
+                    return
+                }
+                if let Some(surface) = state.desktop.window_under(screen_id, location) {
+                    state.desktop.raise(&surface);
+                    state.focus(Some(surface));
+                } else {
+                    // Dragging the empty desktop moves the screen. That's a grab of our own,
+                    // so the pointer leaves the surface it was over until the button is released
+                    let pointer = &mut state.input.pointers[index];
+                    pointer.held = true;
+                    let handle = pointer.handle.clone();
+                    handle.motion(state, None, &MotionEvent { location, serial, time });
+                    return
+                }
             },
             ButtonState::Released => {
                 crit!(state.logger, "CLACK! {screen_id}");
-                state.input.pointers[index].held = false;
+                if state.input.pointers[index].dragging {
+                    // Releasing the button ends a move or resize started with the logo key,
+                    // and the pointer enters whatever ended up under it
+                    state.move_end();
+                    state.resize_end();
+                    let pointer = &mut state.input.pointers[index];
+                    pointer.dragging = false;
+                    pointer.pending  = Some(time);
+                    Self::flush(state, index);
+                    return
+                }
+                if state.resize_end() || state.move_end() {
+                    // The client still gets the release of the button that started the resize,
+                    // which ends the implicit grab, and then the motion it missed
+                    state.input.pointers[index].pending = Some(time);
+                }
+                let pointer = &mut state.input.pointers[index];
+                if pointer.held {
+                    // Enter whatever ended up under the pointer
+                    pointer.held    = false;
+                    pointer.pending = Some(time);
+                    Self::flush(state, index);
+                    return
+                }
             }
         }
+        // Smithay's default grab keeps the pressed surface focused until all buttons are released
+        let handle = state.input.pointers[index].handle.clone();
+        handle.button(state, &ButtonEvent {
+            button,
+            state:  event.state(),
+            serial,
+            time
+        });
         //self.desktop.borrow_mut();
         //let serial = SCOUNTER.next_serial();
         //let button = match evt.button() {
@@ -390,12 +1013,97 @@ impl<E: Engine> Pointer<E> {
         //self.pointer.button(button, state, serial, evt.time());
     }
 
+    /// With the logo key held, the left button moves the window under the pointer
+    /// and the right button resizes it from the nearest corner, whatever decorations
+    /// it has. Returns false if the press isn't a binding.
+    fn drag_start (state: &mut Charlie<E>, index: usize, button: u32, screen_id: ScreenId) -> bool {
+        let logo = state.input.keyboards.get(index)
+            .map(|keyboard| keyboard.handle.modifier_state().logo)
+            .unwrap_or(false);
+        if !logo || !matches!(button, BTN_LEFT | BTN_RIGHT) || state.kiosk.enabled {
+            return false
+        }
+        let location = state.input.pointers[index].location;
+        let surface = match state.desktop.window_under(screen_id, location) {
+            Some(surface) => surface,
+            None => return false
+        };
+        let (area, toplevel) = match (
+            state.desktop.window_state(&surface),
+            state.desktop.screens.get(screen_id)
+        ) {
+            (Some(window), Some(screen)) if window.fullscreen.is_none() => (
+                window.geometry_on(screen).to_f64(),
+                match window.window.toplevel() {
+                    Kind::Xdg(toplevel) => Some(toplevel.clone()),
+                    _ => None
+                }
+            ),
+            _ => return false
+        };
+        state.desktop.raise(&surface);
+        state.focus(Some(surface.clone()));
+        if button == BTN_LEFT {
+            state.move_start(&surface, index);
+        } else {
+            let toplevel = match toplevel {
+                Some(toplevel) => toplevel,
+                None => return false
+            };
+            let left = location.x < area.loc.x + area.size.w / 2.0;
+            let top  = location.y < area.loc.y + area.size.h / 2.0;
+            let edges = match (left, top) {
+                (true, true)   => XdgToplevelResizeEdge::TopLeft,
+                (false, true)  => XdgToplevelResizeEdge::TopRight,
+                (true, false)  => XdgToplevelResizeEdge::BottomLeft,
+                (false, false) => XdgToplevelResizeEdge::BottomRight,
+            };
+            state.resize_start(toplevel, index, edges);
+        }
+        state.input.pointers[index].dragging = true;
+        true
+    }
+
     pub fn on_axis<B: InputBackend>(
         state: &mut Charlie<E>,
         index: usize,
         event: B::PointerAxisEvent,
         screen_id: usize
     ) {
+        Self::flush(state, index);
+        let direction = state.input.pointer_config(&event.device().name()).scroll_direction();
+        let source = event.source();
+        let mut frame = AxisFrame::new(event.time()).source(source);
+        let pointer = &mut state.input.pointers[index];
+        for (slot, axis) in [Axis::Horizontal, Axis::Vertical].into_iter().enumerate() {
+            let steps = event.amount_discrete(axis).map(|steps| steps * direction);
+            let amount = event.amount(axis).map(|amount| amount * direction)
+                .or(steps.map(|steps| steps * SCROLL_STEP))
+                .unwrap_or(0.0);
+            if amount != 0.0 {
+                frame = frame.value(axis, amount);
+            } else if source == AxisSource::Finger {
+                frame = frame.stop(axis);
+            }
+            // High resolution wheels report fractions of a step. Those add up here,
+            // and clients are sent a discrete step whenever a whole one is reached
+            if let Some(steps) = steps {
+                let v120 = (steps * 120.0).round() as i32;
+                let pending = &mut pointer.wheel_v120[slot];
+                if pending.signum() * v120.signum() < 0 {
+                    // Changing direction drops what was left of the other one
+                    *pending = 0;
+                }
+                *pending += v120;
+                let whole = *pending / 120;
+                *pending -= whole * 120;
+                if whole != 0 {
+                    frame = frame.discrete(axis, whole);
+                }
+            }
+        }
+        let handle = pointer.handle.clone();
+        handle.axis(state, frame);
         //let source = match evt.source() {
             //AxisSource::Continuous => wl_pointer::AxisSource::Continuous,
             //AxisSource::Finger => wl_pointer::AxisSource::Finger,
@@ -0,0 +1,902 @@
+use super::prelude::*;
+
+use std::{
+    io::{Read, Write, ErrorKind},
+    os::unix::{fs::PermissionsExt, net::{UnixListener, UnixStream}},
+    path::PathBuf,
+    ffi::OsStr,
+};
+
+use super::action::Action;
+use super::rules::WindowRule;
+use super::acceleration::PointerConfig;
+use super::hotcorner::HotEdge;
+use super::nightlight::parse_time;
+use super::decoration::{Shadow, Border, parse_color};
+use super::layout::{Placement, Barrier};
+use super::manage::Gaps;
+use super::keymap::Keymap;
+use super::transaction::Change;
+use super::color::{ColorDepth, output_format};
+use super::memory::{MemoryPolicy, MemoryUsage};
+use super::clipboard::MimeRule;
+use super::identity::Identity;
+
+use smithay::reexports::calloop::{PostAction, Interest, Mode, generic::Generic};
+
+/// Name of the environment variable that tells clients where the IPC socket is
+pub const IPC_SOCKET_ENV: &str = "CHARLIE_SOCKET";
+
+/// How long to wait for a client to take a reply or an event
+const IPC_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The longest command a client may send
+const IPC_LINE_MAX: usize = 64 * 1024;
+
+/// Whether a command does what a privileged protocol does, or changes who may have one,
+/// and so may only be run by the clients that [Security::ipc_admits](super::security::Security::ipc_admits).
+pub fn ipc_privileged (args: &[&str]) -> bool {
+    matches!(args,
+        ["security", "allow", ..] |
+        ["security", "deny", ..] |
+        ["security", "inhibitor", "release"] |
+        ["screenshot", ..] |
+        ["capture", ..] |
+        ["input", ..] |
+        ["record", "start", ..] |
+        ["replay", ..]
+    )
+}
+
+/// Split a command into its words at whitespace. A word that starts with a double quote
+/// runs to the closing quote, and may contain whitespace and escaped quotes and backslashes.
+pub fn ipc_split (line: &str) -> StdResult<Vec<String>> {
+    let mut words = vec![];
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&first) = chars.peek() {
+        if first.is_whitespace() {
+            chars.next();
+            continue
+        }
+        let mut word = String::new();
+        if first == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => word.push(chars.next().ok_or("unterminated quote")?),
+                    Some(c) => word.push(c),
+                    None => return Err("unterminated quote".into())
+                }
+            }
+        }
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            word.push(c);
+        }
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// A word as it's written in a command, quoted if it's empty, has whitespace, or starts with
+/// a quote, so that [ipc_split] gives it back.
+pub fn ipc_quote (word: &str) -> std::borrow::Cow<'_, str> {
+    if !word.is_empty() && !word.starts_with('"') && !word.contains(char::is_whitespace) {
+        return word.into()
+    }
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\"")).into()
+}
+
+/// Join words into a command that [ipc_split] splits back into them.
+pub fn ipc_join (words: &[&str]) -> String {
+    words.iter().map(|word| ipc_quote(word)).collect::<Vec<_>>().join(" ")
+}
+
+/// Listens for commands from `charliectl` on a Unix socket.
+///
+/// Each connection carries a single line containing a command,
+/// and receives a single line in response. Connections that send `subscribe`
+/// are kept open instead, and receive a line for each event, such as
+/// `output added NAME` and `output removed NAME`.
+pub struct Ipc {
+    logger:      Logger,
+    pub path:    PathBuf,
+    /// Connections that are waiting for events
+    subscribers: Vec<UnixStream>,
+}
+
+impl Ipc {
+
+    /// Bind the IPC socket next to the Wayland socket of this instance, which is only
+    /// done in the runtime directory, where other users can't reach it.
+    pub fn init <E: Engine> (
+        logger:  &Logger,
+        events:  &LoopHandle<'static, Charlie<E>>,
+        display: &OsStr,
+    ) -> StdResult<Self> {
+        let dir  = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+            .ok_or("XDG_RUNTIME_DIR is not set, not listening for IPC")?;
+        let path = dir.join(format!("charlie-{}.sock", display.to_string_lossy()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+        debug!(logger, "Listening for IPC on {path:?}");
+        events.insert_source(
+            Generic::new(listener, Interest::READ, Mode::Level),
+            |_, listener, state| {
+                while let Ok((stream, _)) = listener.accept() {
+                    if let Err(e) = state.ipc_accept(stream) {
+                        warn!(state.logger, "IPC error: {e}");
+                    }
+                }
+                Ok(PostAction::Continue)
+            }
+        )?;
+        Ok(Self { logger: logger.clone(), path, subscribers: vec![] })
+    }
+
+    /// Send an event to the subscribed connections, dropping the ones that went away.
+    pub fn emit (&mut self, event: &str) {
+        let logger = &self.logger;
+        self.subscribers.retain(|stream| match writeln!(&*stream, "{event}") {
+            Ok(()) => true,
+            Err(e) => {
+                debug!(logger, "Dropping IPC subscriber: {e}");
+                false
+            }
+        });
+    }
+
+}
+
+impl Drop for Ipc {
+    fn drop (&mut self) {
+        debug!(self.logger, "Removing {:?}", self.path);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Wait for a command on a new IPC connection without blocking the loop,
+    /// gathering what the client sends until the end of the line.
+    fn ipc_accept (&mut self, stream: UnixStream) -> StdResult<()> {
+        let identity = Identity::of_stream(&stream);
+        stream.set_nonblocking(true)?;
+        let mut line: Vec<u8> = vec![];
+        self.handle.insert_source(
+            Generic::new(stream, Interest::READ, Mode::Level),
+            move |_, stream, state| {
+                let mut chunk = [0u8; 4096];
+                let done = loop {
+                    match stream.read(&mut chunk) {
+                        // Whatever came before the client stopped sending counts as the command
+                        Ok(0) => break true,
+                        Ok(count) => line.extend_from_slice(&chunk[..count]),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break false,
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => {
+                            debug!(state.logger, "Dropping IPC connection: {e}");
+                            return Ok(PostAction::Remove)
+                        }
+                    }
+                    if line.len() > IPC_LINE_MAX {
+                        warn!(state.logger, "Dropping IPC connection: command too long");
+                        return Ok(PostAction::Remove)
+                    }
+                };
+                let end = match line.iter().position(|byte| *byte == b'\n') {
+                    Some(end) => end,
+                    None if done && !line.is_empty() => line.len(),
+                    None if done => return Ok(PostAction::Remove),
+                    None => return Ok(PostAction::Continue)
+                };
+                let command = String::from_utf8_lossy(&line[..end]).into_owned();
+                let result = match stream.try_clone() {
+                    Ok(stream) => state.ipc_reply(stream, &identity, &command),
+                    Err(e) => Err(e.into())
+                };
+                if let Err(e) = result {
+                    warn!(state.logger, "IPC error: {e}");
+                }
+                Ok(PostAction::Remove)
+            }
+        )?;
+        Ok(())
+    }
+
+    /// Execute a command from an IPC connection, and write back the result.
+    fn ipc_reply (&mut self, stream: UnixStream, identity: &Identity, line: &str) -> StdResult<()> {
+        // Replies are written whole, waiting a little for a client that's slow to take them
+        stream.set_nonblocking(false)?;
+        stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+        let words = match ipc_split(line) {
+            Ok(words) => words,
+            Err(e) => {
+                writeln!(&stream, "error: {e}")?;
+                return Ok(())
+            }
+        };
+        let args: Vec<&str> = words.iter().map(String::as_str).collect();
+        debug!(self.logger, "IPC command: {args:?}");
+        if args == ["subscribe"] {
+            // A subscriber that stops reading shouldn't hang the compositor for long
+            writeln!(&stream, "ok")?;
+            if let Some(ipc) = self.ipc.as_mut() {
+                ipc.subscribers.push(stream);
+            }
+            return Ok(())
+        }
+        let reply = match self.ipc_command_for(identity, &args) {
+            Ok(reply) => reply,
+            Err(e)    => format!("error: {e}")
+        };
+        writeln!(&stream, "{}", reply.trim_end())?;
+        Ok(())
+    }
+
+    /// Tell the IPC subscribers that something happened.
+    pub fn ipc_event (&mut self, event: &str) {
+        if let Some(ipc) = self.ipc.as_mut() {
+            ipc.emit(event);
+        }
+    }
+
+    /// Execute a command that's written out as a line, such as a hot corner command.
+    pub fn ipc_line (&mut self, line: &str) -> StdResult<String> {
+        let words = ipc_split(line)?;
+        self.ipc_command(&words.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    /// Execute a command for a client, unless it's privileged and the client isn't.
+    pub fn ipc_command_for (&mut self, identity: &Identity, args: &[&str]) -> StdResult<String> {
+        if ipc_privileged(args) && !self.security.ipc_admits(identity) {
+            warn!(self.logger, "Refusing {args:?} to unprivileged IPC client {:?}", identity.exe);
+            return Err(format!("not permitted: {}", args.join(" ")).into())
+        }
+        self.ipc_command(args)
+    }
+
+    /// Execute a command received over IPC.
+    pub fn ipc_command (&mut self, args: &[&str]) -> StdResult<String> {
+        // Most commands change what's on screen
+        self.redraw.request();
+        if let Some(action) = Action::parse(args) {
+            return self.perform(action?)
+        }
+        match args {
+            ["action", json @ ..] if !json.is_empty() => {
+                let json = json.join(" ");
+                // A unit action like `"quit"` loses its quotes on the way
+                let action: Action = serde_json::from_str(&json)
+                    .or_else(|e| serde_json::from_value(json.clone().into()).map_err(|_| e))?;
+                self.perform(action)
+            },
+            ["workspace"] | ["workspace", "current"] => {
+                Ok(self.desktop.workspaces.current_name().into())
+            },
+            ["workspace", "list"] => {
+                Ok(self.desktop.workspaces.list.iter()
+                    .map(|w|w.name.as_str()).collect::<Vec<_>>().join("\n"))
+            },
+            ["window", "list"] => {
+                Ok(self.desktop.windows_current().map(|window| {
+                    let (app_id, title) = window.app_id_and_title();
+                    format!("{} {} {}", window.id, app_id.unwrap_or_default(), title.unwrap_or_default())
+                }).collect::<Vec<_>>().join("\n"))
+            },
+            ["client", "list"] | ["list", "clients"] => {
+                let clients = self.clients.lock().map_err(|_| "client list is poisoned")?;
+                Ok(clients.iter()
+                    .map(|(id, identity)| format!("{id:?} {}", identity.describe()))
+                    .collect::<Vec<_>>().join("\n"))
+            },
+            ["window", "place", places @ ..] if !places.is_empty() && places.len() % 5 == 0 => {
+                let mut changes = vec![];
+                for place in places.chunks(5) {
+                    let (id, x, y, w, h) = (place[0], place[1], place[2], place[3], place[4]);
+                    changes.push(Change {
+                        surface: self.window_surface(id.parse()?)?,
+                        center:  (x.parse::<f64>()?, y.parse::<f64>()?).into(),
+                        size:    Some((w.parse::<i32>()?, h.parse::<i32>()?).into()),
+                    });
+                }
+                self.transaction_start(changes);
+                Ok("ok".into())
+            },
+            ["window", "icon", id] => {
+                let path = self.window_icon_path(id.parse()?).ok_or("no icon for this window")?;
+                Ok(path.display().to_string())
+            },
+            ["window", "urgent", id, on] => {
+                let window = self.desktop.window_by_id(id.parse()?).ok_or("no such window")?;
+                window.urgent = parse_toggle(on)?;
+                self.redraw();
+                Ok("ok".into())
+            },
+            ["window", "scaling", id] => {
+                let window = self.desktop.window_by_id(id.parse()?).ok_or("no such window")?;
+                Ok(window.scaling.name().into())
+            },
+            ["window", "scaling", id, scaling] => {
+                let surface = self.window_surface(id.parse()?)?;
+                self.window_scaling_set(&surface, scaling.parse()?);
+                Ok("ok".into())
+            },
+            ["window", "layer", id] => {
+                let window = self.desktop.window_by_id(id.parse()?).ok_or("no such window")?;
+                Ok(window.layer.name().into())
+            },
+            ["window", "layer", id, layer] => {
+                let surface = self.window_surface(id.parse()?)?;
+                self.desktop.set_layer(&surface, layer.parse()?);
+                Ok("ok".into())
+            },
+            ["window", "labels", toggle] => {
+                self.hints.labels = parse_toggle(toggle)?;
+                Ok("ok".into())
+            },
+            ["output", "add", name, w, h] => {
+                self.output_add(name, w.parse()?, h.parse()?, 0.0, 0.0)?;
+                Ok("ok".into())
+            },
+            ["output", "transform", screen, transform] => {
+                let transform = match *transform {
+                    "normal"      => Transform::Normal,
+                    "90"          => Transform::_90,
+                    "180"         => Transform::_180,
+                    "270"         => Transform::_270,
+                    "flipped"     => Transform::Flipped,
+                    "flipped-90"  => Transform::Flipped90,
+                    "flipped-180" => Transform::Flipped180,
+                    "flipped-270" => Transform::Flipped270,
+                    _ => return Err(format!("unknown transform: {transform}").into())
+                };
+                self.output_transform(screen.parse()?, transform)?;
+                Ok("ok".into())
+            },
+            ["keyboard", "layout", layout, rest @ ..] => {
+                let mut keymap = Keymap::new(*layout);
+                if let [variant, ..] = rest {
+                    keymap = keymap.variant(*variant);
+                }
+                if let [_, options] = rest {
+                    keymap = keymap.options(*options);
+                }
+                self.keymap_set(keymap)?;
+                Ok("ok".into())
+            },
+            ["output", "place", name, placement @ ..] => {
+                let placement: Placement = placement.join(" ").parse()?;
+                self.layout.placements.insert(name.to_string(), placement);
+                self.layout_arrange();
+                Ok("ok".into())
+            },
+            ["output", "barrier", name, edge, "off"] => {
+                self.layout.barriers.remove(&(name.to_string(), edge.parse()?));
+                Ok("ok".into())
+            },
+            ["output", "barrier", name, edge, barrier] => {
+                let barrier: Barrier = barrier.parse()?;
+                self.layout.barriers.insert((name.to_string(), edge.parse()?), barrier);
+                Ok("ok".into())
+            },
+            ["output", "profile"] => {
+                Ok(self.monitors.current.clone().unwrap_or_else(|| "none".into()))
+            },
+            ["output", "layout"] => {
+                Ok(self.layout.areas.iter().map(|(screen, area)| format!(
+                    "{screen} {},{} {}x{}", area.loc.x, area.loc.y, area.size.w, area.size.h
+                )).collect::<Vec<_>>().join("\n"))
+            },
+            ["output", "mode", name, spec] => {
+                self.output_mode_set(name, spec.parse()?)?;
+                Ok("ok".into())
+            },
+            ["output", "modes", screen] => {
+                Ok(self.output_modes(screen.parse()?).ok_or("no such screen")?.join("\n"))
+            },
+            ["output", "depth", name, depth] => {
+                self.output_depth_set(name, depth.parse()?);
+                Ok("ok".into())
+            },
+            ["output", "format", screen] => {
+                let output = self.engine.output_get(screen.parse()?).ok_or("no such screen")?;
+                let format = output_format(&output);
+                Ok(format!("{format:?} {}", ColorDepth::of(format).bits()))
+            },
+            ["memory"] => {
+                let mut total = MemoryUsage::default();
+                let mut lines = vec![];
+                for (_, pid, usage) in self.memory_usage() {
+                    total += usage;
+                    let pid = pid.map(|pid| pid.to_string()).unwrap_or_else(|| "?".into());
+                    let over = if usage.total() > self.memory.limit.unwrap_or(usize::MAX) { " over" } else { "" };
+                    lines.push(format!("client {pid} shm {} textures {}{over}", usage.shm, usage.textures));
+                }
+                lines.push(format!("total shm {} textures {}", total.shm, total.textures));
+                Ok(lines.join("\n"))
+            },
+            ["memory", "limit", "off"] => {
+                self.memory_limit_set(None, self.memory.policy);
+                Ok("ok".into())
+            },
+            ["memory", "limit", bytes, policy @ ..] => {
+                let policy = match policy {
+                    [] => MemoryPolicy::Evict,
+                    [policy] => policy.parse()?,
+                    _ => return Err("usage: memory limit BYTES [refuse|evict]".into())
+                };
+                self.memory_limit_set(Some(bytes.parse()?), policy);
+                Ok("ok".into())
+            },
+            ["clipboard", "max_size", "off"] => {
+                self.clipboard.max_size = None;
+                Ok("ok".into())
+            },
+            ["clipboard", "max_size", bytes] => {
+                self.clipboard.max_size = Some(bytes.parse()?);
+                Ok("ok".into())
+            },
+            ["clipboard", "allow", pattern] => {
+                self.clipboard.allow.push(pattern.to_string());
+                Ok("ok".into())
+            },
+            ["clipboard", "deny", pattern, domain @ ..] => {
+                let domain = match domain {
+                    [] => None,
+                    [domain] => Some(domain.parse()?),
+                    _ => return Err("usage: clipboard deny PATTERN [host|sandboxed]".into())
+                };
+                self.clipboard.deny.push(MimeRule { domain, pattern: pattern.to_string() });
+                Ok("ok".into())
+            },
+            ["clipboard", "cancel"] => {
+                self.clipboard.cancel(&self.handle);
+                Ok("ok".into())
+            },
+            ["security"] => {
+                Ok(self.security.rules().iter()
+                    .map(|(protocol, rules)| format!("{protocol:?} {rules:?}"))
+                    .collect::<Vec<_>>().join("\n"))
+            },
+            ["security", "allow", protocol, rule] => {
+                self.security.allow(protocol.parse()?, rule.parse()?);
+                Ok("ok".into())
+            },
+            ["security", "deny", protocol] => {
+                self.security.deny_all(protocol.parse()?);
+                Ok("ok".into())
+            },
+            ["security", "inhibitor"] => {
+                Ok(match self.input_inhibitor.owner.as_ref() {
+                    Some(client) => format!("{client:?}"),
+                    None => "none".into()
+                })
+            },
+            ["security", "inhibitor", "release"] => {
+                // In case a locker stops drawing but keeps its connection
+                self.input_release();
+                Ok("ok".into())
+            },
+            ["record", "start", path] => {
+                self.record_start(*path)?;
+                Ok("ok".into())
+            },
+            ["record", "stop"] => {
+                self.record_stop();
+                Ok("ok".into())
+            },
+            ["latency"] => {
+                Ok(self.latency.report())
+            },
+            ["latency", "reset"] => {
+                self.latency.reset();
+                Ok("ok".into())
+            },
+            ["latency", on] => {
+                self.latency.enabled = parse_toggle(on)?;
+                Ok("ok".into())
+            },
+            ["crashes"] => {
+                Ok(self.crashes.reports.iter().cloned().collect::<Vec<_>>().join("\n\n"))
+            },
+            ["protocol"] => {
+                Ok(self.protocols.report(None))
+            },
+            ["protocol", "client", pid] => {
+                Ok(self.protocols.report(Some(pid.parse()?)))
+            },
+            ["protocol", "clear"] => {
+                self.protocols.clear();
+                Ok("ok".into())
+            },
+            ["protocol", "filter"] => {
+                Ok(self.protocols.filter.to_string())
+            },
+            ["protocol", "filter", filter] => {
+                self.protocols.filter = filter.parse()?;
+                Ok("ok".into())
+            },
+            ["protocol", on] => {
+                self.protocols.set_enabled(parse_toggle(on)?);
+                Ok("ok".into())
+            },
+            ["profile", "start", path] => {
+                self.profiler.start(*path);
+                Ok("ok".into())
+            },
+            ["profile", "stop"] => {
+                self.profiler.stop()?;
+                Ok("ok".into())
+            },
+            ["screenshot", path] => {
+                self.screenshot(self.desktop.active_screen, *path)?;
+                Ok("ok".into())
+            },
+            ["screenshot", path, screen] => {
+                self.screenshot(screen.parse()?, *path)?;
+                Ok("ok".into())
+            },
+            ["capture", "output", name] => {
+                Ok(base64::encode(self.capture_output(name)?))
+            },
+            ["capture", "window", id] => {
+                Ok(base64::encode(self.capture_window(id.parse()?)?))
+            },
+            ["input", screen, event @ ..] if !event.is_empty() => {
+                let screen = match *screen {
+                    "active" => self.desktop.active_screen,
+                    screen => screen.parse()?
+                };
+                self.input_inject(screen, event)?;
+                Ok("ok".into())
+            },
+            ["replay", path] => {
+                self.replay_start(path)?;
+                Ok("ok".into())
+            },
+            ["background", r, g, b] => {
+                self.background.color = [r.parse()?, g.parse()?, b.parse()?, 1.0];
+                Ok("ok".into())
+            },
+            ["wallpaper", "off"] => {
+                self.background.clear_wallpaper(None);
+                Ok("ok".into())
+            },
+            ["wallpaper", "off", output] => {
+                self.background.clear_wallpaper(Some(output));
+                Ok("ok".into())
+            },
+            ["wallpaper", path, mode] => {
+                self.background.set_wallpaper(None, *path, mode.parse()?)?;
+                Ok("ok".into())
+            },
+            ["wallpaper", path, mode, output] => {
+                self.background.set_wallpaper(Some(output), *path, mode.parse()?)?;
+                Ok("ok".into())
+            },
+            ["pointer", "confine", screen, x, y, w, h] => {
+                let region = Rectangle::from_loc_and_size(
+                    (x.parse::<f64>()?, y.parse::<f64>()?), (w.parse::<f64>()?, h.parse::<f64>()?)
+                );
+                self.pointer_confine(screen.parse()?, Some(region))?;
+                Ok("ok".into())
+            },
+            ["pointer", "accel", device, profile, speed] => {
+                let config = self.input.pointer_config(device);
+                self.pointer_config_set(device, PointerConfig {
+                    profile: profile.parse()?, ..config
+                }.speed(speed.parse()?));
+                Ok("ok".into())
+            },
+            ["pointer", "sensitivity", device, sensitivity] => {
+                let config = self.input.pointer_config(device);
+                self.pointer_config_set(device, config.sensitivity(sensitivity.parse()?));
+                Ok("ok".into())
+            },
+            ["pointer", "natural-scroll", device, state] => {
+                let config = self.input.pointer_config(device);
+                self.pointer_config_set(device, PointerConfig { natural_scroll: parse_toggle(state)?, ..config });
+                Ok("ok".into())
+            },
+            ["pointer", "left-handed", device, state] => {
+                let config = self.input.pointer_config(device);
+                self.pointer_config_set(device, PointerConfig { left_handed: parse_toggle(state)?, ..config });
+                Ok("ok".into())
+            },
+            ["pointer", "tap", device, state] => {
+                let config = self.input.pointer_config(device);
+                self.pointer_config_set(device, PointerConfig { tap: Some(parse_toggle(state)?), ..config });
+                Ok("ok".into())
+            },
+            ["pointer", "scroll-method", device, method] => {
+                let config = self.input.pointer_config(device);
+                self.pointer_config_set(device, PointerConfig { scroll_method: Some(method.parse()?), ..config });
+                Ok("ok".into())
+            },
+            ["hotcorner", "delay", ms] => {
+                self.hot_corners.delay = Duration::from_millis(ms.parse()?);
+                Ok("ok".into())
+            },
+            ["hotcorner", "resistance", px] => {
+                self.hot_corners.resistance = px.parse()?;
+                Ok("ok".into())
+            },
+            ["hotcorner", "screen", screen, "on"] => {
+                self.hot_corners.disabled.remove(&screen.parse()?);
+                Ok("ok".into())
+            },
+            ["hotcorner", "screen", screen, "off"] => {
+                self.hot_corners.disabled.insert(screen.parse()?);
+                Ok("ok".into())
+            },
+            ["hotcorner", edge, "off"] => {
+                self.hot_corners.actions.remove(&edge.parse::<HotEdge>()?);
+                Ok("ok".into())
+            },
+            ["hotcorner", edge, command @ ..] if !command.is_empty() => {
+                self.hot_corners.actions.insert(edge.parse()?, ipc_join(command));
+                Ok("ok".into())
+            },
+            ["cursor", "size", size, seat @ ..] if seat.len() <= 1 => {
+                self.cursor_set(seat.first().copied(), None, Some(size.parse()?))?;
+                Ok("ok".into())
+            },
+            ["cursor", "theme", theme, seat @ ..] if seat.len() <= 1 => {
+                self.cursor_set(seat.first().copied(), Some(theme), None)?;
+                Ok("ok".into())
+            },
+            ["effect"] | ["effect", "list"] => {
+                Ok(self.effects.describe())
+            },
+            ["effect", "grayscale", on] => {
+                self.effects.grayscale = parse_toggle(on)?;
+                Ok("ok".into())
+            },
+            ["effect", "invert", on] => {
+                self.effects.invert = parse_toggle(on)?;
+                Ok("ok".into())
+            },
+            ["effect", "filter", filter] => {
+                self.effects.filter = filter.parse()?;
+                Ok("ok".into())
+            },
+            ["effect", "vignette", strength] => {
+                self.effects.vignette = if *strength == "off" { 0.0 } else { strength.parse()? };
+                Ok("ok".into())
+            },
+            ["effect", "dim", strength] => {
+                self.effects.dim_unfocused = if *strength == "off" { 0.0 } else { strength.parse()? };
+                Ok("ok".into())
+            },
+            ["decoration", "radius", radius] => {
+                self.desktop.decorations.corner_radius = radius.parse()?;
+                Ok("ok".into())
+            },
+            ["decoration", "shadow", "off"] => {
+                self.desktop.decorations.shadow = None;
+                Ok("ok".into())
+            },
+            ["decoration", "shadow", x, y, blur, strength] => {
+                self.desktop.decorations.shadow = Some(Shadow {
+                    offset:   (x.parse::<i32>()?, y.parse::<i32>()?).into(),
+                    blur:     blur.parse()?,
+                    strength: strength.parse()?,
+                });
+                Ok("ok".into())
+            },
+            ["decoration", "border", "off"] => {
+                self.desktop.decorations.border = None;
+                Ok("ok".into())
+            },
+            ["decoration", "border", "width", width] => {
+                self.desktop.decorations.border.get_or_insert_with(Border::default).width = width.parse()?;
+                Ok("ok".into())
+            },
+            ["decoration", "border", state, color] => {
+                let color = parse_color(color)?;
+                let border = self.desktop.decorations.border.get_or_insert_with(Border::default);
+                match *state {
+                    "focused"   => border.focused   = color,
+                    "unfocused" => border.unfocused = color,
+                    "urgent"    => border.urgent    = color,
+                    _ => return Err(format!("unknown window state: {state}").into())
+                }
+                Ok("ok".into())
+            },
+            ["placement"] => {
+                Ok(self.desktop.placement.name().into())
+            },
+            ["placement", placement] => {
+                self.desktop.placement = placement.parse()?;
+                Ok("ok".into())
+            },
+            ["gaps"] => {
+                let gaps = self.desktop.gaps;
+                Ok(format!("inner {} outer {}", gaps.inner, gaps.outer))
+            },
+            ["gaps", "inner", px] => {
+                self.gaps_set(Gaps { inner: px.parse()?, ..self.desktop.gaps });
+                Ok("ok".into())
+            },
+            ["gaps", "outer", px] => {
+                self.gaps_set(Gaps { outer: px.parse()?, ..self.desktop.gaps });
+                Ok("ok".into())
+            },
+            ["nightlight"] => {
+                Ok(format!("{}K", self.night_light.temperature()))
+            },
+            ["nightlight", "on"] => {
+                self.night_light.enabled = true;
+                Ok("ok".into())
+            },
+            ["nightlight", "off"] => {
+                self.night_light.enabled = false;
+                Ok("ok".into())
+            },
+            ["nightlight", "temperature", day, night] => {
+                self.night_light.day   = day.parse()?;
+                self.night_light.night = night.parse()?;
+                Ok("ok".into())
+            },
+            ["nightlight", "schedule", sunset, sunrise] => {
+                self.night_light.sunset  = parse_time(sunset)?;
+                self.night_light.sunrise = parse_time(sunrise)?;
+                Ok("ok".into())
+            },
+            ["nightlight", "transition", minutes] => {
+                self.night_light.transition = minutes.parse()?;
+                Ok("ok".into())
+            },
+            ["redraw"] => {
+                Ok(self.redraw.stats())
+            },
+            ["redraw", "reset"] => {
+                self.redraw.reset();
+                Ok("ok".into())
+            },
+            ["game"] => {
+                Ok(match self.game.window.as_ref().and_then(|surface| self.desktop.window_state(surface)) {
+                    Some(window) => format!("on {}", window.id),
+                    None => "off".into()
+                })
+            },
+            ["game", toggle] => {
+                if parse_toggle(toggle)? {
+                    self.game_mode_enter()?;
+                } else {
+                    self.game_mode_exit();
+                }
+                Ok("ok".into())
+            },
+            ["budget", "off"] => {
+                self.stalls.budget = None;
+                Ok("ok".into())
+            },
+            ["budget", ms] => {
+                self.stalls.budget = Some(Duration::from_millis(ms.parse()?));
+                Ok("ok".into())
+            },
+            ["stall", "off"] => {
+                self.stalls.set_timeout(None, false)?;
+                Ok("ok".into())
+            },
+            ["stall", ms] => {
+                self.stalls.set_timeout(Some(Duration::from_millis(ms.parse()?)), false)?;
+                Ok("ok".into())
+            },
+            ["stall", ms, "abort"] => {
+                self.stalls.set_timeout(Some(Duration::from_millis(ms.parse()?)), true)?;
+                Ok("ok".into())
+            },
+            ["uploads", "off"] => {
+                self.desktop.textures.upload_budget = None;
+                Ok("ok".into())
+            },
+            ["uploads", kib] => {
+                self.desktop.textures.upload_budget = Some(kib.parse::<usize>()? * 1024);
+                Ok("ok".into())
+            },
+            ["game", "deadline", ms] => {
+                self.game.deadline = Duration::from_millis(ms.parse()?);
+                Ok("ok".into())
+            },
+            ["frames", "occluded", fps] => {
+                self.desktop.occluded_fps = if *fps == "off" { None } else { Some(fps.parse()?) };
+                Ok("ok".into())
+            },
+            ["rule", "fps", app_id, fps] => {
+                let fps = if *fps == "off" { 0 } else { fps.parse()? };
+                self.desktop.rule_add(WindowRule::app_id(*app_id).max_fps(fps));
+                Ok("ok".into())
+            },
+            ["rule", "fps", "content", content_type, fps] => {
+                let fps = if *fps == "off" { 0 } else { fps.parse()? };
+                self.desktop.rule_add(WindowRule::content_type(content_type.parse()?).max_fps(fps));
+                Ok("ok".into())
+            },
+            ["rule", "scaling", app_id, scaling] => {
+                self.desktop.rule_add(WindowRule::app_id(*app_id).scaling(scaling.parse()?));
+                Ok("ok".into())
+            },
+            ["rule", "remember", app_id, remember] => {
+                self.desktop.rule_add(WindowRule::app_id(*app_id).remember_geometry(parse_toggle(remember)?));
+                Ok("ok".into())
+            },
+            ["rule", "modal", app_id, modal] => {
+                self.desktop.rule_add(WindowRule::app_id(*app_id).modal(parse_toggle(modal)?));
+                Ok("ok".into())
+            },
+            ["rule", "placement", app_id, placement] => {
+                self.desktop.rule_add(WindowRule::app_id(*app_id).placement(placement.parse()?));
+                Ok("ok".into())
+            },
+            ["rule", "swallow", app_id, swallow] => {
+                self.desktop.rule_add(WindowRule::app_id(*app_id).swallow(parse_toggle(swallow)?));
+                Ok("ok".into())
+            },
+            ["rule", "swallowable", app_id, swallowable] => {
+                self.desktop.rule_add(WindowRule::app_id(*app_id).swallowable(parse_toggle(swallowable)?));
+                Ok("ok".into())
+            },
+            ["media", "command", key, "off"] => {
+                self.osd.commands.insert(key.parse()?, None);
+                Ok("ok".into())
+            },
+            ["media", "command", key, "default"] => {
+                self.osd.commands.remove(&key.parse()?);
+                Ok("ok".into())
+            },
+            ["media", "command", key, command @ ..] if !command.is_empty() => {
+                self.osd.commands.insert(key.parse()?, Some(command.join(" ")));
+                Ok("ok".into())
+            },
+            ["osd", "show", label, level] => {
+                let level: f64 = level.parse()?;
+                self.osd.show(self.desktop.active_screen, *label, Some(level / 100.0));
+                self.redraw.request();
+                Ok("ok".into())
+            },
+            ["osd", "position", output, edge] => {
+                self.osd.positions.insert(output.to_string(), edge.parse()?);
+                Ok("ok".into())
+            },
+            ["session", "save"] => {
+                self.session_save()?;
+                Ok("ok".into())
+            },
+            ["session", "restore"] => {
+                self.session_restore()?;
+                Ok("ok".into())
+            },
+            ["geometry", "save"] => {
+                self.geometry_save();
+                Ok("ok".into())
+            },
+            ["geometry", "forget"] => {
+                self.geometry.clear();
+                self.geometry.save()?;
+                Ok("ok".into())
+            },
+            _ => Err(format!("unknown command: {}", args.join(" ")).into())
+        }
+    }
+
+    /// The top level surface of the window with this id.
+    fn window_surface (&mut self, id: u64) -> StdResult<WlSurface> {
+        Ok(self.desktop.window_by_id(id).ok_or("no such window")?
+            .window.toplevel().wl_surface().clone())
+    }
+
+}
+
+/// Parse `on` or `off`.
+fn parse_toggle (value: &str) -> StdResult<bool> {
+    match value {
+        "on"  => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, got {value}").into())
+    }
+}
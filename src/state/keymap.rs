@@ -0,0 +1,127 @@
+use super::prelude::*;
+
+use super::input::{REPEAT_DELAY, REPEAT_RATE};
+
+use smithay::{
+    backend::input::KeyState,
+    input::keyboard::FilterResult,
+};
+
+/// Evdev code of the Num Lock key
+const KEY_NUMLOCK: u32 = 69;
+
+/// Evdev code of the Caps Lock key
+const KEY_CAPSLOCK: u32 = 58;
+
+/// Which keymap the keyboards of all seats use, as understood by xkbcommon.
+/// Empty fields pick the defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keymap {
+    pub layout:  String,
+    pub variant: String,
+    pub options: Option<String>,
+}
+
+impl Keymap {
+
+    pub fn new (layout: impl Into<String>) -> Self {
+        Self { layout: layout.into(), ..Default::default() }
+    }
+
+    pub fn variant (mut self, variant: impl Into<String>) -> Self {
+        self.variant = variant.into();
+        self
+    }
+
+    pub fn options (mut self, options: impl Into<String>) -> Self {
+        self.options = Some(options.into());
+        self
+    }
+
+    pub fn xkb (&self) -> XkbConfig<'_> {
+        XkbConfig {
+            layout:  &self.layout,
+            variant: &self.variant,
+            options: self.options.clone(),
+            ..Default::default()
+        }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Turn Num Lock on when keyboards are added, or leave it off.
+    pub fn numlock (mut self, enabled: bool) -> CharlieResult<Self> {
+        self.input.numlock = enabled;
+        for index in 0..self.input.keyboards.len() {
+            self.keyboard_locks_set(index, KeyboardLeds { num_lock: enabled, ..self.keyboard_locks(index) });
+        }
+        Ok(self)
+    }
+
+    /// Use another keymap, keeping Num Lock and Caps Lock as they are.
+    pub fn keymap (mut self, keymap: Keymap) -> CharlieResult<Self> {
+        self.keymap_set(keymap)?;
+        Ok(self)
+    }
+
+    /// Switch all keyboards to another keymap. Replacing the keymap resets the xkb state,
+    /// so the locks are restored afterwards.
+    pub fn keymap_set (&mut self, keymap: Keymap) -> CharlieResult<()> {
+        debug!(self.logger, "Switching to keymap {keymap:?}");
+        let locks: Vec<KeyboardLeds> = (0..self.input.keyboards.len())
+            .map(|index| self.keyboard_locks(index))
+            .collect();
+        self.input.keymap = keymap;
+        if self.input.capabilities.keyboard {
+            for (index, seat) in self.input.seats.iter_mut().enumerate() {
+                let handle = seat.add_keyboard(self.input.keymap.xkb(), REPEAT_DELAY, REPEAT_RATE)
+                    .map_err(CharlieError::other)?;
+                self.input.keyboards[index].handle = handle;
+            }
+        }
+        for (index, locks) in locks.into_iter().enumerate() {
+            self.keyboard_locks_set(index, locks);
+        }
+        self.keyboard_leds_update(true);
+        Ok(())
+    }
+
+    /// Which locks are on for the keyboard of a seat.
+    pub fn keyboard_locks (&self, index: usize) -> KeyboardLeds {
+        let modifiers = self.input.keyboards[index].handle.modifier_state();
+        KeyboardLeds { num_lock: modifiers.num_lock, caps_lock: modifiers.caps_lock }
+    }
+
+    /// Turn locks on or off by pressing their keys, without clients seeing the presses.
+    pub fn keyboard_locks_set (&mut self, index: usize, locks: KeyboardLeds) {
+        let current = self.keyboard_locks(index);
+        let toggles = [
+            (current.num_lock  != locks.num_lock,  KEY_NUMLOCK),
+            (current.caps_lock != locks.caps_lock, KEY_CAPSLOCK),
+        ];
+        let handle = self.input.keyboards[index].handle.clone();
+        let time = self.desktop.now_ms();
+        for (_, key) in toggles.into_iter().filter(|(toggle, _)| *toggle) {
+            for state in [KeyState::Pressed, KeyState::Released] {
+                handle.input::<(), _>(self, key, state, SERIAL_COUNTER.next_serial(), time, |_, _, _| {
+                    FilterResult::Intercept(())
+                });
+            }
+        }
+    }
+
+    /// Light up the LEDs of the keyboards of each seat to match its locks.
+    /// Unless forced, e.g. because a keyboard was plugged in, only changes are sent.
+    pub fn keyboard_leds_update (&mut self, force: bool) {
+        for index in 0..self.input.keyboards.len() {
+            let leds = self.keyboard_locks(index);
+            if force || self.input.keyboards[index].leds != leds {
+                self.input.keyboards[index].leds = leds;
+                self.engine.input_leds(index, leds);
+            }
+        }
+    }
+
+}
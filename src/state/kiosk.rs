@@ -0,0 +1,178 @@
+use super::prelude::*;
+use super::action::Action;
+use super::process::{Startup, Restart};
+use super::swallow::{parent_pid, MAX_ANCESTORS};
+
+use smithay::input::keyboard::{keysyms, ModifiersState};
+
+/// A key and the modifiers held with it, written like `ctrl+alt+BackSpace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub ctrl:   bool,
+    pub alt:    bool,
+    pub shift:  bool,
+    pub logo:   bool,
+    pub keysym: u32,
+}
+
+impl Chord {
+
+    /// Whether this chord was pressed, with exactly these modifiers held.
+    pub fn matches (&self, modifiers: &ModifiersState, keysym: u32) -> bool {
+        self.keysym == keysym
+            && self.ctrl  == modifiers.ctrl
+            && self.alt   == modifiers.alt
+            && self.shift == modifiers.shift
+            && self.logo  == modifiers.logo
+    }
+
+}
+
+impl std::str::FromStr for Chord {
+    type Err = Box<dyn Error>;
+    fn from_str (chord: &str) -> StdResult<Self> {
+        let mut parts: Vec<&str> = chord.split('+').collect();
+        let key = parts.pop().filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("no key in chord: {chord}"))?;
+        let keysym = xkbcommon::xkb::keysym_from_name(key, xkbcommon::xkb::KEYSYM_NO_FLAGS);
+        if keysym == keysyms::KEY_NoSymbol {
+            return Err(format!("unknown key: {key}").into())
+        }
+        let mut result = Self { ctrl: false, alt: false, shift: false, logo: false, keysym };
+        for modifier in parts {
+            match modifier {
+                "ctrl"  => result.ctrl  = true,
+                "alt"   => result.alt   = true,
+                "shift" => result.shift = true,
+                "logo"  => result.logo  = true,
+                _ => return Err(format!("unknown modifier: {modifier}").into())
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// A session that runs one application fullscreen, for signage and embedded use.
+/// Its windows are made fullscreen on the active screen and can't leave it,
+/// and the only key binding is the escape chord. Nothing else is started, and the windows
+/// of other clients are closed: only the clients that the application's process started,
+/// or that it is itself, are let show windows. X11 windows can't be told apart, so they aren't.
+pub struct Kiosk {
+    /// Whether the session is a kiosk
+    pub enabled: bool,
+    /// The application that the kiosk runs
+    pub app:     Option<Startup>,
+    /// The key combination that still does something, ctrl+alt+BackSpace by default
+    pub escape:  Chord,
+    /// What the escape chord does, quitting by default
+    pub action:  Action,
+}
+
+impl Kiosk {
+
+    pub fn new () -> Self {
+        Self {
+            enabled: false,
+            app:     None,
+            escape:  Chord { ctrl: true, alt: true, shift: false, logo: false, keysym: keysyms::KEY_BackSpace },
+            action:  Action::Quit,
+        }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Run only this application, fullscreen, starting it again whenever it exits.
+    pub fn kiosk (mut self, cmd: impl AsRef<str>, args: &[&str]) -> CharlieResult<Self> {
+        let app = Startup::new(cmd, args, Restart::Always);
+        self.kiosk.enabled = true;
+        self.kiosk.app = Some(app.clone());
+        self.processes.startup.push(app);
+        Ok(self)
+    }
+
+    /// Set the key combination that still works in a kiosk, and what it does.
+    pub fn kiosk_escape (mut self, chord: &str, action: Action) -> CharlieResult<Self> {
+        self.kiosk.escape = chord.parse().map_err(|e| CharlieError::Config(format!("{e}")))?;
+        self.kiosk.action = action;
+        Ok(self)
+    }
+
+    /// Whether a process was started from the entry of the kiosk application.
+    fn kiosk_app (&self, entry: &Startup) -> bool {
+        self.kiosk.app.as_ref().map(|app| app.cmd == entry.cmd && app.args == entry.args).unwrap_or(false)
+    }
+
+    /// Keep a kiosk from starting anything but its application.
+    pub fn kiosk_startup (&mut self) {
+        if !self.kiosk.enabled {
+            return
+        }
+        let others: Vec<String> = self.processes.startup.iter().chain(self.processes.companions.iter())
+            .filter(|entry| !self.kiosk_app(entry))
+            .map(|entry| entry.cmd.clone())
+            .collect();
+        if !others.is_empty() {
+            warn!(self.logger, "Not starting {others:?} in a kiosk");
+        }
+        let startup = std::mem::take(&mut self.processes.startup);
+        self.processes.startup = startup.into_iter().filter(|entry| self.kiosk_app(entry)).collect();
+        self.processes.companions.clear();
+    }
+
+    /// Whether a surface may be shown and focused. In a kiosk, windows may only be
+    /// if their client is the application or one of the processes it started.
+    /// Surfaces that aren't windows are left to their own rules.
+    pub fn kiosk_admits (&self, surface: &WlSurface) -> bool {
+        if !self.kiosk.enabled {
+            return true
+        }
+        let window = match self.desktop.window_state(surface) {
+            Some(window) => window,
+            None => return true
+        };
+        let mut next = window.identity().and_then(|identity| identity.pid);
+        for _ in 0..=MAX_ANCESTORS {
+            let pid = match next {
+                Some(pid) => pid,
+                None => break
+            };
+            if self.processes.running.get(&(pid as u32)).map(|process| self.kiosk_app(&process.entry)).unwrap_or(false) {
+                return true
+            }
+            next = parent_pid(pid);
+        }
+        false
+    }
+
+    /// Hide and close a newly mapped window that a kiosk doesn't show.
+    /// Returns whether the window may stay.
+    pub fn kiosk_filter (&mut self, surface: &WlSurface) -> bool {
+        if self.kiosk_admits(surface) {
+            return true
+        }
+        info!(self.logger, "Closing {surface:?}, which isn't the kiosk application's");
+        self.desktop.minimize(surface);
+        self.window_close(surface);
+        false
+    }
+
+    /// Make a newly mapped window of the kiosk application cover the active screen.
+    /// Returns whether the session is a kiosk, and so where windows go is settled.
+    pub fn kiosk_place (&mut self, surface: &WlSurface) -> bool {
+        if !self.kiosk.enabled {
+            return false
+        }
+        let toplevel = match self.desktop.window_state(surface).map(|w| w.window.toplevel()) {
+            Some(Kind::Xdg(toplevel)) => toplevel.clone(),
+            _ => return true
+        };
+        if let Err(e) = self.fullscreen(&toplevel, self.desktop.active_screen) {
+            warn!(self.logger, "Could not make kiosk window fullscreen: {e}");
+        }
+        self.focus(Some(surface.clone()));
+        true
+    }
+
+}
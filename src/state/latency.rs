@@ -0,0 +1,126 @@
+use super::prelude::*;
+
+use std::collections::VecDeque;
+
+/// Samples kept for the percentiles, newest replacing oldest
+const MAX_SAMPLES: usize = 1000;
+
+/// How long an input may go without a visible effect before it's no longer waited for
+const MAX_WAIT: Duration = Duration::from_secs(1);
+
+/// An input whose effect hasn't been seen on screen yet.
+struct InFlight {
+    /// When the input event arrived from the backend
+    input:     Instant,
+    /// When it was written out to clients
+    delivered: Option<Instant>,
+    /// Whether the focused window committed since it was delivered
+    committed: bool,
+}
+
+/// How long it took for one input to reach clients, and to show up on screen.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    delivery:     Duration,
+    presentation: Duration,
+}
+
+/// Measures input latency: from when an input event arrives, to when it's sent to clients,
+/// to when the first frame after the focused window's next commit is rendered.
+///
+/// One input is followed at a time; inputs that arrive while one is followed aren't,
+/// so the cost stays the same however fast events come in. Frames count as shown once
+/// they're submitted to the engine, since there's no presentation feedback to wait for;
+/// the time until the next vblank comes on top of what's reported.
+pub struct Latency {
+    logger:      Logger,
+    /// Whether inputs are being followed
+    pub enabled: bool,
+    in_flight:   Option<InFlight>,
+    samples:     VecDeque<Sample>,
+}
+
+impl Latency {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), enabled: false, in_flight: None, samples: VecDeque::new() }
+    }
+
+    /// An input event arrived from the backend.
+    pub fn input (&mut self) {
+        if !self.enabled {
+            return
+        }
+        let now = Instant::now();
+        if let Some(in_flight) = &self.in_flight {
+            if now - in_flight.input < MAX_WAIT {
+                return
+            }
+            debug!(self.logger, "Input had no visible effect within {MAX_WAIT:?}");
+        }
+        self.in_flight = Some(InFlight { input: now, delivered: None, committed: false });
+    }
+
+    /// Events were written out to clients.
+    pub fn delivered (&mut self) {
+        if let Some(in_flight) = self.in_flight.as_mut() {
+            in_flight.delivered.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// The focused window committed a new state.
+    pub fn committed (&mut self) {
+        if let Some(in_flight) = self.in_flight.as_mut() {
+            if in_flight.delivered.is_some() {
+                in_flight.committed = true;
+            }
+        }
+    }
+
+    /// A frame was rendered and handed to the engine.
+    pub fn presented (&mut self) {
+        let in_flight = match self.in_flight.take() {
+            Some(in_flight) if in_flight.committed => in_flight,
+            in_flight => {
+                self.in_flight = in_flight;
+                return
+            }
+        };
+        let delivered = in_flight.delivered.unwrap_or(in_flight.input);
+        if self.samples.len() >= MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            delivery:     delivered - in_flight.input,
+            presentation: in_flight.input.elapsed(),
+        });
+    }
+
+    /// Forget the samples taken so far.
+    pub fn reset (&mut self) {
+        self.in_flight = None;
+        self.samples.clear();
+    }
+
+    /// The median, 90th and 99th percentile of the latencies, in milliseconds.
+    pub fn report (&self) -> String {
+        let percentiles = |mut values: Vec<Duration>| -> String {
+            if values.is_empty() {
+                return "-".into()
+            }
+            values.sort();
+            [50, 90, 99].iter()
+                .map(|p| {
+                    let index = ((values.len() - 1) * p + 50) / 100;
+                    format!("p{p} {:.1}ms", values[index].as_secs_f64() * 1000.0)
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        format!("samples {}\ndelivery {}\npresentation {}",
+            self.samples.len(),
+            percentiles(self.samples.iter().map(|sample| sample.delivery).collect()),
+            percentiles(self.samples.iter().map(|sample| sample.presentation).collect()))
+    }
+
+}
@@ -0,0 +1,366 @@
+use super::prelude::*;
+
+/// Where an output is placed in the layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    /// At these coordinates, in logical pixels
+    At(i32, i32),
+    /// To the left of the named output, with top edges aligned
+    LeftOf(String),
+    /// To the right of the named output, with top edges aligned
+    RightOf(String),
+    /// Above the named output, with left edges aligned
+    Above(String),
+    /// Below the named output, with left edges aligned
+    Below(String),
+}
+
+impl Placement {
+
+    /// Where an output of this size goes, given the area of the output it's placed next to.
+    fn resolve (&self, size: Size<i32, Logical>, next_to: Option<Rectangle<i32, Logical>>)
+        -> Option<Point<i32, Logical>>
+    {
+        Some(match (self, next_to) {
+            (Self::At(x, y), _)             => (*x, *y),
+            (Self::LeftOf(_),  Some(other)) => (other.loc.x - size.w, other.loc.y),
+            (Self::RightOf(_), Some(other)) => (other.loc.x + other.size.w, other.loc.y),
+            (Self::Above(_),   Some(other)) => (other.loc.x, other.loc.y - size.h),
+            (Self::Below(_),   Some(other)) => (other.loc.x, other.loc.y + other.size.h),
+            _ => return None
+        }.into())
+    }
+
+    /// The output this placement is relative to, if any.
+    fn relative_to (&self) -> Option<&str> {
+        match self {
+            Self::At(_, _) => None,
+            Self::LeftOf(name) | Self::RightOf(name) | Self::Above(name) | Self::Below(name) => Some(name)
+        }
+    }
+
+}
+
+impl std::str::FromStr for Placement {
+    type Err = Box<dyn Error>;
+    /// Parses `left-of NAME`, `right-of NAME`, `above NAME`, `below NAME` or `X,Y`.
+    fn from_str (placement: &str) -> StdResult<Self> {
+        let words: Vec<&str> = placement.split_whitespace().collect();
+        Ok(match words.as_slice() {
+            ["left-of",  name] => Self::LeftOf(name.to_string()),
+            ["right-of", name] => Self::RightOf(name.to_string()),
+            ["above",    name] => Self::Above(name.to_string()),
+            ["below",    name] => Self::Below(name.to_string()),
+            [position] => match position.split_once(',') {
+                Some((x, y)) => Self::At(x.trim().parse()?, y.trim().parse()?),
+                None => return Err(format!("unknown placement: {placement}").into())
+            },
+            _ => return Err(format!("unknown placement: {placement}").into())
+        })
+    }
+}
+
+/// Which edge of an output the pointer went past.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+
+    /// The edge of an area of this size that a point is past, if any.
+    fn past (size: Size<f64, Logical>, location: Point<f64, Logical>) -> Option<Self> {
+        if location.x < 0.0 {
+            Some(Self::Left)
+        } else if location.x >= size.w {
+            Some(Self::Right)
+        } else if location.y < 0.0 {
+            Some(Self::Top)
+        } else if location.y >= size.h {
+            Some(Self::Bottom)
+        } else {
+            None
+        }
+    }
+
+    /// How far a point is past this edge of an area of this size.
+    fn overshoot (&self, size: Size<f64, Logical>, location: Point<f64, Logical>) -> f64 {
+        match self {
+            Self::Left   => -location.x,
+            Self::Right  => location.x - (size.w - 1.0),
+            Self::Top    => -location.y,
+            Self::Bottom => location.y - (size.h - 1.0),
+        }.max(0.0)
+    }
+
+}
+
+impl std::str::FromStr for Edge {
+    type Err = Box<dyn Error>;
+    /// Parses `left`, `right`, `top` or `bottom`.
+    fn from_str (edge: &str) -> StdResult<Self> {
+        Ok(match edge {
+            "left"   => Self::Left,
+            "right"  => Self::Right,
+            "top"    => Self::Top,
+            "bottom" => Self::Bottom,
+            _ => return Err(format!("unknown edge: {edge}").into())
+        })
+    }
+}
+
+/// What keeps the pointer from crossing an edge of an output onto the next one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Barrier {
+    /// The pointer has to be pushed this many more logical pixels against the edge to cross it
+    Sticky(f64),
+    /// The pointer can't cross the edge at all, e.g. onto an output that shows a dashboard
+    Hard,
+}
+
+impl std::str::FromStr for Barrier {
+    type Err = Box<dyn Error>;
+    /// Parses `hard`, or the distance of a sticky edge in logical pixels.
+    fn from_str (barrier: &str) -> StdResult<Self> {
+        Ok(match barrier {
+            "hard" => Self::Hard,
+            distance => Self::Sticky(distance.parse()?)
+        })
+    }
+}
+
+/// How the outputs are arranged relative to each other, in a shared logical space.
+///
+/// This decides where the pointer goes when it's pushed past the edge of a screen,
+/// and where clients are told that outputs are. Each screen still shows its own view
+/// of the workspace. Outputs without a placement are put to the right of the others.
+///
+/// Edges of outputs can have barriers, which the pointer has to be pushed against
+/// for a while before it crosses them, or which it can't cross at all.
+pub struct OutputLayout {
+    logger:         Logger,
+    /// How outputs are placed, by output name
+    pub placements: HashMap<String, Placement>,
+    /// The area of each screen in the layout, as of the latest arrangement
+    pub areas:      Vec<(ScreenId, Rectangle<i32, Logical>)>,
+    /// Barriers on the edges of outputs, by output name and edge
+    pub barriers:   HashMap<(String, Edge), Barrier>,
+    /// The name of the output that shows each screen, as of the latest arrangement
+    names:          Vec<(ScreenId, String)>,
+    /// The edge the pointer is being pushed against, and how far it's been pushed so far
+    pressure:       Option<(ScreenId, Edge, f64)>,
+}
+
+impl OutputLayout {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:     logger.clone(),
+            placements: HashMap::new(),
+            areas:      vec![],
+            barriers:   HashMap::new(),
+            names:      vec![],
+            pressure:   None,
+        }
+    }
+
+    /// Place screens of these sizes, shown on the named outputs, and move the layout
+    /// so that it starts at the origin. Placements relative to outputs that don't exist,
+    /// or to each other in a loop, fall back to the default.
+    pub fn arrange (&mut self, screens: &[(ScreenId, String, Size<i32, Logical>)]) {
+        let mut placed: Vec<(ScreenId, &str, Rectangle<i32, Logical>)> = vec![];
+        let mut remaining: Vec<&(ScreenId, String, Size<i32, Logical>)> = screens.iter().collect();
+        // Resolve placements until none of the remaining ones can be
+        loop {
+            let before = remaining.len();
+            remaining.retain(|screen| {
+                let (screen, name, size) = *screen;
+                let placement = match self.placements.get(name) {
+                    Some(placement) => placement,
+                    None => return true
+                };
+                let next_to = placement.relative_to()
+                    .and_then(|other| placed.iter().find(|(_, name, _)| *name == other))
+                    .map(|(_, _, area)| *area);
+                match placement.resolve(*size, next_to) {
+                    Some(loc) => {
+                        placed.push((*screen, name.as_str(), Rectangle::from_loc_and_size(loc, *size)));
+                        false
+                    },
+                    None => true
+                }
+            });
+            if remaining.len() == before {
+                break
+            }
+        }
+        // Put the rest in a row to the right of everything else
+        for (screen, name, size) in remaining {
+            if self.placements.contains_key(name) {
+                warn!(self.logger, "Could not place output {name}, putting it on the right");
+            }
+            let x = placed.iter().map(|(_, _, area)| area.loc.x + area.size.w).max().unwrap_or(0);
+            let y = placed.iter().map(|(_, _, area)| area.loc.y).min().unwrap_or(0);
+            placed.push((*screen, name.as_str(), Rectangle::from_loc_and_size((x, y), *size)));
+        }
+        self.areas = normalize(placed.iter().map(|(screen, _, area)| (*screen, *area)).collect());
+        self.names = placed.iter().map(|(screen, name, _)| (*screen, name.to_string())).collect();
+        for (a, b) in overlaps(&self.areas) {
+            warn!(self.logger, "Screens {a} and {b} overlap in the output layout");
+        }
+    }
+
+    /// The area of a screen in the layout.
+    pub fn area (&self, screen: ScreenId) -> Option<Rectangle<i32, Logical>> {
+        self.areas.iter().find(|(s, _)| *s == screen).map(|(_, area)| *area)
+    }
+
+    /// Whether the pointer may cross the edge of a screen that it's been moved past,
+    /// going by the barrier on that edge. Pushes against a sticky edge add up until
+    /// they reach its distance, or until the pointer moves away from the edge.
+    pub fn pass (&mut self, from: ScreenId, location: Point<f64, Logical>) -> bool {
+        let size = match self.area(from) {
+            Some(area) => area.size.to_f64(),
+            None => return true
+        };
+        let edge = match Edge::past(size, location) {
+            Some(edge) => edge,
+            None => {
+                self.pressure = None;
+                return true
+            }
+        };
+        let barrier = self.names.iter()
+            .find(|(screen, _)| *screen == from)
+            .and_then(|(_, name)| self.barriers.get(&(name.clone(), edge)));
+        let distance = match barrier {
+            None => return true,
+            Some(Barrier::Hard) => return false,
+            Some(Barrier::Sticky(distance)) => *distance,
+        };
+        let pushed = match self.pressure {
+            Some((screen, pressed, pushed)) if screen == from && pressed == edge => pushed,
+            _ => 0.0
+        } + edge.overshoot(size, location);
+        if pushed >= distance {
+            self.pressure = None;
+            true
+        } else {
+            self.pressure = Some((from, edge, pushed));
+            false
+        }
+    }
+
+    /// Where the pointer ends up when moved to a point past the edge of a screen,
+    /// in the coordinates of the screen it ends up on. Returns None if there's
+    /// no screen in that direction.
+    ///
+    /// If another screen is at that point, the pointer goes there. Otherwise, it goes
+    /// to the nearest screen across the edge it went past, onto the nearest point
+    /// of the facing edge, so that screens that don't touch can still be reached.
+    pub fn cross (&self, from: ScreenId, location: Point<f64, Logical>)
+        -> Option<(ScreenId, Point<f64, Logical>)>
+    {
+        let origin = self.area(from)?.to_f64();
+        let global = origin.loc + location;
+        let edge = Edge::past(origin.size, location)?;
+        let others = || self.areas.iter().filter(|(screen, _)| *screen != from);
+        // Straight onto a screen that's there
+        if let Some((screen, area)) = others().find(|(_, area)| area.to_f64().contains(global)) {
+            return Some((*screen, global - area.to_f64().loc))
+        }
+        // Onto the facing edge of the nearest screen in that direction
+        let (screen, area) = others()
+            .map(|(screen, area)| (*screen, area.to_f64()))
+            .filter(|(_, area)| match edge {
+                Edge::Left   => area.loc.x + area.size.w <= origin.loc.x,
+                Edge::Right  => area.loc.x >= origin.loc.x + origin.size.w,
+                Edge::Top    => area.loc.y + area.size.h <= origin.loc.y,
+                Edge::Bottom => area.loc.y >= origin.loc.y + origin.size.h,
+            })
+            .min_by(|(_, a), (_, b)| distance(*a, global).total_cmp(&distance(*b, global)))?;
+        let (right, bottom) = (area.size.w - 1.0, area.size.h - 1.0);
+        let local = global - area.loc;
+        let local = match edge {
+            Edge::Left   => (right, local.y.clamp(0.0, bottom)),
+            Edge::Right  => (0.0, local.y.clamp(0.0, bottom)),
+            Edge::Top    => (local.x.clamp(0.0, right), bottom),
+            Edge::Bottom => (local.x.clamp(0.0, right), 0.0),
+        };
+        Some((screen, local.into()))
+    }
+
+}
+
+/// Move areas so that the top left corner of their bounding box is at the origin.
+pub fn normalize (areas: Vec<(ScreenId, Rectangle<i32, Logical>)>) -> Vec<(ScreenId, Rectangle<i32, Logical>)> {
+    let x = areas.iter().map(|(_, area)| area.loc.x).min().unwrap_or(0);
+    let y = areas.iter().map(|(_, area)| area.loc.y).min().unwrap_or(0);
+    areas.into_iter()
+        .map(|(screen, area)| (screen, Rectangle::from_loc_and_size((area.loc.x - x, area.loc.y - y), area.size)))
+        .collect()
+}
+
+/// The pairs of screens whose areas overlap.
+pub fn overlaps (areas: &[(ScreenId, Rectangle<i32, Logical>)]) -> Vec<(ScreenId, ScreenId)> {
+    let mut pairs = vec![];
+    for (i, (a, area_a)) in areas.iter().enumerate() {
+        for (b, area_b) in areas[i + 1..].iter() {
+            if area_a.overlaps(*area_b) {
+                pairs.push((*a, *b));
+            }
+        }
+    }
+    pairs
+}
+
+/// How far a point is from the nearest point of an area.
+fn distance (area: Rectangle<f64, Logical>, point: Point<f64, Logical>) -> f64 {
+    let dx = (area.loc.x - point.x).max(point.x - (area.loc.x + area.size.w)).max(0.0);
+    let dy = (area.loc.y - point.y).max(point.y - (area.loc.y + area.size.h)).max(0.0);
+    (dx * dx + dy * dy).sqrt()
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Place an output in the layout, by the name it was added with.
+    pub fn output_place (mut self, name: &str, placement: Placement) -> CharlieResult<Self> {
+        self.layout.placements.insert(name.into(), placement);
+        self.layout_arrange();
+        Ok(self)
+    }
+
+    /// Put a barrier on an edge of an output, by the name it was added with.
+    pub fn output_barrier (mut self, name: &str, edge: Edge, barrier: Barrier) -> CharlieResult<Self> {
+        self.layout.barriers.insert((name.into(), edge), barrier);
+        Ok(self)
+    }
+
+    /// Arrange the outputs again, e.g. after one of them was added or changed size,
+    /// and tell clients where they are.
+    pub fn layout_arrange (&mut self) {
+        let span = self.profiler.begin();
+        let screens: Vec<(ScreenId, String, Size<i32, Logical>)> = self.desktop.screens.iter()
+            .enumerate()
+            // Screens turned off with the lid closed or by a profile aren't part of the layout
+            .filter(|(screen, _)| !self.switches.lid_disabled.contains(screen))
+            .filter(|(screen, _)| !self.monitors.disabled.contains(screen))
+            .filter_map(|(screen, state)| Some((
+                screen,
+                state.output.clone()?,
+                self.screen_area(screen)?.to_i32_round()
+            )))
+            .collect();
+        self.layout.arrange(&screens);
+        for (screen, area) in self.layout.areas.iter() {
+            if let Some(output) = self.engine.output_get(*screen) {
+                output.change_current_state(None, None, None, Some(area.loc));
+            }
+        }
+        self.profiler.end(span, "layout", None);
+    }
+
+}
@@ -0,0 +1,206 @@
+use super::prelude::*;
+
+use super::transaction::Change;
+
+/// Smallest size that windows are resized down to from the keyboard
+const MIN_SIZE: i32 = 64;
+
+/// An edge of a screen that a window can be moved against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+    pub fn name (&self) -> &'static str {
+        match self {
+            Self::Left   => "left",
+            Self::Right  => "right",
+            Self::Top    => "top",
+            Self::Bottom => "bottom",
+        }
+    }
+}
+
+impl std::str::FromStr for Edge {
+    type Err = Box<dyn Error>;
+    fn from_str (edge: &str) -> StdResult<Self> {
+        Ok(match edge {
+            "left"   => Self::Left,
+            "right"  => Self::Right,
+            "top"    => Self::Top,
+            "bottom" => Self::Bottom,
+            _ => return Err(format!("unknown edge: {edge}").into())
+        })
+    }
+}
+
+/// Space kept clear around windows that are arranged on a screen, in logical pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Gaps {
+    /// Between windows that are placed next to each other
+    pub inner: i32,
+    /// Between windows and the edges of their screen
+    pub outer: i32,
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Keep this much space between windows, and around them at the edges of screens.
+    pub fn gaps (mut self, inner: i32, outer: i32) -> CharlieResult<Self> {
+        self.desktop.gaps = Gaps { inner: inner.max(0), outer: outer.max(0) };
+        Ok(self)
+    }
+
+    /// Change the gaps, and fit the windows that fill their screens into the new space.
+    pub fn gaps_set (&mut self, gaps: Gaps) {
+        self.desktop.gaps = Gaps { inner: gaps.inner.max(0), outer: gaps.outer.max(0) };
+        let filled: Vec<WlSurface> = self.desktop.windows_visible()
+            .filter(|window| window.filled.is_some() && window.fullscreen.is_none())
+            .map(|window| window.window.toplevel().wl_surface().clone())
+            .collect();
+        let changes: Vec<Change> = filled.into_iter()
+            .filter_map(|surface| {
+                let rect = self.work_rect(self.window_screen(&surface))?;
+                Some(Change { surface, center: rect.loc, size: Some(rect.size.to_i32_round()) })
+            })
+            .collect();
+        if !changes.is_empty() {
+            self.transaction_start(changes);
+        }
+        self.redraw();
+    }
+
+    /// The part of the workspace that a screen shows, in the coordinates of windows.
+    fn screen_rect (&self, screen: ScreenId) -> Option<Rectangle<f64, Logical>> {
+        let center = self.desktop.screens.get(screen)?.center;
+        let size = self.screen_area(screen)?;
+        Some(Rectangle::from_loc_and_size((-center.x, -center.y), size))
+    }
+
+    /// The part of a screen that windows are arranged in: all of it, except for the outer gap,
+    /// and room for window borders, which are drawn outside of windows.
+    pub fn work_rect (&self, screen: ScreenId) -> Option<Rectangle<f64, Logical>> {
+        let rect = self.screen_rect(screen)?;
+        let border = self.desktop.decorations.border.map(|border| border.width as i32).unwrap_or(0);
+        let inset = (self.desktop.gaps.outer + border) as f64;
+        Some(Rectangle::from_loc_and_size(
+            (rect.loc.x + inset, rect.loc.y + inset),
+            ((rect.size.w - 2.0 * inset).max(1.0), (rect.size.h - 2.0 * inset).max(1.0))
+        ))
+    }
+
+    /// The screen that shows the middle of a window, or the active screen if none does.
+    pub fn window_screen (&self, surface: &WlSurface) -> ScreenId {
+        let middle = match self.desktop.window_state(surface) {
+            Some(window) => {
+                let size = window.window.geometry().size.to_f64();
+                Point::<f64, Logical>::from((window.center.x + size.w / 2.0, window.center.y + size.h / 2.0))
+            },
+            None => return self.desktop.active_screen
+        };
+        (0..self.desktop.screens.len())
+            .find(|screen| self.screen_rect(*screen).map(|rect| rect.contains(middle)).unwrap_or(false))
+            .unwrap_or(self.desktop.active_screen)
+    }
+
+    /// Where a window is and how big, unless it's fullscreen and so can't be moved.
+    pub fn window_geometry (&self, surface: &WlSurface) -> Option<(Point<f64, Logical>, Size<f64, Logical>)> {
+        let window = self.desktop.window_state(surface).filter(|w| w.fullscreen.is_none())?;
+        Some((window.center, window.window.geometry().size.to_f64()))
+    }
+
+    /// Move a window by this many logical pixels.
+    pub fn window_move_by (&mut self, surface: &WlSurface, dx: f64, dy: f64) {
+        if let Some((center, _)) = self.window_geometry(surface) {
+            let center = (center.x + dx, center.y + dy).into();
+            self.transaction_start(vec![Change { surface: surface.clone(), center, size: None }]);
+        }
+    }
+
+    /// Move a window against an edge of the screen it's on.
+    pub fn window_move_to_edge (&mut self, surface: &WlSurface, edge: Edge) {
+        let (center, size) = match self.window_geometry(surface) {
+            Some(geometry) => geometry,
+            None => return
+        };
+        let rect = match self.work_rect(self.window_screen(surface)) {
+            Some(rect) => rect,
+            None => return
+        };
+        let center = match edge {
+            Edge::Left   => (rect.loc.x, center.y),
+            Edge::Right  => (rect.loc.x + rect.size.w - size.w, center.y),
+            Edge::Top    => (center.x, rect.loc.y),
+            Edge::Bottom => (center.x, rect.loc.y + rect.size.h - size.h),
+        }.into();
+        self.transaction_start(vec![Change { surface: surface.clone(), center, size: None }]);
+    }
+
+    /// Move a window to the same place on another screen, keeping it within that screen.
+    pub fn window_move_to_screen (&mut self, surface: &WlSurface, screen: ScreenId) {
+        let (center, size) = match self.window_geometry(surface) {
+            Some(geometry) => geometry,
+            None => return
+        };
+        let (from, to) = match (self.work_rect(self.window_screen(surface)), self.work_rect(screen)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return
+        };
+        let x = (to.loc.x + center.x - from.loc.x).min(to.loc.x + to.size.w - size.w).max(to.loc.x);
+        let y = (to.loc.y + center.y - from.loc.y).min(to.loc.y + to.size.h - size.h).max(to.loc.y);
+        self.transaction_start(vec![Change { surface: surface.clone(), center: (x, y).into(), size: None }]);
+    }
+
+    /// Make a window this many logical pixels bigger, or smaller if negative,
+    /// keeping its top left corner in place.
+    pub fn window_resize_by (&mut self, surface: &WlSurface, dw: i32, dh: i32) {
+        if let Some((center, size)) = self.window_geometry(surface) {
+            let size = size.to_i32_round();
+            let size = ((size.w + dw).max(MIN_SIZE), (size.h + dh).max(MIN_SIZE)).into();
+            self.transaction_start(vec![Change { surface: surface.clone(), center, size: Some(size) }]);
+        }
+    }
+
+    /// Move a window to the middle of the screen it's on.
+    pub fn window_center (&mut self, surface: &WlSurface) {
+        let (_, size) = match self.window_geometry(surface) {
+            Some(geometry) => geometry,
+            None => return
+        };
+        if let Some(rect) = self.work_rect(self.window_screen(surface)) {
+            let center = (rect.loc.x + (rect.size.w - size.w) / 2.0, rect.loc.y + (rect.size.h - size.h) / 2.0).into();
+            self.transaction_start(vec![Change { surface: surface.clone(), center, size: None }]);
+        }
+    }
+
+    /// Make a window fill the screen it's on, within the outer gap,
+    /// or put it back where it was if it does.
+    pub fn window_toggle_floating (&mut self, surface: &WlSurface) {
+        let (center, size) = match self.window_geometry(surface) {
+            Some(geometry) => geometry,
+            None => return
+        };
+        let rect = self.work_rect(self.window_screen(surface));
+        let window = match self.desktop.window_state_mut(surface) {
+            Some(window) => window,
+            None => return
+        };
+        let change = match window.filled.take() {
+            Some((center, size)) => Change { surface: surface.clone(), center, size: Some(size.to_i32_round()) },
+            None => match rect {
+                Some(rect) => {
+                    window.filled = Some((center, size));
+                    Change { surface: surface.clone(), center: rect.loc, size: Some(rect.size.to_i32_round()) }
+                },
+                None => return
+            }
+        };
+        self.transaction_start(vec![change]);
+    }
+
+}
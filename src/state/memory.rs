@@ -0,0 +1,194 @@
+use super::prelude::*;
+
+use std::collections::HashSet;
+
+use super::texture::ImportedSize;
+
+use smithay::{
+    reexports::wayland_server::{Resource, backend::ClientId},
+    wayland::shm::with_buffer_contents,
+};
+
+/// What to do about a client whose buffers and textures take more memory than allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    /// Stop importing its new buffers until it's back under the limit
+    Refuse,
+    /// Drop the textures of its windows that aren't shown, and refuse if that's not enough
+    Evict,
+}
+
+impl std::str::FromStr for MemoryPolicy {
+    type Err = Box<dyn Error>;
+    fn from_str (policy: &str) -> StdResult<Self> {
+        Ok(match policy {
+            "refuse" => Self::Refuse,
+            "evict"  => Self::Evict,
+            _ => return Err(format!("unknown memory policy: {policy}").into())
+        })
+    }
+}
+
+/// Memory taken by the buffers of a client, and by the textures they were imported into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Bytes of shared memory that the current buffers take
+    pub shm:      usize,
+    /// Bytes that the textures take, estimated at 4 bytes per pixel
+    pub textures: usize,
+}
+
+impl MemoryUsage {
+
+    pub fn total (&self) -> usize {
+        self.shm + self.textures
+    }
+
+}
+
+impl std::ops::AddAssign for MemoryUsage {
+    fn add_assign (&mut self, other: Self) {
+        self.shm      += other.shm;
+        self.textures += other.textures;
+    }
+}
+
+/// How much memory the current buffer of a surface takes, and its cached textures.
+/// Shared memory buffers that were released after importing them don't count.
+pub fn surface_memory (surface: &WlSurface) -> MemoryUsage {
+    with_states(surface, |surface_data| {
+        let data = match surface_data.data_map.get::<RendererSurfaceStateUserData>() {
+            Some(data) => data.borrow(),
+            None => return MemoryUsage::default()
+        };
+        let shm = data.buffer.as_ref()
+            .and_then(|buffer| with_buffer_contents(buffer, |_, _, info| (info.stride * info.height) as usize).ok())
+            .unwrap_or(0);
+        let imported = surface_data.data_map.get::<ImportedSize>().and_then(|size| size.0.get());
+        let pixels = data.buffer.as_ref().and_then(buffer_dimensions).map(|size| (size.w * size.h) as usize)
+            .or_else(|| imported.map(|size| (size.w * size.h) as usize))
+            .unwrap_or(0);
+        MemoryUsage { shm, textures: data.textures.len() * pixels * 4 }
+    })
+}
+
+/// Keeps track of the memory that clients' buffers take, optionally capped per client,
+/// to protect memory-constrained devices against runaway clients.
+pub struct Memory {
+    logger:      Logger,
+    /// Most bytes of buffers and textures a client may have; None for no limit
+    pub limit:   Option<usize>,
+    /// What happens to clients over the limit
+    pub policy:  MemoryPolicy,
+    /// Clients whose new buffers aren't imported, because they're over the limit
+    pub refused: HashSet<ClientId>,
+}
+
+impl Memory {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), limit: None, policy: MemoryPolicy::Evict, refused: HashSet::new() }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Limit the memory that each client's buffers and textures may take.
+    pub fn memory_limit (mut self, bytes: usize, policy: MemoryPolicy) -> CharlieResult<Self> {
+        self.memory_limit_set(Some(bytes), policy);
+        Ok(self)
+    }
+
+    /// Change or remove the limit on the memory each client may take.
+    pub fn memory_limit_set (&mut self, bytes: Option<usize>, policy: MemoryPolicy) {
+        self.memory.limit  = bytes;
+        self.memory.policy = policy;
+        self.memory.refused.clear();
+    }
+
+    /// Memory taken by each client that has windows, with its pid if known.
+    pub fn memory_usage (&self) -> Vec<(ClientId, Option<i32>, MemoryUsage)> {
+        let handle = self.display.borrow().handle();
+        let mut usage: Vec<(ClientId, Option<i32>, MemoryUsage)> = vec![];
+        for window in self.desktop.windows_all() {
+            let surface = window.window.toplevel().wl_surface();
+            let client = match surface.client() {
+                Some(client) => client,
+                None => continue
+            };
+            let memory = surface_memory(surface);
+            match usage.iter_mut().find(|(id, _, _)| *id == client.id()) {
+                Some((_, _, total)) => *total += memory,
+                None => {
+                    let pid = client.get_credentials(&handle).ok().map(|credentials| credentials.pid);
+                    usage.push((client.id(), pid, memory))
+                }
+            }
+        }
+        usage
+    }
+
+    /// Memory taken by one client's windows.
+    fn client_memory (&self, client: &ClientId) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        for window in self.desktop.windows_all() {
+            let surface = window.window.toplevel().wl_surface();
+            if surface.client().map(|c| c.id()).as_ref() == Some(client) {
+                usage += surface_memory(surface);
+            }
+        }
+        usage
+    }
+
+    /// Check the client of a surface that committed against the limit.
+    pub fn memory_commit (&mut self, surface: &WlSurface) {
+        let limit = match self.memory.limit {
+            Some(limit) => limit,
+            None => return
+        };
+        let client = match surface.client() {
+            Some(client) => client.id(),
+            None => return
+        };
+        let mut usage = self.client_memory(&client);
+        if usage.total() > limit && self.memory.policy == MemoryPolicy::Evict {
+            self.memory_evict(&client);
+            usage = self.client_memory(&client);
+        }
+        if usage.total() > limit {
+            if self.memory.refused.insert(client) {
+                warn!(self.memory.logger, "Client is over the memory limit ({} > {limit} bytes), not importing its buffers", usage.total());
+            }
+        } else if self.memory.refused.remove(&client) {
+            info!(self.memory.logger, "Client is back under the memory limit, importing its buffers again");
+        }
+    }
+
+    /// Drop the cached textures of a client's windows that aren't shown. Textures whose
+    /// buffer was already released are kept, since they're the only copy of the contents.
+    fn memory_evict (&mut self, client: &ClientId) {
+        let current = self.desktop.workspaces.current;
+        let hidden: Vec<(u64, WlSurface)> = self.desktop.windows_all()
+            .filter(|w| w.workspace != current || w.minimized)
+            .map(|w| (w.id, w.window.toplevel().wl_surface().clone()))
+            .filter(|(_, surface)| surface.client().map(|c| c.id()).as_ref() == Some(client))
+            .collect();
+        for (id, surface) in hidden {
+            let textures = &mut self.desktop.textures;
+            with_states(&surface, |surface_data| {
+                if let Some(data) = surface_data.data_map.get::<RendererSurfaceStateUserData>() {
+                    let mut data = data.borrow_mut();
+                    if let Some(buffer) = data.buffer.clone() {
+                        if !data.textures.is_empty() {
+                            debug!(self.memory.logger, "Evicting textures of window {id}");
+                            data.textures.clear();
+                            textures.invalidate(&buffer);
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+}
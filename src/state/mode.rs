@@ -0,0 +1,135 @@
+use super::prelude::*;
+
+use smithay::output::Mode;
+
+/// Sizes offered by outputs that are windows, as long as they fit on the host's monitor
+const WINDOWED_SIZES: &[(i32, i32)] = &[
+    (3840, 2160), (2560, 1440), (1920, 1200), (1920, 1080), (1680, 1050), (1600, 900),
+    (1440, 900), (1366, 768), (1280, 1024), (1280, 800), (1280, 720), (1024, 768), (800, 600),
+];
+
+/// Refresh rate of outputs that are windows, in millihertz
+const WINDOWED_REFRESH: i32 = 60_000;
+
+/// A mode asked for by size and, optionally, refresh rate,
+/// written like `1920x1080` or `1920x1080@144`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModeSpec {
+    pub size:    Size<i32, Physical>,
+    /// In hertz; if None, the highest rate is picked
+    pub refresh: Option<f64>,
+}
+
+impl ModeSpec {
+
+    /// The mode that matches this best: one of the right size, with the closest refresh rate.
+    pub fn pick (&self, modes: &[Mode]) -> Option<Mode> {
+        let sized = modes.iter().filter(|mode| mode.size == self.size);
+        match self.refresh {
+            Some(hz) => {
+                let millihertz = hz * 1000.0;
+                sized.min_by(|a, b| (a.refresh as f64 - millihertz).abs().total_cmp(&(b.refresh as f64 - millihertz).abs()))
+            },
+            None => sized.max_by_key(|mode| mode.refresh)
+        }.copied()
+    }
+
+}
+
+impl std::str::FromStr for ModeSpec {
+    type Err = Box<dyn Error>;
+    fn from_str (spec: &str) -> StdResult<Self> {
+        let (size, refresh) = match spec.split_once('@') {
+            Some((size, refresh)) => (size, Some(refresh.trim_end_matches("Hz").parse::<f64>()?)),
+            None => (spec, None)
+        };
+        let (w, h) = size.split_once('x').ok_or_else(|| format!("expected WIDTHxHEIGHT[@HZ], got {spec}"))?;
+        Ok(Self { size: (w.parse()?, h.parse()?).into(), refresh })
+    }
+}
+
+/// The modes offered by an output that's a window, given its initial size
+/// and the size of the monitor it's on, if known.
+pub fn windowed_modes (initial: Size<i32, Physical>, monitor: Option<Size<i32, Physical>>) -> Vec<Mode> {
+    let mut modes = vec![Mode { size: initial, refresh: WINDOWED_REFRESH }];
+    for (w, h) in WINDOWED_SIZES.iter().copied() {
+        let fits = monitor.map(|monitor| w <= monitor.w && h <= monitor.h).unwrap_or(w <= 1920 && h <= 1080);
+        if fits && (w, h) != (initial.w, initial.h) {
+            modes.push(Mode { size: (w, h).into(), refresh: WINDOWED_REFRESH });
+        }
+    }
+    modes
+}
+
+/// Describe a mode like it's written in the config.
+pub fn mode_name (mode: &Mode) -> String {
+    format!("{}x{}@{}", mode.size.w, mode.size.h, mode.refresh as f64 / 1000.0)
+}
+
+/// The modes that outputs are configured to use, by output name.
+/// Outputs without one stay in their preferred mode.
+pub struct Modes {
+    logger:        Logger,
+    pub requested: HashMap<String, ModeSpec>,
+}
+
+impl Modes {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), requested: HashMap::new() }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Put the named output in the mode that best matches this, e.g. `1920x1080@144`.
+    pub fn output_mode (mut self, name: &str, spec: &str) -> CharlieResult<Self> {
+        let spec = spec.parse().map_err(|e| CharlieError::Config(format!("{e}")))?;
+        self.output_mode_set(name, spec)?;
+        Ok(self)
+    }
+
+    /// Change the mode of the named output, now if it exists or once it's added.
+    pub fn output_mode_set (&mut self, name: &str, spec: ModeSpec) -> CharlieResult<()> {
+        self.modes.requested.insert(name.into(), spec);
+        match self.desktop.screen_find(name) {
+            Some(screen) => self.output_mode_apply(screen),
+            None => Ok(())
+        }
+    }
+
+    /// Switch the output of a screen to its configured mode, if it has one.
+    pub fn output_mode_apply (&mut self, screen: ScreenId) -> CharlieResult<()> {
+        let output = match self.engine.output_get(screen) {
+            Some(output) => output,
+            None => return Ok(())
+        };
+        let spec = match self.modes.requested.get(&output.name()) {
+            Some(spec) => *spec,
+            None => return Ok(())
+        };
+        let mode = spec.pick(&output.modes()).ok_or_else(|| CharlieError::Config(format!(
+            "{} has no mode {}x{}", output.name(), spec.size.w, spec.size.h
+        )))?;
+        if output.current_mode() != Some(mode) {
+            info!(self.modes.logger, "Switching {} to {}", output.name(), mode_name(&mode));
+            self.engine.output_mode(screen, mode)?;
+            self.layout_arrange();
+        }
+        Ok(())
+    }
+
+    /// List the modes of the output of a screen, marking the current and preferred ones.
+    pub fn output_modes (&self, screen: ScreenId) -> Option<Vec<String>> {
+        let output = self.engine.output_get(screen)?;
+        let (current, preferred) = (output.current_mode(), output.preferred_mode());
+        Some(output.modes().iter().map(|mode| format!(
+            "{}{}{}",
+            mode_name(mode),
+            if Some(*mode) == current { " current" } else { "" },
+            if Some(*mode) == preferred { " preferred" } else { "" },
+        )).collect())
+    }
+
+}
@@ -0,0 +1,217 @@
+use super::prelude::*;
+
+use super::layout::Placement;
+use super::mode::ModeSpec;
+
+/// How a profile sets up one of the outputs it expects to be connected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileOutput {
+    /// Which output this is: its name, like `DP-1`, or its make and model, like `Dell Inc. U2720Q`,
+    /// which also match followed by the serial number; `*` matches any output
+    pub criteria:  String,
+    pub enabled:   bool,
+    /// Where the output goes in the layout, in logical pixels
+    pub position:  Option<(i32, i32)>,
+    pub mode:      Option<ModeSpec>,
+    pub scale:     Option<f64>,
+    pub transform: Option<Transform>,
+}
+
+impl ProfileOutput {
+
+    /// An output that's turned on, and otherwise left as it is.
+    pub fn new (criteria: impl Into<String>) -> Self {
+        Self { criteria: criteria.into(), enabled: true, position: None, mode: None, scale: None, transform: None }
+    }
+
+    /// Turn the output off while the profile applies, moving its windows elsewhere.
+    pub fn disabled (mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+
+    /// Put the output at these coordinates in the layout.
+    pub fn at (mut self, x: i32, y: i32) -> Self {
+        self.position = Some((x, y));
+        self
+    }
+
+    /// Put the output in the mode that best matches this.
+    pub fn mode (mut self, mode: ModeSpec) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Draw windows on the output this many times bigger.
+    pub fn scale (mut self, scale: f64) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// Rotate or flip the output.
+    pub fn transform (mut self, transform: Transform) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    /// Whether this stands for an output with this name and description.
+    fn matches (&self, name: &str, description: &str) -> bool {
+        self.criteria == "*"
+            || self.criteria == name
+            || self.criteria == description
+            || self.criteria.starts_with(&format!("{description} "))
+    }
+
+}
+
+/// A named set of outputs, applied when exactly those outputs are connected,
+/// e.g. one for the laptop on its own and one for when it's docked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputProfile {
+    pub name:    String,
+    pub outputs: Vec<ProfileOutput>,
+}
+
+impl OutputProfile {
+
+    pub fn new (name: impl Into<String>) -> Self {
+        Self { name: name.into(), outputs: vec![] }
+    }
+
+    /// Expect this output to be connected, and set it up like this.
+    pub fn output (mut self, output: ProfileOutput) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Pair each connected output, by name and description, with the entry that stands for it,
+    /// if each one stands for exactly one connected output. Entries are tried in order,
+    /// so more specific ones should come before wildcards.
+    fn assign <'a> (&'a self, connected: &[(ScreenId, String, String)]) -> Option<Vec<(ScreenId, &'a ProfileOutput)>> {
+        if connected.len() != self.outputs.len() {
+            return None
+        }
+        let mut free: Vec<&ProfileOutput> = self.outputs.iter().collect();
+        let mut assigned = vec![];
+        for (screen, name, description) in connected.iter() {
+            let index = free.iter().position(|output| output.matches(name, description))?;
+            assigned.push((*screen, free.remove(index)));
+        }
+        Some(assigned)
+    }
+
+}
+
+/// Output profiles, and which of them applies to the outputs connected now.
+///
+/// Whenever an output is connected or disconnected, the first profile that expects exactly the
+/// connected outputs is applied: their positions, modes, scales and transforms are set, and the
+/// outputs it disables are turned off and left out of the layout.
+pub struct Monitors {
+    logger:       Logger,
+    pub profiles: Vec<OutputProfile>,
+    /// The profile applied last, if it still matches
+    pub current:  Option<String>,
+    /// Screens that the current profile turned off
+    pub disabled: Vec<ScreenId>,
+}
+
+impl Monitors {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), profiles: vec![], current: None, disabled: vec![] }
+    }
+
+}
+
+/// How an output is described by its make and model. Serial numbers aren't known
+/// to the outputs of the engines, so a criteria's serial number is not checked.
+fn describe (output: &Output) -> String {
+    let properties = output.physical_properties();
+    format!("{} {}", properties.make, properties.model)
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Add a profile, to be applied whenever the outputs it expects are the connected ones.
+    pub fn output_profile (mut self, profile: OutputProfile) -> CharlieResult<Self> {
+        self.monitors.profiles.push(profile);
+        self.output_profiles_apply();
+        Ok(self)
+    }
+
+    /// Apply the first profile that matches the connected outputs, if it isn't applied already.
+    pub fn output_profiles_apply (&mut self) {
+        let connected: Vec<(ScreenId, String, String)> = self.desktop.screens.iter().enumerate()
+            .filter_map(|(screen, state)| {
+                let name = state.output.clone()?;
+                let description = self.engine.output_get(screen).map(|output| describe(&output)).unwrap_or_default();
+                Some((screen, name, description))
+            })
+            .collect();
+        let found: Option<(String, Vec<(ScreenId, ProfileOutput)>)> = self.monitors.profiles.iter()
+            .find_map(|profile| Some((
+                profile.name.clone(),
+                profile.assign(&connected)?.into_iter().map(|(screen, output)| (screen, output.clone())).collect()
+            )));
+        let (name, assigned) = match found {
+            Some(found) => found,
+            None => {
+                if let Some(name) = self.monitors.current.take() {
+                    debug!(self.monitors.logger, "Profile {name} no longer matches the connected outputs");
+                }
+                return
+            }
+        };
+        if self.monitors.current.as_ref() == Some(&name) {
+            return
+        }
+        info!(self.monitors.logger, "Applying output profile {name}");
+        // Outputs turned off by the previous profile come back on, unless this one keeps them off
+        for screen in std::mem::take(&mut self.monitors.disabled) {
+            if let Err(e) = self.engine.output_enable(screen, true) {
+                warn!(self.monitors.logger, "Could not turn on screen {screen}: {e}");
+            }
+        }
+        for (screen, output) in assigned.iter() {
+            let name = connected.iter().find(|(id, _, _)| id == screen).map(|(_, name, _)| name.clone()).unwrap_or_default();
+            if !output.enabled {
+                match self.engine.output_enable(*screen, false) {
+                    Ok(()) => self.monitors.disabled.push(*screen),
+                    Err(e) => warn!(self.monitors.logger, "Could not turn off {name}: {e}")
+                }
+                continue
+            }
+            if let Some(mode) = output.mode {
+                if let Err(e) = self.output_mode_set(&name, mode) {
+                    warn!(self.monitors.logger, "Could not set the mode of {name}: {e}");
+                }
+            }
+            if let Some(scale) = output.scale {
+                if let Err(e) = self.output_scale(*screen, scale) {
+                    warn!(self.monitors.logger, "Could not set the scale of {name}: {e}");
+                }
+            }
+            if let Some(transform) = output.transform {
+                if let Err(e) = self.output_transform(*screen, transform) {
+                    warn!(self.monitors.logger, "Could not set the transform of {name}: {e}");
+                }
+            }
+            if let Some((x, y)) = output.position {
+                self.layout.placements.insert(name, Placement::At(x, y));
+            }
+        }
+        // Windows on the outputs that were turned off go to the first one that's left on
+        let target = assigned.iter().find(|(_, output)| output.enabled).map(|(screen, _)| *screen);
+        if let Some(target) = target {
+            for screen in self.monitors.disabled.clone() {
+                self.screen_evacuate(screen, target);
+            }
+        }
+        self.monitors.current = Some(name.clone());
+        self.layout_arrange();
+        self.scripts.hook("on_output_profile", vec![name.clone().into()]);
+        self.ipc_event(&format!("output profile {name}"));
+    }
+
+}
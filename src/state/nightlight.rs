@@ -0,0 +1,223 @@
+use super::prelude::*;
+use super::shader::{Program, FULLSCREEN_VERTEX};
+
+use smithay::backend::renderer::gles2::ffi;
+
+/// Color temperature of plain white, in kelvin
+pub const NEUTRAL_TEMPERATURE: u32 = 6500;
+
+/// Minutes in a day
+const DAY: i32 = 24 * 60;
+
+/// Multiplies what's already in the frame by a color
+const TINT_FRAGMENT: &str = r#"
+#version 100
+precision mediump float;
+uniform vec3 tint;
+void main() {
+    gl_FragColor = vec4(tint, 1.0);
+}
+"#;
+
+/// The RGB multipliers that make white look like it has this color temperature.
+pub fn temperature_color (kelvin: u32) -> [f32; 3] {
+    let t = (kelvin.clamp(1000, 40000) as f64) / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.698727446 * (t - 60.0).powf(-0.1332047592) };
+    let g = if t <= 66.0 {
+        99.4708025861 * t.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (t - 60.0).powf(-0.0755148492)
+    };
+    let b = if t >= 66.0 { 255.0 } else if t <= 19.0 { 0.0 } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+    [r, g, b].map(|c| (c.clamp(0.0, 255.0) / 255.0) as f32)
+}
+
+/// Minutes since local midnight.
+fn local_minutes () -> i32 {
+    use nix::libc::{time, localtime_r, tm};
+    unsafe {
+        let now = time(std::ptr::null_mut());
+        let mut local: tm = std::mem::zeroed();
+        localtime_r(&now, &mut local);
+        local.tm_hour * 60 + local.tm_min
+    }
+}
+
+/// Parse a time of day given as `HH:MM` into minutes since midnight.
+pub fn parse_time (time: &str) -> StdResult<u32> {
+    let (hours, minutes) = time.split_once(':').ok_or("expected HH:MM")?;
+    let (hours, minutes): (u32, u32) = (hours.parse()?, minutes.parse()?);
+    if hours >= 24 || minutes >= 60 {
+        return Err(format!("invalid time: {time}").into())
+    }
+    Ok(hours * 60 + minutes)
+}
+
+/// Shifts the colors of all outputs to warmer ones at night.
+///
+/// Outputs with hardware gamma get a gamma ramp, unless a client has taken over their gamma.
+/// On the others, the finished frame is multiplied by the color of the current temperature.
+pub struct NightLight {
+    logger:         Logger,
+    pub enabled:    bool,
+    /// Color temperature during the day, in kelvin
+    pub day:        u32,
+    /// Color temperature at night, in kelvin
+    pub night:      u32,
+    /// When the night starts, in minutes since midnight
+    pub sunset:     u32,
+    /// When the day starts, in minutes since midnight
+    pub sunrise:    u32,
+    /// How long it takes to go from one temperature to the other, in minutes
+    pub transition: u32,
+    /// Temperature applied on each screen whose gamma isn't set by the shader pass
+    hardware:       HashMap<ScreenId, u32>,
+    /// Tint to apply in the shader pass this frame, if any
+    tint:           Option<[f32; 3]>,
+    program:        Option<Program>,
+}
+
+impl NightLight {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:     logger.clone(),
+            enabled:    false,
+            day:        NEUTRAL_TEMPERATURE,
+            night:      3500,
+            sunset:     19 * 60,
+            sunrise:    7 * 60,
+            transition: 60,
+            hardware:   HashMap::new(),
+            tint:       None,
+            program:    None,
+        }
+    }
+
+    /// The color temperature at a time of day, given in minutes since midnight.
+    pub fn temperature_at (&self, minute: i32) -> u32 {
+        let since_sunset  = (minute - self.sunset as i32).rem_euclid(DAY);
+        let since_sunrise = (minute - self.sunrise as i32).rem_euclid(DAY);
+        let transition = self.transition.max(1) as f64;
+        let (from, to, since) = if since_sunset < since_sunrise {
+            (self.day, self.night, since_sunset)
+        } else {
+            (self.night, self.day, since_sunrise)
+        };
+        let progress = (since as f64 / transition).min(1.0);
+        (from as f64 + (to as f64 - from as f64) * progress).round() as u32
+    }
+
+    /// The color temperature right now.
+    pub fn temperature (&self) -> u32 {
+        if self.enabled { self.temperature_at(local_minutes()) } else { NEUTRAL_TEMPERATURE }
+    }
+
+    /// Forget which temperatures were loaded into hardware gamma, so they're loaded again.
+    pub fn reset (&mut self) {
+        self.hardware.clear();
+    }
+
+    /// Compile the shader, unless already done.
+    pub fn prepare (&mut self, renderer: &mut Gles2Renderer) -> StdResult<()> {
+        if self.tint.is_some() && self.program.is_none() {
+            let program = renderer.with_context(|gl| unsafe {
+                Program::new(gl, FULLSCREEN_VERTEX, TINT_FRAGMENT, &["tint"])
+            })??;
+            self.program = Some(program);
+        }
+        Ok(())
+    }
+
+    /// Tint the finished frame of a screen that isn't handled by hardware gamma.
+    pub fn render (&self, frame: &mut Gles2Frame, screen: ScreenId) -> StdResult<()> {
+        let (tint, program) = match (self.tint, self.program.as_ref()) {
+            (Some(tint), Some(program)) if !self.hardware.contains_key(&screen) => (tint, program),
+            _ => return Ok(())
+        };
+        frame.with_context(|gl| unsafe {
+            // Multiply the destination by the source color
+            gl.Enable(ffi::BLEND);
+            gl.BlendFunc(ffi::ZERO, ffi::SRC_COLOR);
+            program.draw_fullscreen(gl, |gl, program| {
+                gl.Uniform3f(program.uniform("tint"), tint[0], tint[1], tint[2]);
+            });
+            gl.BlendFunc(ffi::ONE, ffi::ONE_MINUS_SRC_ALPHA);
+        })?;
+        Ok(())
+    }
+
+    /// Gamma ramps of a given size that make white look like it has this color temperature.
+    pub fn gamma_ramps (kelvin: u32, size: u32) -> Vec<u16> {
+        let color = temperature_color(kelvin);
+        let size  = size.max(2) as usize;
+        let mut ramps = Vec::with_capacity(size * 3);
+        for channel in color {
+            for i in 0..size {
+                let value = i as f64 / (size - 1) as f64 * channel as f64;
+                ramps.push((value * u16::MAX as f64).round() as u16);
+            }
+        }
+        ramps
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Turn the night light on, going between two color temperatures
+    /// at sunset and sunrise, given in minutes since midnight.
+    pub fn night_light (mut self, day: u32, night: u32, sunset: u32, sunrise: u32) -> CharlieResult<Self> {
+        self.night_light.enabled = true;
+        self.night_light.day     = day;
+        self.night_light.night   = night;
+        self.night_light.sunset  = sunset;
+        self.night_light.sunrise = sunrise;
+        Ok(self)
+    }
+
+    /// Work out the current color temperature and load it into the outputs
+    /// with hardware gamma. The rest are tinted when they're rendered.
+    pub fn night_light_update (&mut self) {
+        let temperature = self.night_light.temperature();
+        let mut shader = false;
+        for screen in 0..self.desktop.screens.len() {
+            if self.gamma.controlled(screen) {
+                // A client has taken over, don't fight it
+                self.night_light.hardware.insert(screen, 0);
+                continue
+            }
+            let size = match self.engine.output_gamma_size(screen) {
+                Some(size) => size,
+                None => {
+                    self.night_light.hardware.remove(&screen);
+                    shader = true;
+                    continue
+                }
+            };
+            if self.night_light.hardware.get(&screen) == Some(&temperature) {
+                continue
+            }
+            let ramps = NightLight::gamma_ramps(temperature, size);
+            match self.engine.output_set_gamma(screen, Some(&ramps)) {
+                Ok(()) => {
+                    debug!(self.night_light.logger, "Screen {screen} gamma at {temperature}K");
+                    self.night_light.hardware.insert(screen, temperature);
+                },
+                Err(e) => {
+                    warn!(self.logger, "Could not set gamma of screen {screen}, using shader: {e}");
+                    self.night_light.hardware.remove(&screen);
+                    shader = true;
+                }
+            }
+        }
+        self.night_light.tint = if shader && temperature != NEUTRAL_TEMPERATURE {
+            Some(temperature_color(temperature))
+        } else {
+            None
+        };
+    }
+
+}
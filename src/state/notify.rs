@@ -0,0 +1,262 @@
+use super::prelude::*;
+use super::text::Text;
+
+use std::sync::atomic::AtomicU32;
+
+use smithay::reexports::calloop::{
+    channel::{channel, Sender, Event as ChannelEvent},
+    timer::{Timer, TimeoutAction},
+    RegistrationToken,
+};
+
+use zbus::{dbus_interface, blocking::{Connection, ConnectionBuilder}, zvariant::OwnedValue};
+
+const BUS_NAME:  &str = "org.freedesktop.Notifications";
+const BUS_PATH:  &str = "/org/freedesktop/Notifications";
+
+/// How long to show notifications that don't say
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Size of notification text at scale 1
+const FONT_SIZE: f32 = 14.0;
+
+/// Space around and between toasts at scale 1
+const MARGIN:  f64 = 10.0;
+
+/// Space between the edge of a toast and its text at scale 1
+const PADDING: f64 = 8.0;
+
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.1, 0.9];
+const SUMMARY:    [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BODY:       [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+/// Why a notification went away, as given in the NotificationClosed signal.
+#[derive(Debug, Clone, Copy)]
+pub enum CloseReason {
+    Expired   = 1,
+    Dismissed = 2,
+    Closed    = 3,
+}
+
+/// A message from the D-Bus thread to the main loop.
+#[derive(Debug)]
+enum Request {
+    Notify(Notification),
+    Close(u32),
+}
+
+/// A notification that is being shown.
+#[derive(Debug)]
+pub struct Notification {
+    pub id:      u32,
+    pub app:     String,
+    pub summary: String,
+    pub body:    String,
+    /// How long until it goes away by itself, if ever
+    timeout:     Option<Duration>,
+    /// Timer that removes it when it expires
+    timer:       Option<RegistrationToken>,
+}
+
+/// Implements org.freedesktop.Notifications by passing requests on to the main loop.
+struct Server {
+    sender:  Sender<Request>,
+    next_id: Arc<AtomicU32>,
+}
+
+#[dbus_interface(name = "org.freedesktop.Notifications")]
+impl Server {
+
+    #[allow(clippy::too_many_arguments)]
+    fn notify (
+        &self,
+        app_name:       String,
+        replaces_id:    u32,
+        _app_icon:      String,
+        summary:        String,
+        body:           String,
+        _actions:       Vec<String>,
+        _hints:         HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let id = if replaces_id > 0 {
+            replaces_id
+        } else {
+            self.next_id.fetch_add(1, Ordering::SeqCst)
+        };
+        let timeout = match expire_timeout {
+            0 => None,
+            t if t < 0 => Some(DEFAULT_TIMEOUT),
+            t => Some(Duration::from_millis(t as u64))
+        };
+        let _ = self.sender.send(Request::Notify(Notification {
+            id, app: app_name, summary, body, timeout, timer: None
+        }));
+        id
+    }
+
+    fn close_notification (&self, id: u32) {
+        let _ = self.sender.send(Request::Close(id));
+    }
+
+    fn get_capabilities (&self) -> Vec<String> {
+        vec!["body".into()]
+    }
+
+    #[dbus_interface(out_args("name", "vendor", "version", "spec_version"))]
+    fn get_server_information (&self) -> (String, String, String, String) {
+        ("Charlie".into(), "Charlie".into(), env!("CARGO_PKG_VERSION").into(), "1.2".into())
+    }
+
+}
+
+/// Notifications received over D-Bus, shown as a stack of toasts
+/// on the screen that last received input.
+pub struct Notifications<R: EngineRenderer> {
+    logger:     Logger,
+    /// Connection to the session bus, once started
+    connection: Option<Connection>,
+    /// Shown notifications, oldest first
+    pub list:   Vec<Notification>,
+    /// Where each notification was last drawn, for clicking on
+    areas:      Vec<(u32, Rectangle<f64, Logical>)>,
+    text:       Text<R>,
+}
+
+impl<R: EngineRenderer> Notifications<R> {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:     logger.clone(),
+            connection: None,
+            list:       vec![],
+            areas:      vec![],
+            text:       Text::from_env(logger),
+        }
+    }
+
+    /// Take the notification service name on the session bus and start serving requests.
+    pub fn init <E: Engine> (&mut self, events: &LoopHandle<'static, Charlie<E>>) -> StdResult<()> {
+        let (sender, receiver) = channel();
+        events.insert_source(receiver, |event, _, state| {
+            if let ChannelEvent::Msg(request) = event {
+                match request {
+                    Request::Notify(notification) => state.notify(notification),
+                    Request::Close(id) => state.notification_close(id, CloseReason::Closed)
+                }
+            }
+        })?;
+        let server = Server { sender, next_id: Arc::new(AtomicU32::new(1)) };
+        let connection = ConnectionBuilder::session()?
+            .name(BUS_NAME)?
+            .serve_at(BUS_PATH, server)?
+            .build()?;
+        info!(self.logger, "Serving notifications as {BUS_NAME}");
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    /// The notification drawn at this point, if any.
+    pub fn at (&self, point: Point<f64, Logical>) -> Option<u32> {
+        self.areas.iter().find(|(_, area)| area.contains(point)).map(|(id, _)| *id)
+    }
+
+    /// Rasterize the text of all notifications for this scale.
+    pub fn prepare (&mut self, renderer: &mut R, scale: f64) -> StdResult<()> {
+        self.text.begin();
+        let size = (FONT_SIZE as f64 * scale) as f32;
+        for notification in self.list.iter() {
+            self.text.prepare(renderer, &notification.summary, size, SUMMARY)?;
+            self.text.prepare(renderer, &notification.body, size, BODY)?;
+        }
+        Ok(())
+    }
+
+    /// Draw the notifications stacked in the top right corner, newest on top.
+    pub fn render (&mut self, frame: &mut R::Frame<'_>, size: Size<i32, Physical>, scale: f64)
+        -> StdResult<()>
+    {
+        self.areas.clear();
+        let font_size = (FONT_SIZE as f64 * scale) as f32;
+        let screen = size.to_f64().to_logical(scale);
+        let mut y = MARGIN;
+        for notification in self.list.iter().rev() {
+            let summary = self.text.measure(&notification.summary, font_size).to_f64().to_logical(scale);
+            let body    = self.text.measure(&notification.body, font_size).to_f64().to_logical(scale);
+            let text_height = if notification.body.is_empty() { summary.h } else { summary.h + body.h };
+            let (w, h) = (summary.w.max(body.w) + PADDING * 2.0, text_height + PADDING * 2.0);
+            let area = Rectangle::<f64, Logical>::from_loc_and_size((screen.w - MARGIN - w, y), (w, h));
+            let physical = area.to_physical(scale).to_i32_round();
+            frame.clear(BACKGROUND, &[physical])?;
+            let origin = Point::<f64, Logical>::from((area.loc.x + PADDING, area.loc.y + PADDING))
+                .to_physical(scale).to_i32_round();
+            self.text.draw_text(frame, origin, &notification.summary, font_size, SUMMARY)?;
+            let below = Point::<f64, Logical>::from((0.0, summary.h)).to_physical(scale).to_i32_round();
+            self.text.draw_text(frame, origin + below, &notification.body, font_size, BODY)?;
+            self.areas.push((notification.id, area));
+            y += h + MARGIN;
+            if y > screen.h {
+                break
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_closed (&self, id: u32, reason: CloseReason) {
+        if let Some(connection) = self.connection.as_ref() {
+            if let Err(e) = connection.emit_signal(
+                None::<&str>, BUS_PATH, BUS_NAME, "NotificationClosed", &(id, reason as u32)
+            ) {
+                warn!(self.logger, "Could not signal closing of notification {id}: {e}");
+            }
+        }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Show a notification, replacing any with the same id.
+    fn notify (&mut self, mut notification: Notification) {
+        debug!(self.logger, "Notification {} from {}: {}",
+            notification.id, notification.app, notification.summary);
+        let id = notification.id;
+        self.notification_remove(id);
+        if let Some(timeout) = notification.timeout {
+            let timer = self.handle.insert_source(
+                Timer::from_duration(timeout),
+                move |_, _, state| {
+                    state.notification_close(id, CloseReason::Expired);
+                    TimeoutAction::Drop
+                }
+            );
+            match timer {
+                Ok(timer) => notification.timer = Some(timer),
+                Err(e) => warn!(self.logger, "Notification {id} will not expire: {e}")
+            }
+        }
+        self.notifications.list.push(notification);
+        self.redraw.request();
+    }
+
+    /// Stop showing a notification and tell its sender why.
+    pub fn notification_close (&mut self, id: u32, reason: CloseReason) {
+        if self.notification_remove(id) {
+            self.notifications.emit_closed(id, reason);
+        }
+    }
+
+    fn notification_remove (&mut self, id: u32) -> bool {
+        let index = match self.notifications.list.iter().position(|n| n.id == id) {
+            Some(index) => index,
+            None => return false
+        };
+        let notification = self.notifications.list.remove(index);
+        self.redraw.request();
+        if let Some(timer) = notification.timer {
+            self.handle.remove(timer);
+        }
+        true
+    }
+
+}
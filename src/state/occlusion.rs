@@ -0,0 +1,52 @@
+use super::prelude::*;
+
+/// The parts of a rectangle that another one doesn't cover: up to four rectangles,
+/// above, below, left and right of the covered part.
+fn subtract (area: Rectangle<i32, Logical>, cover: Rectangle<i32, Logical>) -> Vec<Rectangle<i32, Logical>> {
+    let overlap = match area.intersection(cover) {
+        Some(overlap) if overlap.size.w > 0 && overlap.size.h > 0 => overlap,
+        _ => return vec![area]
+    };
+    let (left, top) = (area.loc.x, area.loc.y);
+    let (right, bottom) = (area.loc.x + area.size.w, area.loc.y + area.size.h);
+    let (overlap_right, overlap_bottom) = (overlap.loc.x + overlap.size.w, overlap.loc.y + overlap.size.h);
+    let rect = |x: i32, y: i32, w: i32, h: i32| Rectangle::from_loc_and_size((x, y), (w, h));
+    [
+        rect(left, top, area.size.w, overlap.loc.y - top),
+        rect(left, overlap_bottom, area.size.w, bottom - overlap_bottom),
+        rect(left, overlap.loc.y, overlap.loc.x - left, overlap.size.h),
+        rect(overlap_right, overlap.loc.y, right - overlap_right, overlap.size.h),
+    ].into_iter().filter(|part| part.size.w > 0 && part.size.h > 0).collect()
+}
+
+/// Whether a rectangle is completely covered by others.
+pub fn covered (area: Rectangle<i32, Logical>, covers: &[Rectangle<i32, Logical>]) -> bool {
+    let mut uncovered = vec![area];
+    for cover in covers {
+        uncovered = uncovered.into_iter().flat_map(|part| subtract(part, *cover)).collect();
+        if uncovered.is_empty() {
+            return true
+        }
+    }
+    false
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Send frame callbacks to windows that can't be seen at most this many times per second,
+    /// or as often as to the others if None.
+    pub fn occluded_fps (mut self, fps: Option<u32>) -> CharlieResult<Self> {
+        self.desktop.occluded_fps = fps;
+        Ok(self)
+    }
+
+    /// Find out which windows can't be seen on any output, before rendering them.
+    pub fn occlusion_update (&mut self) {
+        let areas: Vec<(ScreenId, Rectangle<i32, Logical>)> = (0..self.desktop.screens.len())
+            .filter_map(|screen| self.screen_area(screen).map(|size| (screen, size)))
+            .map(|(screen, size)| (screen, Rectangle::from_loc_and_size((0, 0), size.to_i32_round())))
+            .collect();
+        self.desktop.occlusion_update(&areas);
+    }
+
+}
@@ -0,0 +1,350 @@
+use super::prelude::*;
+use super::manage::Edge;
+use super::text::Text;
+
+use std::process::Command;
+
+use serde::{Serialize, Deserialize};
+
+use smithay::backend::renderer::ImportMem;
+use smithay::input::keyboard::keysyms;
+use smithay::reexports::calloop::channel::{channel, Sender, Event as ChannelEvent};
+
+/// How long the overlay stays up after the last key press, before it fades
+const SHOW_TIME: Duration = Duration::from_millis(1500);
+
+/// How long the overlay takes to fade out
+const FADE_TIME: Duration = Duration::from_millis(300);
+
+/// How often the overlay is drawn while it fades
+const FADE_TICK: Duration = Duration::from_millis(16);
+
+/// Size of the overlay at scale 1
+const WIDTH:  f64 = 240.0;
+const HEIGHT: f64 = 56.0;
+
+/// Space between the overlay and the edge of the screen at scale 1
+const MARGIN:  f64 = 48.0;
+
+/// Space between the edge of the overlay and its contents at scale 1
+const PADDING: f64 = 10.0;
+
+/// Height of the progress bar at scale 1
+const BAR_HEIGHT: f64 = 6.0;
+
+/// Size of the label at scale 1
+const FONT_SIZE: f32 = 14.0;
+
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.1, 0.9];
+const TRACK:      [f32; 4] = [0.3, 0.3, 0.3, 1.0];
+const FILL:       [f32; 4] = [0.32, 0.58, 0.89, 1.0];
+const LABEL:      [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Keys that change the volume or the backlight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKey {
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    MicMute,
+    BrightnessUp,
+    BrightnessDown,
+}
+
+impl MediaKey {
+
+    pub const ALL: [Self; 6] = [
+        Self::VolumeUp, Self::VolumeDown, Self::Mute, Self::MicMute, Self::BrightnessUp, Self::BrightnessDown
+    ];
+
+    /// The media key that this key symbol stands for, if any.
+    pub fn from_keysym (keysym: u32) -> Option<Self> {
+        Some(match keysym {
+            keysyms::KEY_XF86AudioRaiseVolume  => Self::VolumeUp,
+            keysyms::KEY_XF86AudioLowerVolume  => Self::VolumeDown,
+            keysyms::KEY_XF86AudioMute         => Self::Mute,
+            keysyms::KEY_XF86AudioMicMute      => Self::MicMute,
+            keysyms::KEY_XF86MonBrightnessUp   => Self::BrightnessUp,
+            keysyms::KEY_XF86MonBrightnessDown => Self::BrightnessDown,
+            _ => return None
+        })
+    }
+
+    pub fn name (&self) -> &'static str {
+        match self {
+            Self::VolumeUp       => "volume_up",
+            Self::VolumeDown     => "volume_down",
+            Self::Mute           => "mute",
+            Self::MicMute        => "mic_mute",
+            Self::BrightnessUp   => "brightness_up",
+            Self::BrightnessDown => "brightness_down",
+        }
+    }
+
+    /// What the overlay says the level is of.
+    fn label (&self) -> &'static str {
+        match self {
+            Self::VolumeUp | Self::VolumeDown | Self::Mute => "Volume",
+            Self::MicMute => "Microphone",
+            Self::BrightnessUp | Self::BrightnessDown => "Brightness",
+        }
+    }
+
+    /// The shell command run by default, which makes the change and prints the new level.
+    fn default_command (&self) -> &'static str {
+        match self {
+            Self::VolumeUp =>
+                "wpctl set-volume -l 1 @DEFAULT_AUDIO_SINK@ 5%+ && wpctl get-volume @DEFAULT_AUDIO_SINK@",
+            Self::VolumeDown =>
+                "wpctl set-volume @DEFAULT_AUDIO_SINK@ 5%- && wpctl get-volume @DEFAULT_AUDIO_SINK@",
+            Self::Mute =>
+                "wpctl set-mute @DEFAULT_AUDIO_SINK@ toggle && wpctl get-volume @DEFAULT_AUDIO_SINK@",
+            Self::MicMute =>
+                "wpctl set-mute @DEFAULT_AUDIO_SOURCE@ toggle && wpctl get-volume @DEFAULT_AUDIO_SOURCE@",
+            Self::BrightnessUp   => "brightnessctl -m set 5%+",
+            Self::BrightnessDown => "brightnessctl -m set 5%-",
+        }
+    }
+
+}
+
+impl std::str::FromStr for MediaKey {
+    type Err = Box<dyn Error>;
+    fn from_str (key: &str) -> StdResult<Self> {
+        Self::ALL.into_iter().find(|k| k.name() == key)
+            .ok_or_else(|| format!("unknown media key: {key}").into())
+    }
+}
+
+/// Read the level that a command printed: a number followed by `%`, like `brightnessctl -m` prints,
+/// or else the first number, which is a fraction if it has a decimal point, like `wpctl` prints.
+/// `[MUTED]` anywhere in the output means the level is muted.
+pub fn parse_level (output: &str) -> Option<(f64, bool)> {
+    let muted = output.contains("[MUTED]");
+    let tokens = || output.split(|c: char| c.is_whitespace() || c == ',');
+    let percent = tokens()
+        .filter_map(|token| token.strip_suffix('%'))
+        .find_map(|token| token.parse::<f64>().ok());
+    let level = match percent {
+        Some(percent) => percent / 100.0,
+        None => {
+            let token = tokens().find(|token| token.parse::<f64>().is_ok())?;
+            let number: f64 = token.parse().ok()?;
+            if token.contains('.') { number } else { number / 100.0 }
+        }
+    };
+    Some((level.clamp(0.0, 1.0), muted))
+}
+
+/// What the overlay is showing.
+#[derive(Debug, Clone)]
+struct Shown {
+    label:  String,
+    /// From 0 to 1, or None if there's no bar
+    level:  Option<f64>,
+    screen: ScreenId,
+    /// When it starts fading out
+    until:  Instant,
+}
+
+/// An overlay showing the volume or brightness after a media key is pressed,
+/// and the commands that those keys run.
+pub struct Osd<R: EngineRenderer> {
+    logger:        Logger,
+    /// Shell command run for each key, which prints the new level; no command does nothing
+    pub commands:  HashMap<MediaKey, Option<String>>,
+    /// Which edge of each output the overlay is shown at, by output name; the bottom by default
+    pub positions: HashMap<String, Edge>,
+    /// Receives the output of finished commands
+    sender:        Option<Sender<(MediaKey, String)>>,
+    shown:         Option<Shown>,
+    /// Solid textures for each color, which unlike clearing can be drawn translucent
+    colors:        Option<[R::TextureId; 3]>,
+    text:          Text<R>,
+}
+
+impl<R: EngineRenderer> Osd<R> {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:    logger.clone(),
+            commands:  HashMap::new(),
+            positions: HashMap::new(),
+            sender:    None,
+            shown:     None,
+            colors:    None,
+            text:      Text::from_env(logger),
+        }
+    }
+
+    /// Start receiving the output of media key commands.
+    pub fn init <E: Engine> (&mut self, events: &LoopHandle<'static, Charlie<E>>) -> StdResult<()> {
+        let (sender, receiver) = channel();
+        events.insert_source(receiver, |event, _, state| {
+            if let ChannelEvent::Msg((key, output)) = event {
+                state.osd_level(key, &output);
+            }
+        })?;
+        self.sender = Some(sender);
+        Ok(())
+    }
+
+    /// When the overlay next needs drawing: when it starts fading, and then for each step of the fade.
+    pub fn next_wakeup (&self) -> Option<Instant> {
+        let shown = self.shown.as_ref()?;
+        let (now, end) = (Instant::now(), shown.until + FADE_TIME);
+        if now < shown.until {
+            Some(shown.until)
+        } else if now < end {
+            Some((now + FADE_TICK).min(end))
+        } else {
+            None
+        }
+    }
+
+    /// Show a label and a level on a screen, or bring the overlay up again with new contents.
+    pub fn show (&mut self, screen: ScreenId, label: impl Into<String>, level: Option<f64>) {
+        let level = level.map(|level| level.clamp(0.0, 1.0));
+        self.shown = Some(Shown { label: label.into(), level, screen, until: Instant::now() + SHOW_TIME });
+    }
+
+    /// Run the command of a key without waiting for it, showing the level it prints when it's done.
+    fn run (&self, key: MediaKey) {
+        let command = match self.commands.get(&key) {
+            Some(Some(command)) => command.clone(),
+            Some(None) => return,
+            None => key.default_command().into()
+        };
+        let (sender, logger) = match self.sender.clone() {
+            Some(sender) => (sender, self.logger.clone()),
+            None => return
+        };
+        std::thread::spawn(move || match Command::new("sh").args(["-c", &command]).output() {
+            Ok(output) => {
+                let _ = sender.send((key, String::from_utf8_lossy(&output.stdout).into()));
+            },
+            Err(e) => warn!(logger, "Could not run {command}: {e}")
+        });
+    }
+
+    /// Upload the colors and rasterize the label for this scale.
+    pub fn prepare (&mut self, renderer: &mut R, scale: f64) -> StdResult<()> {
+        let shown = match self.shown.as_ref() {
+            Some(shown) => shown,
+            None => return Ok(())
+        };
+        if self.colors.is_none() {
+            let mut texture = |color: [f32; 4]| {
+                // Premultiplied, like the renderer blends
+                let pixel = [color[0] * color[3], color[1] * color[3], color[2] * color[3], color[3]]
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                renderer.import_memory(&pixel, (1, 1).into(), false)
+            };
+            self.colors = Some([texture(BACKGROUND)?, texture(TRACK)?, texture(FILL)?]);
+        }
+        self.text.begin();
+        self.text.prepare(renderer, &shown.label, (FONT_SIZE as f64 * scale) as f32, LABEL)?;
+        Ok(())
+    }
+
+    /// Draw the overlay if it's shown on this screen, over everything but the pointer.
+    pub fn render (
+        &self,
+        frame:  &mut R::Frame<'_>,
+        output: &str,
+        screen: ScreenId,
+        size:   Size<i32, Physical>,
+        scale:  f64
+    ) -> StdResult<()> {
+        let (shown, [background, track, fill]) = match (self.shown.as_ref(), self.colors.as_ref()) {
+            (Some(shown), Some(colors)) if shown.screen == screen => (shown, colors),
+            _ => return Ok(())
+        };
+        let now = Instant::now();
+        let alpha = if now < shown.until {
+            1.0
+        } else {
+            1.0 - now.duration_since(shown.until).as_secs_f32() / FADE_TIME.as_secs_f32()
+        };
+        if alpha <= 0.0 {
+            return Ok(())
+        }
+        let area = size.to_f64().to_logical(scale);
+        let (x, y) = match self.positions.get(output).copied().unwrap_or(Edge::Bottom) {
+            Edge::Top    => ((area.w - WIDTH) / 2.0, MARGIN),
+            Edge::Bottom => ((area.w - WIDTH) / 2.0, area.h - MARGIN - HEIGHT),
+            Edge::Left   => (MARGIN, (area.h - HEIGHT) / 2.0),
+            Edge::Right  => (area.w - MARGIN - WIDTH, (area.h - HEIGHT) / 2.0),
+        };
+        let draw = |frame: &mut R::Frame<'_>, texture: &R::TextureId, rect: Rectangle<f64, Logical>| {
+            let dest = rect.to_physical(scale).to_i32_round();
+            frame.render_texture_from_to(
+                texture,
+                Rectangle::from_loc_and_size((0.0, 0.0), (1.0, 1.0)),
+                dest,
+                &[Rectangle::from_loc_and_size((0, 0), dest.size)],
+                Transform::Normal,
+                alpha
+            )
+        };
+        draw(frame, background, Rectangle::from_loc_and_size((x, y), (WIDTH, HEIGHT)))?;
+        let origin = Point::<f64, Logical>::from((x + PADDING, y + PADDING)).to_physical(scale).to_i32_round();
+        let font_size = (FONT_SIZE as f64 * scale) as f32;
+        self.text.draw_text_faded(frame, origin, &shown.label, font_size, LABEL, alpha)?;
+        if let Some(level) = shown.level {
+            let bar = Rectangle::<f64, Logical>::from_loc_and_size(
+                (x + PADDING, y + HEIGHT - PADDING - BAR_HEIGHT), (WIDTH - PADDING * 2.0, BAR_HEIGHT)
+            );
+            draw(frame, track, bar)?;
+            if level > 0.0 {
+                draw(frame, fill, Rectangle::from_loc_and_size(bar.loc, (bar.size.w * level, bar.size.h)))?;
+            }
+        }
+        Ok(())
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Run the command for a media key, unless a script handles the key with `on_media_key`.
+    pub fn media_key (&mut self, key: MediaKey) {
+        if self.scripts.hook("on_media_key", vec![key.name().into()]) {
+            return
+        }
+        self.osd.run(key);
+    }
+
+    /// Show the level that the command of a media key printed.
+    fn osd_level (&mut self, key: MediaKey, output: &str) {
+        let (level, muted) = match parse_level(output) {
+            Some(level) => level,
+            None => {
+                debug!(self.logger, "No level in the output of {}: {output:?}", key.name());
+                return
+            }
+        };
+        let (label, level) = if muted {
+            (format!("{} muted", key.label()), 0.0)
+        } else {
+            (format!("{} {:.0}%", key.label(), level * 100.0), level)
+        };
+        self.osd.show(self.desktop.active_screen, label, Some(level));
+        self.redraw.request();
+    }
+
+    /// Run this shell command for a media key, which should print the new level,
+    /// or run nothing for the key if None.
+    pub fn media_key_command (mut self, key: MediaKey, command: Option<&str>) -> CharlieResult<Self> {
+        self.osd.commands.insert(key, command.map(Into::into));
+        Ok(self)
+    }
+
+    /// Show the volume and brightness overlay at this edge of an output.
+    pub fn osd_position (mut self, output: impl Into<String>, edge: Edge) -> CharlieResult<Self> {
+        self.osd.positions.insert(output.into(), edge);
+        Ok(self)
+    }
+
+}
@@ -0,0 +1,78 @@
+use super::prelude::*;
+
+use super::stacking::StackLayer;
+use super::transaction::Change;
+
+/// How wide a picture-in-picture window is, as a part of the width of its screen
+const PIP_WIDTH: f64 = 0.25;
+
+/// Smallest width of a picture-in-picture window, in logical pixels
+const PIP_MIN_WIDTH: f64 = 160.0;
+
+/// Where a window was before it was made picture-in-picture, to put it back.
+#[derive(Debug, Clone, Copy)]
+pub struct Pip {
+    pub restore_center: Point<f64, Logical>,
+    pub restore_size:   Size<f64, Logical>,
+    pub restore_layer:  StackLayer,
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Shrink a window into a corner of its screen, above other windows and on every workspace,
+    /// e.g. to keep watching a video while doing something else; or put it back as it was.
+    pub fn window_toggle_pip (&mut self, surface: &WlSurface) {
+        let window = match self.desktop.window_state_mut(surface) {
+            Some(window) if window.fullscreen.is_none() => window,
+            _ => return
+        };
+        if let Some(pip) = window.pip.take() {
+            debug!(self.logger, "Window {} leaves picture-in-picture", window.id);
+            window.layer = pip.restore_layer;
+            let center = pip.restore_center;
+            let size = Some(pip.restore_size.to_i32_round());
+            self.transaction_start(vec![Change { surface: surface.clone(), center, size }]);
+            return
+        }
+        let size = window.size;
+        debug!(self.logger, "Window {} enters picture-in-picture", window.id);
+        window.pip = Some(Pip { restore_center: window.center, restore_size: size, restore_layer: window.layer });
+        window.layer = StackLayer::Above;
+        let rect = match self.work_rect(self.window_screen(surface)) {
+            Some(rect) => rect,
+            None => return
+        };
+        // Keep the window's aspect ratio
+        let width = (rect.size.w * PIP_WIDTH).max(PIP_MIN_WIDTH).min(rect.size.w);
+        let height = if size.w > 0.0 { (width * size.h / size.w).min(rect.size.h) } else { width };
+        let center = (rect.loc.x + rect.size.w - width, rect.loc.y + rect.size.h - height).into();
+        let size = Some(Size::<f64, Logical>::from((width, height)).to_i32_round());
+        self.desktop.raise(surface);
+        self.transaction_start(vec![Change { surface: surface.clone(), center, size }]);
+    }
+
+    /// Move a picture-in-picture window into the corner of its screen that it's nearest to.
+    pub fn pip_snap (&mut self, surface: &WlSurface) {
+        let (center, size) = match self.desktop.window_state(surface) {
+            Some(window) if window.pip.is_some() => (window.center, window.size),
+            _ => return
+        };
+        let rect = match self.work_rect(self.window_screen(surface)) {
+            Some(rect) => rect,
+            None => return
+        };
+        let middle = (center.x + size.w / 2.0, center.y + size.h / 2.0);
+        let x = if middle.0 < rect.loc.x + rect.size.w / 2.0 {
+            rect.loc.x
+        } else {
+            rect.loc.x + rect.size.w - size.w
+        };
+        let y = if middle.1 < rect.loc.y + rect.size.h / 2.0 {
+            rect.loc.y
+        } else {
+            rect.loc.y + rect.size.h - size.h
+        };
+        self.transaction_start(vec![Change { surface: surface.clone(), center: (x, y).into(), size: None }]);
+    }
+
+}
@@ -0,0 +1,59 @@
+use super::prelude::*;
+
+use smithay::{
+    output::{PhysicalProperties, Subpixel, Mode},
+    reexports::wayland_server::backend::GlobalId,
+};
+
+/// How often to wake up and send frame callbacks while there are no real outputs
+pub const PLACEHOLDER_TICK: Duration = Duration::from_millis(100);
+
+/// A virtual output that stands in for the real ones while there are none,
+/// so that clients keep running (slowly) until an output comes back.
+pub struct Placeholder {
+    logger: Logger,
+    active: Option<(Output, GlobalId)>,
+}
+
+impl Placeholder {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), active: None }
+    }
+
+    pub fn output (&self) -> Option<&Output> {
+        self.active.as_ref().map(|(output, _)| output)
+    }
+
+    /// Create and advertise the placeholder output, unless already done.
+    pub fn enable <D> (&mut self, display: &DisplayHandle)
+    where
+        D: wayland_server::GlobalDispatch<
+            wayland_server::protocol::wl_output::WlOutput,
+            smithay::wayland::output::WlOutputData
+        > + 'static
+    {
+        if self.active.is_some() {
+            return
+        }
+        warn!(self.logger, "No outputs left, switching to placeholder output");
+        let output = Output::new("Placeholder".into(), PhysicalProperties {
+            size: (0, 0).into(), subpixel: Subpixel::Unknown,
+            make: "Charlie".into(), model: "Placeholder".into()
+        }, self.logger.clone());
+        let mode = Mode { size: (1920, 1080).into(), refresh: 10_000 };
+        output.change_current_state(Some(mode), None, None, Some((0, 0).into()));
+        output.set_preferred(mode);
+        let global = output.create_global::<D>(display);
+        self.active = Some((output, global));
+    }
+
+    /// Remove the placeholder output once a real one is back.
+    pub fn disable <D: 'static> (&mut self, display: &DisplayHandle) {
+        if let Some((_, global)) = self.active.take() {
+            info!(self.logger, "Output available again, removing placeholder output");
+            display.remove_global::<D>(global);
+        }
+    }
+
+}
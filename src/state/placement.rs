@@ -0,0 +1,188 @@
+use super::prelude::*;
+
+/// How far each window is moved from the previous one when cascading, at scale 1
+const CASCADE_STEP: f64 = 32.0;
+
+/// Where a new window goes, unless it's put back where it was last time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowPlacement {
+    /// Where it covers as little of the other windows as it can,
+    /// preferring the top left
+    Smart,
+    /// Below and to the right of the last window placed this way
+    Cascade,
+    /// In the middle of the active screen
+    Center,
+    /// Centered under the pointer, as far as it fits on the screen
+    UnderPointer,
+    /// Centered over the window it's a dialog of, or in the middle of the screen if it has none
+    Parent,
+}
+
+impl WindowPlacement {
+    pub fn name (&self) -> &'static str {
+        match self {
+            Self::Smart        => "smart",
+            Self::Cascade      => "cascade",
+            Self::Center       => "center",
+            Self::UnderPointer => "under-pointer",
+            Self::Parent       => "parent",
+        }
+    }
+}
+
+impl std::str::FromStr for WindowPlacement {
+    type Err = Box<dyn Error>;
+    fn from_str (placement: &str) -> StdResult<Self> {
+        Ok(match placement {
+            "smart"         => Self::Smart,
+            "cascade"       => Self::Cascade,
+            "center"        => Self::Center,
+            "under-pointer" => Self::UnderPointer,
+            "parent"        => Self::Parent,
+            _ => return Err(format!("unknown window placement: {placement}").into())
+        })
+    }
+}
+
+/// Area in which two rectangles overlap.
+fn overlap (a: Rectangle<f64, Logical>, b: Rectangle<f64, Logical>) -> f64 {
+    let w = (a.loc.x + a.size.w).min(b.loc.x + b.size.w) - a.loc.x.max(b.loc.x);
+    let h = (a.loc.y + a.size.h).min(b.loc.y + b.size.h) - a.loc.y.max(b.loc.y);
+    w.max(0.0) * h.max(0.0)
+}
+
+/// Move a point so that a window of this size there stays within an area, as far as it fits.
+fn clamp (point: Point<f64, Logical>, size: Size<f64, Logical>, area: Rectangle<f64, Logical>)
+    -> Point<f64, Logical>
+{
+    (
+        point.x.min(area.loc.x + area.size.w - size.w).max(area.loc.x),
+        point.y.min(area.loc.y + area.size.h - size.h).max(area.loc.y),
+    ).into()
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Choose how windows that don't match a rule with a placement are placed.
+    /// Dialogs are centered on their parents unless a rule says otherwise.
+    pub fn window_placement (mut self, placement: WindowPlacement) -> CharlieResult<Self> {
+        self.desktop.placement = placement;
+        Ok(self)
+    }
+
+    /// Put a newly mapped window where its placement says.
+    pub fn window_place (&mut self, surface: &WlSurface) {
+        let (placement, parent, size) = match self.desktop.window_state(surface) {
+            Some(window) => (window.placement, window.parent_surface(), window.window.geometry().size.to_f64()),
+            None => return
+        };
+        let placement = match (placement, &parent) {
+            (Some(placement), _) => placement,
+            (None, Some(_)) => WindowPlacement::Parent,
+            (None, None) => self.desktop.placement
+        };
+        let pointer = self.input.pointers.first().map(|pointer| (pointer.screen, pointer.location));
+        let screen = match (placement, pointer) {
+            (WindowPlacement::UnderPointer, Some((screen, _))) => screen,
+            _ => self.desktop.active_screen
+        };
+        let area = match self.work_rect(screen) {
+            Some(area) => area,
+            None => return
+        };
+        let middle = Point::<f64, Logical>::from((
+            area.loc.x + (area.size.w - size.w) / 2.0,
+            area.loc.y + (area.size.h - size.h) / 2.0
+        ));
+        let center = match placement {
+            WindowPlacement::Parent if parent.is_some() => {
+                self.desktop.center_on_parent(surface);
+                return
+            },
+            WindowPlacement::Parent | WindowPlacement::Center => middle,
+            WindowPlacement::UnderPointer => match (pointer, self.desktop.screens.get(screen)) {
+                (Some((_, location)), Some(screen)) => clamp((
+                    location.x - screen.center.x - size.w / 2.0,
+                    location.y - screen.center.y - size.h / 2.0
+                ).into(), size, area),
+                _ => middle
+            },
+            WindowPlacement::Cascade => self.place_cascade(surface, size, area),
+            WindowPlacement::Smart => self.place_smart(surface, size, area),
+        };
+        debug!(self.logger, "Placing {surface:?} {} at {center:?}", placement.name());
+        if let Some(window) = self.desktop.window_state_mut(surface) {
+            window.center = center;
+        }
+    }
+
+    /// The other visible windows, as the areas they cover.
+    fn place_others (&self, surface: &WlSurface) -> Vec<Rectangle<f64, Logical>> {
+        self.desktop.windows_visible()
+            .filter(|window| window.window.toplevel().wl_surface() != surface)
+            .map(|window| Rectangle::from_loc_and_size(window.center, window.window.geometry().size.to_f64()))
+            .collect()
+    }
+
+    /// The first step down the diagonal from the top left of the area where no window starts,
+    /// starting over a little to the right once the window would go past the area.
+    fn place_cascade (&self, surface: &WlSurface, size: Size<f64, Logical>, area: Rectangle<f64, Logical>)
+        -> Point<f64, Logical>
+    {
+        let others = self.place_others(surface);
+        let taken = |point: Point<f64, Logical>| others.iter().any(|other|
+            (other.loc.x - point.x).abs() < CASCADE_STEP / 2.0 && (other.loc.y - point.y).abs() < CASCADE_STEP / 2.0
+        );
+        let rows = ((area.size.h - size.h) / CASCADE_STEP).floor().max(0.0) as usize + 1;
+        let columns = ((area.size.w - size.w) / CASCADE_STEP).floor().max(0.0) as usize + 1;
+        for column in 0..columns {
+            for step in 0..rows.min(columns - column) {
+                let point = Point::<f64, Logical>::from((
+                    area.loc.x + (column + step) as f64 * CASCADE_STEP,
+                    area.loc.y + step as f64 * CASCADE_STEP
+                ));
+                if !taken(point) {
+                    return point
+                }
+            }
+        }
+        area.loc
+    }
+
+    /// The place that overlaps the other windows least, trying places next to their edges
+    /// and against the edges of the area, topmost first, then leftmost.
+    fn place_smart (&self, surface: &WlSurface, size: Size<f64, Logical>, area: Rectangle<f64, Logical>)
+        -> Point<f64, Logical>
+    {
+        let others = self.place_others(surface);
+        let gap = self.desktop.gaps.inner as f64;
+        let mut xs = vec![area.loc.x, area.loc.x + area.size.w - size.w];
+        let mut ys = vec![area.loc.y, area.loc.y + area.size.h - size.h];
+        for other in others.iter() {
+            xs.push(other.loc.x + other.size.w + gap);
+            xs.push(other.loc.x - size.w - gap);
+            ys.push(other.loc.y + other.size.h + gap);
+            ys.push(other.loc.y - size.h - gap);
+        }
+        let mut best: Option<(f64, Point<f64, Logical>)> = None;
+        for y in ys.iter() {
+            for x in xs.iter() {
+                let point = clamp((*x, *y).into(), size, area);
+                let covered: f64 = others.iter()
+                    .map(|other| overlap(Rectangle::from_loc_and_size(point, size), *other))
+                    .sum();
+                let better = match best {
+                    None => true,
+                    Some((least, at)) => covered < least
+                        || covered == least && (point.y, point.x) < (at.y, at.x)
+                };
+                if better {
+                    best = Some((covered, point));
+                }
+            }
+        }
+        best.map(|(_, point)| point).unwrap_or(area.loc)
+    }
+
+}
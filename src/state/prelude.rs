@@ -7,6 +7,7 @@ pub(crate) use smithay::{
         renderer::{
             buffer_dimensions,
             ImportAll,
+            Texture,
             utils::{
                 //on_commit_buffer_handler,
                 //RendererSurfaceState,
@@ -35,7 +36,7 @@ pub(crate) use smithay::{
     },
     reexports::{
         wayland_protocols::xdg::shell::server::xdg_toplevel::{
-            //State      as XdgToplevelState,
+            State      as XdgToplevelState,
             ResizeEdge as XdgToplevelResizeEdge
         },
         wayland_server::{
@@ -44,6 +45,7 @@ pub(crate) use smithay::{
             //Resource,
             protocol::{
                 wl_seat::WlSeat,
+                wl_output::WlOutput,
                 //wl_buffer,
                 wl_surface::WlSurface
             }
@@ -81,6 +83,7 @@ pub(crate) use smithay::{
             //is_sync_subsurface,
             add_destruction_hook,
             with_states,
+            SurfaceAttributes,
         },
         //input_method::InputMethodSeat,
         data_device::{
@@ -93,6 +96,7 @@ pub(crate) use smithay::{
             PopupSurface,
             PositionerState,
             ToplevelSurface,
+            XdgToplevelSurfaceData,
             XdgShellHandler,
             XdgShellState,
         },
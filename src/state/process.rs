@@ -0,0 +1,364 @@
+use super::prelude::*;
+
+use std::process::{Command, Child, ExitStatus};
+
+use smithay::reexports::calloop::{RegistrationToken, signals::{Signals, Signal}, timer::{Timer, TimeoutAction}};
+
+use nix::{sys::signal::{kill, Signal as NixSignal}, unistd::Pid};
+
+/// How long to wait for children to exit after SIGTERM before killing them
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+/// How long to wait before the first respawn of a process, doubling with each further one
+const RESTART_DELAY: Duration = Duration::from_millis(250);
+
+/// The longest wait before respawning a process
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(8);
+
+/// How many times a process may be respawned within [RESTART_WINDOW] before it's given up on
+const RESTART_LIMIT: usize = 5;
+
+/// How far back respawns count towards [RESTART_LIMIT] and the delay
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// What to do when a supervised process exits
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Restart {
+    /// Let it go
+    Never,
+    /// Respawn it if it exited unsuccessfully or was killed by a signal
+    OnCrash,
+    /// Always respawn it
+    Always,
+}
+
+/// A command to run after the compositor is ready
+#[derive(Debug, Clone)]
+pub struct Startup {
+    pub cmd:     String,
+    pub args:    Vec<String>,
+    /// Extra environment variables for this process only
+    pub env:     Vec<(String, String)>,
+    pub restart: Restart,
+    /// The output that this process accompanies, if it runs for one output only
+    pub output:  Option<String>,
+}
+
+impl Startup {
+    pub fn new (cmd: impl AsRef<str>, args: &[&str], restart: Restart) -> Self {
+        Self {
+            cmd:    cmd.as_ref().into(),
+            args:   args.iter().map(|arg|arg.to_string()).collect(),
+            env:    vec![],
+            restart,
+            output: None,
+        }
+    }
+
+    /// Whether this runs the same command as another entry, for the same output.
+    pub fn same (&self, other: &Self) -> bool {
+        self.output == other.output && self.cmd == other.cmd && self.args == other.args
+    }
+}
+
+/// A running child process and the entry it was spawned from
+pub struct Supervised {
+    pub entry:    Startup,
+    pub child:    Child,
+    /// When the entry was respawned lately, oldest first
+    pub restarts: Vec<Instant>,
+}
+
+/// A process that exited and is to be spawned again once its delay has passed
+pub struct Respawn {
+    pub entry:    Startup,
+    pub restarts: Vec<Instant>,
+    pub delay:    Duration,
+    /// The timer that respawns it, once it's scheduled
+    pub token:    Option<RegistrationToken>,
+}
+
+/// Spawns, tracks and reaps the compositor's child processes.
+pub struct Processes {
+    logger:         Logger,
+    /// Commands to run after successful initialization
+    pub startup:    Vec<Startup>,
+    /// Commands to run for each output they accompany, while it's connected
+    pub companions: Vec<Startup>,
+    /// Whether the startup commands were run, and so companions may run too
+    pub started:    bool,
+    /// Environment variables injected into every child
+    pub env:        Vec<(String, String)>,
+    /// Currently running children, by PID
+    pub running:    HashMap<u32, Supervised>,
+    /// Children that exited and are waiting to be respawned, by the number of their respawn
+    pub respawning: HashMap<u64, Respawn>,
+    /// The number of the next respawn
+    respawns:       u64,
+    /// Whether children are being stopped, and so none may be respawned
+    pub stopping:   bool,
+}
+
+impl Processes {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:     logger.clone(),
+            startup:    vec![],
+            companions: vec![],
+            started:    false,
+            env:        vec![],
+            running:    HashMap::new(),
+            respawning: HashMap::new(),
+            respawns:   0,
+            stopping:   false,
+        }
+    }
+
+    /// Reap zombies whenever a child exits.
+    pub fn init <E: Engine> (events: &LoopHandle<'static, Charlie<E>>) -> StdResult<()> {
+        let signals = Signals::new(&[Signal::SIGCHLD])?;
+        events.insert_source(signals, |_, _, state| state.processes_reap())?;
+        Ok(())
+    }
+
+    /// Set an environment variable for all subsequently spawned children.
+    pub fn set_env (&mut self, key: impl Into<String>, val: impl Into<String>) {
+        let key = key.into();
+        self.env.retain(|(k, _)| *k != key);
+        self.env.push((key, val.into()));
+    }
+
+    /// Spawn all startup commands.
+    pub fn spawn_startup (&mut self) -> StdResult<()> {
+        for entry in self.startup.clone().into_iter() {
+            self.spawn(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Spawn a command and start tracking it.
+    pub fn spawn (&mut self, entry: Startup) -> StdResult<u32> {
+        self.spawn_again(entry, vec![])
+    }
+
+    /// Spawn a command that may have been respawned before, and start tracking it.
+    fn spawn_again (&mut self, entry: Startup, restarts: Vec<Instant>) -> StdResult<u32> {
+        debug!(self.logger, "Spawning {} {:?}", entry.cmd, entry.args);
+        let child = Command::new(&entry.cmd)
+            .args(&entry.args)
+            .envs(self.env.iter().cloned())
+            .envs(entry.env.iter().cloned())
+            .spawn()?;
+        let pid = child.id();
+        self.running.insert(pid, Supervised { entry, child, restarts });
+        Ok(pid)
+    }
+
+    /// Spawn a process again once its delay has passed, unless children are being stopped.
+    pub fn respawn (&mut self, respawn: Respawn) {
+        if self.stopping {
+            return
+        }
+        let Respawn { entry, mut restarts, .. } = respawn;
+        restarts.push(Instant::now());
+        if let Err(e) = self.spawn_again(entry, restarts) {
+            error!(self.logger, "Could not respawn: {e}");
+        }
+    }
+
+    /// Collect the exit status of every child that has exited,
+    /// returning the ones that are to be respawned and when.
+    /// The wait doubles with each respawn within [RESTART_WINDOW],
+    /// and a process that keeps exiting is given up on after [RESTART_LIMIT] of them.
+    pub fn reap (&mut self) -> Vec<Respawn> {
+        let mut respawns = vec![];
+        let mut exited: Vec<(u32, ExitStatus)> = vec![];
+        for (pid, process) in self.running.iter_mut() {
+            match process.child.try_wait() {
+                Ok(Some(status)) => exited.push((*pid, status)),
+                Ok(None) => {},
+                Err(e) => warn!(self.logger, "Could not wait for {pid}: {e}")
+            }
+        }
+        for (pid, status) in exited {
+            let Supervised { entry, mut restarts, .. } = self.running.remove(&pid).unwrap();
+            info!(self.logger, "{} ({pid}) exited: {status}", entry.cmd);
+            let respawn = match entry.restart {
+                Restart::Never   => false,
+                Restart::OnCrash => !status.success(),
+                Restart::Always  => true,
+            };
+            if !respawn || self.stopping {
+                continue
+            }
+            restarts.retain(|time| time.elapsed() < RESTART_WINDOW);
+            if restarts.len() >= RESTART_LIMIT {
+                error!(self.logger, "{} exited {} times in {:?}, not respawning it",
+                    entry.cmd, restarts.len() + 1, RESTART_WINDOW);
+                continue
+            }
+            let delay = RESTART_DELAY.saturating_mul(1 << restarts.len()).min(RESTART_DELAY_MAX);
+            debug!(self.logger, "Respawning {} in {delay:?}", entry.cmd);
+            respawns.push(Respawn { entry, restarts, delay, token: None });
+        }
+        respawns
+    }
+
+    /// The companions of an output that are neither running nor waiting to be respawned.
+    pub fn companions_due (&self, output: &str) -> Vec<Startup> {
+        self.companions.iter()
+            .filter(|entry| entry.output.as_deref() == Some(output))
+            .filter(|entry| !self.running.values().any(|process| process.entry.same(entry)))
+            .filter(|entry| !self.respawning.values().any(|respawn| respawn.entry.same(entry)))
+            .cloned()
+            .collect()
+    }
+
+    /// Stop waiting to respawn the companions of an output,
+    /// returning the timers that are to be removed.
+    pub fn companions_cancel (&mut self, output: &str) -> Vec<RegistrationToken> {
+        let mut tokens = vec![];
+        self.respawning.retain(|_, respawn| {
+            if respawn.entry.output.as_deref() != Some(output) {
+                return true
+            }
+            debug!(self.logger, "Not respawning {} for {output}", respawn.entry.cmd);
+            tokens.extend(respawn.token.take());
+            false
+        });
+        tokens
+    }
+
+    /// Ask a child to terminate, without respawning it.
+    pub fn terminate (&mut self, pid: u32) {
+        if let Some(process) = self.running.get_mut(&pid) {
+            debug!(self.logger, "Terminating {} ({pid})", process.entry.cmd);
+            process.entry.restart = Restart::Never;
+            let _ = kill(Pid::from_raw(pid as i32), NixSignal::SIGTERM);
+        }
+    }
+
+    /// Ask all children to terminate, killing the ones that don't comply in time.
+    pub fn shutdown (&mut self) {
+        self.stopping = true;
+        if self.running.is_empty() {
+            return
+        }
+        for (pid, process) in self.running.iter_mut() {
+            debug!(self.logger, "Terminating {} ({pid})", process.entry.cmd);
+            // Don't respawn anything we're in the process of killing
+            process.entry.restart = Restart::Never;
+            let _ = kill(Pid::from_raw(*pid as i32), NixSignal::SIGTERM);
+        }
+        let deadline = Instant::now() + SHUTDOWN_GRACE;
+        while !self.running.is_empty() && Instant::now() < deadline {
+            self.running.retain(|_, process| !matches!(process.child.try_wait(), Ok(Some(_))));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        for (pid, mut process) in self.running.drain() {
+            warn!(self.logger, "Killing {} ({pid})", process.entry.cmd);
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+        }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Reap the children that exited, and schedule the ones that are to be respawned.
+    fn processes_reap (&mut self) {
+        for respawn in self.processes.reap() {
+            schedule(&self.handle, &mut self.processes, |state| &mut state.processes, respawn);
+        }
+    }
+
+}
+
+/// Respawn a process once its delay has passed, keeping it with its timer
+/// among the processes that are waiting to be respawned until then.
+fn schedule <T> (
+    handle:    &LoopHandle<'static, T>,
+    processes: &mut Processes,
+    of:        fn (&mut T) -> &mut Processes,
+    mut respawn: Respawn,
+) {
+    let id = processes.respawns;
+    processes.respawns += 1;
+    let timer = Timer::from_duration(respawn.delay);
+    match handle.insert_source(timer, move |_, _, state| {
+        let processes = of(state);
+        if let Some(respawn) = processes.respawning.remove(&id) {
+            processes.respawn(respawn);
+        }
+        TimeoutAction::Drop
+    }) {
+        Ok(token) => {
+            respawn.token = Some(token);
+            processes.respawning.insert(id, respawn);
+        },
+        Err(e) => warn!(processes.logger, "Could not schedule respawn: {e}")
+    }
+}
+
+impl Drop for Processes {
+    fn drop (&mut self) {
+        self.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use smithay::reexports::calloop::EventLoop;
+
+    fn processes () -> Processes {
+        let mut processes = Processes::new(&Logger::root(slog::Discard, o!()));
+        let mut entry = Startup::new("true", &[], Restart::OnCrash);
+        entry.output = Some("HDMI-1".into());
+        processes.companions.push(entry);
+        processes
+    }
+
+    fn respawn (processes: &Processes) -> Respawn {
+        let entry = processes.companions[0].clone();
+        Respawn { entry, restarts: vec![], delay: Duration::ZERO, token: None }
+    }
+
+    #[test]
+    fn companions_due_unless_respawning () {
+        let mut processes = processes();
+        let mut events = EventLoop::<Processes>::try_new().unwrap();
+        assert_eq!(processes.companions_due("HDMI-1").len(), 1);
+        assert_eq!(processes.companions_due("DP-1").len(), 0);
+        // A companion that crashed isn't started a second time when its output returns
+        let respawn = respawn(&processes);
+        schedule(&events.handle(), &mut processes, |processes| processes, respawn);
+        assert_eq!(processes.respawning.len(), 1);
+        assert_eq!(processes.companions_due("HDMI-1").len(), 0);
+        events.dispatch(Duration::from_millis(10), &mut processes).unwrap();
+        assert_eq!(processes.respawning.len(), 0);
+        assert_eq!(processes.running.len(), 1);
+    }
+
+    #[test]
+    fn companions_cancel_respawn () {
+        let mut processes = processes();
+        let mut events = EventLoop::<Processes>::try_new().unwrap();
+        let respawn = respawn(&processes);
+        schedule(&events.handle(), &mut processes, |processes| processes, respawn);
+        assert!(processes.companions_cancel("DP-1").is_empty());
+        // The output went away before the companion was respawned
+        let tokens = processes.companions_cancel("HDMI-1");
+        assert_eq!(tokens.len(), 1);
+        for token in tokens {
+            events.handle().remove(token);
+        }
+        assert!(processes.respawning.is_empty());
+        assert_eq!(processes.companions_due("HDMI-1").len(), 1);
+        events.dispatch(Duration::from_millis(10), &mut processes).unwrap();
+        assert!(processes.running.is_empty());
+    }
+}
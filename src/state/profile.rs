@@ -0,0 +1,117 @@
+use super::prelude::*;
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+/// Spans kept before the oldest ones are dropped, so that a forgotten profile doesn't eat memory
+const MAX_SPANS: usize = 1 << 20;
+
+/// A phase of the main loop that took some time.
+struct Span {
+    name:   &'static str,
+    /// Output the phase was about, if any
+    screen: Option<ScreenId>,
+    /// Microseconds since the profile started
+    start:  u128,
+    /// Microseconds that the phase took
+    length: u128,
+}
+
+/// Measures how long the phases of the main loop take, such as input dispatch, client
+/// dispatch, layout and rendering each output, and writes them out in the Chrome trace
+/// event format, which can be opened with `chrome://tracing` or Perfetto.
+///
+/// While not profiling, measuring a phase costs a branch.
+pub struct Profiler {
+    logger: Logger,
+    /// Where the spans are written once profiling stops
+    path:   Option<PathBuf>,
+    /// When profiling started
+    start:  Instant,
+    spans:  Vec<Span>,
+}
+
+impl Profiler {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), path: None, start: Instant::now(), spans: vec![] }
+    }
+
+    /// Whether spans are being collected.
+    pub fn enabled (&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Start timing a phase. Returns None while not profiling.
+    #[inline]
+    pub fn begin (&self) -> Option<Instant> {
+        self.enabled().then(Instant::now)
+    }
+
+    /// Finish timing a phase that was started with [Profiler::begin].
+    pub fn end (&mut self, begun: Option<Instant>, name: &'static str, screen: Option<ScreenId>) {
+        let begun = match begun {
+            Some(begun) if self.enabled() => begun,
+            _ => return
+        };
+        if self.spans.len() >= MAX_SPANS {
+            self.spans.drain(..MAX_SPANS / 2);
+        }
+        self.spans.push(Span {
+            name,
+            screen,
+            start:  begun.saturating_duration_since(self.start).as_micros(),
+            length: begun.elapsed().as_micros(),
+        });
+    }
+
+    /// Start collecting spans, to be written to a file when stopped.
+    pub fn start (&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        info!(self.logger, "Profiling to {}", path.display());
+        self.path  = Some(path);
+        self.start = Instant::now();
+        self.spans.clear();
+    }
+
+    /// Stop collecting spans and write them out.
+    pub fn stop (&mut self) -> StdResult<()> {
+        let path = match self.path.take() {
+            Some(path) => path,
+            None => return Ok(())
+        };
+        let spans = std::mem::take(&mut self.spans);
+        let mut file = BufWriter::new(File::create(&path)?);
+        write!(file, "{{\"traceEvents\":[")?;
+        for (index, span) in spans.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            write!(
+                file,
+                "{separator}\n{{\"name\":\"{}\",\"cat\":\"charlie\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":{},\"tid\":{}",
+                span.name, span.start, span.length, std::process::id(), span.screen.map(|s| s + 1).unwrap_or(0)
+            )?;
+            match span.screen {
+                Some(screen) => write!(file, ",\"args\":{{\"screen\":{screen}}}}}")?,
+                None => write!(file, "}}")?
+            }
+        }
+        writeln!(file, "\n],\"displayTimeUnit\":\"ms\"}}")?;
+        file.flush()?;
+        info!(self.logger, "Wrote {} spans to {}", spans.len(), path.display());
+        Ok(())
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Measure the phases of the main loop from startup, writing them out on exit.
+    pub fn profile (mut self, path: impl Into<PathBuf>) -> CharlieResult<Self> {
+        self.profiler.start(path);
+        Ok(self)
+    }
+
+}
@@ -0,0 +1,186 @@
+use super::prelude::*;
+
+use std::collections::VecDeque;
+
+use smithay::reexports::wayland_server::Resource;
+
+use super::identity::ClientState;
+
+/// Messages kept in the log, newest replacing oldest
+const MAX_ENTRIES: usize = 4096;
+
+/// How much of the arguments of a message is kept
+const MAX_ARGS: usize = 160;
+
+/// Which interfaces are logged, written like `wl_surface,xdg_*,-xdg_popup`:
+/// names or prefixes ending in `*` to log, and ones after a `-` to leave out.
+/// With nothing to log given, all interfaces that aren't left out are logged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProtocolFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl ProtocolFilter {
+
+    pub fn matches (&self, interface: &str) -> bool {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => interface.starts_with(prefix),
+            None => interface == pattern
+        };
+        (self.include.is_empty() || self.include.iter().any(matches)) && !self.exclude.iter().any(matches)
+    }
+
+}
+
+impl std::str::FromStr for ProtocolFilter {
+    type Err = Box<dyn Error>;
+    fn from_str (filter: &str) -> StdResult<Self> {
+        let mut parsed = Self::default();
+        for pattern in filter.split(',').map(str::trim).filter(|pattern| !pattern.is_empty()) {
+            match pattern.strip_prefix('-') {
+                Some("") => return Err(format!("empty pattern in filter: {filter}").into()),
+                Some(exclude) => parsed.exclude.push(exclude.into()),
+                None => parsed.include.push(pattern.into())
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+impl std::fmt::Display for ProtocolFilter {
+    fn fmt (&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let patterns: Vec<String> = self.include.iter().cloned()
+            .chain(self.exclude.iter().map(|pattern| format!("-{pattern}")))
+            .collect();
+        write!(f, "{}", if patterns.is_empty() { "*".into() } else { patterns.join(",") })
+    }
+}
+
+/// A request that a client sent.
+struct Entry {
+    /// When it was dispatched
+    time:   Instant,
+    /// Process of the client, if known
+    pid:    Option<i32>,
+    /// Like `wl_surface@12`
+    object: String,
+    /// The request and its arguments, cut short
+    args:   String,
+}
+
+/// Records the requests that clients send, for debugging them without WAYLAND_DEBUG
+/// on the client side. Requests are kept in a ring buffer and listed over IPC.
+///
+/// Requests to the protocols delegated with `wayland_delegate` are logged, which are the core
+/// protocols and xdg-shell. Events aren't, since they're sent straight from the handlers,
+/// without going through anything that could record them.
+pub struct ProtocolLog {
+    logger:      Logger,
+    /// Whether requests are being recorded
+    pub enabled: bool,
+    pub filter:  ProtocolFilter,
+    /// When logging started, which entry times are relative to
+    started:     Instant,
+    entries:     VecDeque<Entry>,
+}
+
+impl ProtocolLog {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:  logger.clone(),
+            enabled: false,
+            filter:  ProtocolFilter::default(),
+            started: Instant::now(),
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Start or stop recording. Starting again begins a new log.
+    pub fn set_enabled (&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.clear();
+        }
+        info!(self.logger, "Protocol log {}", if enabled { "on" } else { "off" });
+        self.enabled = enabled;
+    }
+
+    pub fn clear (&mut self) {
+        self.started = Instant::now();
+        self.entries.clear();
+    }
+
+    /// Record a request, if it's to a logged interface.
+    pub fn request <I: Resource> (&mut self, client: &Client, resource: &I, request: &impl std::fmt::Debug) {
+        if !self.enabled || !self.filter.matches(I::interface().name) {
+            return
+        }
+        let mut args = format!("{request:?}");
+        if args.len() > MAX_ARGS {
+            let mut end = MAX_ARGS;
+            while !args.is_char_boundary(end) {
+                end -= 1;
+            }
+            args.truncate(end);
+            args.push('…');
+        }
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            time:   Instant::now(),
+            pid:    client.get_data::<ClientState>().and_then(|state| state.identity.pid),
+            object: resource.id().to_string(),
+            args,
+        });
+    }
+
+    /// List the recorded requests, oldest first, optionally only those of one process:
+    /// milliseconds since logging started, the pid of the client, the object and the request.
+    pub fn report (&self, pid: Option<i32>) -> String {
+        self.entries.iter()
+            .filter(|entry| pid.is_none() || entry.pid == pid)
+            .map(|entry| self.line(entry))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The latest recorded requests, oldest first, in the same form as in the report.
+    pub fn recent (&self, count: usize) -> Vec<String> {
+        self.entries.iter().skip(self.entries.len().saturating_sub(count))
+            .map(|entry| self.line(entry))
+            .collect()
+    }
+
+    fn line (&self, entry: &Entry) -> String {
+        format!(
+            "{:.3} {} {} {}",
+            (entry.time - self.started).as_secs_f64() * 1000.0,
+            entry.pid.map(|pid| pid.to_string()).unwrap_or_else(|| "?".into()),
+            entry.object,
+            entry.args
+        )
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Record the requests of clients to the interfaces that match this filter,
+    /// or don't if None.
+    pub fn protocol_log (mut self, filter: Option<&str>) -> CharlieResult<Self> {
+        if let Some(filter) = filter {
+            self.protocols.filter = filter.parse().map_err(|e| CharlieError::Config(format!("{e}")))?;
+            self.protocols.set_enabled(true);
+        }
+        Ok(self)
+    }
+
+    /// Record a request from a client. Called by the delegated dispatchers, before
+    /// the request is handled.
+    pub fn protocol_request <I: Resource> (&mut self, client: &Client, resource: &I, request: &impl std::fmt::Debug) {
+        self.protocols.request(client, resource, request)
+    }
+
+}
@@ -0,0 +1,522 @@
+use super::prelude::*;
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Write},
+    path::PathBuf,
+};
+
+use smithay::{
+    backend::input::{
+        AbsolutePositionEvent,
+        Axis,
+        AxisSource,
+        ButtonState,
+        Device,
+        DeviceCapability,
+        Event,
+        KeyState,
+        KeyboardKeyEvent,
+        PointerAxisEvent,
+        PointerButtonEvent,
+        PointerMotionAbsoluteEvent,
+        PointerMotionEvent,
+        UnusedEvent,
+    },
+    reexports::calloop::timer::{Timer, TimeoutAction},
+};
+
+/// An input event, reduced to the parts that the compositor reads.
+///
+/// Each event is stored as one line of text:
+/// `<milliseconds since start> <screen> <kind> <arguments...>`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recorded {
+    Key            { code: u32, pressed: bool },
+    Motion         { dx: f64, dy: f64, dx_unaccel: f64, dy_unaccel: f64 },
+    MotionAbsolute { x: f64, y: f64 },
+    Button         { code: u32, pressed: bool },
+    Axis {
+        source:     AxisSource,
+        horizontal: Option<f64>,
+        vertical:   Option<f64>,
+        horizontal_discrete: Option<f64>,
+        vertical_discrete:   Option<f64>,
+    },
+}
+
+impl Recorded {
+
+    /// Reduce an input event from any backend. Returns None for unsupported kinds.
+    pub fn from_event <B: InputBackend> (event: &InputEvent<B>) -> Option<Self> {
+        Some(match event {
+            InputEvent::Keyboard { event } => Self::Key {
+                code:    event.key_code(),
+                pressed: event.state() == KeyState::Pressed
+            },
+            InputEvent::PointerMotion { event } => Self::Motion {
+                dx:         event.delta_x(),
+                dy:         event.delta_y(),
+                dx_unaccel: event.delta_x_unaccel(),
+                dy_unaccel: event.delta_y_unaccel(),
+            },
+            InputEvent::PointerMotionAbsolute { event } => Self::MotionAbsolute {
+                x: event.x(),
+                y: event.y()
+            },
+            InputEvent::PointerButton { event } => Self::Button {
+                code:    event.button_code(),
+                pressed: event.state() == ButtonState::Pressed
+            },
+            InputEvent::PointerAxis { event } => Self::Axis {
+                source:     event.source(),
+                horizontal: event.amount(Axis::Horizontal),
+                vertical:   event.amount(Axis::Vertical),
+                horizontal_discrete: event.amount_discrete(Axis::Horizontal),
+                vertical_discrete:   event.amount_discrete(Axis::Vertical),
+            },
+            _ => return None
+        })
+    }
+
+    fn to_line (&self) -> String {
+        fn state (pressed: bool) -> &'static str {
+            if pressed { "pressed" } else { "released" }
+        }
+        fn amount (amount: Option<f64>) -> String {
+            amount.map(|a| a.to_string()).unwrap_or_else(|| "-".into())
+        }
+        match self {
+            Self::Key { code, pressed } =>
+                format!("key {code} {}", state(*pressed)),
+            Self::Motion { dx, dy, dx_unaccel, dy_unaccel } =>
+                format!("motion {dx} {dy} {dx_unaccel} {dy_unaccel}"),
+            Self::MotionAbsolute { x, y } =>
+                format!("absolute {x} {y}"),
+            Self::Button { code, pressed } =>
+                format!("button {code} {}", state(*pressed)),
+            Self::Axis { source, horizontal, vertical, horizontal_discrete, vertical_discrete } =>
+                format!("axis {} {} {} {} {}",
+                    match source {
+                        AxisSource::Wheel      => "wheel",
+                        AxisSource::WheelTilt  => "tilt",
+                        AxisSource::Finger     => "finger",
+                        AxisSource::Continuous => "continuous",
+                    },
+                    amount(*horizontal),
+                    amount(*vertical),
+                    amount(*horizontal_discrete),
+                    amount(*vertical_discrete)
+                ),
+        }
+    }
+
+    fn from_args (args: &[&str]) -> StdResult<Self> {
+        fn state (state: &str) -> StdResult<bool> {
+            match state {
+                "pressed"  => Ok(true),
+                "released" => Ok(false),
+                _ => Err(format!("unknown state: {state}").into())
+            }
+        }
+        fn amount (amount: &str) -> StdResult<Option<f64>> {
+            Ok(if amount == "-" { None } else { Some(amount.parse()?) })
+        }
+        Ok(match args {
+            ["key", code, pressed] => Self::Key {
+                code: code.parse()?, pressed: state(pressed)?
+            },
+            ["motion", dx, dy, dx_unaccel, dy_unaccel] => Self::Motion {
+                dx: dx.parse()?, dy: dy.parse()?,
+                dx_unaccel: dx_unaccel.parse()?, dy_unaccel: dy_unaccel.parse()?
+            },
+            ["absolute", x, y] => Self::MotionAbsolute {
+                x: x.parse()?, y: y.parse()?
+            },
+            ["button", code, pressed] => Self::Button {
+                code: code.parse()?, pressed: state(pressed)?
+            },
+            ["axis", source, horizontal, vertical, horizontal_discrete, vertical_discrete] => Self::Axis {
+                source: match *source {
+                    "wheel"      => AxisSource::Wheel,
+                    "tilt"       => AxisSource::WheelTilt,
+                    "finger"     => AxisSource::Finger,
+                    "continuous" => AxisSource::Continuous,
+                    _ => return Err(format!("unknown axis source: {source}").into())
+                },
+                horizontal: amount(horizontal)?,
+                vertical:   amount(vertical)?,
+                horizontal_discrete: amount(horizontal_discrete)?,
+                vertical_discrete:   amount(vertical_discrete)?,
+            },
+            _ => return Err(format!("unknown event: {}", args.join(" ")).into())
+        })
+    }
+
+}
+
+/// Writes every input event that reaches the compositor to a file.
+pub struct Recorder {
+    logger: Logger,
+    path:   PathBuf,
+    file:   LineWriter<File>,
+    start:  Instant,
+}
+
+impl Recorder {
+
+    pub fn new (logger: &Logger, path: impl Into<PathBuf>) -> StdResult<Self> {
+        let path = path.into();
+        let file = LineWriter::new(File::create(&path)?);
+        info!(logger, "Recording input to {}", path.display());
+        Ok(Self { logger: logger.clone(), path, file, start: Instant::now() })
+    }
+
+    pub fn record <B: InputBackend> (&mut self, event: &InputEvent<B>, screen: ScreenId) {
+        if let Some(event) = Recorded::from_event(event) {
+            let time = self.start.elapsed().as_millis();
+            if let Err(e) = writeln!(self.file, "{time} {screen} {}", event.to_line()) {
+                warn!(self.logger, "Could not record input to {}: {e}", self.path.display());
+            }
+        }
+    }
+
+}
+
+/// Load a recording made by [Recorder].
+pub fn load (path: impl AsRef<Path>) -> StdResult<Vec<(u32, ScreenId, Recorded)>> {
+    parse(BufReader::new(File::open(path)?))
+}
+
+/// Read the events of a recording, which must be in the order they happened.
+fn parse (reader: impl BufRead) -> StdResult<Vec<(u32, ScreenId, Recorded)>> {
+    let mut events: Vec<(u32, ScreenId, Recorded)> = vec![];
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args.as_slice() {
+            [] => continue,
+            [time, screen, event @ ..] => {
+                let time: u32 = time.parse().map_err(|e| format!("line {}: {e}", number + 1))?;
+                if let Some((previous, _, _)) = events.last() {
+                    if time < *previous {
+                        return Err(format!(
+                            "line {}: event at {time}ms comes after one at {previous}ms", number + 1
+                        ).into())
+                    }
+                }
+                events.push((
+                    time,
+                    screen.parse()?,
+                    Recorded::from_args(event).map_err(|e| format!("line {}: {e}", number + 1))?
+                ))
+            },
+            _ => return Err(format!("line {}: truncated event", number + 1).into())
+        }
+    }
+    Ok(events)
+}
+
+/// Input backend that feeds recorded events back into the compositor.
+#[derive(Debug)]
+pub struct ReplayInput;
+
+/// The device that replayed events come from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReplayDevice;
+
+impl Device for ReplayDevice {
+    fn id (&self) -> String {
+        "replay".into()
+    }
+    fn name (&self) -> String {
+        "Replayed input".into()
+    }
+    fn has_capability (&self, capability: DeviceCapability) -> bool {
+        matches!(capability, DeviceCapability::Keyboard | DeviceCapability::Pointer)
+    }
+    fn usb_id (&self) -> Option<(u32, u32)> {
+        None
+    }
+    fn syspath (&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// A recorded event on its way back in.
+#[derive(Debug, Clone)]
+pub struct ReplayEvent {
+    time:  u32,
+    event: Recorded,
+}
+
+impl Event<ReplayInput> for ReplayEvent {
+    fn time (&self) -> u32 {
+        self.time
+    }
+    fn device (&self) -> ReplayDevice {
+        ReplayDevice
+    }
+}
+
+impl KeyboardKeyEvent<ReplayInput> for ReplayEvent {
+    fn key_code (&self) -> u32 {
+        match self.event { Recorded::Key { code, .. } => code, _ => 0 }
+    }
+    fn state (&self) -> KeyState {
+        match self.event {
+            Recorded::Key { pressed: true, .. } => KeyState::Pressed,
+            _ => KeyState::Released
+        }
+    }
+    fn count (&self) -> u32 {
+        match self.event { Recorded::Key { pressed: true, .. } => 1, _ => 0 }
+    }
+}
+
+impl PointerMotionEvent<ReplayInput> for ReplayEvent {
+    fn delta_x (&self) -> f64 {
+        match self.event { Recorded::Motion { dx, .. } => dx, _ => 0.0 }
+    }
+    fn delta_y (&self) -> f64 {
+        match self.event { Recorded::Motion { dy, .. } => dy, _ => 0.0 }
+    }
+    fn delta_x_unaccel (&self) -> f64 {
+        match self.event { Recorded::Motion { dx_unaccel, .. } => dx_unaccel, _ => 0.0 }
+    }
+    fn delta_y_unaccel (&self) -> f64 {
+        match self.event { Recorded::Motion { dy_unaccel, .. } => dy_unaccel, _ => 0.0 }
+    }
+}
+
+impl AbsolutePositionEvent<ReplayInput> for ReplayEvent {
+    fn x (&self) -> f64 {
+        match self.event { Recorded::MotionAbsolute { x, .. } => x, _ => 0.0 }
+    }
+    fn y (&self) -> f64 {
+        match self.event { Recorded::MotionAbsolute { y, .. } => y, _ => 0.0 }
+    }
+    /// Recorded positions are already in output coordinates.
+    fn x_transformed (&self, _width: i32) -> f64 {
+        self.x()
+    }
+    fn y_transformed (&self, _height: i32) -> f64 {
+        self.y()
+    }
+}
+
+impl PointerMotionAbsoluteEvent<ReplayInput> for ReplayEvent {}
+
+impl PointerButtonEvent<ReplayInput> for ReplayEvent {
+    fn button_code (&self) -> u32 {
+        match self.event { Recorded::Button { code, .. } => code, _ => 0 }
+    }
+    fn state (&self) -> ButtonState {
+        match self.event {
+            Recorded::Button { pressed: true, .. } => ButtonState::Pressed,
+            _ => ButtonState::Released
+        }
+    }
+}
+
+impl PointerAxisEvent<ReplayInput> for ReplayEvent {
+    fn amount (&self, axis: Axis) -> Option<f64> {
+        match (&self.event, axis) {
+            (Recorded::Axis { horizontal, .. }, Axis::Horizontal) => *horizontal,
+            (Recorded::Axis { vertical, .. }, Axis::Vertical) => *vertical,
+            _ => None
+        }
+    }
+    fn amount_discrete (&self, axis: Axis) -> Option<f64> {
+        match (&self.event, axis) {
+            (Recorded::Axis { horizontal_discrete, .. }, Axis::Horizontal) => *horizontal_discrete,
+            (Recorded::Axis { vertical_discrete, .. }, Axis::Vertical) => *vertical_discrete,
+            _ => None
+        }
+    }
+    fn source (&self) -> AxisSource {
+        match self.event { Recorded::Axis { source, .. } => source, _ => AxisSource::Wheel }
+    }
+}
+
+impl InputBackend for ReplayInput {
+    type Device                     = ReplayDevice;
+    type KeyboardKeyEvent           = ReplayEvent;
+    type PointerAxisEvent           = ReplayEvent;
+    type PointerButtonEvent         = ReplayEvent;
+    type PointerMotionEvent         = ReplayEvent;
+    type PointerMotionAbsoluteEvent = ReplayEvent;
+    type GestureSwipeBeginEvent     = UnusedEvent;
+    type GestureSwipeUpdateEvent    = UnusedEvent;
+    type GestureSwipeEndEvent       = UnusedEvent;
+    type GesturePinchBeginEvent     = UnusedEvent;
+    type GesturePinchUpdateEvent    = UnusedEvent;
+    type GesturePinchEndEvent       = UnusedEvent;
+    type GestureHoldBeginEvent      = UnusedEvent;
+    type GestureHoldEndEvent        = UnusedEvent;
+    type TouchDownEvent             = UnusedEvent;
+    type TouchUpEvent               = UnusedEvent;
+    type TouchMotionEvent           = UnusedEvent;
+    type TouchCancelEvent           = UnusedEvent;
+    type TouchFrameEvent            = UnusedEvent;
+    type TabletToolAxisEvent        = UnusedEvent;
+    type TabletToolProximityEvent   = UnusedEvent;
+    type TabletToolTipEvent         = UnusedEvent;
+    type TabletToolButtonEvent      = UnusedEvent;
+    type SpecialEvent               = UnusedEvent;
+}
+
+impl ReplayEvent {
+    fn into_input (self) -> InputEvent<ReplayInput> {
+        match self.event {
+            Recorded::Key { .. }            => InputEvent::Keyboard { event: self },
+            Recorded::Motion { .. }         => InputEvent::PointerMotion { event: self },
+            Recorded::MotionAbsolute { .. } => InputEvent::PointerMotionAbsolute { event: self },
+            Recorded::Button { .. }         => InputEvent::PointerButton { event: self },
+            Recorded::Axis { .. }           => InputEvent::PointerAxis { event: self },
+        }
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Start writing all input events to a file, replacing any current recording.
+    pub fn record_start (&mut self, path: impl Into<PathBuf>) -> CharlieResult<()> {
+        self.input.recorder = Some(Recorder::new(&self.logger, path)?);
+        Ok(())
+    }
+
+    /// Stop writing input events to a file.
+    pub fn record_stop (&mut self) {
+        if let Some(recorder) = self.input.recorder.take() {
+            info!(self.logger, "Stopped recording input to {}", recorder.path.display());
+        }
+    }
+
+    /// Feed a recording back into the compositor with its original timing.
+    pub fn replay_start (&mut self, path: impl AsRef<Path>) -> CharlieResult<()> {
+        let events = load(path.as_ref())
+            .context(format!("Could not load recording {}", path.as_ref().display()))?;
+        info!(self.logger, "Replaying {} input events from {}", events.len(), path.as_ref().display());
+        let mut events = events.into_iter().peekable();
+        let first = match events.peek() {
+            Some((time, _, _)) => *time,
+            None => return Ok(())
+        };
+        let mut last = first;
+        self.handle.insert_source(Timer::immediate(), move |_, _, state| {
+            // Dispatch everything that is due, then wait until the next event
+            while let Some((time, screen, event)) = events.next_if(|(time, _, _)| *time <= last) {
+                let event = ReplayEvent { time: time.saturating_sub(first), event }.into_input();
+                if let Err(e) = state.update((event, screen)) {
+                    warn!(state.logger, "Could not replay input event: {e}");
+                }
+            }
+            match events.peek() {
+                Some((time, _, _)) => {
+                    let wait = Duration::from_millis(time.saturating_sub(last) as u64);
+                    last = *time;
+                    TimeoutAction::ToDuration(wait)
+                },
+                None => {
+                    info!(state.logger, "Replay finished");
+                    TimeoutAction::Drop
+                }
+            }
+        }).map_err(CharlieError::other)?;
+        Ok(())
+    }
+
+    /// Feed one event, written as in a recording, into the compositor as if a device sent it,
+    /// e.g. for remote desktop sessions.
+    pub fn input_inject (&mut self, screen: ScreenId, args: &[&str]) -> StdResult<()> {
+        Ok(self.input_emulate(screen, Recorded::from_args(args)?)?)
+    }
+
+    /// Feed one event into the compositor as if a device sent it just now.
+    pub fn input_emulate (&mut self, screen: ScreenId, event: Recorded) -> CharlieResult<()> {
+        let event = ReplayEvent { time: self.desktop.now_ms(), event }.into_input();
+        self.update((event, screen))
+    }
+
+    /// Record all input events to a file.
+    pub fn record (mut self, path: impl Into<PathBuf>) -> CharlieResult<Self> {
+        self.record_start(path)?;
+        Ok(self)
+    }
+
+    /// Replay recorded input events once the event loop starts.
+    pub fn replay (mut self, path: impl AsRef<Path>) -> CharlieResult<Self> {
+        self.replay_start(path)?;
+        Ok(self)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events () -> Vec<Recorded> {
+        vec![
+            Recorded::Key { code: 30, pressed: true },
+            Recorded::Key { code: 30, pressed: false },
+            Recorded::Motion { dx: 1.5, dy: -2.0, dx_unaccel: 1.0, dy_unaccel: -1.25 },
+            Recorded::MotionAbsolute { x: 640.0, y: 360.5 },
+            Recorded::Button { code: 272, pressed: true },
+            Recorded::Axis {
+                source:     AxisSource::Wheel,
+                horizontal: None,
+                vertical:   Some(15.0),
+                horizontal_discrete: None,
+                vertical_discrete:   Some(1.0),
+            },
+            Recorded::Axis {
+                source:     AxisSource::Finger,
+                horizontal: Some(-3.5),
+                vertical:   None,
+                horizontal_discrete: None,
+                vertical_discrete:   None,
+            },
+        ]
+    }
+
+    #[test]
+    fn event_round_trip () {
+        for event in events() {
+            let line = event.to_line();
+            let args: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(Recorded::from_args(&args).unwrap(), event, "{line}");
+        }
+    }
+
+    #[test]
+    fn recording_round_trip () {
+        let recording: String = events().iter().enumerate()
+            .map(|(index, event)| format!("{} {} {}\n", index * 10, index % 2, event.to_line()))
+            .collect();
+        let loaded = parse(recording.as_bytes()).unwrap();
+        assert_eq!(loaded.len(), events().len());
+        for (index, ((time, screen, event), expected)) in loaded.into_iter().zip(events()).enumerate() {
+            assert_eq!(time, index as u32 * 10);
+            assert_eq!(screen, index % 2);
+            assert_eq!(event, expected);
+        }
+    }
+
+    #[test]
+    fn recording_out_of_order () {
+        let recording = "20 0 key 30 pressed\n10 0 key 30 released\n";
+        let error = parse(recording.as_bytes()).unwrap_err().to_string();
+        assert!(error.starts_with("line 2:"), "{error}");
+    }
+
+    #[test]
+    fn recording_malformed () {
+        assert!(parse("0 0 key 30 held\n".as_bytes()).is_err());
+        assert!(parse("0 0\n".as_bytes()).is_err());
+        assert!(parse("-5 0 key 30 pressed\n".as_bytes()).is_err());
+        assert!(parse("\n\n".as_bytes()).unwrap().is_empty());
+    }
+
+}
@@ -0,0 +1,163 @@
+use super::prelude::*;
+
+/// How often the night light is brought up to date while it's on
+const NIGHT_LIGHT_TICK: Duration = Duration::from_secs(60);
+
+/// Decides when the main loop renders, and how long it sleeps in between.
+///
+/// Outputs are only rendered after something changed: a client committed, input arrived,
+/// a command ran, or a tick that was asked for came due. Until then the main loop sleeps
+/// until the next event, or the earliest time something has to be looked at again.
+pub struct Redraw {
+    logger:      Logger,
+    /// Whether the outputs have to be rendered again
+    needed:      bool,
+    /// When to render again even if nothing else happens
+    ticks:       Vec<Instant>,
+    /// The earliest time the main loop has to wake up, whether to render or not
+    wake:        Option<Instant>,
+    /// Turns of the main loop since the counters were reset
+    pub wakeups: u64,
+    /// Turns of the main loop that rendered
+    pub renders: u64,
+    /// When the counters were reset
+    pub since:   Instant,
+}
+
+impl Redraw {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:  logger.clone(),
+            needed:  true,
+            ticks:   vec![],
+            wake:    None,
+            wakeups: 0,
+            renders: 0,
+            since:   Instant::now(),
+        }
+    }
+
+    /// Render the outputs on the next turn of the main loop.
+    pub fn request (&mut self) {
+        self.needed = true;
+    }
+
+    /// Render the outputs when this time comes, e.g. for the next step of an animation,
+    /// unless they'll be rendered by then already.
+    pub fn request_at (&mut self, at: Instant) {
+        if !self.ticks.iter().any(|tick| *tick <= at) {
+            self.ticks.push(at);
+        }
+        self.wake_at(at);
+    }
+
+    /// Wake the main loop up by this time, without necessarily rendering.
+    pub fn wake_at (&mut self, at: Instant) {
+        self.wake = Some(self.wake.map(|wake| wake.min(at)).unwrap_or(at));
+    }
+
+    /// Whether to render on this turn, counting the turn. Ticks that came due count as damage.
+    pub fn take (&mut self) -> bool {
+        let now = Instant::now();
+        self.wakeups += 1;
+        let due = self.ticks.len();
+        self.ticks.retain(|tick| *tick > now);
+        if self.ticks.len() < due {
+            self.needed = true;
+        }
+        self.wake = self.ticks.iter().min().copied();
+        let needed = std::mem::take(&mut self.needed);
+        if needed {
+            self.renders += 1;
+        }
+        needed
+    }
+
+    /// How long the main loop may sleep: not at all if there's damage already,
+    /// up to the next wakeup if one is due, or else until an event arrives.
+    pub fn timeout (&self, poll: Option<Duration>) -> Option<Duration> {
+        if self.needed {
+            return Some(Duration::ZERO)
+        }
+        let wake = self.wake.map(|wake| wake.saturating_duration_since(Instant::now()));
+        match (wake, poll) {
+            (Some(wake), Some(poll)) => Some(wake.min(poll)),
+            (wake, poll) => wake.or(poll)
+        }
+    }
+
+    /// How many times the main loop woke up and rendered since the counters were reset,
+    /// and over how long.
+    pub fn stats (&self) -> String {
+        let seconds = self.since.elapsed().as_secs_f64();
+        format!(
+            "{} wakeups ({:.1}/s), {} renders ({:.1}/s) in {seconds:.1}s",
+            self.wakeups, self.wakeups as f64 / seconds.max(f64::EPSILON),
+            self.renders, self.renders as f64 / seconds.max(f64::EPSILON),
+        )
+    }
+
+    /// Start counting wakeups and renders from zero.
+    pub fn reset (&mut self) {
+        debug!(self.logger, "Main loop: {}", self.stats());
+        self.wakeups = 0;
+        self.renders = 0;
+        self.since   = Instant::now();
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Render the outputs again on the next turn of the main loop.
+    pub fn redraw (&mut self) {
+        self.redraw.request()
+    }
+
+    /// How long the main loop may sleep, after asking for the wakeups
+    /// that the state of the compositor needs.
+    pub fn redraw_timeout (&mut self) -> Option<Duration> {
+        self.redraw_schedule();
+        let poll = match (self.engine.poll_interval(), self.game.frame_wait()) {
+            (Some(engine), Some(game)) => Some(engine.min(game)),
+            (engine, game) => engine.or(game)
+        };
+        self.redraw.timeout(poll)
+    }
+
+    /// Ask for the wakeups that the state of the compositor needs,
+    /// now that the work of this turn of the main loop is done.
+    fn redraw_schedule (&mut self) {
+        let now = Instant::now();
+        // Ping the focused client on time, and notice when it doesn't answer
+        self.redraw.wake_at(self.watchdog.next_wakeup());
+        // Keep resizing and transactions going, and show their results
+        if let Some(due) = self.resizing.next_wakeup() {
+            self.redraw.request_at(due);
+        }
+        if let Some(deadline) = self.transactions.next_wakeup() {
+            self.redraw.request_at(deadline);
+        }
+        // Send the frame callbacks held back by frame rate limits
+        if let Some(due) = self.desktop.frames_held() {
+            self.redraw.request_at(due);
+        }
+        // Notice pastes that stopped moving; the ones that move wake the loop by themselves
+        if let Some(stall) = self.clipboard.next_wakeup() {
+            self.redraw.wake_at(stall);
+        }
+        if let Some(stall) = self.xwm.as_ref().and_then(|xwm| xwm.selection.next_wakeup()) {
+            self.redraw.wake_at(stall);
+        }
+        // Fade out the volume and brightness overlay
+        if let Some(due) = self.osd.next_wakeup() {
+            self.redraw.request_at(due);
+        }
+        // Follow the night light as time goes by
+        if self.night_light.enabled {
+            self.redraw.request_at(now + NIGHT_LIGHT_TICK);
+        }
+    }
+
+}
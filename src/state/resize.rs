@@ -0,0 +1,454 @@
+use super::prelude::*;
+
+use smithay::wayland::shell::xdg::ToplevelConfigure;
+
+/// Smallest size, in logical pixels, that an interactive resize asks a window for
+const MIN_SIZE: i32 = 16;
+
+/// Color of the outline that previews the size a window is being resized to
+const PREVIEW_COLOR: [f32; 4] = [0.4, 0.6, 1.0, 0.8];
+
+/// Width of the preview outline, in logical pixels
+const PREVIEW_WIDTH: f64 = 2.0;
+
+/// How close, in logical pixels, a dragged edge gets to the edge of another window before snapping to it
+const SNAP_DISTANCE: f64 = 12.0;
+
+/// How a window that hasn't caught up with an interactive resize is drawn,
+/// so that it follows the pointer instead of jumping when the client commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizePolicy {
+    /// Cut off the parts of the buffer that are past the requested size
+    Clip,
+    /// Stretch or shrink the buffer to the requested size
+    Scale,
+}
+
+impl std::str::FromStr for ResizePolicy {
+    type Err = Box<dyn Error>;
+    fn from_str (policy: &str) -> StdResult<Self> {
+        Ok(match policy {
+            "clip"  => Self::Clip,
+            "scale" => Self::Scale,
+            _ => return Err(format!("unknown resize policy: {policy}").into())
+        })
+    }
+}
+
+/// How the modifiers held while resizing change the size, read again on each motion.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResizeModifiers {
+    /// Keep the aspect ratio the window had when the resize started (Shift)
+    pub proportional: bool,
+    /// Move the opposite edges too, keeping the window centered where it was (Ctrl)
+    pub centered:     bool,
+}
+
+/// A window being resized by dragging one of its edges.
+pub struct Resize {
+    surface:    ToplevelSurface,
+    edges:      XdgToplevelResizeEdge,
+    /// The screen the resize started on; motion on other screens is ignored
+    screen:     ScreenId,
+    /// Where the pointer was when the resize started
+    origin:     Point<f64, Logical>,
+    /// Where the window was, and how big, when the resize started
+    start:      Rectangle<f64, Logical>,
+    /// The modifiers held at the latest motion
+    modifiers:  ResizeModifiers,
+    /// Edges of the other visible windows that dragged edges snap to, along each axis
+    snap_x:     Vec<f64>,
+    snap_y:     Vec<f64>,
+    /// The latest size asked for by the pointer, not yet sent to the client
+    pending:    Option<Size<i32, Logical>>,
+    /// The size sent in the latest configure
+    requested:  Size<i32, Logical>,
+    /// When the latest configure was sent
+    configured: Option<Instant>,
+    /// Whether the button has been released, and the client is catching up with the final size
+    released:   bool,
+    /// Serial of the configure with the requested size, once the client has acked it
+    acked:      Option<Serial>,
+    /// Whether the client has committed since acking the requested size
+    caught_up:  bool,
+}
+
+impl Resize {
+
+    fn has (&self, edge: XdgToplevelResizeEdge) -> bool {
+        use XdgToplevelResizeEdge::*;
+        match edge {
+            Left   => matches!(self.edges, Left | TopLeft | BottomLeft),
+            Right  => matches!(self.edges, Right | TopRight | BottomRight),
+            Top    => matches!(self.edges, Top | TopLeft | TopRight),
+            Bottom => matches!(self.edges, Bottom | BottomLeft | BottomRight),
+            _ => false
+        }
+    }
+
+    /// Where the window starts along an axis, how long it is, and whether
+    /// its near and far edges along it are dragged.
+    fn axis (&self, horizontal: bool) -> (f64, f64, bool, bool) {
+        use XdgToplevelResizeEdge::*;
+        if horizontal {
+            (self.start.loc.x, self.start.size.w, self.has(Left), self.has(Right))
+        } else {
+            (self.start.loc.y, self.start.size.h, self.has(Top), self.has(Bottom))
+        }
+    }
+
+    /// Where the window starts along an axis when it's this long: edges that aren't dragged
+    /// stay in place, unless the window is kept centered.
+    fn place (&self, horizontal: bool, length: f64) -> f64 {
+        let (start, start_length, near, far) = self.axis(horizontal);
+        let dragged = near || far;
+        if (dragged && self.modifiers.centered) || (!dragged && self.modifiers.proportional) {
+            start + (start_length - length) / 2.0
+        } else if near {
+            start + start_length - length
+        } else {
+            start
+        }
+    }
+
+    /// Where the top left corner of the window goes when it's this big.
+    fn anchor (&self, size: Size<f64, Logical>) -> Point<f64, Logical> {
+        (self.place(true, size.w), self.place(false, size.h)).into()
+    }
+
+    /// Pull the dragged edge along an axis to the nearest edge of another window within reach.
+    fn snap (&self, horizontal: bool, length: f64) -> f64 {
+        let (_, _, near, far) = self.axis(horizontal);
+        if !near && !far {
+            return length
+        }
+        let place = self.place(horizontal, length);
+        let edge = if near { place } else { place + length };
+        let targets = if horizontal { &self.snap_x } else { &self.snap_y };
+        let nearest = targets.iter()
+            .map(|target| target - edge)
+            .filter(|distance| distance.abs() <= SNAP_DISTANCE)
+            .min_by(|a, b| a.abs().total_cmp(&b.abs()));
+        let factor = if self.modifiers.centered { 2.0 } else { 1.0 };
+        match nearest {
+            Some(distance) if near => length - distance * factor,
+            Some(distance) => length + distance * factor,
+            None => length
+        }
+    }
+
+    /// The size the window should have with the pointer at this location.
+    fn size_at (&self, location: Point<f64, Logical>) -> Size<i32, Logical> {
+        let delta = location - self.origin;
+        // Centered resizes move both edges, so the window grows twice as fast
+        let factor = if self.modifiers.centered { 2.0 } else { 1.0 };
+        let mut size = self.start.size;
+        if self.has(XdgToplevelResizeEdge::Left) {
+            size.w -= delta.x * factor
+        } else if self.has(XdgToplevelResizeEdge::Right) {
+            size.w += delta.x * factor
+        }
+        if self.has(XdgToplevelResizeEdge::Top) {
+            size.h -= delta.y * factor
+        } else if self.has(XdgToplevelResizeEdge::Bottom) {
+            size.h += delta.y * factor
+        }
+        size.w = self.snap(true, size.w);
+        size.h = self.snap(false, size.h);
+        if self.modifiers.proportional && self.start.size.w > 0.0 && self.start.size.h > 0.0 {
+            // Follow whichever dragged axis changed the most
+            let (_, _, left, right) = self.axis(true);
+            let (_, _, top, bottom) = self.axis(false);
+            let horizontal = left || right;
+            let vertical   = top || bottom;
+            let ratio = self.start.size.w / self.start.size.h;
+            let grown_w = (size.w / self.start.size.w - 1.0).abs();
+            let grown_h = (size.h / self.start.size.h - 1.0).abs();
+            if horizontal && (!vertical || grown_w >= grown_h) {
+                size.h = size.w / ratio
+            } else {
+                size.w = size.h * ratio
+            }
+        }
+        (size.w.round().max(MIN_SIZE as f64) as i32, size.h.round().max(MIN_SIZE as f64) as i32).into()
+    }
+
+    /// Ask the client for the latest size, if there's one it hasn't been asked for yet.
+    fn configure (&mut self) {
+        if let Some(size) = self.pending.take() {
+            let released = self.released;
+            self.surface.with_pending_state(|state| {
+                state.size = Some(size);
+                if released {
+                    state.states.unset(XdgToplevelState::Resizing);
+                } else {
+                    state.states.set(XdgToplevelState::Resizing);
+                }
+            });
+            self.surface.send_configure();
+            self.requested  = size;
+            self.configured = Some(Instant::now());
+            self.acked      = None;
+            self.caught_up  = false;
+        }
+    }
+
+}
+
+/// Interactive resizing of windows.
+///
+/// Pointer motion only records the size the window should have. Sizes are sent to the client
+/// at most once per frame and no more often than `interval`, so slow clients aren't flooded
+/// with configures; sizes that pile up in between are dropped in favor of the latest one.
+/// When the button is released, the final size is sent right away.
+///
+/// Holding Shift keeps the aspect ratio of the window, and holding Ctrl resizes it from both
+/// sides, keeping it centered. Dragged edges snap to the edges of other visible windows.
+///
+/// A client has caught up once it commits after acking the configure with the latest size,
+/// even if it picked another size, e.g. to fit a grid of character cells.
+pub struct Resizing {
+    logger:       Logger,
+    /// The shortest time between two configures of the window being resized
+    pub interval: Duration,
+    /// Whether to outline the requested size while the client hasn't caught up with it
+    pub preview:  bool,
+    /// How to draw the window while the client hasn't caught up, or None to draw it as committed
+    pub policy:   Option<ResizePolicy>,
+    /// The resize in progress, if any
+    active:       Option<Resize>,
+}
+
+impl Resizing {
+
+    /// The window being resized, if any.
+    pub fn window (&self) -> Option<&WlSurface> {
+        self.active.as_ref().map(|resize| resize.surface.wl_surface())
+    }
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:   logger.clone(),
+            interval: Duration::from_micros(16_667),
+            preview:  true,
+            policy:   Some(ResizePolicy::Clip),
+            active:   None,
+        }
+    }
+
+    /// When the window being resized may be configured again, if one is
+    /// and it has a size waiting. The first size is sent as soon as it's known.
+    pub fn next_wakeup (&self) -> Option<Instant> {
+        let resize = self.active.as_ref()?;
+        resize.pending?;
+        resize.configured.map(|last| last + self.interval)
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Send configures during interactive resizes at most this often,
+    /// and whether to outline the requested size until the window catches up.
+    pub fn resize_throttle (mut self, interval: Duration, preview: bool) -> CharlieResult<Self> {
+        self.resizing.interval = interval;
+        self.resizing.preview  = preview;
+        Ok(self)
+    }
+
+    /// Clip or scale windows to the size they're being resized to until they catch up,
+    /// or draw them as committed if None.
+    pub fn resize_policy (mut self, policy: Option<ResizePolicy>) -> CharlieResult<Self> {
+        self.resizing.policy = policy;
+        Ok(self)
+    }
+
+    /// Start resizing a window from the given edges, following a pointer.
+    pub fn resize_start (&mut self, surface: ToplevelSurface, index: usize, edges: XdgToplevelResizeEdge) {
+        let (origin, screen) = {
+            let pointer = &self.input.pointers[index];
+            (pointer.location, pointer.screen)
+        };
+        let window = match self.desktop.window_state(surface.wl_surface()) {
+            Some(window) if window.fullscreen.is_none() => window,
+            _ => return
+        };
+        let size = window.window.geometry().size;
+        debug!(self.resizing.logger, "Resizing window {} from {edges:?}", window.id);
+        let start = Rectangle::from_loc_and_size(window.center, size.to_f64());
+        let (mut snap_x, mut snap_y) = (vec![], vec![]);
+        for other in self.desktop.windows_visible() {
+            if other.window.toplevel().wl_surface() == surface.wl_surface() {
+                continue
+            }
+            let size = other.window.geometry().size.to_f64();
+            snap_x.extend([other.center.x, other.center.x + size.w]);
+            snap_y.extend([other.center.y, other.center.y + size.h]);
+        }
+        self.resizing.active = Some(Resize {
+            surface,
+            edges,
+            screen,
+            origin,
+            start,
+            modifiers:  ResizeModifiers::default(),
+            snap_x,
+            snap_y,
+            pending:    None,
+            requested:  size,
+            configured: None,
+            released:   false,
+            acked:      None,
+            caught_up:  false,
+        });
+    }
+
+    /// Follow the pointer while resizing, with the modifiers held at this point.
+    /// Returns false if no resize is in progress.
+    pub fn resize_motion (&mut self, screen: ScreenId, location: Point<f64, Logical>, modifiers: ResizeModifiers)
+        -> bool
+    {
+        match self.resizing.active.as_mut() {
+            Some(resize) if !resize.released => {
+                if resize.screen == screen {
+                    resize.modifiers = modifiers;
+                    let size = resize.size_at(location);
+                    resize.pending = if size != resize.requested { Some(size) } else { None };
+                }
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Send the latest size to the window being resized, unless it was configured too recently.
+    pub fn resize_flush (&mut self) {
+        let interval = self.resizing.interval;
+        if let Some(resize) = self.resizing.active.as_mut() {
+            if resize.configured.map(|last| last.elapsed() >= interval).unwrap_or(true) {
+                resize.configure();
+            }
+        }
+        self.resize_place();
+    }
+
+    /// Finish resizing when the button is released, sending the size at that point.
+    /// Returns false if no resize is in progress.
+    pub fn resize_end (&mut self) -> bool {
+        let resize = match self.resizing.active.as_mut() {
+            Some(resize) if !resize.released => resize,
+            _ => return false
+        };
+        resize.released = true;
+        // Even if the size didn't change, the client needs to leave the resizing state
+        resize.pending = Some(resize.pending.unwrap_or(resize.requested));
+        resize.configure();
+        let (surface, size) = (resize.surface.wl_surface().clone(), resize.requested);
+        if let Some(window) = self.desktop.window_state_mut(&surface) {
+            window.size = size.to_f64();
+        }
+        self.resize_place();
+        true
+    }
+
+    /// Note that the window being resized acked a configure. If it's the one with
+    /// the latest size, the window catches up with its next commit.
+    pub fn resize_ack (&mut self, surface: &WlSurface, configure: &ToplevelConfigure) {
+        match self.resizing.active.as_mut() {
+            Some(resize) if resize.surface.wl_surface() == surface
+                && configure.state.size == Some(resize.requested) =>
+            {
+                resize.acked = Some(configure.serial)
+            },
+            _ => {}
+        }
+    }
+
+    /// Keep the edges opposite the dragged ones in place as the window commits new sizes,
+    /// and stop tracking the resize once the window has caught up with the final size.
+    pub fn resize_commit (&mut self, surface: &WlSurface) {
+        let resize = match self.resizing.active.as_mut() {
+            Some(resize) if resize.surface.wl_surface() == surface => resize,
+            _ => return
+        };
+        if let Some(serial) = resize.acked {
+            if !resize.caught_up {
+                debug!(self.resizing.logger, "Window caught up with configure {serial:?}");
+            }
+            resize.caught_up = true;
+        }
+        let done = resize.released && resize.caught_up;
+        self.resize_place();
+        if done && self.resizing.active.take().is_some() {
+            self.geometry_remember(surface);
+        }
+    }
+
+    /// Draw the window being resized at the size it was asked for until it catches up,
+    /// keeping the edges opposite the dragged ones in place.
+    fn resize_place (&mut self) {
+        let policy = self.resizing.policy;
+        let resize = match self.resizing.active.as_ref() {
+            Some(resize) => resize,
+            None => return
+        };
+        let (requested, caught_up) = (resize.requested, resize.caught_up);
+        let surface = resize.surface.wl_surface().clone();
+        let committed = match self.desktop.window_state(&surface) {
+            Some(window) => window.window.geometry().size,
+            None => {
+                self.resizing.active = None;
+                return
+            }
+        };
+        let resizing = match policy {
+            Some(policy) if !caught_up && committed != requested => Some((requested, policy)),
+            _ => None
+        };
+        let shown: Size<i32, Logical> = match resizing {
+            Some((size, ResizePolicy::Scale)) => size,
+            Some((size, ResizePolicy::Clip))  => (committed.w.min(size.w), committed.h.min(size.h)).into(),
+            None => committed
+        };
+        let anchor = resize.anchor(shown.to_f64());
+        if let Some(window) = self.desktop.window_state_mut(&surface) {
+            window.resizing = resizing;
+            window.center   = anchor;
+        }
+    }
+
+    /// Outline the size that the window being resized was asked for, until it gets there.
+    pub fn resize_render (
+        &self, frame: &mut <E::Renderer as Renderer>::Frame<'_>, screen_id: ScreenId, scale: f64
+    ) -> StdResult<()> {
+        let resize = match self.resizing.active.as_ref() {
+            Some(resize) if self.resizing.preview && resize.screen == screen_id => resize,
+            _ => return Ok(())
+        };
+        let (window, screen) = match (
+            self.desktop.window_state(resize.surface.wl_surface()),
+            self.desktop.screens.get(screen_id)
+        ) {
+            (Some(window), Some(screen)) => (window, screen),
+            _ => return Ok(())
+        };
+        let target = resize.pending.unwrap_or(resize.requested);
+        if window.window.geometry().size == target {
+            return Ok(())
+        }
+        // Anchor the outline at the edges that stay in place
+        let anchor = resize.anchor(target.to_f64());
+        let loc = Point::<f64, Logical>::from((anchor.x + screen.center.x, anchor.y + screen.center.y));
+        let loc  = loc.to_physical(scale).to_i32_round();
+        let size = target.to_f64().to_physical(scale).to_i32_round();
+        let line = (PREVIEW_WIDTH * scale).round().max(1.0) as i32;
+        frame.clear(PREVIEW_COLOR, &[
+            Rectangle::from_loc_and_size(loc, (size.w, line)),
+            Rectangle::from_loc_and_size((loc.x, loc.y + size.h - line), (size.w, line)),
+            Rectangle::from_loc_and_size(loc, (line, size.h)),
+            Rectangle::from_loc_and_size((loc.x + size.w - line, loc.y), (line, size.h)),
+        ]).map_err(CharlieError::other)?;
+        Ok(())
+    }
+
+}
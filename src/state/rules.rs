@@ -0,0 +1,138 @@
+use super::prelude::*;
+
+use super::identity::Identity;
+use super::content::ContentType;
+use super::scaling::Scaling;
+use super::placement::WindowPlacement;
+
+/// Properties applied to windows whose app id, title and client match.
+///
+/// Fields left as `None` don't constrain matching or don't override anything.
+#[derive(Debug, Clone, Default)]
+pub struct WindowRule {
+    /// Match windows with this app id (X11 windows have none)
+    pub app_id:  Option<String>,
+    /// Match windows of clients in the Flatpak or Snap with this app id or name,
+    /// which unlike the app id of a window, the client can't choose
+    pub sandbox_app_id: Option<String>,
+    /// Match windows of clients whose executable has this file name
+    pub executable: Option<String>,
+    /// Match windows whose title contains this string
+    pub title:   Option<String>,
+    /// Match windows whose client says they show this kind of content
+    pub content_type: Option<ContentType>,
+    /// Maximum rate at which the window receives frame callbacks
+    pub max_fps: Option<u32>,
+    /// Whether to put the window back where it was the last time it was open
+    pub remember_geometry: Option<bool>,
+    /// How to sample the window's buffer when it's drawn scaled
+    pub scaling: Option<Scaling>,
+    /// Whether to hide the window while a window started from it is open, as terminals do
+    pub swallow: Option<bool>,
+    /// Whether the window may hide the terminal it was started from
+    pub swallowable: Option<bool>,
+    /// Where to put the window when it's mapped
+    pub placement: Option<WindowPlacement>,
+    /// Whether the window, if it's a dialog, keeps its parent from taking input, whatever its client says
+    pub modal: Option<bool>,
+}
+
+impl WindowRule {
+
+    /// A rule matching all windows with the given app id.
+    pub fn app_id (app_id: impl Into<String>) -> Self {
+        Self { app_id: Some(app_id.into()), ..Default::default() }
+    }
+
+    /// Limit matching windows to a number of frames per second.
+    pub fn max_fps (mut self, fps: u32) -> Self {
+        self.max_fps = Some(fps);
+        self
+    }
+
+    /// Set whether matching windows reopen at their previous position and size.
+    pub fn remember_geometry (mut self, remember: bool) -> Self {
+        self.remember_geometry = Some(remember);
+        self
+    }
+
+    /// Sample matching windows in this way when they're drawn scaled.
+    pub fn scaling (mut self, scaling: Scaling) -> Self {
+        self.scaling = Some(scaling);
+        self
+    }
+
+    /// Set whether matching windows are hidden while windows started from them are open.
+    pub fn swallow (mut self, swallow: bool) -> Self {
+        self.swallow = Some(swallow);
+        self
+    }
+
+    /// Set whether matching windows hide the terminal they were started from.
+    pub fn swallowable (mut self, swallowable: bool) -> Self {
+        self.swallowable = Some(swallowable);
+        self
+    }
+
+    /// Put matching windows in this way when they're mapped.
+    pub fn placement (mut self, placement: WindowPlacement) -> Self {
+        self.placement = Some(placement);
+        self
+    }
+
+    /// Set whether matching dialogs keep their parents from taking input while they're open.
+    pub fn modal (mut self, modal: bool) -> Self {
+        self.modal = Some(modal);
+        self
+    }
+
+    /// A rule matching all windows of the sandboxed app with the given app id.
+    pub fn sandbox_app_id (app_id: impl Into<String>) -> Self {
+        Self { sandbox_app_id: Some(app_id.into()), ..Default::default() }
+    }
+
+    /// A rule matching all windows of clients with the given executable.
+    pub fn executable (name: impl Into<String>) -> Self {
+        Self { executable: Some(name.into()), ..Default::default() }
+    }
+
+    /// A rule matching all windows that show the given kind of content.
+    pub fn content_type (content_type: ContentType) -> Self {
+        Self { content_type: Some(content_type), ..Default::default() }
+    }
+
+    pub fn matches (
+        &self,
+        app_id:       Option<&str>,
+        title:        Option<&str>,
+        identity:     Option<&Identity>,
+        content_type: ContentType
+    ) -> bool {
+        if self.content_type.map(|expected| expected != content_type).unwrap_or(false) {
+            return false
+        }
+        if let Some(expected) = &self.sandbox_app_id {
+            if identity.and_then(|identity| identity.sandbox_app_id()) != Some(expected.as_str()) {
+                return false
+            }
+        }
+        if let Some(expected) = &self.executable {
+            let exe = identity.and_then(|identity| identity.exe.as_ref()).and_then(|exe| exe.file_name());
+            if exe.map(|exe| exe != expected.as_str()).unwrap_or(true) {
+                return false
+            }
+        }
+        if let Some(expected) = &self.app_id {
+            if app_id != Some(expected.as_str()) {
+                return false
+            }
+        }
+        if let Some(expected) = &self.title {
+            if !title.map(|title|title.contains(expected.as_str())).unwrap_or(false) {
+                return false
+            }
+        }
+        true
+    }
+
+}
@@ -0,0 +1,73 @@
+use super::prelude::*;
+
+use smithay::backend::renderer::TextureFilter;
+
+/// How a window's buffer is sampled when it's drawn bigger or smaller than it is,
+/// e.g. on an output with a fractional scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scaling {
+    /// Blend neighbouring pixels, which is smooth but blurry
+    #[default]
+    Linear,
+    /// Take the nearest pixel, which keeps edges sharp but uneven
+    Nearest,
+    /// Take the nearest pixel, and draw a fullscreen window at the largest whole multiple
+    /// of its size that fits the screen, in the middle, so that every pixel is as big
+    Integer,
+}
+
+impl Scaling {
+
+    pub fn name (&self) -> &'static str {
+        match self {
+            Self::Linear  => "linear",
+            Self::Nearest => "nearest",
+            Self::Integer => "integer",
+        }
+    }
+
+    /// The filter that the renderer samples textures with.
+    pub fn filter (&self) -> TextureFilter {
+        match self {
+            Self::Linear => TextureFilter::Linear,
+            Self::Nearest | Self::Integer => TextureFilter::Nearest,
+        }
+    }
+
+}
+
+impl std::str::FromStr for Scaling {
+    type Err = Box<dyn Error>;
+    fn from_str (scaling: &str) -> StdResult<Self> {
+        Ok(match scaling {
+            "linear"  => Self::Linear,
+            "nearest" => Self::Nearest,
+            "integer" => Self::Integer,
+            _ => return Err(format!("unknown scaling: {scaling}").into())
+        })
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Sample a window in this way when it's drawn scaled, until its rules are applied again.
+    pub fn window_scaling_set (&mut self, surface: &WlSurface, scaling: Scaling) {
+        if let Some(window) = self.desktop.window_state_mut(surface) {
+            debug!(self.logger, "Window {} scaling: {}", window.id, scaling.name());
+            window.scaling = scaling;
+            self.redraw();
+        }
+    }
+
+    /// Scale a window by whole numbers, with nearest neighbour sampling and black bars around
+    /// it, whenever it's fullscreen, e.g. for pixel art games; or go back to smooth scaling.
+    pub fn window_toggle_integer_scaling (&mut self, surface: &WlSurface) {
+        let scaling = match self.desktop.window_state(surface) {
+            Some(window) if window.scaling == Scaling::Integer => Scaling::Linear,
+            Some(_) => Scaling::Integer,
+            None => return
+        };
+        self.window_scaling_set(surface, scaling);
+    }
+
+}
@@ -0,0 +1,260 @@
+use super::prelude::*;
+
+use std::path::PathBuf;
+
+use rhai::{Engine as ScriptEngine, AST, Scope, Dynamic, Map, Array, EvalAltResult};
+
+use smithay::input::keyboard::ModifiersState;
+
+use super::action::{Action, valid_length};
+
+/// Scripts that take longer than this many steps are stopped, so they can't hang the compositor
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Something that a script asked the compositor to do.
+#[derive(Debug, Clone)]
+pub enum Issued {
+    /// An IPC command, as passed to `command`
+    Command(String),
+    /// An action, as asked for by one of the shorthands
+    Action(Action),
+}
+
+impl std::fmt::Display for Issued {
+    fn fmt (&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Command(command) => write!(f, "{command}"),
+            Self::Action(action)   => write!(f, "{action}"),
+        }
+    }
+}
+
+type Queue = Rc<RefCell<Vec<Issued>>>;
+
+/// A window id or a screen number that a script passed, which can't be negative.
+fn index <T: TryFrom<i64>> (what: &str, value: i64) -> Result<T, Box<EvalAltResult>> {
+    T::try_from(value).map_err(|_| format!("there's no {what} {value}").into())
+}
+
+/// A window size that a script passed, which must be positive.
+fn length (value: i64) -> Result<f64, Box<EvalAltResult>> {
+    let length = value as f64;
+    if valid_length(length) { Ok(length) } else { Err(format!("invalid window size: {value}").into()) }
+}
+
+/// A loaded script and its global variables.
+struct Script {
+    path:  PathBuf,
+    ast:   AST,
+    scope: Scope<'static>,
+}
+
+/// Rhai scripts that respond to compositor events.
+///
+/// Scripts define any of these functions, which are called when the event happens:
+///
+/// * `on_window_mapped(window)`
+/// * `on_focus_changed(window)`, where window is `()` if nothing has focus
+/// * `on_output_added(name)`
+/// * `on_output_removed(name)`, after its windows were moved to another screen
+/// * `on_output_profile(name)`, when the connected outputs change and a profile is applied
+/// * `on_key(key, modifiers)`, which consumes the key if it returns `true`
+/// * `on_hot_corner(edge, screen)`, for edges that have a command set
+/// * `on_tablet_mode(enabled)`, when the laptop is folded into a tablet or back
+///
+/// Windows are passed as maps with `id`, `app_id`, `title` and `content_type`.
+///
+/// Scripts act on the compositor by calling `command("...")` with an IPC command,
+/// or one of the shorthands for actions `spawn`, `workspace`, `focus_window`, `close_window`,
+/// `move_window`, `resize_window`, `raise_window`, `lower_window`, `warp_pointer`,
+/// `confine_pointer` and `release_pointer`. `spawn` takes a program, and optionally an array
+/// of its arguments. Commands are queued and performed on the next turn of the main loop,
+/// after the script has returned.
+pub struct Scripts {
+    logger:  Logger,
+    engine:  ScriptEngine,
+    scripts: Vec<Script>,
+    /// Commands issued by scripts that haven't been performed yet
+    queue:   Queue,
+}
+
+impl Scripts {
+
+    pub fn new (logger: &Logger) -> Self {
+        let queue: Queue = Rc::new(RefCell::new(vec![]));
+        let mut engine = ScriptEngine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let log = logger.clone();
+        engine.on_print(move |text| info!(log, "script: {text}"));
+        let log = logger.clone();
+        engine.on_debug(move |text, _, pos| debug!(log, "script ({pos:?}): {text}"));
+        let q = queue.clone();
+        engine.register_fn("command", move |command: &str| {
+            q.borrow_mut().push(Issued::Command(command.into()))
+        });
+        let q = queue.clone();
+        engine.register_fn("spawn", move |program: &str| {
+            q.borrow_mut().push(Issued::Action(Action::Exec(program.into(), vec![])))
+        });
+        let q = queue.clone();
+        engine.register_fn("spawn", move |program: &str, args: Array| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            q.borrow_mut().push(Issued::Action(Action::Exec(program.into(), args)))
+        });
+        let q = queue.clone();
+        engine.register_fn("workspace", move |name: &str| {
+            q.borrow_mut().push(Issued::Action(Action::Workspace(name.into())))
+        });
+        let q = queue.clone();
+        engine.register_fn("focus_window", move |id: i64| -> Result<(), Box<EvalAltResult>> {
+            q.borrow_mut().push(Issued::Action(Action::Focus(index("window", id)?)));
+            Ok(())
+        });
+        let q = queue.clone();
+        engine.register_fn("close_window", move |id: i64| -> Result<(), Box<EvalAltResult>> {
+            q.borrow_mut().push(Issued::Action(Action::Close(index("window", id)?)));
+            Ok(())
+        });
+        let q = queue.clone();
+        engine.register_fn("move_window", move |id: i64, x: i64, y: i64| -> Result<(), Box<EvalAltResult>> {
+            q.borrow_mut().push(Issued::Action(Action::Move(index("window", id)?, x as f64, y as f64)));
+            Ok(())
+        });
+        let q = queue.clone();
+        engine.register_fn("resize_window", move |id: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            q.borrow_mut().push(Issued::Action(Action::Resize(index("window", id)?, length(w)?, length(h)?)));
+            Ok(())
+        });
+        let q = queue.clone();
+        engine.register_fn("raise_window", move |id: i64| -> Result<(), Box<EvalAltResult>> {
+            q.borrow_mut().push(Issued::Action(Action::Raise(Some(index("window", id)?))));
+            Ok(())
+        });
+        let q = queue.clone();
+        engine.register_fn("lower_window", move |id: i64| -> Result<(), Box<EvalAltResult>> {
+            q.borrow_mut().push(Issued::Action(Action::Lower(Some(index("window", id)?))));
+            Ok(())
+        });
+        let q = queue.clone();
+        engine.register_fn("warp_pointer", move |screen: i64, x: i64, y: i64| -> Result<(), Box<EvalAltResult>> {
+            q.borrow_mut().push(Issued::Action(Action::WarpPointer(index("screen", screen)?, x as f64, y as f64)));
+            Ok(())
+        });
+        let q = queue.clone();
+        engine.register_fn("confine_pointer", move |screen: i64| -> Result<(), Box<EvalAltResult>> {
+            q.borrow_mut().push(Issued::Action(Action::ConfinePointer(index("screen", screen)?)));
+            Ok(())
+        });
+        let q = queue.clone();
+        engine.register_fn("release_pointer", move || {
+            q.borrow_mut().push(Issued::Action(Action::ReleasePointer))
+        });
+        Self { logger: logger.clone(), engine, scripts: vec![], queue }
+    }
+
+    /// Where scripts are loaded from by default: `$XDG_CONFIG_HOME/charlie`.
+    pub fn config_dir () -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|config| config.join("charlie"))
+    }
+
+    /// Load all `.rhai` files in a directory, in alphabetical order.
+    pub fn load_dir (&mut self, dir: impl AsRef<Path>) -> StdResult<()> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map(|ext| ext == "rhai").unwrap_or(false))
+            .collect();
+        paths.sort();
+        for path in paths {
+            if let Err(e) = self.load(&path) {
+                error!(self.logger, "Could not load script {}: {e}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile a script and run its top level statements.
+    pub fn load (&mut self, path: impl Into<PathBuf>) -> StdResult<()> {
+        let path = path.into();
+        let ast = self.engine.compile_file(path.clone())?;
+        let mut scope = Scope::new();
+        self.engine.run_ast_with_scope(&mut scope, &ast)?;
+        info!(self.logger, "Loaded script {}", path.display());
+        self.scripts.push(Script { path, ast, scope });
+        Ok(())
+    }
+
+    /// Call a hook in every script that defines it.
+    /// Returns true if any of them returned true.
+    pub fn hook (&mut self, name: &str, args: Vec<Dynamic>) -> bool {
+        let mut handled = false;
+        for script in self.scripts.iter_mut() {
+            if !script.ast.iter_functions().any(|f| f.name == name) {
+                continue
+            }
+            match self.engine.call_fn::<Dynamic>(&mut script.scope, &script.ast, name, args.clone()) {
+                Ok(result) => handled |= result.as_bool().unwrap_or(false),
+                Err(e) => warn!(self.logger, "{name} in {}: {e}", script.path.display())
+            }
+        }
+        handled
+    }
+
+    /// Offer a key press to the scripts.
+    pub fn key (&mut self, modifiers: &ModifiersState, keysym: u32) -> bool {
+        let mut mods = Map::new();
+        mods.insert("ctrl".into(),  modifiers.ctrl.into());
+        mods.insert("alt".into(),   modifiers.alt.into());
+        mods.insert("shift".into(), modifiers.shift.into());
+        mods.insert("logo".into(),  modifiers.logo.into());
+        let key = xkbcommon::xkb::keysym_get_name(keysym);
+        self.hook("on_key", vec![key.into(), mods.into()])
+    }
+
+    /// Take the commands that scripts have issued so far.
+    pub fn take_commands (&self) -> Vec<Issued> {
+        std::mem::take(&mut *self.queue.borrow_mut())
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Load a script.
+    pub fn script (mut self, path: impl Into<PathBuf>) -> CharlieResult<Self> {
+        let path = path.into();
+        self.scripts.load(&path).context(format!("Could not load script {}", path.display()))?;
+        Ok(self)
+    }
+
+    /// Describe a window to scripts.
+    pub fn script_window (&self, surface: &WlSurface) -> Dynamic {
+        let window = match self.desktop.window_state(surface) {
+            Some(window) => window,
+            None => return Dynamic::UNIT
+        };
+        let (app_id, title) = window.app_id_and_title();
+        let mut map = Map::new();
+        map.insert("id".into(),     (window.id as i64).into());
+        map.insert("app_id".into(), app_id.map(Dynamic::from).unwrap_or(Dynamic::UNIT));
+        map.insert("title".into(),  title.map(Dynamic::from).unwrap_or(Dynamic::UNIT));
+        map.insert("content_type".into(), window.content_type.name().into());
+        map.into()
+    }
+
+    /// Perform the commands issued by scripts.
+    pub fn scripts_flush (&mut self) {
+        for command in self.scripts.take_commands() {
+            let result = match &command {
+                Issued::Command(line) => self.ipc_line(line),
+                Issued::Action(action) => self.perform(action.clone()),
+            };
+            match result {
+                Ok(reply) => debug!(self.logger, "Script command {command}: {reply}"),
+                Err(e) => warn!(self.logger, "Script command {command} failed: {e}")
+            }
+        }
+    }
+
+}
@@ -0,0 +1,303 @@
+use super::prelude::*;
+
+use std::sync::RwLock;
+
+use super::identity::{ClientState, Identity};
+
+/// Protocols that give clients power over the session or other clients,
+/// and so are only offered to the clients that a policy lets have them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// `charlie_control_v1`
+    Control,
+    /// `zwlr_gamma_control_manager_v1`
+    GammaControl,
+    /// `zwlr_virtual_pointer_manager_v1`
+    VirtualPointer,
+    /// `zwp_virtual_keyboard_manager_v1`
+    VirtualKeyboard,
+    /// `zwlr_input_inhibit_manager_v1`
+    InputInhibitor,
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = Box<dyn Error>;
+    fn from_str (protocol: &str) -> StdResult<Self> {
+        Ok(match protocol {
+            "control"          => Self::Control,
+            "gamma-control"    => Self::GammaControl,
+            "virtual-pointer"  => Self::VirtualPointer,
+            "virtual-keyboard" => Self::VirtualKeyboard,
+            "input-inhibitor"  => Self::InputInhibitor,
+            _ => return Err(format!("unknown privileged protocol: {protocol}").into())
+        })
+    }
+}
+
+/// Which clients a policy lets have a protocol. Clients that are matched by their
+/// executable or unit must also run as the same user as the compositor, outside of
+/// any sandbox, whose apps can only be let in by the socket they connect to, or by all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMatch {
+    /// Clients whose executable has this file name, or this path if it's absolute
+    Executable(String),
+    /// Clients that run in this systemd unit, e.g. `screenshot.service`
+    Unit(String),
+    /// Clients that connected to the privileged socket
+    Socket,
+    /// All clients
+    Any,
+}
+
+impl std::str::FromStr for ClientMatch {
+    type Err = Box<dyn Error>;
+    fn from_str (spec: &str) -> StdResult<Self> {
+        Ok(match spec.split_once(':') {
+            Some(("exe", name))  => Self::Executable(name.into()),
+            Some(("unit", name)) => Self::Unit(name.into()),
+            _ if spec == "socket" => Self::Socket,
+            _ if spec == "any"    => Self::Any,
+            _ => return Err(format!("expected exe:NAME, unit:NAME, socket or any, got {spec}").into())
+        })
+    }
+}
+
+/// Whether a client is one of the clients that a policy names.
+fn matches (identity: &Identity, privileged: bool, rule: &ClientMatch) -> bool {
+    match rule {
+        ClientMatch::Any => true,
+        ClientMatch::Socket => privileged,
+        _ if !identity.same_user() => false,
+        // A sandbox can run anything under any name, in a unit of its choice
+        _ if identity.sandbox.is_some() => false,
+        ClientMatch::Executable(name) => identity.exe.as_ref()
+            .map(|exe| if name.starts_with('/') {
+                exe.as_os_str() == name.as_str()
+            } else {
+                exe.file_name().map(|file| file == name.as_str()).unwrap_or(false)
+            })
+            .unwrap_or(false),
+        ClientMatch::Unit(unit) => identity.unit.as_deref() == Some(unit.as_str()),
+    }
+}
+
+/// The clients that may have a privileged protocol. Kept with the global,
+/// which can only see its own data when deciding which clients to show itself to.
+#[derive(Debug, Clone)]
+pub struct Gate(Arc<RwLock<Vec<ClientMatch>>>);
+
+impl Gate {
+
+    pub fn new (rules: Vec<ClientMatch>) -> Self {
+        Self(Arc::new(RwLock::new(rules)))
+    }
+
+    /// Whether a client may see and bind the global.
+    pub fn admits (&self, client: &Client) -> bool {
+        let state = match client.get_data::<ClientState>() {
+            Some(state) => state,
+            // Such as the Xwayland server, which connects by other means
+            None => return false
+        };
+        self.0.read().map(|rules| rules.iter().any(|rule| matches(&state.identity, state.privileged, rule))).unwrap_or(false)
+    }
+
+}
+
+/// Decides which clients are offered the privileged protocols.
+///
+/// Control is offered to `charliectl`, gamma control to everyone, and the virtual
+/// pointer and keyboard and the input inhibitor only to the clients of the privileged socket,
+/// unless configured otherwise. Clients can also be let in by connecting to the privileged socket,
+/// which is only created if it's given a name, and can be protected by file permissions.
+///
+/// IPC commands that do what the privileged protocols do, or change who may have them, are only
+/// performed for the IPC clients that are let in by name: unless configured otherwise, only
+/// the `charlie-portal` that's installed next to the compositor, which takes screenshots and
+/// injects input for the clients that the user lets do so.
+pub struct Security {
+    logger:     Logger,
+    gates:      HashMap<Protocol, Gate>,
+    /// IPC clients that may run the privileged commands
+    ipc:        Vec<ClientMatch>,
+    /// Name of the socket whose clients match [ClientMatch::Socket]
+    pub socket: Option<String>,
+}
+
+impl Security {
+
+    pub fn new (logger: &Logger) -> Self {
+        let mut gates = HashMap::new();
+        gates.insert(Protocol::Control, Gate::new(vec![ClientMatch::Executable("charliectl".into())]));
+        gates.insert(Protocol::GammaControl, Gate::new(vec![ClientMatch::Any]));
+        gates.insert(Protocol::VirtualPointer, Gate::new(vec![ClientMatch::Socket]));
+        gates.insert(Protocol::VirtualKeyboard, Gate::new(vec![ClientMatch::Socket]));
+        gates.insert(Protocol::InputInhibitor, Gate::new(vec![ClientMatch::Socket]));
+        let ipc = std::env::current_exe().ok()
+            .map(|exe| ClientMatch::Executable(exe.with_file_name("charlie-portal").to_string_lossy().into()))
+            .into_iter()
+            .collect();
+        Self { logger: logger.clone(), gates, ipc, socket: None }
+    }
+
+    /// The gate of a protocol, to keep with its global.
+    pub fn gate (&self, protocol: Protocol) -> Gate {
+        self.gates[&protocol].clone()
+    }
+
+    /// Let more clients have a protocol.
+    pub fn allow (&mut self, protocol: Protocol, rule: ClientMatch) {
+        debug!(self.logger, "Offering {protocol:?} to {rule:?}");
+        if let Ok(mut rules) = self.gates[&protocol].0.write() {
+            rules.push(rule);
+        }
+    }
+
+    /// Stop offering a protocol to anyone.
+    pub fn deny_all (&mut self, protocol: Protocol) {
+        if let Ok(mut rules) = self.gates[&protocol].0.write() {
+            rules.clear();
+        }
+    }
+
+    /// Let more IPC clients run the privileged commands.
+    pub fn allow_ipc (&mut self, rule: ClientMatch) {
+        debug!(self.logger, "Letting {rule:?} run privileged IPC commands");
+        self.ipc.push(rule);
+    }
+
+    /// Whether the IPC client on the other end of a connection may run the privileged commands.
+    /// There's no privileged IPC socket, so [ClientMatch::Socket] lets nobody in.
+    pub fn ipc_admits (&self, identity: &Identity) -> bool {
+        self.ipc.iter().any(|rule| matches(identity, false, rule))
+    }
+
+    /// Who may have each protocol.
+    pub fn rules (&self) -> Vec<(Protocol, Vec<ClientMatch>)> {
+        self.gates.iter()
+            .map(|(protocol, gate)| (*protocol, gate.0.read().map(|rules| rules.clone()).unwrap_or_default()))
+            .collect()
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Offer a privileged protocol to these clients too.
+    pub fn privileged (mut self, protocol: Protocol, rule: ClientMatch) -> CharlieResult<Self> {
+        self.security.allow(protocol, rule);
+        Ok(self)
+    }
+
+    /// Offer a privileged protocol only to the clients allowed after this.
+    pub fn unprivileged (mut self, protocol: Protocol) -> CharlieResult<Self> {
+        self.security.deny_all(protocol);
+        Ok(self)
+    }
+
+    /// Let these IPC clients run the privileged commands, such as `security allow`.
+    pub fn privileged_ipc (mut self, rule: ClientMatch) -> CharlieResult<Self> {
+        self.security.allow_ipc(rule);
+        Ok(self)
+    }
+
+    /// Listen on another socket, in the runtime directory, whose clients
+    /// are let in by [ClientMatch::Socket].
+    pub fn privileged_socket (mut self, name: impl Into<String>) -> CharlieResult<Self> {
+        self.security.socket = Some(name.into());
+        Ok(self)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::identity::Sandbox;
+
+    fn ours (exe: &str, unit: &str) -> Identity {
+        Identity {
+            pid:     Some(1),
+            uid:     Some(nix::unistd::getuid().as_raw()),
+            exe:     Some(exe.into()),
+            unit:    Some(unit.into()),
+            sandbox: None,
+        }
+    }
+
+    #[test]
+    fn parse_rules () {
+        assert_eq!("exe:charliectl".parse::<ClientMatch>().unwrap(), ClientMatch::Executable("charliectl".into()));
+        assert_eq!("exe:/usr/bin/grim".parse::<ClientMatch>().unwrap(), ClientMatch::Executable("/usr/bin/grim".into()));
+        assert_eq!("unit:foo.service".parse::<ClientMatch>().unwrap(), ClientMatch::Unit("foo.service".into()));
+        assert_eq!("socket".parse::<ClientMatch>().unwrap(), ClientMatch::Socket);
+        assert_eq!("any".parse::<ClientMatch>().unwrap(), ClientMatch::Any);
+        for spec in ["", "all", "pid:1", "sockets", "exe"] {
+            assert!(spec.parse::<ClientMatch>().is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn match_executable () {
+        let identity = ours("/usr/bin/charliectl", "foo.service");
+        assert!(matches(&identity, false, &ClientMatch::Executable("charliectl".into())));
+        assert!(matches(&identity, false, &ClientMatch::Executable("/usr/bin/charliectl".into())));
+        assert!(!matches(&identity, false, &ClientMatch::Executable("/tmp/charliectl".into())));
+        assert!(!matches(&identity, false, &ClientMatch::Executable("bin/charliectl".into())));
+        assert!(!matches(&identity, false, &ClientMatch::Executable("grim".into())));
+        assert!(!matches(&Identity::default(), false, &ClientMatch::Executable("charliectl".into())));
+    }
+
+    #[test]
+    fn match_unit () {
+        let identity = ours("/usr/bin/grim", "screenshot.service");
+        assert!(matches(&identity, false, &ClientMatch::Unit("screenshot.service".into())));
+        assert!(!matches(&identity, false, &ClientMatch::Unit("other.service".into())));
+    }
+
+    #[test]
+    fn match_other_user () {
+        let identity = Identity {
+            uid: Some(nix::unistd::getuid().as_raw().wrapping_add(1)),
+            ..ours("/usr/bin/charliectl", "foo.service")
+        };
+        assert!(!matches(&identity, false, &ClientMatch::Executable("charliectl".into())));
+        assert!(!matches(&identity, false, &ClientMatch::Unit("foo.service".into())));
+        assert!(matches(&identity, true, &ClientMatch::Socket));
+        assert!(matches(&identity, false, &ClientMatch::Any));
+    }
+
+    #[test]
+    fn match_sandboxed () {
+        for sandbox in [Sandbox::Flatpak("org.example.App".into()), Sandbox::Snap("example".into())] {
+            let identity = Identity {
+                sandbox: Some(sandbox),
+                ..ours("/usr/bin/charliectl", "foo.service")
+            };
+            assert!(!matches(&identity, false, &ClientMatch::Executable("charliectl".into())));
+            assert!(!matches(&identity, false, &ClientMatch::Executable("/usr/bin/charliectl".into())));
+            assert!(!matches(&identity, false, &ClientMatch::Unit("foo.service".into())));
+            assert!(!matches(&identity, false, &ClientMatch::Socket));
+            assert!(matches(&identity, true, &ClientMatch::Socket));
+            assert!(matches(&identity, false, &ClientMatch::Any));
+        }
+    }
+
+    #[test]
+    fn ipc_unprivileged () {
+        use std::os::unix::net::UnixStream;
+        let logger = Logger::root(slog::Discard, o!());
+        let mut security = Security::new(&logger);
+        // Whoever is on the other end of this connection is the test, which nobody let in
+        let (ours, _theirs) = UnixStream::pair().unwrap();
+        let identity = Identity::of_stream(&ours);
+        assert!(identity.pid.is_some());
+        assert!(!security.ipc_admits(&identity));
+        security.allow_ipc(ClientMatch::Socket);
+        assert!(!security.ipc_admits(&identity));
+        security.allow_ipc(ClientMatch::Executable("charlie-portal".into()));
+        assert!(!security.ipc_admits(&identity));
+        security.allow_ipc(ClientMatch::Executable(identity.exe.clone().unwrap().to_string_lossy().into()));
+        assert!(security.ipc_admits(&identity));
+    }
+}
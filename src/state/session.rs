@@ -0,0 +1,240 @@
+use super::prelude::*;
+
+use std::path::PathBuf;
+
+use super::content::ContentType;
+use super::identity::Identity;
+use super::process::{Startup, Restart};
+use super::rules::WindowRule;
+
+/// A window as it was when the session was saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedWindow {
+    pub app_id:    String,
+    pub title:     String,
+    /// Name of the workspace the window was on
+    pub workspace: String,
+    /// Name of the output the window was on, if any showed it
+    pub output:    Option<String>,
+    pub center:    Point<f64, Logical>,
+    pub size:      Size<f64, Logical>,
+}
+
+impl SavedWindow {
+
+    /// Entries are kept one per line, tab separated, with the title last
+    /// since it's the field most likely to contain anything.
+    fn to_line (&self) -> String {
+        let clean = |text: &str| text.replace(['\t', '\n'], " ");
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            clean(&self.app_id), clean(&self.workspace), self.output.as_deref().map(clean).unwrap_or("-".into()),
+            self.center.x, self.center.y, self.size.w, self.size.h, clean(&self.title))
+    }
+
+    fn parse (line: &str) -> StdResult<Self> {
+        let fields: Vec<&str> = line.splitn(8, '\t').collect();
+        match fields.as_slice() {
+            [app_id, workspace, output, x, y, w, h, title] => Ok(Self {
+                app_id:    app_id.to_string(),
+                title:     title.to_string(),
+                workspace: workspace.to_string(),
+                output:    if *output == "-" { None } else { Some(output.to_string()) },
+                center:    (x.parse::<f64>()?, y.parse::<f64>()?).into(),
+                size:      (w.parse::<f64>()?, h.parse::<f64>()?).into(),
+            }),
+            _ => Err("expected: app_id workspace output x y w h title".into())
+        }
+    }
+
+}
+
+/// The layout of the windows, saved on exit or on demand, and put back on the next start.
+///
+/// Apps that have a launch command are started again, and each window that maps is matched
+/// against the saved windows that haven't been claimed yet, in the same way that window rules
+/// match: by app id, preferring the window with the same title. A matched window goes back to
+/// its workspace, and to its position if its output is still there.
+pub struct SessionStore {
+    logger:      Logger,
+    /// File that the session is loaded from and saved to
+    pub path:    Option<PathBuf>,
+    /// Commands that start apps again, by app id
+    pub apps:    HashMap<String, Startup>,
+    /// Saved windows that no window has mapped as yet
+    pending:     Vec<SavedWindow>,
+}
+
+impl SessionStore {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), path: None, apps: HashMap::new(), pending: vec![] }
+    }
+
+    /// Where the session is stored by default: `$XDG_STATE_HOME/charlie/session`.
+    pub fn default_path () -> Option<PathBuf> {
+        std::env::var_os("XDG_STATE_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+            .map(|state| state.join("charlie").join("session"))
+    }
+
+    /// Use the given file, reading the windows saved in it.
+    /// A missing file is not an error, since it's created on first save.
+    pub fn load (&mut self, path: impl Into<PathBuf>) -> StdResult<()> {
+        let path = path.into();
+        self.pending.clear();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                match SavedWindow::parse(line) {
+                    Ok(window) => self.pending.push(window),
+                    Err(e) => warn!(self.logger, "Skipping session entry {line:?}: {e}")
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e.into())
+        }
+        debug!(self.logger, "Loaded {} saved windows from {}", self.pending.len(), path.display());
+        self.path = Some(path);
+        Ok(())
+    }
+
+    /// Write these windows to the file.
+    pub fn save (&self, windows: &[SavedWindow]) -> StdResult<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(())
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text: String = windows.iter().map(SavedWindow::to_line).collect();
+        // Replace the file at once, so a crash can't leave it half written
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, text)?;
+        std::fs::rename(&temp, path)?;
+        debug!(self.logger, "Saved {} windows to {}", windows.len(), path.display());
+        Ok(())
+    }
+
+    /// Claim the saved window that a newly mapped window stands for, if any.
+    fn take (
+        &mut self,
+        app_id:       &str,
+        title:        Option<&str>,
+        identity:     Option<&Identity>,
+        content_type: ContentType
+    ) -> Option<SavedWindow> {
+        let matches = |saved: &SavedWindow, by_title: bool| WindowRule {
+            title: by_title.then(|| saved.title.clone()),
+            ..WindowRule::app_id(&saved.app_id)
+        }.matches(Some(app_id), title, identity, content_type);
+        let index = self.pending.iter().position(|saved| matches(saved, true))
+            .or_else(|| self.pending.iter().position(|saved| matches(saved, false)))?;
+        Some(self.pending.remove(index))
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Save and restore the session in this file instead of the default one.
+    pub fn session_store (mut self, path: impl Into<PathBuf>) -> CharlieResult<Self> {
+        let path = path.into();
+        self.session.load(&path).context(format!("Could not load {}", path.display()))?;
+        Ok(self)
+    }
+
+    /// Start an app with this command when restoring a session that had its windows open.
+    pub fn session_app (mut self, app_id: impl Into<String>, cmd: impl AsRef<str>, args: &[&str])
+        -> CharlieResult<Self>
+    {
+        self.session.apps.insert(app_id.into(), Startup::new(cmd, args, Restart::Never));
+        Ok(self)
+    }
+
+    /// Write out where all windows with an app id are.
+    pub fn session_save (&mut self) -> StdResult<()> {
+        let windows: Vec<SavedWindow> = self.desktop.windows_all()
+            .filter(|window| window.mapped && window.parent_surface().is_none())
+            .filter_map(|window| {
+                let (app_id, title) = window.app_id_and_title();
+                let surface = window.window.toplevel().wl_surface();
+                let (center, size) = match (&window.fullscreen, &window.pip) {
+                    (Some(fullscreen), _) => (fullscreen.restore_center, fullscreen.restore_size),
+                    (None, Some(pip)) => (pip.restore_center, pip.restore_size),
+                    (None, None) => (window.center, window.size)
+                };
+                let screen = self.window_screen(surface);
+                Some(SavedWindow {
+                    app_id:    app_id?,
+                    title:     title.unwrap_or_default(),
+                    workspace: self.desktop.workspaces.list.get(window.workspace)?.name.clone(),
+                    output:    self.desktop.screens.get(screen).and_then(|screen| screen.output.clone()),
+                    center,
+                    size,
+                })
+            })
+            .collect();
+        self.session.save(&windows)
+    }
+
+    /// Start the apps of the saved windows that have launch commands, once each.
+    pub fn session_launch (&mut self) {
+        let mut launched = std::collections::HashSet::new();
+        let entries: Vec<Startup> = self.session.pending.iter()
+            .filter(|saved| launched.insert(saved.app_id.clone()))
+            .filter_map(|saved| self.session.apps.get(&saved.app_id).cloned())
+            .collect();
+        for entry in entries {
+            info!(self.session.logger, "Restoring {}", entry.cmd);
+            if let Err(e) = self.processes.spawn(entry) {
+                warn!(self.session.logger, "Could not restore app: {e}");
+            }
+        }
+    }
+
+    /// Load the session file again, and start its apps.
+    pub fn session_restore (&mut self) -> StdResult<()> {
+        let path = self.session.path.clone().ok_or("no session file")?;
+        self.session.load(path)?;
+        self.session_launch();
+        Ok(())
+    }
+
+    /// Put a newly mapped window where a saved window of the same app was.
+    /// Returns false if it doesn't stand for any saved window.
+    pub fn session_place (&mut self, surface: &WlSurface) -> bool {
+        let saved = match self.desktop.window_state(surface) {
+            Some(window) => match window.app_id_and_title() {
+                (Some(app_id), title) => self.session.take(
+                    &app_id, title.as_deref(), window.identity().as_ref(), window.content_type
+                ),
+                (None, _) => None
+            },
+            None => None
+        };
+        let saved = match saved {
+            Some(saved) => saved,
+            None => return false
+        };
+        let workspace = self.desktop.workspaces.find_or_add(&saved.workspace);
+        let output_present = saved.output.as_deref()
+            .map(|output| self.desktop.screen_find(output).is_some())
+            .unwrap_or(false);
+        let window = match self.desktop.window_state_mut(surface) {
+            Some(window) => window,
+            None => return false
+        };
+        debug!(self.session.logger, "Restoring window {} of {} to {}", window.id, saved.app_id, saved.workspace);
+        window.workspace = workspace;
+        if output_present {
+            window.center = saved.center;
+        }
+        window.size = saved.size;
+        if let Kind::Xdg(toplevel) = window.window.toplevel() {
+            toplevel.with_pending_state(|state| state.size = Some(saved.size.to_i32_round()));
+            toplevel.send_configure();
+        }
+        true
+    }
+
+}
@@ -0,0 +1,99 @@
+use super::prelude::*;
+
+use std::ffi::CString;
+
+use smithay::backend::renderer::gles2::ffi::{self, Gles2};
+
+/// Vertex shader that covers the whole target with a quad drawn from [QUAD]
+pub const FULLSCREEN_VERTEX: &str = r#"
+#version 100
+attribute vec2 position;
+varying vec2 v_coords;
+void main() {
+    v_coords = position * 0.5 + 0.5;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+"#;
+
+/// Corners of a quad covering the whole target, as a triangle strip
+const QUAD: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+
+/// A linked GLES program and the locations of the variables it uses.
+#[derive(Debug)]
+pub struct Program {
+    pub id:       u32,
+    /// Location of the `position` attribute
+    position:     u32,
+    /// Locations of uniforms, by name
+    uniforms:     HashMap<&'static str, i32>,
+}
+
+impl Program {
+
+    /// Compile and link a program from vertex and fragment shader sources.
+    pub unsafe fn new (gl: &Gles2, vertex: &str, fragment: &str, uniforms: &[&'static str])
+        -> StdResult<Self>
+    {
+        let vertex   = compile(gl, ffi::VERTEX_SHADER, vertex)?;
+        let fragment = compile(gl, ffi::FRAGMENT_SHADER, fragment)?;
+        let id = gl.CreateProgram();
+        gl.AttachShader(id, vertex);
+        gl.AttachShader(id, fragment);
+        gl.LinkProgram(id);
+        gl.DetachShader(id, vertex);
+        gl.DetachShader(id, fragment);
+        gl.DeleteShader(vertex);
+        gl.DeleteShader(fragment);
+        let mut status = ffi::FALSE as i32;
+        gl.GetProgramiv(id, ffi::LINK_STATUS, &mut status);
+        if status == ffi::FALSE as i32 {
+            gl.DeleteProgram(id);
+            return Err("could not link shader program".into())
+        }
+        let name = CString::new("position")?;
+        let position = gl.GetAttribLocation(id, name.as_ptr()) as u32;
+        let mut locations = HashMap::new();
+        for uniform in uniforms {
+            let name = CString::new(*uniform)?;
+            locations.insert(*uniform, gl.GetUniformLocation(id, name.as_ptr()));
+        }
+        Ok(Self { id, position, uniforms: locations })
+    }
+
+    /// Location of a uniform that was named when the program was created.
+    pub fn uniform (&self, name: &str) -> i32 {
+        self.uniforms.get(name).copied().unwrap_or(-1)
+    }
+
+    /// Use the program and draw a quad covering the whole target with it.
+    /// Set the program's uniforms in `setup`, which runs after it's in use.
+    pub unsafe fn draw_fullscreen (&self, gl: &Gles2, setup: impl FnOnce(&Gles2, &Self)) {
+        gl.UseProgram(self.id);
+        setup(gl, self);
+        gl.BindBuffer(ffi::ARRAY_BUFFER, 0);
+        gl.EnableVertexAttribArray(self.position);
+        gl.VertexAttribPointer(self.position, 2, ffi::FLOAT, ffi::FALSE, 0, QUAD.as_ptr() as *const _);
+        gl.DrawArrays(ffi::TRIANGLE_STRIP, 0, 4);
+        gl.DisableVertexAttribArray(self.position);
+        gl.UseProgram(0);
+    }
+
+}
+
+unsafe fn compile (gl: &Gles2, kind: ffi::types::GLenum, source: &str) -> StdResult<u32> {
+    let shader = gl.CreateShader(kind);
+    let source = CString::new(source)?;
+    gl.ShaderSource(shader, 1, &source.as_ptr(), std::ptr::null());
+    gl.CompileShader(shader);
+    let mut status = ffi::FALSE as i32;
+    gl.GetShaderiv(shader, ffi::COMPILE_STATUS, &mut status);
+    if status == ffi::FALSE as i32 {
+        let mut log = vec![0u8; 1024];
+        let mut length = 0;
+        gl.GetShaderInfoLog(shader, log.len() as i32, &mut length, log.as_mut_ptr() as *mut _);
+        gl.DeleteShader(shader);
+        log.truncate(length.max(0) as usize);
+        return Err(format!("could not compile shader: {}", String::from_utf8_lossy(&log)).into())
+    }
+    Ok(shader)
+}
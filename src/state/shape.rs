@@ -0,0 +1,196 @@
+use super::prelude::*;
+
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum,
+    backend::{ClientId, GlobalId},
+    protocol::wl_pointer::WlPointer,
+};
+
+use self::protocol::{
+    wp_cursor_shape_manager_v1::{self, WpCursorShapeManagerV1},
+    wp_cursor_shape_device_v1::{self, WpCursorShapeDeviceV1, Shape},
+};
+
+/// Code generated from `protocols/cursor-shape-v1.xml`.
+pub mod protocol {
+    #![allow(non_upper_case_globals, non_camel_case_types, unused)]
+    use smithay::reexports::wayland_server;
+    use smithay::reexports::wayland_server::protocol::*;
+    use wayland_protocols::wp::tablet::zv2::server::*;
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        use wayland_protocols::wp::tablet::zv2::server::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/cursor-shape-v1.xml");
+    }
+    use self::__interfaces::*;
+    wayland_scanner::generate_server_code!("protocols/cursor-shape-v1.xml");
+}
+
+/// The name of a cursor shape, as in CSS, which is what cursor themes call it.
+fn shape_name (shape: Shape) -> &'static str {
+    match shape {
+        Shape::Default      => "default",
+        Shape::ContextMenu  => "context-menu",
+        Shape::Help         => "help",
+        Shape::Pointer      => "pointer",
+        Shape::Progress     => "progress",
+        Shape::Wait         => "wait",
+        Shape::Cell         => "cell",
+        Shape::Crosshair    => "crosshair",
+        Shape::Text         => "text",
+        Shape::VerticalText => "vertical-text",
+        Shape::Alias        => "alias",
+        Shape::Copy         => "copy",
+        Shape::Move         => "move",
+        Shape::NoDrop       => "no-drop",
+        Shape::NotAllowed   => "not-allowed",
+        Shape::Grab         => "grab",
+        Shape::Grabbing     => "grabbing",
+        Shape::EResize      => "e-resize",
+        Shape::NResize      => "n-resize",
+        Shape::NeResize     => "ne-resize",
+        Shape::NwResize     => "nw-resize",
+        Shape::SResize      => "s-resize",
+        Shape::SeResize     => "se-resize",
+        Shape::SwResize     => "sw-resize",
+        Shape::WResize      => "w-resize",
+        Shape::EwResize     => "ew-resize",
+        Shape::NsResize     => "ns-resize",
+        Shape::NeswResize   => "nesw-resize",
+        Shape::NwseResize   => "nwse-resize",
+        Shape::ColResize    => "col-resize",
+        Shape::RowResize    => "row-resize",
+        Shape::AllScroll    => "all-scroll",
+        Shape::ZoomIn       => "zoom-in",
+        Shape::ZoomOut      => "zoom-out",
+        _                   => "default",
+    }
+}
+
+/// Lets clients pick cursors by name (`wp_cursor_shape_v1`), so that the compositor draws
+/// them from its own theme at the right size for each output, instead of clients drawing
+/// cursor surfaces of their own.
+///
+/// A shape is shown on the pointer that's over one of the asking client's surfaces, until
+/// the pointer moves onto another client's. Shapes missing from the theme leave the cursor
+/// as it was. Tablet tools get devices too, but those stay inert, since there are no tablets.
+pub struct CursorShapes {
+    logger: Logger,
+    global: GlobalId,
+}
+
+impl CursorShapes {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle) -> Self {
+        Self {
+            logger: logger.clone(),
+            global: display.create_global::<Charlie<E>, WpCursorShapeManagerV1, _>(1, ()),
+        }
+    }
+
+    /// Stop advertising the protocol.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.global.clone());
+    }
+
+}
+
+impl<E: Engine> GlobalDispatch<WpCursorShapeManagerV1, ()> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<WpCursorShapeManagerV1>,
+        _data:     &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<E: Engine> Dispatch<WpCursorShapeManagerV1, ()> for Charlie<E> {
+    fn request (
+        _state:    &mut Self,
+        _client:   &Client,
+        _resource: &WpCursorShapeManagerV1,
+        request:   wp_cursor_shape_manager_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_cursor_shape_manager_v1::Request::GetPointer { cursor_shape_device, pointer } => {
+                data_init.init(cursor_shape_device, Some(pointer));
+            },
+            wp_cursor_shape_manager_v1::Request::GetTabletToolV2 { cursor_shape_device, .. } => {
+                data_init.init(cursor_shape_device, None);
+            },
+            wp_cursor_shape_manager_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<WpCursorShapeDeviceV1, Option<WlPointer>> for Charlie<E> {
+    fn request (
+        state:      &mut Self,
+        client:     &Client,
+        resource:   &WpCursorShapeDeviceV1,
+        request:    wp_cursor_shape_device_v1::Request,
+        pointer:    &Option<WlPointer>,
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_cursor_shape_device_v1::Request::SetShape { shape, .. } => {
+                let shape = match shape {
+                    WEnum::Value(shape) => shape,
+                    WEnum::Unknown(value) => {
+                        resource.post_error(
+                            wp_cursor_shape_device_v1::Error::InvalidShape,
+                            format!("unknown cursor shape {value}")
+                        );
+                        return
+                    }
+                };
+                if pointer.as_ref().map(|pointer| pointer.is_alive()).unwrap_or(false) {
+                    state.cursor_shape_set(client.id(), shape_name(shape));
+                }
+            },
+            wp_cursor_shape_device_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Show a named cursor on the pointers that are over a surface of this client.
+    pub fn cursor_shape_set (&mut self, client: ClientId, shape: &str) {
+        let mut changed = false;
+        for index in 0..self.input.pointers.len() {
+            let (screen, location) = {
+                let pointer = &self.input.pointers[index];
+                (pointer.screen, pointer.location)
+            };
+            let over = self.desktop.surface_under(screen, location)
+                .and_then(|(surface, _)| surface.client())
+                .map(|over| over.id());
+            if over.as_ref() != Some(&client) {
+                continue
+            }
+            let cursor = &mut self.input.pointers[index].cursor;
+            match cursor.set_shape(shape) {
+                Ok(()) => {
+                    cursor.owner = Some(client.clone());
+                    changed = true;
+                },
+                Err(e) => debug!(self.cursor_shapes.logger, "Not showing cursor {shape}: {e}")
+            }
+        }
+        if changed {
+            self.redraw();
+        }
+    }
+
+}
@@ -0,0 +1,178 @@
+use super::prelude::*;
+
+use smithay::{
+    reexports::wayland_server::protocol::{wl_buffer::WlBuffer, wl_shm::Format},
+    wayland::shm::{with_buffer_contents, BufferData},
+};
+
+/// Formats of shared memory buffers that are advertised
+/// in addition to the mandatory ARGB8888 and XRGB8888
+pub const EXTRA_FORMATS: [Format; 7] = [
+    Format::Abgr8888,
+    Format::Xbgr8888,
+    Format::Rgb565,
+    Format::Argb2101010,
+    Format::Xrgb2101010,
+    Format::Abgr2101010,
+    Format::Xbgr2101010,
+];
+
+/// Whether all renderers import buffers of this format as they are.
+/// Buffers of the other formats are converted first.
+pub fn native (format: Format) -> bool {
+    matches!(format, Format::Argb8888 | Format::Xrgb8888)
+}
+
+/// How many bytes a pixel of this format takes, if it's one that can be converted.
+pub fn bytes_per_pixel (format: Format) -> Option<usize> {
+    match format {
+        Format::Rgb565 => Some(2),
+        Format::Argb8888 | Format::Xrgb8888 | Format::Abgr8888 | Format::Xbgr8888
+            | Format::Argb2101010 | Format::Xrgb2101010 | Format::Abgr2101010 | Format::Xbgr2101010
+            => Some(4),
+        _ => None
+    }
+}
+
+/// Widen a channel of this many bits to 8 bits, so that the maximum stays the maximum.
+fn widen (value: u32, bits: u32) -> u8 {
+    let max = (1 << bits) - 1;
+    ((value & max) * 255 / max) as u8
+}
+
+/// Convert a pixel to RGBA. Formats name the channels from the most significant bits
+/// down, and pixels are stored in little endian, whatever the byte order of the machine.
+pub fn pixel_to_rgba (format: Format, pixel: &[u8]) -> Option<[u8; 4]> {
+    let word = match bytes_per_pixel(format)? {
+        2 => u16::from_le_bytes([pixel[0], pixel[1]]) as u32,
+        _ => u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]),
+    };
+    Some(match format {
+        Format::Argb8888 => [widen(word >> 16, 8), widen(word >> 8, 8), widen(word, 8), widen(word >> 24, 8)],
+        Format::Xrgb8888 => [widen(word >> 16, 8), widen(word >> 8, 8), widen(word, 8), 255],
+        Format::Abgr8888 => [widen(word, 8), widen(word >> 8, 8), widen(word >> 16, 8), widen(word >> 24, 8)],
+        Format::Xbgr8888 => [widen(word, 8), widen(word >> 8, 8), widen(word >> 16, 8), 255],
+        Format::Rgb565   => [widen(word >> 11, 5), widen(word >> 5, 6), widen(word, 5), 255],
+        Format::Argb2101010 => [widen(word >> 20, 10), widen(word >> 10, 10), widen(word, 10), widen(word >> 30, 2)],
+        Format::Xrgb2101010 => [widen(word >> 20, 10), widen(word >> 10, 10), widen(word, 10), 255],
+        Format::Abgr2101010 => [widen(word, 10), widen(word >> 10, 10), widen(word >> 20, 10), widen(word >> 30, 2)],
+        Format::Xbgr2101010 => [widen(word, 10), widen(word >> 10, 10), widen(word >> 20, 10), 255],
+        _ => return None
+    })
+}
+
+/// Convert the pixels of a buffer to RGBA, row by row.
+/// Returns None if the format can't be converted or the buffer is too small.
+pub fn buffer_to_rgba (data: &[u8], info: &BufferData) -> Option<Vec<u8>> {
+    let bpp = bytes_per_pixel(info.format)?;
+    let (width, height, stride) = (info.width as usize, info.height as usize, info.stride as usize);
+    let offset = info.offset as usize;
+    if stride < width * bpp || data.len() < offset + stride * height.saturating_sub(1) + width * bpp {
+        return None
+    }
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        let start = offset + row * stride;
+        for pixel in data[start..start + width * bpp].chunks_exact(bpp) {
+            pixels.extend_from_slice(&pixel_to_rgba(info.format, pixel)?);
+        }
+    }
+    Some(pixels)
+}
+
+/// If a buffer is in shared memory, in a format that the renderers don't import as it is,
+/// convert it to RGBA pixels that they can import from memory.
+pub fn shm_convert (buffer: &WlBuffer) -> Option<StdResult<(Vec<u8>, Size<i32, Buffer>)>> {
+    with_buffer_contents(buffer, |ptr, len, info| {
+        if native(info.format) {
+            return None
+        }
+        let data = unsafe { std::slice::from_raw_parts(ptr, len) };
+        Some(match buffer_to_rgba(data, &info) {
+            Some(pixels) => Ok((pixels, (info.width, info.height).into())),
+            None => Err(format!("could not convert {:?} buffer", info.format).into())
+        })
+    }).ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb565 (word: u16) -> Option<[u8; 4]> {
+        pixel_to_rgba(Format::Rgb565, &word.to_le_bytes())
+    }
+
+    fn word (format: Format, word: u32) -> Option<[u8; 4]> {
+        pixel_to_rgba(format, &word.to_le_bytes())
+    }
+
+    #[test]
+    fn rgb565_channels () {
+        assert_eq!(rgb565(0xFFFF), Some([255, 255, 255, 255]));
+        assert_eq!(rgb565(0x0000), Some([0, 0, 0, 255]));
+        assert_eq!(rgb565(0xF800), Some([255, 0, 0, 255]));
+        assert_eq!(rgb565(0x07E0), Some([0, 255, 0, 255]));
+        assert_eq!(rgb565(0x001F), Some([0, 0, 255, 255]));
+        // Halfway up 5 and 6 bits isn't halfway up 8 bits
+        assert_eq!(rgb565(16 << 11), Some([131, 0, 0, 255]));
+        assert_eq!(rgb565(32 << 5), Some([0, 129, 0, 255]));
+        assert_eq!(rgb565(1), Some([0, 0, 8, 255]));
+    }
+
+    #[test]
+    fn byte_order_8888 () {
+        let bytes = [0x33, 0x22, 0x11, 0x80];
+        assert_eq!(pixel_to_rgba(Format::Argb8888, &bytes), Some([0x11, 0x22, 0x33, 0x80]));
+        assert_eq!(pixel_to_rgba(Format::Xrgb8888, &bytes), Some([0x11, 0x22, 0x33, 0xFF]));
+        assert_eq!(pixel_to_rgba(Format::Abgr8888, &bytes), Some([0x33, 0x22, 0x11, 0x80]));
+        assert_eq!(pixel_to_rgba(Format::Xbgr8888, &bytes), Some([0x33, 0x22, 0x11, 0xFF]));
+    }
+
+    #[test]
+    fn alpha_2101010 () {
+        let rgb = (1023 << 20) | 512;
+        assert_eq!(word(Format::Argb2101010, (3 << 30) | rgb), Some([255, 0, 127, 255]));
+        assert_eq!(word(Format::Argb2101010, (2 << 30) | rgb), Some([255, 0, 127, 170]));
+        assert_eq!(word(Format::Argb2101010, (1 << 30) | rgb), Some([255, 0, 127, 85]));
+        assert_eq!(word(Format::Argb2101010, rgb), Some([255, 0, 127, 0]));
+        assert_eq!(word(Format::Xrgb2101010, rgb), Some([255, 0, 127, 255]));
+        assert_eq!(word(Format::Abgr2101010, (1 << 30) | rgb), Some([127, 0, 255, 85]));
+        assert_eq!(word(Format::Xbgr2101010, rgb), Some([127, 0, 255, 255]));
+    }
+
+    #[test]
+    fn unconvertible_format () {
+        assert_eq!(pixel_to_rgba(Format::C8, &[0, 0, 0, 0]), None);
+        assert_eq!(bytes_per_pixel(Format::C8), None);
+    }
+
+    fn info (format: Format, offset: i32, width: i32, height: i32, stride: i32) -> BufferData {
+        BufferData { offset, width, height, stride, format }
+    }
+
+    #[test]
+    fn buffer_stride_and_offset () {
+        // Two rows of two RGB565 pixels, three bytes in, with two bytes of padding per row
+        let data = [
+            0xAA, 0xAA, 0xAA,
+            0x00, 0xF8, 0xE0, 0x07, 0xAA, 0xAA,
+            0x1F, 0x00, 0xFF, 0xFF,
+        ];
+        assert_eq!(buffer_to_rgba(&data, &info(Format::Rgb565, 3, 2, 2, 6)), Some(vec![
+            255, 0,   0,   255,   0,   255, 0,   255,
+            0,   0,   255, 255,   255, 255, 255, 255,
+        ]));
+    }
+
+    #[test]
+    fn buffer_too_small () {
+        let data = [0u8; 12];
+        // The last row ends a byte past the data
+        assert_eq!(buffer_to_rgba(&data, &info(Format::Rgb565, 3, 2, 2, 6)), None);
+        // Rows that overlap
+        assert_eq!(buffer_to_rgba(&data, &info(Format::Argb8888, 0, 2, 1, 4)), None);
+        assert_eq!(buffer_to_rgba(&data, &info(Format::Argb8888, 0, 3, 1, 12)), Some(vec![0; 12]));
+    }
+
+}
@@ -0,0 +1,141 @@
+use super::prelude::*;
+
+use serde_json::{json, Value};
+
+use smithay::output::Mode;
+
+/// Requests from the protocol log included in a snapshot
+const SNAPSHOT_REQUESTS: usize = 200;
+
+impl<E: Engine> Charlie<E> {
+
+    /// Everything about the state of the compositor that a bug report might need, as JSON:
+    /// outputs, workspaces, windows, input devices and focus, grabs in progress,
+    /// main loop statistics and the latest requests in the protocol log.
+    pub fn snapshot (&self) -> Value {
+        let screens: Vec<Value> = self.desktop.screens.iter().enumerate().map(|(id, screen)| {
+            let output = self.engine.output_get(id).map(|output| {
+                let mode = |mode: Option<Mode>| mode.map(|mode| json!({
+                    "width":   mode.size.w,
+                    "height":  mode.size.h,
+                    "refresh": mode.refresh,
+                }));
+                json!({
+                    "name":      output.name(),
+                    "mode":      mode(output.current_mode()),
+                    "preferred": mode(output.preferred_mode()),
+                    "scale":     output.current_scale().fractional_scale(),
+                    "transform": format!("{:?}", output.current_transform()),
+                })
+            });
+            json!({
+                "id":     id,
+                "center": [screen.center.x, screen.center.y],
+                "size":   [screen.size.w, screen.size.h],
+                "output": output,
+                "active": id == self.desktop.active_screen,
+            })
+        }).collect();
+        let workspaces: Vec<Value> = self.desktop.workspaces.list.iter().enumerate()
+            .map(|(index, workspace)| json!({
+                "name":      workspace.name,
+                "current":   index == self.desktop.workspaces.current,
+                "minimized": workspace.minimized.len(),
+            }))
+            .collect();
+        let window_id = |surface: Option<&WlSurface>| surface
+            .and_then(|surface| self.desktop.window_state(surface))
+            .map(|window| window.id);
+        let windows: Vec<Value> = self.desktop.windows_all().map(|window| {
+            let (app_id, title) = window.app_id_and_title();
+            let size = window.window.geometry().size;
+            let surface = window.window.toplevel().wl_surface();
+            json!({
+                "id":           window.id,
+                "app_id":       app_id,
+                "title":        title,
+                "x11":          matches!(window.window.toplevel(), Kind::X11(_)),
+                "client":       window.identity().map(|identity| identity.describe()),
+                "workspace":    self.desktop.workspaces.list.get(window.workspace).map(|w| w.name.clone()),
+                "center":       [window.center.x, window.center.y],
+                "size":         [size.w, size.h],
+                "focused":      self.desktop.focused.as_ref() == Some(surface),
+                "mapped":       window.mapped,
+                "minimized":    window.minimized,
+                "fullscreen":   window.fullscreen.as_ref().map(|fullscreen| fullscreen.screen),
+                "layer":        window.layer.name(),
+                "parent":       window_id(window.parent_surface().as_ref()),
+                "urgent":       window.urgent,
+                "unresponsive": window.unresponsive,
+                "occluded":     window.occluded,
+                "content_type": window.content_type.name(),
+                "pip":          window.pip.is_some(),
+            })
+        }).collect();
+        let keyboards: Vec<Value> = self.input.keyboards.iter().map(|keyboard| {
+            let modifiers = keyboard.handle.modifier_state();
+            json!({
+                "focus":     window_id(keyboard.handle.current_focus().as_ref()),
+                "modifiers": {
+                    "ctrl":  modifiers.ctrl,
+                    "alt":   modifiers.alt,
+                    "shift": modifiers.shift,
+                    "logo":  modifiers.logo,
+                },
+            })
+        }).collect();
+        let pointers: Vec<Value> = self.input.pointers.iter().map(|pointer| json!({
+            "screen":   pointer.screen,
+            "location": [pointer.location.x, pointer.location.y],
+            "confined": pointer.confine.map(|confine| confine.screen),
+            "grab":     pointer.grab(),
+        })).collect();
+        json!({
+            "version":    env!("CARGO_PKG_VERSION"),
+            "screens":    screens,
+            "workspaces": workspaces,
+            "windows":    windows,
+            "seat": {
+                "focused":   window_id(self.desktop.focused.as_ref()),
+                "keyboards": keyboards,
+                "pointers":  pointers,
+            },
+            "grabs": {
+                "resize":    window_id(self.resizing.window()),
+                "move":      window_id(self.moving.window()),
+                "hints":     self.hints.active(),
+                "inhibitor": self.input_inhibitor.owner.as_ref().map(|client| format!("{client:?}")),
+                "kiosk":     self.kiosk.enabled,
+            },
+            "loop": {
+                "wakeups": self.redraw.wakeups,
+                "renders": self.redraw.renders,
+                "seconds": self.redraw.since.elapsed().as_secs_f64(),
+                "latency": self.latency.report(),
+            },
+            "protocol": {
+                "enabled":  self.protocols.enabled,
+                "filter":   self.protocols.filter.to_string(),
+                "requests": self.protocols.recent(SNAPSHOT_REQUESTS),
+            },
+        })
+    }
+
+    /// Write a snapshot to a file, or to a new file in the runtime directory if None,
+    /// returning where it went.
+    pub fn snapshot_save (&self, path: Option<&str>) -> StdResult<PathBuf> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+                    .unwrap_or_else(std::env::temp_dir);
+                let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+                dir.join(format!("charlie-snapshot-{time}.json"))
+            }
+        };
+        std::fs::write(&path, serde_json::to_string_pretty(&self.snapshot())?)?;
+        info!(self.logger, "Wrote snapshot to {}", path.display());
+        Ok(path)
+    }
+
+}
@@ -0,0 +1,58 @@
+use super::prelude::*;
+
+/// Which band of the stack a window is kept in.
+/// Windows are drawn from the lowest layer up, then fullscreen windows over all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StackLayer {
+    /// Always below normal windows
+    Below,
+    #[default]
+    Normal,
+    /// Always above normal windows
+    Above,
+}
+
+impl StackLayer {
+    pub fn name (&self) -> &'static str {
+        match self {
+            Self::Below  => "below",
+            Self::Normal => "normal",
+            Self::Above  => "above",
+        }
+    }
+}
+
+impl std::str::FromStr for StackLayer {
+    type Err = Box<dyn Error>;
+    fn from_str (layer: &str) -> StdResult<Self> {
+        Ok(match layer {
+            "below"  => Self::Below,
+            "normal" => Self::Normal,
+            "above"  => Self::Above,
+            _ => return Err(format!("unknown stacking layer: {layer}").into())
+        })
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Put a window in a layer, or back into the normal layer if it's already there.
+    pub fn toggle_layer (&mut self, surface: &WlSurface, layer: StackLayer) {
+        let current = match self.desktop.window_state(surface) {
+            Some(window) => window.layer,
+            None => return
+        };
+        let layer = if current == layer { StackLayer::Normal } else { layer };
+        debug!(self.logger, "Moving {surface:?} to the {} layer", layer.name());
+        self.desktop.set_layer(surface, layer);
+    }
+
+    /// Toggle the focused window between a layer and the normal one.
+    pub fn toggle_focused_layer (&mut self, layer: StackLayer) {
+        if let Some(surface) = self.desktop.focused.clone() {
+            self.toggle_layer(&surface, layer)
+        }
+    }
+
+}
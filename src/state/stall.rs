@@ -0,0 +1,171 @@
+use super::prelude::*;
+
+/// How often the monitor thread looks at the main loop, as a fraction of the stall timeout
+const CHECKS_PER_TIMEOUT: u32 = 4;
+
+/// What the main loop is doing, shared with the monitor thread.
+#[derive(Debug)]
+struct Heartbeat {
+    /// The phase the main loop is in
+    phase:    &'static str,
+    /// When it entered that phase
+    since:    Instant,
+    /// Whether the main loop is waiting for events, which may take as long as it likes
+    idle:     bool,
+    /// How long the main loop may stay in one phase
+    timeout:  Option<Duration>,
+    /// Whether to exit once a stall is noticed, rather than wait for it to end
+    abort:    bool,
+    /// Whether the current stall has been reported
+    reported: bool,
+}
+
+/// Notices when a turn of the main loop takes longer than the frame budget, and when the
+/// main loop stops turning altogether, e.g. when a client request deadlocks on surface state.
+///
+/// Turns over budget are logged with how long each phase took. Stalls are noticed by a thread
+/// of their own, which logs the phase the main loop is stuck in. A stuck dispatch can't be
+/// unwound from another thread, so if asked to, the monitor aborts the compositor instead,
+/// leaving a core dump and letting the session manager start it again, rather than
+/// leaving the session frozen.
+pub struct Stalls {
+    logger:     Logger,
+    /// How long a turn of the main loop may take before it's reported
+    pub budget: Option<Duration>,
+    /// When the current turn started
+    turn:       Instant,
+    /// The phases of the current turn, and when each started
+    phases:     Vec<(&'static str, Instant)>,
+    heartbeat:  Arc<Mutex<Heartbeat>>,
+    /// Whether the monitor thread is running
+    monitoring: bool,
+}
+
+impl Stalls {
+
+    pub fn new (logger: &Logger) -> Self {
+        let now = Instant::now();
+        Self {
+            logger:     logger.clone(),
+            budget:     None,
+            turn:       now,
+            phases:     vec![],
+            heartbeat:  Arc::new(Mutex::new(Heartbeat {
+                phase: "startup", since: now, idle: true, timeout: None, abort: false, reported: false
+            })),
+            monitoring: false,
+        }
+    }
+
+    /// Report the main loop once it's been in one phase for this long, and abort if asked to.
+    pub fn set_timeout (&mut self, timeout: Option<Duration>, abort: bool) -> StdResult<()> {
+        {
+            let mut heartbeat = self.heartbeat.lock().unwrap();
+            heartbeat.timeout = timeout;
+            heartbeat.abort   = abort;
+        }
+        if timeout.is_some() && !self.monitoring {
+            let logger = self.logger.clone();
+            let heartbeat = self.heartbeat.clone();
+            std::thread::Builder::new().name("stall monitor".into()).spawn(move || monitor(logger, heartbeat))?;
+            self.monitoring = true;
+        }
+        Ok(())
+    }
+
+    /// Start a turn of the main loop.
+    pub fn turn_start (&mut self) {
+        self.turn = Instant::now();
+        self.phases.clear();
+        self.enter("input");
+    }
+
+    /// Note that the main loop moved on to another phase.
+    pub fn enter (&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.phases.push((phase, now));
+        let mut heartbeat = self.heartbeat.lock().unwrap();
+        heartbeat.phase    = phase;
+        heartbeat.since    = now;
+        heartbeat.idle     = false;
+        heartbeat.reported = false;
+    }
+
+    /// Note that the main loop is waiting for events again.
+    pub fn idle (&self) {
+        self.heartbeat.lock().unwrap().idle = true;
+    }
+
+    /// Finish a turn of the main loop before waiting for events, reporting it if it was over budget.
+    pub fn turn_end (&mut self) {
+        let now = Instant::now();
+        self.idle();
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return
+        };
+        let total = now - self.turn;
+        if total <= budget {
+            return
+        }
+        let mut phases: Vec<(&'static str, Duration)> = self.phases.iter().enumerate()
+            .map(|(index, (phase, start))| {
+                let end = self.phases.get(index + 1).map(|(_, end)| *end).unwrap_or(now);
+                (*phase, end - *start)
+            })
+            .collect();
+        phases.sort_by(|a, b| b.1.cmp(&a.1));
+        let report: Vec<String> = phases.iter()
+            .map(|(phase, length)| format!("{phase}={:.1}ms", length.as_secs_f64() * 1000.0))
+            .collect();
+        warn!(self.logger, "Turn took {:.1}ms, over the budget of {:.1}ms, mostly in {}: {}",
+            total.as_secs_f64() * 1000.0, budget.as_secs_f64() * 1000.0,
+            phases.first().map(|(phase, _)| *phase).unwrap_or("nothing"), report.join(" "));
+    }
+
+}
+
+/// Look at the main loop every so often, until the compositor exits.
+fn monitor (logger: Logger, heartbeat: Arc<Mutex<Heartbeat>>) {
+    loop {
+        let timeout = heartbeat.lock().unwrap().timeout;
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => {
+                // Not watching for now, but it may be asked to again
+                std::thread::sleep(Duration::from_secs(1));
+                continue
+            }
+        };
+        std::thread::sleep(timeout / CHECKS_PER_TIMEOUT);
+        let mut heartbeat = heartbeat.lock().unwrap();
+        let stuck = heartbeat.since.elapsed();
+        if heartbeat.idle || heartbeat.reported || stuck < timeout {
+            continue
+        }
+        heartbeat.reported = true;
+        crit!(logger, "Main loop stalled for {:.1}s in phase {}",
+            stuck.as_secs_f64(), heartbeat.phase);
+        if heartbeat.abort {
+            crit!(logger, "Aborting, since the main loop is stuck");
+            std::process::abort();
+        }
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Report turns of the main loop that take longer than this.
+    pub fn frame_budget (mut self, budget: Duration) -> CharlieResult<Self> {
+        self.stalls.budget = Some(budget);
+        Ok(self)
+    }
+
+    /// Report the main loop when it's stuck in one phase for this long,
+    /// and abort the compositor if asked to.
+    pub fn stall_timeout (mut self, timeout: Duration, abort: bool) -> CharlieResult<Self> {
+        self.stalls.set_timeout(Some(timeout), abort).map_err(CharlieError::other)?;
+        Ok(self)
+    }
+
+}
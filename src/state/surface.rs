@@ -0,0 +1,57 @@
+use super::prelude::*;
+
+use smithay::{
+    reexports::wayland_server::{Resource, backend::ObjectId},
+    wayland::compositor::SurfaceData,
+};
+
+thread_local! {
+    /// Surfaces whose state is being accessed on this thread, innermost last
+    static HELD: RefCell<Vec<ObjectId>> = RefCell::new(vec![]);
+}
+
+/// Accessing the state of a surface from within an access to the same surface,
+/// which would deadlock on the lock that smithay keeps the state behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reentered(pub ObjectId);
+
+impl std::fmt::Display for Reentered {
+    fn fmt (&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the state of {} is already being accessed", self.0)
+    }
+}
+
+impl Error for Reentered {}
+
+/// Lets go of a surface once the access to it ends, even by unwinding.
+struct Held;
+
+impl Drop for Held {
+    fn drop (&mut self) {
+        HELD.with(|held| held.borrow_mut().pop());
+    }
+}
+
+/// Access the state of a surface, like `with_states`, but fail instead of deadlocking
+/// when called from within an access to the same surface, e.g. from a commit hook
+/// or a closure that calls back into code which looks at the window.
+pub fn with_surface <T> (surface: &WlSurface, access: impl FnOnce(&SurfaceData) -> T)
+    -> Result<T, Reentered>
+{
+    let id = surface.id();
+    let reentered = HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        if held.contains(&id) {
+            return true
+        }
+        held.push(id.clone());
+        false
+    });
+    if reentered {
+        let error = Reentered(id);
+        error!(slog_scope::logger(), "Not accessing surface state, since it would deadlock: {error}");
+        return Err(error)
+    }
+    let _held = Held;
+    Ok(with_states(surface, access))
+}
@@ -0,0 +1,77 @@
+use super::prelude::*;
+
+use super::desktop::WindowState;
+use super::transaction::Change;
+
+/// How many generations of parent processes are searched for the one that started a window
+pub const MAX_ANCESTORS: usize = 16;
+
+/// The parent of a process, as reported by `/proc`, unless it's init.
+pub fn parent_pid (pid: i32) -> Option<i32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name is in parentheses and may contain anything, so count fields after it
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    fields.get(1)?.parse().ok().filter(|ppid| *ppid > 1)
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// If a newly mapped window was started from a terminal that swallows, hide the terminal
+    /// and put the window in its place. Returns whether it did.
+    pub fn swallow (&mut self, surface: &WlSurface) -> bool {
+        let (pid, workspace) = match self.desktop.window_state(surface) {
+            Some(window) if window.swallowable => match window.identity().and_then(|i| i.pid) {
+                Some(pid) => (pid, window.workspace),
+                None => return false
+            },
+            _ => return false
+        };
+        let mut ancestors = Vec::new();
+        let mut next = parent_pid(pid);
+        while let Some(ancestor) = next {
+            if ancestors.len() >= MAX_ANCESTORS {
+                break
+            }
+            ancestors.push(ancestor);
+            next = parent_pid(ancestor);
+        }
+        // The nearest ancestor wins, in case terminals were started from terminals
+        let terminal = ancestors.iter().find_map(|ancestor| self.desktop.windows_all()
+            .find(|window| window.swallows
+                && window.mapped
+                && !window.minimized
+                && window.workspace == workspace
+                && window.identity().and_then(|i| i.pid) == Some(*ancestor))
+            .map(|window| window.window.toplevel().wl_surface().clone()));
+        let terminal = match terminal {
+            Some(terminal) if &terminal != surface => terminal,
+            _ => return false
+        };
+        if let Some((center, size)) = self.window_geometry(&terminal) {
+            let size = Some(size.to_i32_round());
+            self.transaction_start(vec![Change { surface: surface.clone(), center, size }]);
+        }
+        debug!(self.logger, "Window {surface:?} swallows terminal {terminal:?}");
+        self.minimize(&terminal);
+        if let Some(window) = self.desktop.window_state_mut(surface) {
+            window.swallowed = Some(terminal);
+        }
+        self.desktop.raise(surface);
+        self.focus(Some(surface.clone()));
+        true
+    }
+
+    /// Show the terminal that a window which went away had swallowed, if it's still around.
+    /// Returns the terminal if it was shown.
+    pub fn unswallow (&mut self, window: &WindowState) -> Option<WlSurface> {
+        let terminal = window.swallowed.clone()?;
+        let minimized = self.desktop.window_state(&terminal)?.minimized;
+        if !minimized {
+            return None
+        }
+        self.desktop.unminimize(&terminal);
+        self.desktop.raise(&terminal);
+        Some(terminal)
+    }
+
+}
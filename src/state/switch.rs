@@ -0,0 +1,172 @@
+use super::prelude::*;
+use super::process::{Startup, Restart};
+use super::desktop::WindowState;
+use super::transaction::Change;
+
+use smithay::backend::input::{Switch, SwitchState, SwitchToggleEvent};
+
+/// Prefixes of the names of outputs built into laptops, which go dark when the lid closes
+const INTERNAL_OUTPUTS: &[&str] = &["eDP", "LVDS", "DSI"];
+
+/// What happens when the lid, the tablet mode switch, or the power button are used.
+pub struct Switches {
+    logger:              Logger,
+    /// IPC command run when the power button is pressed
+    pub power_action:    Option<String>,
+    /// On-screen keyboard to start in tablet mode
+    pub tablet_keyboard: Option<Startup>,
+    /// Whether the laptop is folded into a tablet
+    pub tablet_mode:     bool,
+    /// Screens that were turned off because the lid is closed
+    pub lid_disabled:    Vec<ScreenId>,
+    /// The on-screen keyboard, while it's running
+    keyboard_pid:        Option<u32>,
+}
+
+impl Switches {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:          logger.clone(),
+            power_action:    Some("spawn systemctl suspend".into()),
+            tablet_keyboard: None,
+            tablet_mode:     false,
+            lid_disabled:    vec![],
+            keyboard_pid:    None,
+        }
+    }
+
+    /// Whether an output is the built-in panel of a laptop.
+    pub fn internal (output: &str) -> bool {
+        INTERNAL_OUTPUTS.iter().any(|prefix| output.starts_with(prefix))
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Run an IPC command when the power button is pressed, or nothing if None.
+    pub fn power_button (mut self, command: Option<&str>) -> CharlieResult<Self> {
+        self.switches.power_action = command.map(Into::into);
+        Ok(self)
+    }
+
+    /// Run an on-screen keyboard while in tablet mode.
+    pub fn tablet_keyboard (mut self, cmd: impl AsRef<str>, args: &[&str]) -> CharlieResult<Self> {
+        self.switches.tablet_keyboard = Some(Startup::new(cmd, args, Restart::OnCrash));
+        Ok(self)
+    }
+
+    /// Respond to a switch being flipped.
+    pub fn switch_toggle <B: InputBackend> (&mut self, event: B::SwitchToggleEvent) {
+        let on = matches!(event.state(), SwitchState::On);
+        match event.switch() {
+            Some(Switch::Lid) => if on { self.lid_close() } else { self.lid_open() },
+            Some(Switch::TabletMode) => self.tablet_mode_set(on),
+            None => {}
+        }
+    }
+
+    /// Turn off the built-in panel, moving its windows to another screen.
+    /// If it's the only screen, it stays on, since the system is about to sleep anyway.
+    pub fn lid_close (&mut self) {
+        let screens: Vec<(ScreenId, bool)> = self.desktop.screens.iter().enumerate()
+            .map(|(id, screen)| (id, screen.output.as_deref().map(Switches::internal).unwrap_or(false)))
+            .collect();
+        let target = match screens.iter().find(|(_, internal)| !internal) {
+            Some((target, _)) => *target,
+            None => {
+                debug!(self.switches.logger, "Lid closed, but there's no external screen");
+                return
+            }
+        };
+        for (screen, _) in screens.into_iter().filter(|(_, internal)| *internal) {
+            if let Err(e) = self.engine.output_enable(screen, false) {
+                warn!(self.logger, "Could not turn off screen {screen}: {e}");
+                continue
+            }
+            info!(self.logger, "Lid closed, turned off screen {screen}");
+            self.switches.lid_disabled.push(screen);
+            self.screen_evacuate(screen, target);
+        }
+        self.layout_arrange();
+    }
+
+    /// Turn the built-in panel back on.
+    pub fn lid_open (&mut self) {
+        for screen in std::mem::take(&mut self.switches.lid_disabled) {
+            match self.engine.output_enable(screen, true) {
+                Ok(()) => info!(self.logger, "Lid opened, turned on screen {screen}"),
+                Err(e) => warn!(self.logger, "Could not turn on screen {screen}: {e}")
+            }
+        }
+        self.layout_arrange();
+    }
+
+    /// Move the windows that are only visible on one screen to the same place on another,
+    /// or as close to it as fits. Screens whose output is gone go by their last area in the layout.
+    pub fn screen_evacuate (&mut self, from: ScreenId, to: ScreenId) {
+        let offset = self.desktop.screens[from].center - self.desktop.screens[to].center;
+        let area = |screen| self.screen_area(screen)
+            .or_else(|| self.layout.area(screen).map(|area| area.size.to_f64()));
+        let (area_from, area_to) = match (area(from), area(to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return
+        };
+        let visible = |area: Size<f64, Logical>, screen: &ScreenState, window: &WindowState| {
+            window.geometry_on(screen).to_f64().overlaps(Rectangle::from_loc_and_size((0.0, 0.0), area))
+        };
+        let (screen_from, screen_to) = (
+            ScreenState::new(self.desktop.screens[from].center, area_from),
+            ScreenState::new(self.desktop.screens[to].center, area_to)
+        );
+        let stranded: Vec<Change> = self.desktop.windows_all()
+            .filter(|w| visible(area_from, &screen_from, w) && !visible(area_to, &screen_to, w))
+            .map(|w| {
+                // Keep the window inside the other screen if it fits
+                let size = w.window.geometry().size.to_f64();
+                let mut center = w.center + offset;
+                let screen = &self.desktop.screens[to];
+                let (x, y) = (center.x + screen.center.x, center.y + screen.center.y);
+                center.x -= (x + size.w - area_to.w).max(0.0).min(x.max(0.0));
+                center.y -= (y + size.h - area_to.h).max(0.0).min(y.max(0.0));
+                center.x -= x.min(0.0);
+                center.y -= y.min(0.0);
+                Change { surface: w.window.toplevel().wl_surface().clone(), center, size: None }
+            })
+            .collect();
+        self.transaction_start(stranded);
+    }
+
+    /// Enter or leave tablet mode, starting or stopping the on-screen keyboard.
+    pub fn tablet_mode_set (&mut self, enabled: bool) {
+        if self.switches.tablet_mode == enabled {
+            return
+        }
+        info!(self.logger, "Tablet mode {}", if enabled { "on" } else { "off" });
+        self.switches.tablet_mode = enabled;
+        if enabled {
+            if let Some(keyboard) = self.switches.tablet_keyboard.clone() {
+                match self.processes.spawn(keyboard) {
+                    Ok(pid) => self.switches.keyboard_pid = Some(pid),
+                    Err(e) => warn!(self.logger, "Could not start on-screen keyboard: {e}")
+                }
+            }
+        } else if let Some(pid) = self.switches.keyboard_pid.take() {
+            self.processes.terminate(pid);
+        }
+        self.scripts.hook("on_tablet_mode", vec![enabled.into()]);
+    }
+
+    /// Perform the power button action.
+    pub fn power_pressed (&mut self) {
+        let command = match self.switches.power_action.clone() {
+            Some(command) => command,
+            None => return
+        };
+        if let Err(e) = self.ipc_line(&command) {
+            warn!(self.logger, "Power button command {command} failed: {e}");
+        }
+    }
+
+}
@@ -0,0 +1,107 @@
+use super::prelude::*;
+
+use std::{fs::File, os::fd::{FromRawFd, RawFd}};
+
+use smithay::{
+    backend::renderer::utils::RendererSurfaceState as State,
+    wayland::explicit_synchronization::{
+        ExplicitSyncState,
+        ExplicitBufferRelease,
+        get_explicit_synchronization_state,
+    },
+    reexports::calloop::{PostAction, Interest, Mode, generic::Generic},
+};
+
+/// Explicit synchronization of client buffers (`zwp_linux_explicit_synchronization_v1`).
+///
+/// Buffers committed with an acquire fence are only taken into use once the fence signals,
+/// i.e. once the client's GPU is done drawing them. Buffers committed with a release object
+/// are released after the frames that sampled them have finished rendering.
+/// The newer `linux-drm-syncobj` protocol isn't provided by smithay yet.
+pub struct ExplicitSync {
+    logger:   Logger,
+    pub state: ExplicitSyncState,
+    /// Release objects of buffers that have been replaced, to send once rendering is done
+    releases: Vec<ExplicitBufferRelease>,
+}
+
+/// The release object of the buffer that a surface currently shows, kept in its data map.
+struct CurrentRelease(RefCell<Option<ExplicitBufferRelease>>);
+
+impl ExplicitSync {
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle) -> Self {
+        Self {
+            logger:   logger.clone(),
+            state:    ExplicitSyncState::new::<Charlie<E>, _>(display, logger.clone()),
+            releases: vec![],
+        }
+    }
+}
+
+#[delegate_explicit_sync]
+impl<E: Engine> Charlie<E> {
+
+    /// Collect the synchronization state of a commit. Returns the acquire fence,
+    /// if the new buffer must not be used until it signals.
+    pub fn explicit_sync_commit (&mut self, surface: &WlSurface) -> Option<RawFd> {
+        let (acquire, replaced) = with_states(surface, |states| {
+            let sync = get_explicit_synchronization_state(states);
+            states.data_map.insert_if_missing(|| CurrentRelease(RefCell::new(None)));
+            let current = states.data_map.get::<CurrentRelease>().unwrap();
+            // A new buffer, with or without its own release object, replaces the old one
+            let attached = states.cached_state.current::<SurfaceAttributes>().buffer.is_some();
+            let replaced = if attached || sync.release.is_some() {
+                current.0.replace(sync.release)
+            } else {
+                None
+            };
+            (sync.acquire, replaced)
+        });
+        self.sync.releases.extend(replaced);
+        acquire
+    }
+
+    /// Take a buffer into use once its acquire fence signals.
+    pub fn explicit_sync_wait (&mut self, surface: WlSurface, fence: RawFd) {
+        debug!(self.sync.logger, "Waiting for acquire fence of {surface:?}");
+        let source = Generic::new(fence, Interest::READ, Mode::OneShot);
+        let result = self.handle.insert_source(source, move |_, fence, state| {
+            // The fence was handed over to us by the client, so close it
+            drop(unsafe { File::from_raw_fd(*fence) });
+            if surface.alive() {
+                with_states(&surface, |states| {
+                    if let Some(data) = states.data_map.get::<RefCell<State>>() {
+                        data.borrow_mut().update_buffer(states);
+                    }
+                });
+                if let Some(window) = state.desktop.window_state(&surface) {
+                    state.thumbnails.damage(window.id);
+                }
+            }
+            Ok(PostAction::Remove)
+        });
+        if let Err(e) = result {
+            // Better to risk sampling an unfinished buffer than to never show it
+            warn!(self.logger, "Could not wait for acquire fence, using buffer right away: {e}");
+            drop(unsafe { File::from_raw_fd(fence) });
+        }
+    }
+
+    /// Release the buffers that were replaced, now that the frames using them are rendered.
+    pub fn explicit_sync_release (&mut self) {
+        if self.sync.releases.is_empty() {
+            return
+        }
+        // Frames are submitted but may still be rendering on the GPU; wait for them,
+        // since there's no way to hand the client a fence for that yet
+        if let Some(renderer) = self.engine.renderer().gles2() {
+            if let Err(e) = renderer.with_context(|gl| unsafe { gl.Finish() }) {
+                warn!(self.logger, "Could not wait for rendering to finish: {e}");
+            }
+        }
+        for release in self.sync.releases.drain(..) {
+            release.immediate_release();
+        }
+    }
+
+}
@@ -0,0 +1,179 @@
+use super::prelude::*;
+
+use std::collections::HashSet;
+
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum,
+    backend::{ClientId, GlobalId, ObjectId},
+};
+
+use self::protocol::{
+    wp_tearing_control_manager_v1::{self, WpTearingControlManagerV1},
+    wp_tearing_control_v1::{self, WpTearingControlV1, PresentationHint},
+};
+
+/// Code generated from `protocols/tearing-control-v1.xml`.
+pub mod protocol {
+    #![allow(non_upper_case_globals, non_camel_case_types, unused)]
+    use smithay::reexports::wayland_server;
+    use smithay::reexports::wayland_server::protocol::*;
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/tearing-control-v1.xml");
+    }
+    use self::__interfaces::*;
+    wayland_scanner::generate_server_code!("protocols/tearing-control-v1.xml");
+}
+
+/// Whether a surface has a tearing control object, kept with the surface.
+#[derive(Default)]
+struct TearingControlled(Cell<bool>);
+
+/// Lets clients say that their surfaces may be shown with tearing (`wp_tearing_control_v1`).
+///
+/// A screen whose fullscreen window asked for it flips pages as soon as frames are ready,
+/// if the engine can do that; otherwise it keeps waiting for vblank, without complaint.
+pub struct Tearing {
+    logger:  Logger,
+    global:  GlobalId,
+    /// Surfaces whose clients said that tearing is fine for them
+    hinted:  HashSet<ObjectId>,
+    /// Screens whose outputs were let tear
+    screens: HashSet<ScreenId>,
+}
+
+impl Tearing {
+
+    pub fn new <E: Engine> (logger: &Logger, display: &DisplayHandle) -> Self {
+        Self {
+            logger:  logger.clone(),
+            global:  display.create_global::<Charlie<E>, WpTearingControlManagerV1, _>(1, ()),
+            hinted:  HashSet::new(),
+            screens: HashSet::new(),
+        }
+    }
+
+    /// Stop advertising the protocol.
+    pub fn destroy <E: Engine> (&self, display: &DisplayHandle) {
+        display.remove_global::<Charlie<E>>(self.global.clone());
+    }
+
+    /// Whether the client of a surface asked for tearing page flips.
+    pub fn hinted (&self, surface: &WlSurface) -> bool {
+        self.hinted.contains(&surface.id())
+    }
+
+}
+
+impl<E: Engine> GlobalDispatch<WpTearingControlManagerV1, ()> for Charlie<E> {
+    fn bind (
+        _state:    &mut Self,
+        _display:  &DisplayHandle,
+        _client:   &Client,
+        resource:  New<WpTearingControlManagerV1>,
+        _data:     &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<E: Engine> Dispatch<WpTearingControlManagerV1, ()> for Charlie<E> {
+    fn request (
+        _state:    &mut Self,
+        _client:   &Client,
+        resource:  &WpTearingControlManagerV1,
+        request:   wp_tearing_control_manager_v1::Request,
+        _data:     &(),
+        _display:  &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_tearing_control_manager_v1::Request::GetTearingControl { id, surface } => {
+                let taken = with_states(&surface, |states| {
+                    states.data_map.insert_if_missing(TearingControlled::default);
+                    states.data_map.get::<TearingControlled>().unwrap().0.replace(true)
+                });
+                if taken {
+                    resource.post_error(
+                        wp_tearing_control_manager_v1::Error::TearingControlExists,
+                        "surface already has a tearing control"
+                    );
+                    return
+                }
+                data_init.init(id, surface);
+            },
+            wp_tearing_control_manager_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+}
+
+impl<E: Engine> Dispatch<WpTearingControlV1, WlSurface> for Charlie<E> {
+    fn request (
+        state:      &mut Self,
+        _client:    &Client,
+        _resource:  &WpTearingControlV1,
+        request:    wp_tearing_control_v1::Request,
+        surface:    &WlSurface,
+        _display:   &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_tearing_control_v1::Request::SetPresentationHint { hint } => {
+                if matches!(hint, WEnum::Value(PresentationHint::Async)) {
+                    state.tearing.hinted.insert(surface.id());
+                } else {
+                    state.tearing.hinted.remove(&surface.id());
+                }
+            },
+            wp_tearing_control_v1::Request::Destroy => {},
+            _ => {}
+        }
+    }
+    fn destroyed (
+        state:   &mut Self,
+        _client: ClientId,
+        _object: ObjectId,
+        surface: &WlSurface,
+    ) {
+        if surface.is_alive() {
+            with_states(surface, |states| {
+                if let Some(taken) = states.data_map.get::<TearingControlled>() {
+                    taken.0.set(false);
+                }
+            });
+        }
+        state.tearing.hinted.remove(&surface.id());
+    }
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Let the outputs tear whose fullscreen windows asked for it, and make the others wait
+    /// for vblank again. Outputs that can't tear are left alone.
+    pub fn tearing_update (&mut self) {
+        let wanted: HashSet<ScreenId> = self.desktop.windows_visible()
+            .filter(|window| self.tearing.hinted(window.window.toplevel().wl_surface()))
+            .filter_map(|window| window.fullscreen.as_ref().map(|f| f.screen))
+            .collect();
+        let stopped: Vec<ScreenId> = self.tearing.screens.difference(&wanted).copied().collect();
+        for screen in stopped {
+            self.tearing.screens.remove(&screen);
+            if let Err(e) = self.engine.output_set_tearing(screen, false) {
+                warn!(self.tearing.logger, "Could not stop tearing on screen {screen}: {e}");
+            }
+        }
+        for screen in wanted {
+            if self.tearing.screens.contains(&screen) {
+                continue
+            }
+            // Outputs that can't tear keep flipping on vblank
+            if self.engine.output_set_tearing(screen, true).is_ok() {
+                debug!(self.tearing.logger, "Tearing on screen {screen}");
+                self.tearing.screens.insert(screen);
+            }
+        }
+    }
+
+}
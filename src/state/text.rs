@@ -0,0 +1,319 @@
+use super::prelude::*;
+
+use smithay::backend::renderer::ImportMem;
+
+use ab_glyph::{Font, FontVec, GlyphId, PxScale, ScaleFont, point};
+
+/// Fonts to try, in order, if CHARLIE_FONT is not set
+const FALLBACK_FONTS: &[&str] = &[
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+];
+
+/// Width of the glyph atlas, and its height until it fills up
+const ATLAS_SIZE: i32 = 1024;
+
+/// How tall the glyph atlas may grow before it's cleared instead
+const ATLAS_HEIGHT_MAX: i32 = 4096;
+
+/// Padding between glyphs in the atlas, so that sampling doesn't bleed
+const ATLAS_PADDING: i32 = 1;
+
+/// Glyphs are cached by id, pixel size and color, since textures can't be tinted when drawn.
+type GlyphKey = (GlyphId, u32, [u8; 4]);
+
+/// Where a rasterized glyph is in the atlas.
+#[derive(Debug, Clone, Copy)]
+struct GlyphEntry {
+    /// Area of the atlas containing the glyph
+    src:    Rectangle<i32, Buffer>,
+    /// Position of the glyph's top left corner relative to the pen on the baseline
+    offset: Point<i32, Physical>,
+}
+
+/// A single texture into which glyphs are packed in rows, as they are first used.
+/// It grows taller as it fills up, and once it can't, it's cleared before the next frame,
+/// so that glyphs already prepared for this one stay where they are.
+struct Atlas<R: EngineRenderer> {
+    pixels:  Vec<u8>,
+    glyphs:  HashMap<GlyphKey, Option<GlyphEntry>>,
+    height:  i32,
+    /// Where the next glyph goes
+    cursor:  Point<i32, Buffer>,
+    /// Height of the tallest glyph in the current row
+    row:     i32,
+    /// Whether there are glyphs that haven't been uploaded yet
+    dirty:   bool,
+    /// Whether a glyph didn't fit, and so the atlas is cleared when the next frame begins
+    full:    bool,
+    texture: Option<R::TextureId>,
+}
+
+impl<R: EngineRenderer> Atlas<R> {
+
+    fn new () -> Self {
+        Self {
+            pixels:  vec![0; (ATLAS_SIZE * ATLAS_SIZE * 4) as usize],
+            glyphs:  HashMap::new(),
+            height:  ATLAS_SIZE,
+            cursor:  (0, 0).into(),
+            row:     0,
+            dirty:   false,
+            full:    false,
+            texture: None,
+        }
+    }
+
+    fn clear (&mut self) {
+        *self = Self::new();
+    }
+
+    /// Reserve an area of the atlas, growing it if needed, or return None if it's full.
+    fn allocate (&mut self, w: i32, h: i32) -> Option<Point<i32, Buffer>> {
+        if w > ATLAS_SIZE || h > ATLAS_HEIGHT_MAX {
+            return None
+        }
+        if self.cursor.x + w > ATLAS_SIZE {
+            self.cursor = (0, self.cursor.y + self.row + ATLAS_PADDING).into();
+            self.row = 0;
+        }
+        while self.cursor.y + h > self.height {
+            if self.height * 2 > ATLAS_HEIGHT_MAX {
+                return None
+            }
+            // The glyphs keep their places, as rows are only added at the bottom
+            self.height *= 2;
+            self.pixels.resize((ATLAS_SIZE * self.height * 4) as usize, 0);
+            self.dirty = true;
+        }
+        let location = self.cursor;
+        self.cursor.x += w + ATLAS_PADDING;
+        self.row = self.row.max(h);
+        Some(location)
+    }
+
+}
+
+/// Rasterizes text with a single font, for drawing compositor UI.
+///
+/// Like [super::cursor::Cursor], text is prepared with the renderer
+/// before the frame begins, and drawn into the frame afterwards.
+/// The strings of each frame are prepared after a call to [Self::begin].
+pub struct Text<R: EngineRenderer> {
+    logger: Logger,
+    font:   Option<FontVec>,
+    atlas:  Atlas<R>,
+}
+
+impl<R: EngineRenderer> Text<R> {
+
+    /// Load the font given by CHARLIE_FONT, or the first of the common fonts that exists.
+    pub fn from_env (logger: &Logger) -> Self {
+        let path = std::env::var("CHARLIE_FONT").ok()
+            .or_else(|| FALLBACK_FONTS.iter()
+                .find(|path| Path::new(path).exists())
+                .map(|path| path.to_string()));
+        match path {
+            Some(path) => Self::load(logger, path),
+            None => {
+                warn!(logger, "No font found, text will not be drawn. Set CHARLIE_FONT to a TTF/OTF file");
+                Self { logger: logger.clone(), font: None, atlas: Atlas::new() }
+            }
+        }
+    }
+
+    /// Load a font from a file.
+    /// If that fails, drawing text does nothing.
+    pub fn load (logger: &Logger, path: impl AsRef<Path>) -> Self {
+        let font = match Self::load_font(path.as_ref()) {
+            Ok(font) => Some(font),
+            Err(e) => {
+                warn!(logger, "Could not load font {}: {e}", path.as_ref().display());
+                None
+            }
+        };
+        Self { logger: logger.clone(), font, atlas: Atlas::new() }
+    }
+
+    fn load_font (path: &Path) -> StdResult<FontVec> {
+        Ok(FontVec::try_from_vec(std::fs::read(path)?)?)
+    }
+
+    /// The size of the area covered by a string.
+    pub fn measure (&self, text: &str, size: f32) -> Size<i32, Physical> {
+        let font = match self.font.as_ref() {
+            Some(font) => font.as_scaled(PxScale::from(size)),
+            None => return (0, 0).into()
+        };
+        let mut width: f32 = 0.0;
+        let mut lines = 0;
+        for line in text.lines() {
+            lines += 1;
+            let mut caret = 0.0;
+            let mut last = None;
+            for c in line.chars() {
+                let id = font.glyph_id(c);
+                if let Some(last) = last {
+                    caret += font.kern(last, id);
+                }
+                caret += font.h_advance(id);
+                last = Some(id);
+            }
+            width = width.max(caret);
+        }
+        let height = lines.max(1) as f32 * font.height()
+            + (lines.max(1) - 1) as f32 * font.line_gap();
+        (width.ceil() as i32, height.ceil() as i32).into()
+    }
+
+    /// Start preparing the strings of a new frame. If the atlas filled up during the last one,
+    /// it's cleared now, while no string refers to its glyphs.
+    pub fn begin (&mut self) {
+        if self.atlas.full {
+            debug!(self.logger, "Glyph atlas full, clearing");
+            self.atlas.clear();
+        }
+    }
+
+    /// Rasterize any glyphs of this string that aren't in the atlas yet, and upload it.
+    /// Glyphs that don't fit are left out until the atlas is cleared by [Self::begin].
+    pub fn prepare (
+        &mut self,
+        renderer: &mut R,
+        text:     &str,
+        size:     f32,
+        color:    [f32; 4]
+    ) -> StdResult<()> {
+        let font = match self.font.as_ref() {
+            Some(font) => font,
+            None => return Ok(())
+        };
+        let (px, color) = (size.round() as u32, rgba8(color));
+        for c in text.chars().filter(|c| !c.is_control()) {
+            let id = font.glyph_id(c);
+            if self.atlas.glyphs.contains_key(&(id, px, color)) {
+                continue
+            }
+            match Self::rasterize(font, &mut self.atlas, id, px, color) {
+                Ok(entry) => {
+                    self.atlas.glyphs.insert((id, px, color), entry);
+                },
+                // Out of room: the glyphs of the other strings of this frame must stay,
+                // so this one is tried again once the atlas is cleared
+                Err(()) => self.atlas.full = true
+            }
+        }
+        if self.atlas.dirty || self.atlas.texture.is_none() {
+            self.atlas.texture = Some(renderer.import_memory(
+                &self.atlas.pixels,
+                (ATLAS_SIZE, self.atlas.height).into(),
+                false
+            )?);
+            self.atlas.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Draw a glyph into the atlas. Returns None for glyphs with no outline,
+    /// or an error if the atlas is full.
+    fn rasterize (
+        font:  &FontVec,
+        atlas: &mut Atlas<R>,
+        id:    GlyphId,
+        px:    u32,
+        color: [u8; 4]
+    ) -> Result<Option<GlyphEntry>, ()> {
+        let glyph = id.with_scale_and_position(PxScale::from(px as f32), point(0.0, 0.0));
+        let outline = match font.outline_glyph(glyph) {
+            Some(outline) => outline,
+            None => return Ok(None)
+        };
+        let bounds = outline.px_bounds();
+        let (w, h) = (bounds.width().ceil() as i32, bounds.height().ceil() as i32);
+        let location = atlas.allocate(w, h).ok_or(())?;
+        outline.draw(|x, y, coverage| {
+            let (x, y) = (location.x + x as i32, location.y + y as i32);
+            if x >= ATLAS_SIZE || y >= atlas.height {
+                return
+            }
+            let index = ((y * ATLAS_SIZE + x) * 4) as usize;
+            // The renderer blends premultiplied alpha
+            let alpha = coverage.clamp(0.0, 1.0) * color[3] as f32 / 255.0;
+            atlas.pixels[index]     = (color[0] as f32 * alpha) as u8;
+            atlas.pixels[index + 1] = (color[1] as f32 * alpha) as u8;
+            atlas.pixels[index + 2] = (color[2] as f32 * alpha) as u8;
+            atlas.pixels[index + 3] = (255.0 * alpha) as u8;
+        });
+        atlas.dirty = true;
+        Ok(Some(GlyphEntry {
+            src:    Rectangle::from_loc_and_size(location, (w, h)),
+            offset: (bounds.min.x.floor() as i32, bounds.min.y.floor() as i32).into(),
+        }))
+    }
+
+    /// Draw a string that has been prepared with the same size and color.
+    /// The position is the top left corner of the first line.
+    pub fn draw_text (
+        &self,
+        frame:    &mut R::Frame<'_>,
+        position: Point<i32, Physical>,
+        text:     &str,
+        size:     f32,
+        color:    [f32; 4]
+    ) -> StdResult<()> {
+        self.draw_text_faded(frame, position, text, size, color, 1.0)
+    }
+
+    /// Draw a prepared string with this much opacity on top of that of its color.
+    pub fn draw_text_faded (
+        &self,
+        frame:    &mut R::Frame<'_>,
+        position: Point<i32, Physical>,
+        text:     &str,
+        size:     f32,
+        color:    [f32; 4],
+        alpha:    f32
+    ) -> StdResult<()> {
+        let (font, texture) = match (self.font.as_ref(), self.atlas.texture.as_ref()) {
+            (Some(font), Some(texture)) => (font.as_scaled(PxScale::from(size)), texture),
+            _ => return Ok(())
+        };
+        let (px, color) = (size.round() as u32, rgba8(color));
+        let mut baseline = position.y as f32 + font.ascent();
+        for line in text.lines() {
+            let mut caret = position.x as f32;
+            let mut last = None;
+            for c in line.chars() {
+                let id = font.glyph_id(c);
+                if let Some(last) = last {
+                    caret += font.kern(last, id);
+                }
+                if let Some(Some(glyph)) = self.atlas.glyphs.get(&(id, px, color)) {
+                    let location = Point::<i32, Physical>::from((caret.round() as i32, baseline.round() as i32))
+                        + glyph.offset;
+                    let dest = Rectangle::from_loc_and_size(location, (glyph.src.size.w, glyph.src.size.h));
+                    frame.render_texture_from_to(
+                        texture,
+                        glyph.src.to_f64(),
+                        dest,
+                        &[Rectangle::from_loc_and_size((0, 0), dest.size)],
+                        Transform::Normal,
+                        alpha
+                    )?;
+                }
+                caret += font.h_advance(id);
+                last = Some(id);
+            }
+            baseline += font.height() + font.line_gap();
+        }
+        Ok(())
+    }
+
+}
+
+fn rgba8 (color: [f32; 4]) -> [u8; 4] {
+    color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
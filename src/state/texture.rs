@@ -0,0 +1,121 @@
+use super::prelude::*;
+
+use smithay::{
+    reexports::wayland_server::{Resource, backend::ObjectId, protocol::wl_buffer::WlBuffer},
+    wayland::shm::with_buffer_contents,
+};
+
+/// The size of the texture that a surface's buffer was imported into,
+/// kept with the surface for when the buffer was already released.
+#[derive(Default)]
+pub struct ImportedSize(pub Cell<Option<Size<i32, Buffer>>>);
+
+/// The texture that a surface was last drawn with, kept with the surface so that it can
+/// still be drawn while the upload of its next buffer waits for a later frame.
+pub struct ShownTexture<T>(pub RefCell<Option<T>>);
+
+impl<T> Default for ShownTexture<T> {
+    fn default () -> Self {
+        Self(RefCell::new(None))
+    }
+}
+
+/// Whether a buffer is in shared memory, and so is copied when imported.
+pub fn is_shm (buffer: &WlBuffer) -> bool {
+    with_buffer_contents(buffer, |_, _, _| ()).is_ok()
+}
+
+/// Textures that buffers were imported into, by buffer.
+///
+/// Surfaces that attach a buffer that's already been imported, such as subsurfaces sharing
+/// a buffer, or a buffer attached again without damage, reuse the texture instead of importing
+/// it again. A texture is dropped once its buffer is destroyed, or attached again with damage.
+///
+/// Copying shared memory into textures happens on the render thread, so a few large buffers
+/// arriving at once could take longer than a frame. With an upload budget, each frame copies
+/// at most that many bytes (and always at least one buffer), and the surfaces whose uploads
+/// are left for the next frame keep showing the texture they had, so the new contents replace
+/// the old at once, when their upload is done.
+pub struct TextureCache<R: EngineRenderer> {
+    logger:   Logger,
+    textures: HashMap<ObjectId, (WlBuffer, R::TextureId)>,
+    /// Most bytes of shared memory to upload in one frame; None for no limit
+    pub upload_budget: Option<usize>,
+    /// Bytes uploaded in the current frame
+    uploaded: usize,
+    /// Whether uploads were left for the next frame
+    deferred: bool,
+}
+
+impl<R: EngineRenderer> TextureCache<R> {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:        logger.clone(),
+            textures:      HashMap::new(),
+            upload_budget: None,
+            uploaded:      0,
+            deferred:      false,
+        }
+    }
+
+    /// Start importing the buffers for a new frame.
+    pub fn frame_start (&mut self) {
+        self.uploaded = 0;
+        self.deferred = false;
+        self.prune();
+    }
+
+    /// Whether a buffer of this many bytes may be uploaded in the current frame.
+    /// If not, it's noted so that another frame can be drawn to upload it.
+    pub fn may_upload (&mut self, bytes: usize) -> bool {
+        match self.upload_budget {
+            Some(budget) if self.uploaded > 0 && self.uploaded + bytes > budget => {
+                self.deferred = true;
+                false
+            },
+            _ => {
+                self.uploaded += bytes;
+                true
+            }
+        }
+    }
+
+    /// Whether some uploads were left for the next frame.
+    pub fn deferred (&self) -> bool {
+        self.deferred
+    }
+
+    /// The texture a buffer was imported into, if it's still current.
+    pub fn get (&self, buffer: &WlBuffer) -> Option<R::TextureId> {
+        self.textures.get(&buffer.id()).map(|(_, texture)| texture.clone())
+    }
+
+    pub fn insert (&mut self, buffer: &WlBuffer, texture: R::TextureId) {
+        self.textures.insert(buffer.id(), (buffer.clone(), texture));
+    }
+
+    /// Forget the texture of a buffer whose contents changed.
+    pub fn invalidate (&mut self, buffer: &WlBuffer) {
+        if self.textures.remove(&buffer.id()).is_some() {
+            debug!(self.logger, "Dropped texture of {buffer:?}");
+        }
+    }
+
+    /// Forget the textures of buffers that were destroyed.
+    pub fn prune (&mut self) {
+        self.textures.retain(|_, (buffer, _)| buffer.is_alive());
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Upload at most this many bytes of shared memory buffers per frame,
+    /// leaving the rest for the following frames.
+    pub fn upload_budget (mut self, bytes: usize) -> CharlieResult<Self> {
+        self.desktop.textures.upload_budget = Some(bytes);
+        Ok(self)
+    }
+
+}
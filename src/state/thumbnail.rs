@@ -0,0 +1,154 @@
+use super::prelude::*;
+
+use smithay::backend::renderer::{Bind, Offscreen, Unbind};
+
+/// A window rendered into a texture of its own.
+pub struct Thumbnail {
+    /// Size of the texture
+    pub size:    Size<i32, Physical>,
+    pub texture: Gles2Texture,
+    /// Whether the window has changed since this was rendered
+    dirty:       bool,
+    /// When this was rendered
+    rendered:    Instant,
+}
+
+/// Offscreen renders of windows at requested sizes, for previews in compositor UI.
+///
+/// Consumers request a thumbnail for a window id and a size, and pick up the texture
+/// on subsequent frames. Thumbnails are rendered anew only after their window commits,
+/// and no more often than the rate limit, so that a window playing video doesn't cost
+/// a second render of it on every frame. They are rendered offscreen with GLES2, so engines whose renderer isn't built on it have none.
+pub struct Thumbnails {
+    logger:    Logger,
+    /// Which windows have thumbnails requested, and at what size
+    requested: HashMap<u64, Size<i32, Physical>>,
+    /// Thumbnails rendered so far, by window id
+    cache:     HashMap<u64, Thumbnail>,
+    /// Most times per second that a thumbnail is rendered again; None for no limit
+    pub max_rate: Option<u32>,
+}
+
+impl Thumbnails {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:    logger.clone(),
+            requested: HashMap::new(),
+            cache:     HashMap::new(),
+            max_rate:  Some(10),
+        }
+    }
+
+    /// Keep a thumbnail of this window at this size up to date, starting with the next frame.
+    pub fn request (&mut self, id: u64, size: Size<i32, Physical>) {
+        if self.requested.insert(id, size) != Some(size) {
+            self.damage(id);
+        }
+    }
+
+    /// Stop updating the thumbnail of this window and free it.
+    pub fn release (&mut self, id: u64) {
+        self.requested.remove(&id);
+        self.cache.remove(&id);
+    }
+
+    /// Mark the thumbnail of a window as out of date.
+    pub fn damage (&mut self, id: u64) {
+        if let Some(thumbnail) = self.cache.get_mut(&id) {
+            thumbnail.dirty = true;
+        }
+    }
+
+    /// The latest thumbnail of a window, if one has been rendered yet.
+    pub fn get (&self, id: u64) -> Option<&Thumbnail> {
+        self.cache.get(&id)
+    }
+
+    /// When a changed thumbnail may be rendered again, going by the rate limit.
+    fn due (&self, thumbnail: &Thumbnail) -> Instant {
+        match self.max_rate {
+            Some(rate) if rate > 0 => thumbnail.rendered + Duration::from_secs(1) / rate,
+            _ => thumbnail.rendered
+        }
+    }
+
+    /// Which thumbnails need to be rendered now, and when the next of the others will be due.
+    fn outdated (&self) -> (Vec<(u64, Size<i32, Physical>)>, Option<Instant>) {
+        let now = Instant::now();
+        let mut later: Option<Instant> = None;
+        let outdated = self.requested.iter()
+            .filter(|(id, size)| match self.cache.get(id) {
+                // A new size is needed right away, since the old one doesn't fit
+                Some(thumbnail) if thumbnail.size != **size => true,
+                Some(thumbnail) if thumbnail.dirty => {
+                    let due = self.due(thumbnail);
+                    if due > now {
+                        later = Some(later.map_or(due, |later| later.min(due)));
+                    }
+                    due <= now
+                },
+                Some(_) => false,
+                None => true
+            })
+            .map(|(id, size)| (*id, *size))
+            .collect();
+        (outdated, later)
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Render each window thumbnail again at most this many times per second.
+    pub fn thumbnail_rate (mut self, rate: u32) -> CharlieResult<Self> {
+        self.thumbnails.max_rate = Some(rate);
+        Ok(self)
+    }
+
+    /// Render the thumbnails of the windows that have changed since the last time.
+    /// This happens between output frames, since it binds the renderer to other targets.
+    pub fn thumbnails_update (&mut self) -> CharlieResult<()> {
+        let (outdated, later) = self.thumbnails.outdated();
+        // Changes that came too soon are picked up once the rate limit allows
+        if let Some(later) = later {
+            self.redraw.request_at(later);
+        }
+        if outdated.is_empty() {
+            return Ok(())
+        }
+        let mut renderer = self.engine.renderer();
+        let renderer = match renderer.gles2() {
+            Some(renderer) => renderer,
+            None => return Ok(())
+        };
+        self.desktop.import(&mut *renderer, &self.memory.refused)?;
+        for (id, size) in outdated {
+            let window = match self.desktop.windows_all().find(|w| w.id == id) {
+                Some(window) => window,
+                None => {
+                    // The window is gone
+                    self.thumbnails.release(id);
+                    continue
+                }
+            };
+            let texture = match self.thumbnails.cache.remove(&id) {
+                Some(thumbnail) if thumbnail.size == size => thumbnail.texture,
+                _ => Offscreen::<Gles2Texture>::create_buffer(
+                    &mut *renderer, (size.w, size.h).into()
+                )?
+            };
+            renderer.bind(texture.clone())?;
+            let mut frame = renderer.render(size, Transform::Normal)?;
+            frame.clear([0.0, 0.0, 0.0, 0.0], &[Rectangle::from_loc_and_size((0, 0), size)])?;
+            if let Err(e) = window.render_thumbnail::<Gles2Renderer>(&mut frame, size) {
+                warn!(self.thumbnails.logger, "Could not render thumbnail of window {id}: {e}");
+            }
+            frame.finish()?;
+            self.thumbnails.cache.insert(id, Thumbnail { size, texture, dirty: false, rendered: Instant::now() });
+        }
+        renderer.unbind()?;
+        Ok(())
+    }
+
+}
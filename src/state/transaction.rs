@@ -0,0 +1,148 @@
+use super::prelude::*;
+
+use smithay::wayland::shell::xdg::ToplevelConfigure;
+
+/// A new place, and optionally a new size, for a window.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub surface: WlSurface,
+    pub center:  Point<f64, Logical>,
+    pub size:    Option<Size<i32, Logical>>,
+}
+
+/// A change that's waiting for its window to catch up.
+struct Pending {
+    change: Change,
+    /// Whether the window has acked the configure with the new size
+    acked:  bool,
+    /// Whether the window has committed since acking, or didn't need to resize
+    ready:  bool,
+}
+
+/// Changes to several windows that are shown together.
+struct Transaction {
+    changes:  Vec<Pending>,
+    /// When to give up on the windows that haven't caught up, and apply anyway
+    deadline: Instant,
+}
+
+impl Transaction {
+
+    fn ready (&self) -> bool {
+        self.changes.iter().all(|change| change.ready)
+    }
+
+}
+
+/// Applies changes to the geometry of several windows at once.
+///
+/// The windows that need to change size are sent configures first. Windows are only moved,
+/// all together on the same frame, once every one of them has acked and committed its new
+/// size, or once `timeout` has passed, so that the intermediate states aren't shown.
+pub struct Transactions {
+    logger:      Logger,
+    /// How long to wait for windows to catch up
+    pub timeout: Duration,
+    /// Transactions that are waiting for windows, oldest first
+    pending:     Vec<Transaction>,
+}
+
+impl Transactions {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self { logger: logger.clone(), timeout: Duration::from_millis(200), pending: vec![] }
+    }
+
+    /// When the oldest transaction gives up waiting, if there is one.
+    pub fn next_wakeup (&self) -> Option<Instant> {
+        self.pending.first().map(|transaction| transaction.deadline)
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Wait at most this long for windows to catch up before applying a transaction.
+    pub fn transaction_timeout (mut self, timeout: Duration) -> CharlieResult<Self> {
+        self.transactions.timeout = timeout;
+        Ok(self)
+    }
+
+    /// Configure the windows that change size, and move them all once they've caught up.
+    pub fn transaction_start (&mut self, changes: Vec<Change>) {
+        let mut pending = Vec::with_capacity(changes.len());
+        let mut x11 = vec![];
+        for change in changes {
+            let window = match self.desktop.window_state_mut(&change.surface) {
+                Some(window) => window,
+                None => continue
+            };
+            let resize = change.size.filter(|size| *size != window.window.geometry().size);
+            let ready = match (resize, window.window.toplevel()) {
+                (Some(size), Kind::Xdg(toplevel)) => {
+                    window.size = size.to_f64();
+                    toplevel.with_pending_state(|state| state.size = Some(size));
+                    toplevel.send_configure();
+                    false
+                },
+                // X11 windows are resized right away, since they don't ack configures
+                (Some(size), Kind::X11(_)) => {
+                    window.size = size.to_f64();
+                    x11.push((change.surface.clone(), change.center.to_i32_round(), size));
+                    true
+                },
+                // Windows that keep their size only move
+                _ => true
+            };
+            pending.push(Pending { change, acked: false, ready });
+        }
+        for (surface, location, size) in x11 {
+            self.x11_configure_window(&surface, location, size);
+        }
+        debug!(self.transactions.logger, "Starting transaction of {} windows", pending.len());
+        let deadline = Instant::now() + self.transactions.timeout;
+        self.transactions.pending.push(Transaction { changes: pending, deadline });
+    }
+
+    /// Note that a window acked a configure with the size a transaction asked for.
+    pub fn transaction_ack (&mut self, surface: &WlSurface, configure: &ToplevelConfigure) {
+        for transaction in self.transactions.pending.iter_mut() {
+            for pending in transaction.changes.iter_mut().filter(|p| p.change.surface == *surface) {
+                if pending.change.size.is_some() && configure.state.size == pending.change.size {
+                    pending.acked = true;
+                }
+            }
+        }
+    }
+
+    /// Note that a window committed, catching up with the transactions whose size it acked.
+    pub fn transaction_commit (&mut self, surface: &WlSurface) {
+        for transaction in self.transactions.pending.iter_mut() {
+            for pending in transaction.changes.iter_mut().filter(|p| p.change.surface == *surface) {
+                pending.ready |= pending.acked;
+            }
+        }
+    }
+
+    /// Apply the transactions that are ready or overdue, in the order they were started.
+    /// A transaction that's still waiting holds back the ones started after it.
+    pub fn transactions_flush (&mut self) {
+        let now = Instant::now();
+        while let Some(transaction) = self.transactions.pending.first() {
+            if !transaction.ready() {
+                if now < transaction.deadline {
+                    break
+                }
+                warn!(self.transactions.logger, "Applying transaction before all windows caught up");
+            }
+            let transaction = self.transactions.pending.remove(0);
+            for Pending { change, .. } in transaction.changes {
+                if let Some(window) = self.desktop.window_state_mut(&change.surface) {
+                    window.center = change.center;
+                }
+                self.geometry_remember(&change.surface);
+            }
+        }
+    }
+
+}
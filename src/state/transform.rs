@@ -0,0 +1,84 @@
+use super::prelude::*;
+
+use smithay::utils::Coordinate;
+
+/// Split a transform into counter-clockwise quarter turns and whether it's flipped first.
+fn parts (transform: Transform) -> (u8, bool) {
+    match transform {
+        Transform::Normal     => (0, false),
+        Transform::_90        => (1, false),
+        Transform::_180       => (2, false),
+        Transform::_270       => (3, false),
+        Transform::Flipped    => (0, true),
+        Transform::Flipped90  => (1, true),
+        Transform::Flipped180 => (2, true),
+        Transform::Flipped270 => (3, true),
+    }
+}
+
+fn from_parts (turns: u8, flipped: bool) -> Transform {
+    match (turns % 4, flipped) {
+        (0, false) => Transform::Normal,
+        (1, false) => Transform::_90,
+        (2, false) => Transform::_180,
+        (3, false) => Transform::_270,
+        (0, true)  => Transform::Flipped,
+        (1, true)  => Transform::Flipped90,
+        (2, true)  => Transform::Flipped180,
+        _          => Transform::Flipped270,
+    }
+}
+
+/// The transform that undoes this one.
+pub fn invert (transform: Transform) -> Transform {
+    match parts(transform) {
+        (turns, false) => from_parts(4 - turns, false),
+        // Flips are their own inverse, and so are flipped rotations
+        (_, true) => transform
+    }
+}
+
+/// The transform equivalent to applying `inner` and then `outer`.
+pub fn compose (outer: Transform, inner: Transform) -> Transform {
+    let (outer_turns, outer_flipped) = parts(outer);
+    let (inner_turns, inner_flipped) = parts(inner);
+    // A flip reverses the direction of the rotations that come before it
+    let inner_turns = if outer_flipped { 4 - inner_turns } else { inner_turns };
+    from_parts(outer_turns + inner_turns, outer_flipped != inner_flipped)
+}
+
+/// The size of an area after applying the transform.
+pub fn transform_size <N: Coordinate, K> (transform: Transform, size: Size<N, K>) -> Size<N, K> {
+    match parts(transform) {
+        (1, _) | (3, _) => (size.h, size.w).into(),
+        _ => size
+    }
+}
+
+/// Map a point in an area of the given (untransformed) size to where it ends up after the transform.
+pub fn transform_point <K> (
+    transform: Transform,
+    point:     Point<f64, K>,
+    area:      Size<f64, K>
+) -> Point<f64, K> {
+    let (x, y, w, h) = (point.x, point.y, area.w, area.h);
+    match transform {
+        Transform::Normal     => (x, y),
+        Transform::_90        => (h - y, x),
+        Transform::_180       => (w - x, h - y),
+        Transform::_270       => (y, w - x),
+        Transform::Flipped    => (w - x, y),
+        Transform::Flipped90  => (y, x),
+        Transform::Flipped180 => (x, h - y),
+        Transform::Flipped270 => (h - y, w - x),
+    }.into()
+}
+
+/// Map a point on a transformed area back to where it was before the transform.
+pub fn untransform_point <K> (
+    transform: Transform,
+    point:     Point<f64, K>,
+    area:      Size<f64, K>
+) -> Point<f64, K> {
+    transform_point(invert(transform), point, transform_size(transform, area))
+}
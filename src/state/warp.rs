@@ -0,0 +1,90 @@
+use super::prelude::*;
+use super::input::Pointer;
+
+/// An area that the pointer can't leave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Confine {
+    /// The screen the pointer is kept on
+    pub screen: ScreenId,
+    /// The part of the screen the pointer is kept in, or the whole screen
+    pub region: Option<Rectangle<f64, Logical>>,
+}
+
+impl Confine {
+
+    /// Where the pointer ends up when moved to a point on a screen,
+    /// or None if it can't go to that screen at all.
+    pub fn apply (&self, screen: ScreenId, location: Point<f64, Logical>) -> Option<Point<f64, Logical>> {
+        if screen != self.screen {
+            return None
+        }
+        Some(match self.region {
+            Some(region) => (
+                location.x.clamp(region.loc.x, region.loc.x + (region.size.w - 1.0).max(0.0)),
+                location.y.clamp(region.loc.y, region.loc.y + (region.size.h - 1.0).max(0.0))
+            ).into(),
+            None => location
+        })
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Move all pointers to a point on a screen, telling clients as if the user had moved them.
+    pub fn pointer_warp (&mut self, screen: ScreenId, location: Point<f64, Logical>) -> CharlieResult<()> {
+        if screen >= self.desktop.screens.len() {
+            return Err(CharlieError::Config(format!("no screen {screen}")))
+        }
+        let time = self.desktop.now_ms();
+        for index in 0..self.input.pointers.len() {
+            Pointer::move_to(self, index, location, screen, time);
+        }
+        Ok(())
+    }
+
+    /// Move the pointers to the middle of the focused window, on the screen they're on.
+    pub fn pointer_warp_to_focused (&mut self) {
+        let focused = match self.desktop.focused.as_ref().and_then(|s| self.desktop.window_state(s)) {
+            Some(window) => window,
+            None => return
+        };
+        let screen = self.input.pointers.first().map(|p| p.screen).unwrap_or(0);
+        let geometry = match self.desktop.screens.get(screen) {
+            Some(screen) => focused.geometry_on(screen).to_f64(),
+            None => return
+        };
+        let center = (
+            geometry.loc.x + geometry.size.w / 2.0,
+            geometry.loc.y + geometry.size.h / 2.0
+        ).into();
+        if let Err(e) = self.pointer_warp(screen, center) {
+            warn!(self.logger, "Could not warp pointer: {e}");
+        }
+    }
+
+    /// Keep all pointers on a screen, or in a region of it.
+    /// Pointers outside are moved into it.
+    pub fn pointer_confine (&mut self, screen: ScreenId, region: Option<Rectangle<f64, Logical>>)
+        -> CharlieResult<()>
+    {
+        if screen >= self.desktop.screens.len() {
+            return Err(CharlieError::Config(format!("no screen {screen}")))
+        }
+        let confine = Confine { screen, region };
+        for pointer in self.input.pointers.iter_mut() {
+            pointer.confine = Some(confine);
+        }
+        let location = self.input.pointers.first().map(|p| p.location).unwrap_or_default();
+        let location = confine.apply(screen, location).unwrap_or(location);
+        self.pointer_warp(screen, location)
+    }
+
+    /// Let all pointers move freely again.
+    pub fn pointer_release (&mut self) {
+        for pointer in self.input.pointers.iter_mut() {
+            pointer.confine = None;
+        }
+    }
+
+}
@@ -0,0 +1,158 @@
+use super::prelude::*;
+
+use smithay::{
+    reexports::wayland_server::{Resource, backend::protocol::ProtocolError},
+    wayland::shell::xdg::ShellClient,
+};
+
+use nix::{sys::signal::{kill, Signal}, unistd::Pid};
+
+/// Notices clients that stopped responding, by pinging them through `xdg_wm_base`.
+///
+/// The client of the focused window is pinged every `interval`. If it doesn't answer
+/// within `timeout`, its windows are marked unresponsive and drawn faded out until it does.
+pub struct Watchdog {
+    logger:       Logger,
+    /// How often to ping the client of the focused window
+    pub interval: Duration,
+    /// How long a client may take to answer before it's considered unresponsive
+    pub timeout:  Duration,
+    /// When the last ping was sent
+    last:         Option<Instant>,
+    /// Clients that haven't answered yet, and when they were pinged
+    pending:      Vec<(ShellClient, Instant)>,
+}
+
+impl Watchdog {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:   logger.clone(),
+            interval: Duration::from_secs(2),
+            timeout:  Duration::from_secs(5),
+            last:     None,
+            pending:  vec![],
+        }
+    }
+
+    /// When the next ping is due, or the earliest a pinged client becomes overdue.
+    pub fn next_wakeup (&self) -> Instant {
+        let ping = self.last.map(|last| last + self.interval).unwrap_or_else(Instant::now);
+        self.pending.iter().map(|(_, sent)| *sent + self.timeout).fold(ping, Instant::min)
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Ping the focused client this often, and consider it unresponsive after this long.
+    pub fn watchdog (mut self, interval: Duration, timeout: Duration) -> CharlieResult<Self> {
+        self.watchdog.interval = interval;
+        self.watchdog.timeout  = timeout;
+        Ok(self)
+    }
+
+    /// Ping the client of the focused window if it's due, and mark the windows
+    /// of clients that took too long to answer as unresponsive.
+    pub fn watchdog_tick (&mut self) {
+        let now = Instant::now();
+        if self.watchdog.last.map(|last| now - last >= self.watchdog.interval).unwrap_or(true) {
+            self.watchdog.last = Some(now);
+            if let Some(client) = self.desktop.focused.as_ref().and_then(|s| self.shell_client(s)) {
+                if !self.watchdog.pending.iter().any(|(pending, _)| *pending == client) {
+                    match client.send_ping(SERIAL_COUNTER.next_serial()) {
+                        Ok(()) => self.watchdog.pending.push((client, now)),
+                        Err(e) => debug!(self.watchdog.logger, "Could not ping client: {e:?}")
+                    }
+                }
+            }
+        }
+        let timeout = self.watchdog.timeout;
+        let overdue: Vec<ShellClient> = self.watchdog.pending.iter()
+            .filter(|(client, sent)| client.alive() && now - *sent >= timeout)
+            .map(|(client, _)| client.clone())
+            .collect();
+        self.watchdog.pending.retain(|(client, _)| client.alive());
+        for client in overdue {
+            self.windows_responsive(&client, false);
+        }
+    }
+
+    /// Stop waiting for a client that answered a ping.
+    pub fn watchdog_pong (&mut self, client: ShellClient) {
+        self.watchdog.pending.retain(|(pending, _)| *pending != client);
+        self.windows_responsive(&client, true);
+    }
+
+    /// Mark the windows of a client as responsive or not.
+    fn windows_responsive (&mut self, client: &ShellClient, responsive: bool) {
+        let surfaces: Vec<WlSurface> = self.desktop.windows_all()
+            .map(|w| w.window.toplevel().wl_surface().clone())
+            .filter(|surface| self.shell_client(surface).as_ref() == Some(client))
+            .collect();
+        for surface in surfaces {
+            if let Some(window) = self.desktop.window_state_mut(&surface) {
+                if window.unresponsive == responsive {
+                    window.unresponsive = !responsive;
+                    self.redraw.request();
+                    if responsive {
+                        info!(self.watchdog.logger, "Window {} is responding again", window.id);
+                    } else {
+                        warn!(self.watchdog.logger, "Window {} is not responding", window.id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The `xdg_wm_base` client that a window belongs to.
+    fn shell_client (&self, surface: &WlSurface) -> Option<ShellClient> {
+        match self.desktop.window_state(surface)?.window.toplevel() {
+            Kind::Xdg(toplevel) => toplevel.client(),
+            Kind::X11(_) => None
+        }
+    }
+
+    /// Close the focused window. If it's not responding, kill its client instead:
+    /// its process, if it's known and isn't ours, or otherwise its connection.
+    pub fn window_kill (&mut self) {
+        let surface = match self.desktop.focused.clone() {
+            Some(surface) => surface,
+            None => return
+        };
+        let window = match self.desktop.window_state(&surface) {
+            Some(window) => window,
+            None => return
+        };
+        if !window.unresponsive {
+            self.window_close(&surface);
+            return
+        }
+        let client = match surface.client() {
+            Some(client) => client,
+            None => return
+        };
+        let handle = self.display.borrow().handle();
+        let pid = client.get_credentials(&handle).ok()
+            .map(|credentials| credentials.pid)
+            .filter(|pid| *pid > 0 && *pid as u32 != std::process::id());
+        match pid {
+            Some(pid) => {
+                warn!(self.logger, "Killing unresponsive window {} (pid {pid})", window.id);
+                if let Err(e) = kill(Pid::from_raw(pid), Signal::SIGKILL) {
+                    warn!(self.logger, "Could not kill {pid}: {e}");
+                }
+            },
+            None => {
+                warn!(self.logger, "Disconnecting unresponsive window {}", window.id);
+                client.kill(&handle, ProtocolError {
+                    code:             0,
+                    object_id:        0,
+                    object_interface: "wl_display".into(),
+                    message:          "client is not responding".into(),
+                });
+            }
+        }
+    }
+
+}
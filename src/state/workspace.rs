@@ -0,0 +1,97 @@
+use super::prelude::*;
+
+/// A named group of windows, only one of which is shown at a time.
+#[derive(Debug)]
+pub struct Workspace {
+    pub name: String,
+    /// Hidden windows of this workspace, most recently minimized last
+    pub minimized: Vec<WlSurface>,
+}
+
+impl Workspace {
+    pub fn new (name: impl Into<String>) -> Self {
+        Self { name: name.into(), minimized: vec![] }
+    }
+}
+
+/// The list of workspaces and the navigation history between them.
+pub struct Workspaces {
+    logger: Logger,
+    /// All workspaces that have been created so far. Indices are stable.
+    pub list:     Vec<Workspace>,
+    /// Index of the visible workspace
+    pub current:  usize,
+    /// Index of the previously visible workspace
+    pub previous: Option<usize>,
+    /// Whether switching to the current workspace goes back to the previous one
+    pub auto_back_and_forth: bool,
+}
+
+impl Workspaces {
+
+    pub fn new (logger: &Logger) -> Self {
+        Self {
+            logger:   logger.clone(),
+            list:     vec![Workspace::new("1")],
+            current:  0,
+            previous: None,
+            auto_back_and_forth: false,
+        }
+    }
+
+    /// Find a workspace by name.
+    pub fn find (&self, name: &str) -> Option<usize> {
+        self.list.iter().position(|w| w.name == name)
+    }
+
+    /// Find a workspace by name, creating it if missing.
+    pub fn find_or_add (&mut self, name: &str) -> usize {
+        match self.find(name) {
+            Some(index) => index,
+            None => {
+                debug!(self.logger, "Creating workspace {name}");
+                self.list.push(Workspace::new(name));
+                self.list.len() - 1
+            }
+        }
+    }
+
+    /// Name of the visible workspace.
+    pub fn current_name (&self) -> &str {
+        &self.list[self.current].name
+    }
+
+    /// Switch to a workspace as if by keybinding,
+    /// going back and forth if it's already the current one and that's enabled.
+    pub fn switch (&mut self, index: usize) {
+        if index == self.current {
+            if self.auto_back_and_forth {
+                self.back_and_forth()
+            }
+        } else {
+            self.show(index)
+        }
+    }
+
+    /// Switch to the named workspace, creating it if missing.
+    pub fn jump (&mut self, name: &str) {
+        let index = self.find_or_add(name);
+        if index != self.current {
+            self.show(index)
+        }
+    }
+
+    /// Switch to the previously visible workspace.
+    pub fn back_and_forth (&mut self) {
+        if let Some(previous) = self.previous {
+            self.show(previous)
+        }
+    }
+
+    fn show (&mut self, index: usize) {
+        debug!(self.logger, "Switching to workspace {}", self.list[index].name);
+        self.previous = Some(self.current);
+        self.current  = index;
+    }
+
+}
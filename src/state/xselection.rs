@@ -0,0 +1,576 @@
+use super::prelude::*;
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    os::{fd::{AsRawFd, FromRawFd, RawFd}, unix::net::UnixStream},
+};
+
+use x11rb::{
+    protocol::{
+        xfixes::SelectionNotifyEvent as OwnerChangeEvent,
+        xproto::{
+            Atom, AtomEnum, PropMode, Property, PropertyNotifyEvent,
+            SelectionNotifyEvent, SelectionRequestEvent, SELECTION_NOTIFY_EVENT,
+        },
+    },
+    wrapper::ConnectionExt as _,
+};
+
+use smithay::{
+    reexports::calloop::Interest,
+    reexports::wayland_server::{Resource, protocol::wl_data_source::WlDataSource},
+    wayland::data_device::{set_data_device_selection, with_source_metadata},
+};
+
+use super::clipboard::{source_domain, Watch};
+use super::host::TEXT_MIME_TYPES;
+
+/// Biggest piece of a selection sent to an X11 client in one property; bigger ones go in chunks (`INCR`)
+const X11_CHUNK: usize = 64 * 1024;
+
+/// Most bytes moved per transfer in one turn of the main loop
+const CHUNK: usize = 256 * 1024;
+
+/// How long a transfer may go without progress before it's cancelled
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The MIME types that a Wayland client may know an X11 target by, preferred first.
+fn target_mime_types (target: &str) -> Vec<String> {
+    let names: &[&str] = match target {
+        "UTF8_STRING" | "TEXT" => &["text/plain;charset=utf-8", "UTF8_STRING", "text/plain", "STRING"],
+        "STRING" => &["STRING", "text/plain", "text/plain;charset=utf-8", "UTF8_STRING"],
+        // TARGETS, TIMESTAMP, MULTIPLE and the like aren't data
+        name if !name.contains('/') => &[],
+        name => return vec![name.into()]
+    };
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+/// Data pasted by a Wayland client from the selection of an X11 client.
+struct Paste {
+    /// The X11 target that the data is asked for as
+    target:      Atom,
+    to:          File,
+    /// Wakes the loop when the client can take more data
+    writable:    Watch,
+    /// Data received but not written yet
+    pending:     Vec<u8>,
+    /// Whether the conversion was asked for
+    requested:   bool,
+    /// Whether the X11 client sends the data in chunks
+    incremental: bool,
+    /// Whether all of the data was received
+    received:    bool,
+    /// When data last moved
+    progress:    Instant,
+}
+
+/// Data copied by an X11 client from the selection of a Wayland client, or from the host.
+struct Copy {
+    request:  SelectionRequestEvent,
+    /// Property of the requestor that the data goes into
+    property: Atom,
+    /// Where the data comes from; None once it's all been read
+    from:     Option<UnixStream>,
+    /// Wakes the loop when there's data to read
+    readable: Option<Watch>,
+    data:     Vec<u8>,
+    /// How much of the data was sent so far, once it's sent in chunks
+    sent:     Option<usize>,
+    /// When data last moved
+    progress: Instant,
+}
+
+/// The CLIPBOARD selection, bridged between X11 and Wayland clients.
+///
+/// When an X11 client takes the clipboard, its targets are offered to Wayland clients
+/// as MIME types, and each paste is converted from the X11 client in turn. When a Wayland
+/// client or the host takes the selection, the window manager takes the X11 clipboard in
+/// its place, and answers X11 clients from the Wayland source. Pieces that don't fit
+/// a single property go in chunks, as `INCR` transfers.
+#[derive(Default)]
+pub struct XSelection {
+    /// The targets offered by the X11 client that owns the clipboard, by MIME type
+    pub offer:  Option<Vec<(String, Atom)>>,
+    /// The Wayland client selection that X11 clients paste from
+    pub source: Option<WlDataSource>,
+    /// Pastes from X11, converted one at a time since they share a property
+    pastes:     VecDeque<Paste>,
+    copies:     Vec<Copy>,
+}
+
+impl XSelection {
+
+    /// Whether any selection is being pasted between X11 and Wayland.
+    pub fn transferring (&self) -> bool {
+        !self.pastes.is_empty() || !self.copies.is_empty()
+    }
+
+    /// When the transfer that moved least lately will have stalled, if any are going.
+    pub fn next_wakeup (&self) -> Option<Instant> {
+        self.pastes.iter().map(|paste| paste.progress)
+            .chain(self.copies.iter().map(|copy| copy.progress))
+            .map(|progress| progress + STALL_TIMEOUT)
+            .min()
+    }
+
+    /// Stop all transfers, and with them waking the loop for their pipes.
+    pub fn cancel <E: Engine> (&mut self, handle: &LoopHandle<'static, Charlie<E>>) {
+        for mut paste in self.pastes.drain(..) {
+            paste.writable.set(handle, false);
+        }
+        for mut copy in self.copies.drain(..) {
+            copy.watch(handle, false);
+        }
+    }
+
+}
+
+impl<E: Engine> Charlie<E> {
+
+    /// Take the X11 clipboard for a Wayland client that set the selection,
+    /// or for the text from the host if None.
+    pub fn x11_selection_claim (&mut self, source: Option<WlDataSource>) {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return
+        };
+        // XWayland itself takes the selection through the window manager
+        if source.as_ref().and_then(|source| source.client()).as_ref() == Some(&xwm.client) {
+            return
+        }
+        xwm.selection.offer  = None;
+        xwm.selection.source = source;
+        for mut paste in xwm.selection.pastes.drain(..) {
+            paste.writable.set(&self.handle, false);
+        }
+        let result = xwm.conn.set_selection_owner(xwm.window, xwm.atoms.CLIPBOARD, x11rb::CURRENT_TIME)
+            .map(|_| ())
+            .and_then(|_| xwm.conn.flush());
+        if let Err(e) = result {
+            warn!(xwm.logger, "Could not take the X11 clipboard: {e}");
+        }
+    }
+
+    /// Respond to another X11 client taking the clipboard, by asking it what it offers.
+    pub(super) fn x11_selection_owner (&mut self, event: OwnerChangeEvent) -> Result<(), ReplyOrIdError> {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return Ok(())
+        };
+        if event.selection != xwm.atoms.CLIPBOARD || event.owner == xwm.window {
+            return Ok(())
+        }
+        if event.owner == x11rb::NONE {
+            // The owner went away, and what it offered with it
+            xwm.selection.offer = None;
+            return Ok(())
+        }
+        debug!(xwm.logger, "X11 window {:x} took the clipboard", event.owner);
+        xwm.conn.convert_selection(
+            xwm.window, xwm.atoms.CLIPBOARD, xwm.atoms.TARGETS, xwm.atoms._CHARLIE_TARGETS, event.selection_timestamp
+        )?;
+        Ok(())
+    }
+
+    /// Respond to the owner of the X11 clipboard converting it for us:
+    /// either the list of targets, or the data of a paste.
+    pub(super) fn x11_selection_notify (&mut self, event: SelectionNotifyEvent) -> Result<(), ReplyOrIdError> {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return Ok(())
+        };
+        if event.requestor != xwm.window || event.selection != xwm.atoms.CLIPBOARD {
+            return Ok(())
+        }
+        if event.target == xwm.atoms.TARGETS {
+            if event.property == x11rb::NONE {
+                return Ok(())
+            }
+            let reply = xwm.conn.get_property(
+                true, xwm.window, xwm.atoms._CHARLIE_TARGETS, AtomEnum::ATOM, 0, 4096
+            )?.reply()?;
+            let mut offer: Vec<(String, Atom)> = vec![];
+            for target in reply.value32().into_iter().flatten() {
+                let name = xwm.conn.get_atom_name(target)?.reply()?.name;
+                for mime_type in target_mime_types(&String::from_utf8_lossy(&name)) {
+                    if !offer.iter().any(|(offered, _)| offered == &mime_type) {
+                        offer.push((mime_type, target));
+                    }
+                }
+            }
+            debug!(xwm.logger, "X11 clipboard offers {:?}", offer.iter().map(|(mime, _)| mime).collect::<Vec<_>>());
+            let mime_types: Vec<String> = offer.iter().map(|(mime_type, _)| mime_type.clone()).collect();
+            xwm.selection.offer  = Some(offer);
+            xwm.selection.source = None;
+            self.input.selection = None;
+            self.clipboard.release(&self.handle);
+            let handle = self.display.borrow().handle();
+            for seat in self.input.seats.iter() {
+                set_data_device_selection(&handle, seat, mime_types.clone());
+            }
+            return Ok(())
+        }
+        let paste = match xwm.selection.pastes.front_mut() {
+            Some(paste) if paste.requested && paste.target == event.target => paste,
+            _ => return Ok(())
+        };
+        if event.property == x11rb::NONE {
+            warn!(xwm.logger, "X11 clipboard owner refused to convert the selection");
+            if let Some(mut paste) = xwm.selection.pastes.pop_front() {
+                paste.writable.set(&self.handle, false);
+            }
+            return self.x11_selection_next()
+        }
+        let reply = xwm.conn.get_property(
+            true, xwm.window, xwm.atoms._CHARLIE_SELECTION, AtomEnum::ANY, 0, u32::MAX / 4
+        )?.reply()?;
+        if reply.type_ == xwm.atoms.INCR {
+            // Deleting the property asks for the first chunk
+            paste.incremental = true;
+        } else {
+            paste.pending.extend_from_slice(&reply.value);
+            paste.received = true;
+        }
+        paste.progress = Instant::now();
+        Ok(())
+    }
+
+    /// Respond to a property of ours or of a requestor changing while a selection is
+    /// sent in chunks. Returns whether the property was one of the selection's.
+    pub(super) fn x11_selection_property (&mut self, event: &PropertyNotifyEvent) -> Result<bool, ReplyOrIdError> {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return Ok(false)
+        };
+        // The next chunk of a paste from an X11 client
+        if event.window == xwm.window && event.atom == xwm.atoms._CHARLIE_SELECTION {
+            if event.state != Property::NEW_VALUE {
+                return Ok(true)
+            }
+            let paste = match xwm.selection.pastes.front_mut() {
+                Some(paste) if paste.incremental && !paste.received => paste,
+                _ => return Ok(true)
+            };
+            let reply = xwm.conn.get_property(
+                true, xwm.window, xwm.atoms._CHARLIE_SELECTION, AtomEnum::ANY, 0, u32::MAX / 4
+            )?.reply()?;
+            // An empty chunk ends the transfer
+            if reply.value.is_empty() {
+                paste.received = true;
+            }
+            paste.pending.extend_from_slice(&reply.value);
+            paste.progress = Instant::now();
+            return Ok(true)
+        }
+        // A requestor took the last chunk of a copy, and wants the next one
+        if event.state != Property::DELETE {
+            return Ok(false)
+        }
+        let index = xwm.selection.copies.iter().position(|copy|
+            copy.request.requestor == event.window && copy.property == event.atom && copy.sent.is_some()
+        );
+        let index = match index {
+            Some(index) => index,
+            None => return Ok(false)
+        };
+        let copy = &mut xwm.selection.copies[index];
+        let sent = copy.sent.unwrap();
+        let end = (sent + X11_CHUNK).min(copy.data.len());
+        xwm.conn.change_property8(
+            PropMode::REPLACE, copy.request.requestor, copy.property, copy.request.target, &copy.data[sent..end]
+        )?;
+        copy.progress = Instant::now();
+        if sent == end {
+            // That was the empty chunk that ends the transfer
+            xwm.selection.copies.remove(index).watch(&self.handle, false);
+        } else {
+            copy.sent = Some(end);
+        }
+        Ok(true)
+    }
+
+    /// Respond to an X11 client asking for the clipboard that's taken for a Wayland client.
+    pub(super) fn x11_selection_request (&mut self, request: SelectionRequestEvent) -> Result<(), ReplyOrIdError> {
+        let offered = self.x11_selection_offered();
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return Ok(())
+        };
+        // Old clients leave it to the owner to pick the property
+        let property = if request.property == x11rb::NONE { request.target } else { request.property };
+        let refuse = |xwm: &super::xwayland::Xwm| x11_notify(&xwm.conn, &request, x11rb::NONE);
+        if request.selection != xwm.atoms.CLIPBOARD {
+            return refuse(xwm)
+        }
+        if request.target == xwm.atoms.TARGETS {
+            let mut targets = vec![xwm.atoms.TARGETS];
+            for mime_type in offered.iter() {
+                targets.push(xwm.conn.intern_atom(false, mime_type.as_bytes())?.reply()?.atom);
+            }
+            if offered.iter().any(|mime_type| TEXT_MIME_TYPES.contains(&mime_type.as_str())) {
+                targets.extend([xwm.atoms.UTF8_STRING, xwm.atoms.TEXT, AtomEnum::STRING.into()]);
+            }
+            targets.sort();
+            targets.dedup();
+            xwm.conn.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &targets)?;
+            return x11_notify(&xwm.conn, &request, property)
+        }
+        let target = String::from_utf8_lossy(&xwm.conn.get_atom_name(request.target)?.reply()?.name).to_string();
+        let mime_type = match target_mime_types(&target).into_iter().find(|mime_type| offered.contains(mime_type)) {
+            Some(mime_type) => mime_type,
+            None => return refuse(xwm)
+        };
+        debug!(xwm.logger, "X11 window {:x} pastes {mime_type}", request.requestor);
+        let (from, data) = match (xwm.selection.source.as_ref(), self.input.selection.as_ref()) {
+            (Some(source), _) if source.is_alive() => {
+                let (ours, theirs) = match UnixStream::pair() {
+                    Ok(pair) => pair,
+                    Err(e) => {
+                        warn!(xwm.logger, "Could not create selection pipe: {e}");
+                        return refuse(xwm)
+                    }
+                };
+                if let Err(e) = ours.set_nonblocking(true) {
+                    warn!(xwm.logger, "Could not make selection pipe non-blocking: {e}");
+                    return refuse(xwm)
+                }
+                // The source gets its own copy of the other end when the event is sent
+                source.send(mime_type, theirs.as_raw_fd());
+                (Some(ours), vec![])
+            },
+            (None, Some(text)) => (None, text.clone().into_bytes()),
+            _ => return refuse(xwm)
+        };
+        let readable = from.as_ref().map(|from| Watch::new(from, Interest::READ));
+        let mut copy = Copy { request, property, from, readable, data, sent: None, progress: Instant::now() };
+        copy.watch(&self.handle, true);
+        xwm.selection.copies.push(copy);
+        Ok(())
+    }
+
+    /// The MIME types of the selection that's offered to X11 clients.
+    fn x11_selection_offered (&self) -> Vec<String> {
+        let source = match self.xwm.as_ref().and_then(|xwm| xwm.selection.source.as_ref()) {
+            Some(source) if source.is_alive() => source,
+            Some(_) => return vec![],
+            None => return match self.input.selection {
+                Some(_) => TEXT_MIME_TYPES.iter().map(|mime_type| mime_type.to_string()).collect(),
+                None => vec![]
+            }
+        };
+        let domain = source_domain(source);
+        with_source_metadata(source, |metadata| metadata.mime_types.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|mime_type| self.clipboard.allowed(domain, mime_type))
+            .collect()
+    }
+
+    /// Start sending the clipboard of an X11 client to a Wayland client that asked to paste it.
+    pub fn x11_selection_paste (&mut self, mime_type: &str, fd: RawFd) {
+        // Take ownership of the pipe so it's closed once the paste is over
+        let to = unsafe { File::from_raw_fd(fd) };
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return
+        };
+        let target = xwm.selection.offer.as_ref()
+            .and_then(|offer| offer.iter().find(|(offered, _)| offered == mime_type))
+            .map(|(_, target)| *target);
+        let target = match target {
+            Some(target) => target,
+            None => return
+        };
+        if let Err(e) = nix::fcntl::fcntl(to.as_raw_fd(), nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::O_NONBLOCK)) {
+            warn!(xwm.logger, "Could not make selection pipe non-blocking: {e}");
+            return
+        }
+        let writable = Watch::new(&to, Interest::WRITE);
+        xwm.selection.pastes.push_back(Paste {
+            target,
+            to,
+            writable,
+            pending:     vec![],
+            requested:   false,
+            incremental: false,
+            received:    false,
+            progress:    Instant::now(),
+        });
+        if let Err(e) = self.x11_selection_next() {
+            warn!(self.logger, "Could not convert the X11 clipboard: {e}");
+        }
+    }
+
+    /// Ask the owner of the X11 clipboard for the data of the next paste, unless one is going.
+    fn x11_selection_next (&mut self) -> Result<(), ReplyOrIdError> {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return Ok(())
+        };
+        if let Some(paste) = xwm.selection.pastes.front_mut().filter(|paste| !paste.requested) {
+            xwm.conn.convert_selection(
+                xwm.window, xwm.atoms.CLIPBOARD, paste.target, xwm.atoms._CHARLIE_SELECTION, x11rb::CURRENT_TIME
+            )?;
+            xwm.conn.flush()?;
+            paste.requested  = true;
+            paste.progress   = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Move the selection transfers between X11 and Wayland clients along,
+    /// dropping the ones that are done or went wrong.
+    pub fn x11_selection_flush (&mut self) {
+        let max_size = self.clipboard.max_size;
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return
+        };
+        if !xwm.selection.transferring() {
+            return
+        }
+        // Pass the data of the current paste on to the Wayland client
+        let mut next = false;
+        if let Some(paste) = xwm.selection.pastes.front_mut() {
+            match paste.advance() {
+                Ok(true) => {},
+                Ok(false) => next = true,
+                Err(e) => {
+                    warn!(xwm.logger, "Cancelled paste from X11: {e}");
+                    next = true;
+                }
+            }
+            paste.writable.set(&self.handle, !next && !paste.pending.is_empty());
+        }
+        if next {
+            xwm.selection.pastes.pop_front();
+        }
+        // Read the data of the copies, and hand it to the X11 clients
+        let (conn, logger, incr, handle) = (&xwm.conn, &xwm.logger, xwm.atoms.INCR, &self.handle);
+        xwm.selection.copies.retain_mut(|copy| {
+            let going = match copy.advance(conn, incr, max_size) {
+                Ok(going) => going,
+                Err(e) => {
+                    warn!(logger, "Cancelled copy to X11: {e}");
+                    if copy.sent.is_none() {
+                        let _ = x11_notify(conn, &copy.request, x11rb::NONE);
+                    }
+                    false
+                }
+            };
+            copy.watch(handle, going);
+            going
+        });
+        let _ = xwm.conn.flush();
+        if next {
+            if let Err(e) = self.x11_selection_next() {
+                warn!(self.logger, "Could not convert the X11 clipboard: {e}");
+            }
+        }
+    }
+
+}
+
+impl Paste {
+
+    /// Write another chunk of data. Returns whether the paste is still going.
+    fn advance (&mut self) -> StdResult<bool> {
+        if !self.pending.is_empty() {
+            match self.to.write(&self.pending[..self.pending.len().min(CHUNK)]) {
+                Ok(written) => {
+                    self.pending.drain(..written);
+                    self.progress = Instant::now();
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e.into())
+            }
+        }
+        if self.progress.elapsed() > STALL_TIMEOUT {
+            return Err("selection transfer stalled".into())
+        }
+        Ok(!self.received || !self.pending.is_empty())
+    }
+
+}
+
+impl Copy {
+
+    /// Have the loop wake up while there's data to read, or stop it if the copy is over.
+    fn watch <E: Engine> (&mut self, handle: &LoopHandle<'static, Charlie<E>>, going: bool) {
+        let reading = going && self.from.is_some() && self.sent.is_none();
+        if let Some(readable) = self.readable.as_mut() {
+            readable.set(handle, reading);
+        }
+    }
+
+    /// Read another chunk of data, and once it's all read, send it to the requestor,
+    /// whole or as the start of chunks. Returns whether the copy is still going.
+    fn advance (&mut self, conn: &RustConnection, incr: Atom, max_size: Option<usize>) -> StdResult<bool> {
+        if self.sent.is_some() {
+            // Waiting for the requestor to take the chunks
+            if self.progress.elapsed() > STALL_TIMEOUT {
+                return Err("selection transfer stalled".into())
+            }
+            return Ok(true)
+        }
+        if let Some(from) = self.from.as_mut() {
+            let mut chunk = vec![0; CHUNK];
+            match from.read(&mut chunk) {
+                Ok(0) => {
+                    self.from = None;
+                    self.progress = Instant::now();
+                },
+                Ok(read) => {
+                    self.data.extend_from_slice(&chunk[..read]);
+                    self.progress = Instant::now();
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e.into())
+            }
+        }
+        if let Some(max_size) = max_size.filter(|max_size| self.data.len() > *max_size) {
+            return Err(format!("selection is bigger than {max_size} bytes").into())
+        }
+        if self.from.is_some() {
+            if self.progress.elapsed() > STALL_TIMEOUT {
+                return Err("selection transfer stalled".into())
+            }
+            return Ok(true)
+        }
+        let request = &self.request;
+        if self.data.len() <= X11_CHUNK {
+            conn.change_property8(PropMode::REPLACE, request.requestor, self.property, request.target, &self.data)?;
+            x11_notify(conn, request, self.property)?;
+            return Ok(false)
+        }
+        // Too big for one property: announce its size, and send a chunk each time the last one is taken
+        conn.change_window_attributes(
+            request.requestor, &ChangeWindowAttributesAux::default().event_mask(EventMask::PROPERTY_CHANGE)
+        )?;
+        conn.change_property32(PropMode::REPLACE, request.requestor, self.property, incr, &[self.data.len() as u32])?;
+        x11_notify(conn, request, self.property)?;
+        self.sent     = Some(0);
+        self.progress = Instant::now();
+        Ok(true)
+    }
+
+}
+
+/// Let the requestor of a selection know that it's been converted into a property,
+/// or that it couldn't be if the property is `NONE`.
+fn x11_notify (conn: &RustConnection, request: &SelectionRequestEvent, property: Atom) -> Result<(), ReplyOrIdError> {
+    let event = SelectionNotifyEvent {
+        response_type: SELECTION_NOTIFY_EVENT,
+        sequence:      0,
+        time:          request.time,
+        requestor:     request.requestor,
+        selection:     request.selection,
+        target:        request.target,
+        property,
+    };
+    conn.send_event(false, request.requestor, EventMask::NO_EVENT, event)?;
+    Ok(())
+}
@@ -2,23 +2,197 @@ use super::prelude::*;
 
 use std::{collections::HashMap, convert::TryFrom, os::unix::net::UnixStream, sync::Arc};
 
-use x11rb::protocol::xproto::{ConfigureRequestEvent, ClientMessageEvent};
+use x11rb::{
+    properties::{WmHints, WmSizeHints},
+    protocol::xproto::{
+        Atom, AtomEnum, ClientMessageData, CreateWindowAux, ConfigureRequestEvent, ClientMessageEvent,
+        PropMode, PropertyNotifyEvent, CLIENT_MESSAGE_EVENT,
+    },
+    protocol::xfixes::{ConnectionExt as _, SelectionEventMask},
+    wrapper::ConnectionExt as _,
+};
+
+use smithay::reexports::wayland_server::Resource;
+
+use super::fullscreen::Fullscreen;
+use super::command::Command;
+use super::xselection::XSelection;
 
 atom_manager! {
     Atoms: AtomsCookie {
         WM_S0,
         WL_SURFACE_ID,
+        WM_PROTOCOLS,
+        WM_DELETE_WINDOW,
+        _NET_SUPPORTED,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_STATE_MAXIMIZED_VERT,
+        _NET_WM_STATE_MAXIMIZED_HORZ,
+        CLIPBOARD,
+        TARGETS,
+        UTF8_STRING,
+        TEXT,
+        INCR,
+        _CHARLIE_SELECTION,
+        _CHARLIE_TARGETS,
         _ANVIL_CLOSE_CONNECTION,
     }
 }
 
 pub type Unpaired = HashMap<u32, (X11Window, Point<i32, Logical>)>;
 
-pub fn init_xwayland <T: 'static> (
+/// `_NET_WM_STATE` client messages remove, add or toggle a state
+const NET_WM_STATE_REMOVE: u32 = 0;
+const NET_WM_STATE_ADD:    u32 = 1;
+const NET_WM_STATE_TOGGLE: u32 = 2;
+
+/// What an X11 window asks of the window manager through its properties.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct X11Hints {
+    pub min_size:      Option<Size<i32, Logical>>,
+    pub max_size:      Option<Size<i32, Logical>>,
+    /// The size that resize increments are counted from
+    pub base_size:     Option<Size<i32, Logical>>,
+    /// Steps the window is resized in, e.g. the size of a character cell in a terminal
+    pub increment:     Option<Size<i32, Logical>>,
+    /// Whether the window wants attention (`WM_HINTS` urgency)
+    pub urgent:        bool,
+    /// Whether the window can be asked to close with `WM_DELETE_WINDOW`, rather than killed
+    pub delete_window: bool,
+}
+
+impl X11Hints {
+
+    /// The size nearest to this one that the window allows.
+    pub fn constrain (&self, size: Size<i32, Logical>) -> Size<i32, Logical> {
+        let (mut w, mut h) = (size.w, size.h);
+        if let Some(increment) = self.increment {
+            let base = self.base_size.or(self.min_size).unwrap_or_default();
+            if increment.w > 0 && w > base.w {
+                w = base.w + (w - base.w) / increment.w * increment.w;
+            }
+            if increment.h > 0 && h > base.h {
+                h = base.h + (h - base.h) / increment.h * increment.h;
+            }
+        }
+        if let Some(max) = self.max_size {
+            if max.w > 0 { w = w.min(max.w) }
+            if max.h > 0 { h = h.min(max.h) }
+        }
+        if let Some(min) = self.min_size {
+            w = w.max(min.w);
+            h = h.max(min.h);
+        }
+        (w.max(1), h.max(1)).into()
+    }
+
+}
+
+/// An X11 window that's been paired with its surface.
+#[derive(Debug, Clone)]
+pub struct X11Managed {
+    pub surface: WlSurface,
+    pub hints:   X11Hints,
+}
+
+/// The window manager side of XWayland: the connection to it, and the windows it manages.
+pub struct Xwm {
+    pub logger:    Logger,
+    pub conn:      Arc<RustConnection>,
+    pub atoms:     Atoms,
+    /// The XWayland client, whose surfaces may stand for X11 windows
+    pub client:    Client,
+    /// The window of the window manager itself, which owns its selections
+    pub window:    X11Window,
+    /// X11 windows whose surface hasn't been created yet
+    unpaired:      Unpaired,
+    pub windows:   HashMap<X11Window, X11Managed>,
+    /// The clipboard, as shared with Wayland clients
+    pub selection: XSelection,
+}
+
+impl Xwm {
+
+    /// The X11 window that a surface stands for.
+    pub fn window_of (&self, surface: &WlSurface) -> Option<X11Window> {
+        self.windows.iter().find(|(_, managed)| &managed.surface == surface).map(|(window, _)| *window)
+    }
+
+    /// Read the hints of a window from its properties.
+    fn hints (&self, window: X11Window) -> Result<X11Hints, ReplyOrIdError> {
+        let size = |size: Option<(i32, i32)>| size.map(Size::from);
+        let normal = WmSizeHints::get_normal_hints(&*self.conn, window)?.reply().ok();
+        let wm_hints = WmHints::get(&*self.conn, window)?.reply().ok();
+        let protocols = self.conn.get_property(false, window, self.atoms.WM_PROTOCOLS, AtomEnum::ATOM, 0, 32)?
+            .reply()?;
+        let delete_window = protocols.value32().map(|mut atoms| atoms.any(|atom| atom == self.atoms.WM_DELETE_WINDOW))
+            .unwrap_or(false);
+        Ok(X11Hints {
+            min_size:  normal.as_ref().and_then(|hints| size(hints.min_size)),
+            max_size:  normal.as_ref().and_then(|hints| size(hints.max_size)),
+            base_size: normal.as_ref().and_then(|hints| size(hints.base_size)),
+            increment: normal.as_ref().and_then(|hints| size(hints.size_increment)),
+            urgent:    wm_hints.map(|hints| hints.urgent).unwrap_or(false),
+            delete_window,
+        })
+    }
+
+    /// Ask a window to close if it takes `WM_DELETE_WINDOW`, or disconnect its X11 client.
+    pub fn close (&self, window: X11Window) -> Result<(), ReplyOrIdError> {
+        let delete = self.windows.get(&window).map(|managed| managed.hints.delete_window).unwrap_or(false);
+        if delete {
+            debug!(self.logger, "Asking X11 window {window:x} to close");
+            let event = ClientMessageEvent {
+                response_type: CLIENT_MESSAGE_EVENT,
+                format:        32,
+                sequence:      0,
+                window,
+                type_:         self.atoms.WM_PROTOCOLS,
+                data:          ClientMessageData::from([self.atoms.WM_DELETE_WINDOW, x11rb::CURRENT_TIME, 0, 0, 0]),
+            };
+            self.conn.send_event(false, window, EventMask::NO_EVENT, event)?;
+        } else {
+            debug!(self.logger, "Killing X11 window {window:x}, which can't be asked to close");
+            self.conn.kill_client(window)?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Move and resize a window, within what its hints allow.
+    pub fn configure (&self, window: X11Window, location: Point<i32, Logical>, size: Size<i32, Logical>)
+        -> Result<(), ReplyOrIdError>
+    {
+        let size = self.windows.get(&window).map(|managed| managed.hints.constrain(size)).unwrap_or(size);
+        let aux = ConfigureWindowAux::default()
+            .x(location.x).y(location.y).width(size.w as u32).height(size.h as u32);
+        self.conn.configure_window(window, &aux)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Let a window know whether it's fullscreen or maximized, through `_NET_WM_STATE`.
+    pub fn set_state (&self, window: X11Window, fullscreen: bool, maximized: bool) -> Result<(), ReplyOrIdError> {
+        let mut states: Vec<Atom> = vec![];
+        if fullscreen {
+            states.push(self.atoms._NET_WM_STATE_FULLSCREEN);
+        }
+        if maximized {
+            states.extend([self.atoms._NET_WM_STATE_MAXIMIZED_VERT, self.atoms._NET_WM_STATE_MAXIMIZED_HORZ]);
+        }
+        self.conn.change_property32(PropMode::REPLACE, window, self.atoms._NET_WM_STATE, AtomEnum::ATOM, &states)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+}
+
+pub fn init_xwayland <E: Engine> (
     logger:  &Logger,
-    events:  &LoopHandle<'static, T>,
+    events:  &LoopHandle<'static, Charlie<E>>,
     display: &DisplayHandle,
-    ready:   Box<dyn Fn(&mut T)->Result<(), Box<dyn Error>>>
+    ready:   Box<dyn Fn(&mut Charlie<E>)->Result<(), Box<dyn Error>>>
 ) -> Result<(), Box<dyn Error>> {
     let (xwayland, channel) = XWayland::new(logger.clone(), &display);
     let cb_logger   = logger.clone();
@@ -26,70 +200,63 @@ pub fn init_xwayland <T: 'static> (
     let cb_display  = display.clone();
     events.insert_source(channel, move |event, _, app| match event {
         XWaylandEvent::Ready { connection, client, .. } => {
-            let (x11conn, x11atoms, x11source) =
+            let (x11conn, x11atoms, x11window, x11source) =
                 x11_connect(&cb_logger, &cb_display.clone(), connection).unwrap();
-            let mut unpaired: Unpaired = Default::default();
+            app.xwm = Some(Xwm {
+                logger:    cb_logger.clone(),
+                conn:      x11conn,
+                atoms:     x11atoms,
+                client,
+                window:    x11window,
+                unpaired:  Default::default(),
+                windows:   HashMap::new(),
+                selection: Default::default(),
+            });
             let x11_logger  = cb_logger.clone();
-            let x11_display = cb_display.clone();
             cb_events.clone().insert_source(x11source, move |event, _, state| {
-                debug!(x11_logger, "X11: Got event {:?}", event);
-                x11_handle(
-                    &x11_logger,
-                    &x11_display,
-                    &client,
-                    &x11conn,
-                    x11atoms,
-                    event, 
-                    &mut unpaired
-                ).unwrap();
+                if let Err(e) = state.x11_handle(event) {
+                    warn!(x11_logger, "X11: Could not handle event: {e}");
+                }
             }).unwrap();
             debug!(cb_logger, "DISPLAY={:?}", ::std::env::var("DISPLAY"));
             ready(app).unwrap()
         },
         XWaylandEvent::Exited => {
-            crit!(cb_logger, "XWayland exited")
+            crit!(cb_logger, "XWayland exited");
+            if let Some(mut xwm) = app.xwm.take() {
+                xwm.selection.cancel(&app.handle);
+            }
         },
     })?;
     xwayland.start(events.clone())?;
     Ok(())
 }
 
-fn x11_handle (
-    logger:   &Logger,
-    display:  &DisplayHandle,
-    client:   &Client,
-    conn:     &Arc<RustConnection>,
-    atoms:    Atoms,
-    event:    X11Event,
-    unpaired: &mut Unpaired,
-) -> Result<(), ReplyOrIdError> {
-    debug!(logger, "X11: Got event {:?}", event);
-    match event {
-        X11Event::ConfigureRequest(r) => { x11_configure(conn, r)?; }
-        X11Event::MapRequest(r) => { conn.map_window(r.window)?; }
-        X11Event::ClientMessage(msg) => { x11_client_message(logger, display, client, &conn, msg, atoms, unpaired)?; }
-        _ => {}
-    }
-    conn.flush()?;
-    Ok(())
-}
-
 fn x11_connect (
     logger:     &Logger,
     display:    &DisplayHandle,
     connection: UnixStream,
-) -> Result<(Arc<RustConnection>, Atoms, X11Source), Box<dyn Error>> {
+) -> Result<(Arc<RustConnection>, Atoms, X11Window, X11Source), Box<dyn Error>> {
     debug!(logger, "New X11 connection");
     let screen = 0; // Create an X11 connection. XWaylandState only uses screen 0.
     let stream = DefaultStream::from_unix_stream(connection)?;
     let conn   = RustConnection::connect_to_stream(stream, 0)?;
     let atoms  = Atoms::new(&conn)?.reply()?;
     let screen = &conn.setup().roots[0];
-    // Actually become the WM by redirecting some operations
+    // Actually become the WM by redirecting some operations,
+    // and hear about windows going away
     conn.change_window_attributes(
         screen.root,
-        &ChangeWindowAttributesAux::default().event_mask(EventMask::SUBSTRUCTURE_REDIRECT),
+        &ChangeWindowAttributesAux::default()
+            .event_mask(EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY),
     )?;
+    // Tell clients which window states are understood
+    conn.change_property32(PropMode::REPLACE, screen.root, atoms._NET_SUPPORTED, AtomEnum::ATOM, &[
+        atoms._NET_WM_STATE,
+        atoms._NET_WM_STATE_FULLSCREEN,
+        atoms._NET_WM_STATE_MAXIMIZED_VERT,
+        atoms._NET_WM_STATE_MAXIMIZED_HORZ,
+    ])?;
     // Tell XWaylandState that we are the WM by acquiring the WM_S0 selection. No X11 clients are accepted before this.
     let win = conn.generate_id()?;
     conn.create_window(
@@ -98,22 +265,32 @@ fn x11_connect (
         0, 0, 1, 1, 0,
         WindowClass::INPUT_OUTPUT,
         x11rb::COPY_FROM_PARENT,
-        &Default::default(),
+        // Hear about the chunks of selections sent to it
+        &CreateWindowAux::default().event_mask(EventMask::PROPERTY_CHANGE),
     )?;
     conn.set_selection_owner(win, atoms.WM_S0, x11rb::CURRENT_TIME)?;
+    // Hear about X11 clients taking the clipboard, to offer it to Wayland clients
+    conn.xfixes_query_version(5, 0)?.reply()?;
+    conn.xfixes_select_selection_input(win, atoms.CLIPBOARD,
+        SelectionEventMask::SET_SELECTION_OWNER
+            | SelectionEventMask::SELECTION_WINDOW_DESTROY
+            | SelectionEventMask::SELECTION_CLIENT_CLOSE)?;
     // XWaylandState wants us to do this to function properly...?
     conn.composite_redirect_subwindows(screen.root, Redirect::MANUAL)?;
     conn.flush()?;
     let conn = Arc::new(conn);
     //let unpaired = Default::default();
-    Ok((conn.clone(), atoms, X11Source::new(conn, win, atoms._ANVIL_CLOSE_CONNECTION, logger.clone())))
+    Ok((conn.clone(), atoms, win, X11Source::new(conn, win, atoms._ANVIL_CLOSE_CONNECTION, logger.clone())))
 }
 
 pub fn x11_configure (
-    conn: &Arc<RustConnection>,
-    r:    ConfigureRequestEvent
+    conn:  &Arc<RustConnection>,
+    r:     ConfigureRequestEvent,
+    hints: Option<&X11Hints>,
 ) -> Result<(), ReplyOrIdError> {
-    // Just grant the wish
+    // Grant the wish, within the window's size hints
+    let size = hints.map(|hints| hints.constrain((r.width as i32, r.height as i32).into()))
+        .unwrap_or_else(|| (r.width as i32, r.height as i32).into());
     let mut aux = ConfigureWindowAux::default();
     if r.value_mask & u16::from(ConfigWindow::STACK_MODE) != 0 {
         aux = aux.stack_mode(r.stack_mode);
@@ -128,10 +305,10 @@ pub fn x11_configure (
         aux = aux.y(i32::try_from(r.y).unwrap());
     }
     if r.value_mask & u16::from(ConfigWindow::WIDTH) != 0 {
-        aux = aux.width(u32::try_from(r.width).unwrap());
+        aux = aux.width(u32::try_from(size.w).unwrap());
     }
     if r.value_mask & u16::from(ConfigWindow::HEIGHT) != 0 {
-        aux = aux.height(u32::try_from(r.height).unwrap());
+        aux = aux.height(u32::try_from(size.h).unwrap());
     }
     if r.value_mask & u16::from(ConfigWindow::BORDER_WIDTH) != 0 {
         aux = aux.border_width(u32::try_from(r.border_width).unwrap());
@@ -140,87 +317,269 @@ pub fn x11_configure (
     Ok(())
 }
 
-fn x11_client_message (
-    logger:   &Logger,
-    display:  &DisplayHandle,
-    client:   &Client,
-    conn:     &Arc<RustConnection>,
-    msg:      ClientMessageEvent,
-    atoms:    Atoms,
-    unpaired: &mut Unpaired
-) -> Result<(), ReplyOrIdError> {
-    if msg.type_ == atoms.WL_SURFACE_ID {
-        // We get a WL_SURFACE_ID message when Xwayland creates a WlSurface for a
-        // window. Both the creation of the surface and this client message happen at
-        // roughly the same time and are sent over different sockets (X11 socket and
-        // wayland socket). Thus, we could receive these two in any order. Hence, it
-        // can happen that we get None below when X11 was faster than Wayland.
-        let location = {
-            match conn.get_geometry(msg.window)?.reply() {
-                Ok(geo) => (geo.x as i32, geo.y as i32).into(),
-                Err(err) => {
-                    error!(
-                        logger,
-                        "Failed to get geometry for {:x}, perhaps the window was already destroyed?",
-                        msg.window;
-                        "err" => format!("{:?}", err),
+impl<E: Engine> Charlie<E> {
+
+    /// Respond to an event from XWayland.
+    fn x11_handle (&mut self, event: X11Event) -> Result<(), ReplyOrIdError> {
+        let xwm = match self.xwm.as_ref() {
+            Some(xwm) => xwm,
+            None => return Ok(())
+        };
+        debug!(xwm.logger, "X11: Got event {:?}", event);
+        let conn = xwm.conn.clone();
+        match event {
+            X11Event::ConfigureRequest(r) => {
+                x11_configure(&conn, r, xwm.windows.get(&r.window).map(|managed| &managed.hints))?;
+            },
+            X11Event::MapRequest(r) => {
+                // Hear about changes to the window's hints
+                conn.change_window_attributes(
+                    r.window, &ChangeWindowAttributesAux::default().event_mask(EventMask::PROPERTY_CHANGE)
+                )?;
+                conn.map_window(r.window)?;
+            },
+            X11Event::ClientMessage(msg) => {
+                self.x11_client_message(msg)?;
+            },
+            X11Event::PropertyNotify(e) => {
+                if !self.x11_selection_property(&e)? {
+                    self.x11_property(e)?;
+                }
+            },
+            X11Event::XfixesSelectionNotify(e) => {
+                self.x11_selection_owner(e)?;
+            },
+            X11Event::SelectionNotify(e) => {
+                self.x11_selection_notify(e)?;
+            },
+            X11Event::SelectionRequest(e) => {
+                self.x11_selection_request(e)?;
+            },
+            X11Event::DestroyNotify(e) => {
+                let managed = self.xwm.as_mut().and_then(|xwm| xwm.windows.remove(&e.window));
+                if let Some(managed) = managed {
+                    self.window_destroyed(&managed.surface);
+                }
+            },
+            _ => {}
+        }
+        conn.flush()?;
+        Ok(())
+    }
+
+    fn x11_client_message (&mut self, msg: ClientMessageEvent) -> Result<(), ReplyOrIdError> {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return Ok(())
+        };
+        if msg.type_ == xwm.atoms.WL_SURFACE_ID {
+            // We get a WL_SURFACE_ID message when Xwayland creates a WlSurface for a
+            // window. Both the creation of the surface and this client message happen at
+            // roughly the same time and are sent over different sockets (X11 socket and
+            // wayland socket). Thus, we could receive these two in any order. Hence, it
+            // can happen that we get None below when X11 was faster than Wayland.
+            let location = {
+                match xwm.conn.get_geometry(msg.window)?.reply() {
+                    Ok(geo) => (geo.x as i32, geo.y as i32).into(),
+                    Err(err) => {
+                        error!(
+                            xwm.logger,
+                            "Failed to get geometry for {:x}, perhaps the window was already destroyed?",
+                            msg.window;
+                            "err" => format!("{:?}", err),
+                        );
+                        (0, 0).into()
+                    }
+                }
+            };
+            let id = msg.data.as_data32()[0];
+            let display = self.display.borrow().handle();
+            match xwm.client.object_from_protocol_id(&display, id) {
+                Err(_) => {
+                    xwm.unpaired.insert(id, (msg.window, location));
+                }
+                Ok(surface) => {
+                    debug!(
+                        xwm.logger,
+                        "X11 surface {:x?} corresponds to WlSurface {:x} = {:?}",
+                        msg.window,
+                        id,
+                        surface,
                     );
-                    (0, 0).into()
+                    self.x11_new_window(msg.window, surface, location)?;
                 }
             }
+        } else if msg.type_ == xwm.atoms._NET_WM_STATE {
+            let data = msg.data.as_data32();
+            let surface = match xwm.windows.get(&msg.window) {
+                Some(managed) => managed.surface.clone(),
+                None => return Ok(())
+            };
+            let (fullscreen, maximized) = match self.desktop.window_state(&surface) {
+                Some(window) => (window.fullscreen.is_some(), window.filled.is_some()),
+                None => return Ok(())
+            };
+            let wanted = |current: bool| match data[0] {
+                NET_WM_STATE_REMOVE => false,
+                NET_WM_STATE_ADD    => true,
+                NET_WM_STATE_TOGGLE => !current,
+                _ => current
+            };
+            let atoms = xwm.atoms;
+            let changes = |atom: Atom| data[1] == atom || data[2] == atom;
+            if changes(atoms._NET_WM_STATE_FULLSCREEN) && wanted(fullscreen) != fullscreen {
+                self.x11_fullscreen(&surface, !fullscreen);
+            }
+            if (changes(atoms._NET_WM_STATE_MAXIMIZED_VERT) || changes(atoms._NET_WM_STATE_MAXIMIZED_HORZ))
+                && wanted(maximized) != maximized
+            {
+                self.window_toggle_floating(&surface);
+            }
+            self.x11_state_sync(&surface);
+        }
+        Ok(())
+    }
+
+    /// Start managing an X11 window once it's paired with its surface.
+    fn x11_new_window (&mut self, window: X11Window, surface: WlSurface, location: Point<i32, Logical>)
+        -> Result<(), ReplyOrIdError>
+    {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return Ok(())
+        };
+        debug!(xwm.logger, "Matched X11 surface {:x?} to {:x?}", window, surface);
+        if give_role(&surface, "x11_surface").is_err() {
+            // It makes no sense to post a protocol error here since that would only kill Xwayland
+            error!(xwm.logger, "Surface {:x?} already has a role?!", surface);
+            return Ok(())
+        }
+        let hints = xwm.hints(window)?;
+        xwm.windows.insert(window, X11Managed { surface: surface.clone(), hints: hints.clone() });
+        self.desktop.window_add(Window::new(Kind::X11(X11Surface { surface: surface.clone() })));
+        if let Some(state) = self.desktop.window_state_mut(&surface) {
+            state.center = location.to_f64();
+            state.urgent = hints.urgent;
+        }
+        self.redraw.request();
+        self.commands.push(Command::Focus(Some(surface)));
+        Ok(())
+    }
+
+    /// Pair an X11 window whose surface was created after XWayland told us about it.
+    pub fn x11_commit (&mut self, surface: &WlSurface) {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return
         };
-        let id = msg.data.as_data32()[0];
-        let surface = client.object_from_protocol_id(display, id);
-        match surface {
-            Err(_) => {
-                unpaired.insert(id, (msg.window, location));
+        if surface.client().as_ref() != Some(&xwm.client) {
+            return
+        }
+        if let Some((window, location)) = xwm.unpaired.remove(&surface.id().protocol_id()) {
+            if let Err(e) = self.x11_new_window(window, surface.clone(), location) {
+                warn!(self.logger, "Could not manage X11 window {window:x}: {e}");
             }
-            Ok(surface) => {
-                debug!(
-                    logger,
-                    "X11 surface {:x?} corresponds to WlSurface {:x} = {:?}",
-                    msg.window,
-                    id,
-                    surface,
-                );
-                x11_new_window(logger, msg.window, surface, location);
+        }
+    }
+
+    /// Follow changes to the hints of a window.
+    fn x11_property (&mut self, event: PropertyNotifyEvent) -> Result<(), ReplyOrIdError> {
+        let xwm = match self.xwm.as_mut() {
+            Some(xwm) => xwm,
+            None => return Ok(())
+        };
+        let watched = [AtomEnum::WM_HINTS.into(), AtomEnum::WM_NORMAL_HINTS.into(), xwm.atoms.WM_PROTOCOLS];
+        if !watched.contains(&event.atom) || !xwm.windows.contains_key(&event.window) {
+            return Ok(())
+        }
+        let hints = xwm.hints(event.window)?;
+        let managed = xwm.windows.get_mut(&event.window).unwrap();
+        let urgent = hints.urgent && !managed.hints.urgent;
+        managed.hints = hints;
+        let surface = managed.surface.clone();
+        if urgent && self.desktop.focused.as_ref() != Some(&surface) {
+            if let Some(window) = self.desktop.window_state_mut(&surface) {
+                window.urgent = true;
+                self.redraw.request();
             }
         }
+        Ok(())
     }
-    Ok(())
-}
 
-pub fn x11_new_window (
-    logger:   &Logger,
-    window:   X11Window,
-    surface:  WlSurface,
-    location: Point<i32, Logical>,
-    //space:    &mut Space<Window>,
-) {
-    debug!(logger, "Matched X11 surface {:x?} to {:x?}", window, surface);
-    if give_role(&surface, "x11_surface").is_err() {
-        // It makes no sense to post a protocol error here since that would only kill Xwayland
-        error!(logger, "Surface {:x?} already has a role?!", surface);
-        return;
-    }
-    let x11surface = X11Surface { surface };
-    //space.map_element(Window::new(Kind::X11(x11surface)), location, true);
-}
+    /// Make an X11 window cover its screen, or put it back where it was.
+    fn x11_fullscreen (&mut self, surface: &WlSurface, fullscreen: bool) {
+        let screen = self.window_screen(surface);
+        let rect = match self.desktop.screens.get(screen) {
+            Some(state) => Rectangle::<f64, Logical>::from_loc_and_size((-state.center.x, -state.center.y), state.size),
+            None => return
+        };
+        let window = match self.desktop.window_state_mut(surface) {
+            Some(window) => window,
+            None => return
+        };
+        let (center, size) = if fullscreen {
+            let size = window.window.geometry().size.to_f64();
+            window.fullscreen = Some(Fullscreen {
+                screen,
+                restore_center: window.center,
+                restore_size:   size,
+                restore_mode:   None,
+            });
+            (rect.loc, rect.size)
+        } else {
+            match window.fullscreen.take() {
+                Some(fullscreen) => (fullscreen.restore_center, fullscreen.restore_size),
+                None => return
+            }
+        };
+        window.center = center;
+        window.size   = size;
+        self.x11_configure_window(surface, center.to_i32_round(), size.to_i32_round());
+    }
 
-// Called when a WlSurface commits.
-//pub fn commit_hook (
-    //surface: &WlSurface,
-    //dh:      &DisplayHandle,
-    //state:   &mut XWaylandConnection,
-//) {
-    //if let Ok(client) = dh.get_client(surface.id()) {
-        //// Is this the Xwayland client?
-        //if client == state.client {
-            //// Is the surface among the unpaired surfaces (see comment next to WL_SURFACE_ID
-            //// handling above)
-            //if let Some((window, location)) = state.unpaired.remove(&surface.id().protocol_id()) {
-                //state.new_window(window, surface.clone(), location);
-            //}
-        //}
-    //}
-//}
+    /// Move and resize the X11 window of a surface.
+    pub fn x11_configure_window (&self, surface: &WlSurface, location: Point<i32, Logical>, size: Size<i32, Logical>) {
+        let xwm = match self.xwm.as_ref() {
+            Some(xwm) => xwm,
+            None => return
+        };
+        if let Some(window) = xwm.window_of(surface) {
+            if let Err(e) = xwm.configure(window, location, size) {
+                warn!(xwm.logger, "Could not configure X11 window {window:x}: {e}");
+            }
+        }
+    }
+
+    /// Let the X11 window of a surface know whether it's fullscreen or maximized.
+    fn x11_state_sync (&self, surface: &WlSurface) {
+        let (xwm, window) = match (self.xwm.as_ref(), self.desktop.window_state(surface)) {
+            (Some(xwm), Some(window)) => (xwm, window),
+            _ => return
+        };
+        if let Some(x11) = xwm.window_of(surface) {
+            if let Err(e) = xwm.set_state(x11, window.fullscreen.is_some(), window.filled.is_some()) {
+                warn!(xwm.logger, "Could not set the state of X11 window {x11:x}: {e}");
+            }
+        }
+    }
+
+    /// Ask a window to close: an xdg toplevel through xdg-shell,
+    /// an X11 window with `WM_DELETE_WINDOW` if it takes it.
+    pub fn window_close (&self, surface: &WlSurface) {
+        let window = match self.desktop.window_state(surface) {
+            Some(window) => window,
+            None => return
+        };
+        match window.window.toplevel() {
+            Kind::Xdg(toplevel) => toplevel.send_close(),
+            Kind::X11(_) => if let Some(xwm) = self.xwm.as_ref() {
+                if let Some(x11) = xwm.window_of(surface) {
+                    if let Err(e) = xwm.close(x11) {
+                        warn!(xwm.logger, "Could not close X11 window {x11:x}: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+}
@@ -1,5 +1,7 @@
 use crate::prelude::*;
 
+use smithay::backend::renderer::{ImportAll, ImportMem, ImportDma};
+
 pub type StdResult<T> = Result<T, Box<dyn Error>>;
 
 pub type Shared<T> = Rc<RefCell<T>>;
@@ -7,13 +9,13 @@ pub type Shared<T> = Rc<RefCell<T>>;
 /// Something that respond to user input.
 pub trait Update<UpdateParams> {
     /// Respond to input
-    fn update (&mut self, context: UpdateParams) -> StdResult<()>;
+    fn update (&mut self, context: UpdateParams) -> CharlieResult<()>;
 }
 
 /// Something that can be rendered to a display.
 pub trait Render<'r, RenderParams> {
     /// Render to display
-    fn render (&'r mut self, context: &'r mut RenderParams) -> StdResult<()>;
+    fn render (&'r mut self, context: &'r mut RenderParams) -> CharlieResult<()>;
 }
 
 ///// All types that implement Render + Update are widgets
@@ -22,23 +24,75 @@ pub trait Render<'r, RenderParams> {
 pub trait Engine: Outputs + Inputs + 'static {
     /// Create a new instance of this engine
     fn new <T: App<Self>> (logger: &Logger, display: &DisplayHandle)
-        -> Result<Self, Box<dyn Error>> where Self: Sized;
+        -> CharlieResult<Self> where Self: Sized;
     /// Obtain a copy of the logger.
     fn logger (&self)
         -> Logger;
+    /// The renderer that draws the outputs
+    type Renderer: EngineRenderer;
     /// Obtain a mutable reference to the renderer.
     fn renderer (&self)
-        -> RefMut<Gles2Renderer>;
+        -> RefMut<Self::Renderer>;
     fn update <U: App<Self> + 'static> (app: &mut U)
-        -> StdResult<()> where Self: Sized;
+        -> CharlieResult<()> where Self: Sized;
     fn render <R: App<Self> + 'static> (app: &mut R)
-        -> StdResult<()> where Self: Sized;
+        -> CharlieResult<()> where Self: Sized;
 
     fn dmabuf_state (&mut self) -> &mut smithay::wayland::dmabuf::DmabufState;
 
+    /// Which devices, formats and modifiers clients should prefer for buffers shown on a screen.
+    /// When `scanout` is set, the buffer covers the whole output and could be scanned out directly.
+    fn dmabuf_feedback (&self, screen: ScreenId, scanout: bool)
+        -> Option<&smithay::wayland::dmabuf::DmabufFeedback> { None }
+
+    /// Whether the engine can keep running.
+    fn running (&self) -> bool;
+    /// How often the engine has to be updated to notice input,
+    /// if it can't wake the main loop up when input arrives
+    fn poll_interval (&self) -> Option<Duration> { None }
+
+    /// Whether the session is active, i.e. we're allowed to render and read input.
+    fn session_active (&self) -> bool { true }
+
+    /// Switch to another virtual terminal.
+    fn change_vt (&mut self, vt: i32) -> CharlieResult<()> {
+        Err(CharlieError::Unsupported("VT switching"))
+    }
+
+    /// Destroy the engine's globals and release its resources.
+    fn stop <T: App<Self>> (&mut self, display: &DisplayHandle)
+        -> CharlieResult<()> where Self: Sized;
+
     fn shm_state (&self) -> &smithay::wayland::shm::ShmState;
 }
 
+/// What the compositor needs from the renderer of an engine.
+///
+/// Windows, wallpapers, cursors, shadows and text are drawn through smithay's renderer traits,
+/// so that any renderer will do. Effects, the night light tint and thumbnails need GLES2;
+/// renderers that can't provide it keep the default methods here, and go without those.
+pub trait EngineRenderer: Renderer + ImportAll + ImportMem + ImportDma + 'static {
+    /// The GLES2 renderer behind this one, if any.
+    fn gles2 (&mut self) -> Option<&mut Gles2Renderer> { None }
+    /// The GLES2 frame behind a frame of this renderer, if any.
+    fn gles2_frame <'a, 'frame> (frame: &'a mut Self::Frame<'frame>)
+        -> Option<&'a mut Gles2Frame<'frame>> { None }
+}
+
+impl EngineRenderer for Gles2Renderer {
+    fn gles2 (&mut self) -> Option<&mut Gles2Renderer> {
+        Some(self)
+    }
+    fn gles2_frame <'a, 'frame> (frame: &'a mut Gles2Frame<'frame>)
+        -> Option<&'a mut Gles2Frame<'frame>>
+    {
+        Some(frame)
+    }
+}
+
+/// Textures of the renderer of an engine.
+pub type EngineTexture<E> = <<E as Engine>::Renderer as Renderer>::TextureId;
+
 pub trait App<E: Engine> {
 
     fn engine (&self) -> &E;
@@ -50,7 +104,20 @@ pub trait App<E: Engine> {
         output: &Output,
         size:   &Size<i32, Physical>,
         screen: ScreenId
-    ) -> StdResult<()>;
+    ) -> CharlieResult<()>;
+
+    /// Respond to an input event that arrived on a screen
+    fn input <B: InputBackend> (&mut self, event: InputEvent<B>, screen: ScreenId)
+        -> CharlieResult<()>;
+
+    /// Respond to the output that displays a screen going away, e.g. because it was unplugged
+    fn output_lost (&mut self, screen: ScreenId) -> CharlieResult<()>;
+
+    /// Render the outputs again, e.g. because a host window was exposed or resized
+    fn redraw (&mut self);
+
+    /// Render the outputs again once this time comes, e.g. because it's too soon now
+    fn redraw_at (&mut self, at: Instant);
 
 }
 
@@ -61,20 +128,83 @@ pub trait App<E: Engine> {
 pub trait Outputs {
     /// Called when an output is added
     fn output_added (&mut self, name: &str, screen: usize, width: i32, height: i32)
-        -> Result<(), Box<dyn Error>> { unimplemented!(); }
+        -> CharlieResult<()> { Ok(()) }
     /// Called when an output's properties change
-    fn output_changed (&mut self) -> Result<(), Box<dyn Error>> { unimplemented!(); }
+    fn output_changed (&mut self) -> CharlieResult<()> { Ok(()) }
     /// Called when an output is removed
-    fn output_removed (&mut self) -> Result<(), Box<dyn Error>> { unimplemented!(); }
+    fn output_removed (&mut self) -> CharlieResult<()> { Ok(()) }
+    /// Switch an output to the mode closest to the given size,
+    /// returning the size of the mode that was previously set
+    fn output_set_mode (&mut self, screen: ScreenId, size: Size<i32, Physical>)
+        -> CharlieResult<Size<i32, Physical>> { Err(CharlieError::Unsupported("mode switching")) }
+    /// Switch the output that displays a screen to one of the modes it offers
+    fn output_mode (&mut self, screen: ScreenId, mode: smithay::output::Mode)
+        -> CharlieResult<()> { Err(CharlieError::Unsupported("mode switching")) }
+    /// Render the output that displays a screen into framebuffers of another pixel format,
+    /// e.g. to get 10 bits per channel. Engines should record it with
+    /// [crate::state::color::output_format_set]
+    fn output_set_format (&mut self, screen: ScreenId, format: smithay::backend::allocator::Fourcc)
+        -> CharlieResult<()> { Err(CharlieError::Unsupported("changing the pixel format of outputs")) }
+    /// Turn the output that displays a screen off or back on
+    fn output_enable (&mut self, screen: ScreenId, enabled: bool)
+        -> CharlieResult<()> { Err(CharlieError::Unsupported("turning outputs off")) }
+    /// Get the output that displays a screen
+    fn output_get (&self, screen: ScreenId) -> Option<Output> { None }
+    /// How many entries the gamma ramps of an output have, if it has hardware gamma
+    fn output_gamma_size (&self, screen: ScreenId) -> Option<u32> { None }
+    /// Load red, green and blue gamma ramps into an output, one after the other,
+    /// or reset it to linear gamma if there are none
+    fn output_set_gamma (&mut self, screen: ScreenId, ramps: Option<&[u16]>)
+        -> CharlieResult<()> { Err(CharlieError::Unsupported("gamma control")) }
+    /// Let the output that displays a screen flip pages as soon as frames are ready,
+    /// without waiting for vblank, or make it wait again
+    fn output_set_tearing (&mut self, screen: ScreenId, enabled: bool)
+        -> CharlieResult<()> { Err(CharlieError::Unsupported("tearing page flips")) }
+    /// How many outputs are currently available for rendering
+    fn output_count (&self) -> usize;
+}
+
+/// Kinds of input that an engine can deliver, and therefore that seats advertise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputCapabilities {
+    pub keyboard: bool,
+    pub pointer:  bool,
+    pub touch:    bool,
+}
+
+impl InputCapabilities {
+    pub const ALL: Self = Self { keyboard: true, pointer: true, touch: true };
+}
+
+/// Locks shown on the LEDs of keyboards.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyboardLeds {
+    pub num_lock:  bool,
+    pub caps_lock: bool,
 }
 
 pub trait Inputs {
     /// Called when an input is added
-    fn input_added (&mut self, name: &str) -> Result<(), Box<dyn Error>> { unimplemented!(); }
+    fn input_added (&mut self, name: &str) -> CharlieResult<()> { Ok(()) }
     /// Called when an input's properties change
-    fn input_changed (&mut self) -> Result<(), Box<dyn Error>> { unimplemented!(); }
+    fn input_changed (&mut self) -> CharlieResult<()> { Ok(()) }
     /// Called when an input is removed
-    fn input_removed (&mut self) -> Result<(), Box<dyn Error>> { unimplemented!(); }
+    fn input_removed (&mut self) -> CharlieResult<()> { Ok(()) }
+    /// Apply the settings that only the device itself can, such as tapping and the scroll method,
+    /// to the named device, or to all devices without settings of their own if the name is
+    /// [crate::state::acceleration::ALL_DEVICES]. Motion should reach the compositor
+    /// unaccelerated, since acceleration is applied afterwards.
+    fn input_configure (&mut self, device: &str, config: &crate::state::acceleration::PointerConfig)
+        -> CharlieResult<()> { Ok(()) }
+    /// Light up the LEDs of the keyboards of a seat
+    fn input_leds (&mut self, seat: usize, leds: KeyboardLeds) {}
+    /// Which kinds of input are currently available
+    fn input_capabilities (&self) -> InputCapabilities { InputCapabilities::ALL }
+    /// Text on the clipboard of the session hosting the compositor,
+    /// if it changed since the last call
+    fn host_selection (&mut self) -> Option<String> { None }
+    /// Cursor theme and size of the session hosting the compositor
+    fn host_cursor_theme (&self) -> Option<(String, u32)> { None }
 }
 
 // TODO:
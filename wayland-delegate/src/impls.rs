@@ -33,6 +33,8 @@ pub fn delegate (
                 dhandle:   &wayland_server::DisplayHandle,
                 data_init: &mut wayland_server::DataInit<'_, Self>,
             ) {
+                // The delegating state records requests for the protocol log
+                state.protocol_request(client, resource, &request);
                 <#target as wayland_server::Dispatch<#interface, #data, Self>>::request(
                     state, client, resource, request, data, dhandle, data_init
                 )
@@ -410,3 +412,33 @@ pub fn delegate_kde_decoration (input: TokenStream) -> TokenStream {
     ])
 }
 
+
+pub fn delegate_explicit_sync (input: TokenStream) -> TokenStream {
+    let ItemImpl { generics: g, self_ty: s, .. } = parse(input.clone()).unwrap();
+    let t = quote! {
+        smithay::wayland::explicit_synchronization::ExplicitSyncState
+    };
+    delegator(input, &[
+        delegate_global(&g, &s, &t, quote! {
+            wayland_protocols::wp::linux_explicit_synchronization::zv1::server::zwp_linux_explicit_synchronization_v1::ZwpLinuxExplicitSynchronizationV1
+        }, quote! {
+            ()
+        }),
+    ], &[
+        delegate(&g, &s, &t, quote! {
+            wayland_protocols::wp::linux_explicit_synchronization::zv1::server::zwp_linux_explicit_synchronization_v1::ZwpLinuxExplicitSynchronizationV1
+        }, quote! {
+            ()
+        }),
+        delegate(&g, &s, &t, quote! {
+            wayland_protocols::wp::linux_explicit_synchronization::zv1::server::zwp_linux_surface_synchronization_v1::ZwpLinuxSurfaceSynchronizationV1
+        }, quote! {
+            smithay::wayland::explicit_synchronization::ESUserData
+        }),
+        delegate(&g, &s, &t, quote! {
+            wayland_protocols::wp::linux_explicit_synchronization::zv1::server::zwp_linux_buffer_release_v1::ZwpLinuxBufferReleaseV1
+        }, quote! {
+            ()
+        }),
+    ])
+}
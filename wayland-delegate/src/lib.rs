@@ -52,3 +52,5 @@ delegator!(delegate_xdg_activation);
 delegator!(delegate_xdg_decoration);
 
 delegator!(delegate_kde_decoration);
+
+delegator!(delegate_explicit_sync);